@@ -5,8 +5,9 @@ use std::str::FromStr;
 use dialoguer::{theme::ColorfulTheme, MultiSelect};
 use filecoin_proofs::{
     constants::{
-        DefaultPieceHasher, POREP_PARTITIONS, PUBLISHED_SECTOR_SIZES, WINDOW_POST_CHALLENGE_COUNT,
-        WINDOW_POST_SECTOR_COUNT, WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT,
+        register_sector_size, supported_sector_sizes, CustomSectorSize, DefaultPieceHasher,
+        POREP_PARTITIONS, WINDOW_POST_CHALLENGE_COUNT, WINDOW_POST_SECTOR_COUNT,
+        WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT,
     },
     parameters::{public_params, window_post_public_params, winning_post_public_params},
     types::{PaddedBytesAmount, PoRepConfig, PoRepProofPartitions, PoStConfig, SectorSize},
@@ -128,6 +129,15 @@ struct Opt {
         help = "A comma-separated list of sector sizes (in number of bytes)."
     )]
     sector_sizes: Vec<u64>,
+    #[structopt(
+        long = "register-sector-size",
+        value_name = "FIELDS",
+        number_of_values = 1,
+        help = "Register an unpublished sector size, so that its params can be generated, e.g. \
+        sector_size=65536,base_arity=8,sub_arity=4,top_arity=0,layers=2,porep_partitions=1,\
+        porep_minimum_challenges=2,window_post_sector_count=2. Can be given more than once."
+    )]
+    register_sector_sizes: Vec<CustomSectorSize>,
     #[structopt(
         long = "api-version",
         value_name = "SEMANTIC VERSION",
@@ -195,10 +205,18 @@ pub fn main() {
 
     let mut opts = Opt::from_args();
 
+    for custom in &opts.register_sector_sizes {
+        if let Err(err) = register_sector_size(*custom) {
+            error!("failed to register sector size: {:?}", err);
+            exit(1);
+        }
+    }
+    let supported_sizes = supported_sector_sizes();
+
     // If no sector-sizes were given provided via. the CLI, display an interactive menu. Otherwise,
     // filter out invalid CLI sector-size arguments.
     if opts.sector_sizes.is_empty() {
-        let sector_size_strings: Vec<String> = PUBLISHED_SECTOR_SIZES
+        let sector_size_strings: Vec<String> = supported_sizes
             .iter()
             .map(|sector_size| {
                 let human_size = sector_size
@@ -218,11 +236,11 @@ pub fn main() {
             .interact()
             .expect("interaction failed")
             .into_iter()
-            .map(|i| PUBLISHED_SECTOR_SIZES[i])
+            .map(|i| supported_sizes[i])
             .collect();
     } else {
         opts.sector_sizes.retain(|size| {
-            if PUBLISHED_SECTOR_SIZES.contains(size) {
+            if supported_sizes.contains(size) {
                 true
             } else {
                 let human_size = size
@@ -6,6 +6,7 @@ use std::process::{exit, Command};
 
 use anyhow::{ensure, Context, Result};
 use dialoguer::{theme::ColorfulTheme, MultiSelect, Select};
+use fil_proofs_param::fetch::{HttpFetcher, Source};
 use filecoin_proofs::param::{
     get_digest_for_file_within_cache, get_full_path_for_file_within_cache, has_extension,
 };
@@ -16,14 +17,16 @@ use log::{error, info, trace, warn};
 use pbr::{ProgressBar, Units};
 use reqwest::{blocking::Client, header, Proxy, Url};
 use storage_proofs_core::parameter_cache::{
-    parameter_cache_dir, parameter_cache_dir_name, ParameterMap, GROTH_PARAMETER_EXT,
+    parameter_cache_dir, parameter_cache_dir_name, ParameterData, ParameterMap,
+    GROTH_PARAMETER_EXT, SRS_PARAMETERS_DATA,
 };
 use structopt::StructOpt;
 use tar::Archive;
 
 lazy_static! {
     static ref CLI_ABOUT: String = format!(
-        "Downloads missing or outdated Groth parameter files from ipfs using ipget.\n\n\
+        "Downloads missing or outdated Groth parameter files from ipfs using ipget, or over \
+        HTTP(S) from the given gateways or mirrors.\n\n\
 
         Set the $FIL_PROOFS_PARAMETER_CACHE env-var to specify the path to the parameter cache
         directory (location where params are written), otherwise params will be written to '{}'.",
@@ -217,6 +220,30 @@ fn download_file_with_ipget(
     Ok(())
 }
 
+/// How parameter files are downloaded.
+enum Backend {
+    Ipget { path: PathBuf, args: Option<String> },
+    Http(HttpFetcher),
+}
+
+impl Backend {
+    fn download(
+        &self,
+        filename: &str,
+        data: &ParameterData,
+        path: &Path,
+        verbose: bool,
+    ) -> Result<()> {
+        match self {
+            Backend::Ipget {
+                path: ipget_path,
+                args,
+            } => download_file_with_ipget(&data.cid, path, ipget_path, args, verbose),
+            Backend::Http(fetcher) => fetcher.fetch(filename, &data.cid, &data.digest, path),
+        }
+    }
+}
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "paramfetch", version = "1.1", about = CLI_ABOUT.as_str())]
 struct Cli {
@@ -227,6 +254,12 @@ struct Cli {
         help = "Use a specific JSON file."
     )]
     json: Option<String>,
+    #[structopt(
+        long,
+        conflicts_with = "json",
+        help = "Use the built-in srs-inner-product.json instead of parameters.json."
+    )]
+    srs: bool,
     #[structopt(long, short = "r", help = "Prompt to retry file downloads on failure.")]
     retry: bool,
     #[structopt(
@@ -273,6 +306,28 @@ struct Cli {
         help = "Specify additional arguments for ipget."
     )]
     ipget_args: Option<String>,
+    #[structopt(
+        long = "gateway",
+        value_name = "URL",
+        use_delimiter = true,
+        parse(try_from_str = Url::parse),
+        conflicts_with_all = &["ipget-bin", "ipget-version", "ipget-args"],
+        long_help = "A comma-separated list of IPFS HTTP gateways, such as https://ipfs.io/ipfs, \
+            to download files from instead of using ipget. Files are fetched from <URL>/<CID>."
+    )]
+    gateways: Vec<Url>,
+    #[structopt(
+        long = "mirror",
+        value_name = "URL",
+        use_delimiter = true,
+        parse(try_from_str = Url::parse),
+        conflicts_with_all = &["ipget-bin", "ipget-version", "ipget-args"],
+        long_help = "A comma-separated list of HTTP(S) mirrors of the parameter cache to download \
+            files from instead of using ipget. Files are fetched from <URL>/<FILENAME>. Mirrors are \
+            tried before gateways, interrupted downloads are resumed and every file is checked \
+            against its digest before it is moved into the parameter cache."
+    )]
+    mirrors: Vec<Url>,
 }
 
 pub fn main() {
@@ -299,6 +354,15 @@ pub fn main() {
                 })
                 .unwrap()
         }
+        None if cli.srs => {
+            trace!("using built-in srs json");
+            serde_json::from_str(SRS_PARAMETERS_DATA)
+                .map_err(|e| {
+                    error!("failed to parse built-in srs json, exiting\n{:?}", e);
+                    exit(1);
+                })
+                .unwrap()
+        }
         None => {
             trace!("using built-in json");
             serde_json::from_str(DEFAULT_JSON)
@@ -370,48 +434,32 @@ pub fn main() {
         return;
     }
 
-    let ipget_path = if let Some(path_str) = cli.ipget_bin {
-        let path = PathBuf::from(path_str);
-        if !path.exists() {
-            error!(
-                "provided ipget binary not found: {}, exiting",
-                path.display()
-            );
-            exit(1);
+    let sources: Vec<Source> = cli
+        .mirrors
+        .into_iter()
+        .map(Source::Mirror)
+        .chain(cli.gateways.into_iter().map(Source::Gateway))
+        .collect();
+    let backend = if sources.is_empty() {
+        Backend::Ipget {
+            path: get_ipget(cli.ipget_bin, cli.ipget_version, cli.verbose),
+            args: cli.ipget_args,
         }
-
-        path
     } else {
-        let ipget_version = cli
-            .ipget_version
-            .unwrap_or_else(|| DEFAULT_IPGET_VERSION.to_string());
-        let tmp_path = get_ipget_path(&ipget_version);
-        let path = PathBuf::from(&tmp_path);
-        if !path.exists() {
-            info!("ipget binary not found: {}", path.display());
-            download_ipget(&ipget_version, cli.verbose).expect("ipget download failed");
-        }
-
-        path
+        trace!("using sources: {:?}", sources);
+        Backend::Http(HttpFetcher::new(sources).expect("failed to create HTTP client"))
     };
-    trace!("using ipget binary: {}", ipget_path.display());
 
     trace!("creating param cache dir(s) if they don't exist");
     create_dir_all(parameter_cache_dir()).expect("failed to create param cache dir");
 
     loop {
         for filename in &filenames {
-            info!("downloading params file with ipget: {}", filename);
+            info!("downloading params file: {}", filename);
             let path = get_full_path_for_file_within_cache(filename);
-            match download_file_with_ipget(
-                &parameter_map[filename].cid,
-                &path,
-                &ipget_path,
-                &cli.ipget_args,
-                cli.verbose,
-            ) {
+            match backend.download(filename, &parameter_map[filename], &path, cli.verbose) {
                 Ok(_) => info!("finished downloading params file"),
-                Err(e) => warn!("failed to download params file: {}", e),
+                Err(e) => warn!("failed to download params file: {:?}", e),
             };
         }
         filenames = get_filenames_requiring_download(&parameter_map, filenames);
@@ -437,3 +485,32 @@ pub fn main() {
         }
     }
 }
+
+/// Returns the path to the ipget binary to use, downloading it if needed.
+fn get_ipget(ipget_bin: Option<String>, ipget_version: Option<String>, verbose: bool) -> PathBuf {
+    let ipget_path = if let Some(path_str) = ipget_bin {
+        let path = PathBuf::from(path_str);
+        if !path.exists() {
+            error!(
+                "provided ipget binary not found: {}, exiting",
+                path.display()
+            );
+            exit(1);
+        }
+
+        path
+    } else {
+        let ipget_version = ipget_version.unwrap_or_else(|| DEFAULT_IPGET_VERSION.to_string());
+        let tmp_path = get_ipget_path(&ipget_version);
+        let path = PathBuf::from(&tmp_path);
+        if !path.exists() {
+            info!("ipget binary not found: {}", path.display());
+            download_ipget(&ipget_version, verbose).expect("ipget download failed");
+        }
+
+        path
+    };
+    trace!("using ipget binary: {}", ipget_path.display());
+
+    ipget_path
+}
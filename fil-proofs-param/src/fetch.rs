@@ -0,0 +1,155 @@
+//! Fetching parameter files over HTTP(S) from IPFS gateways and mirrors of the parameter cache.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use filecoin_proofs::param::get_digest_for_file;
+use log::{info, trace, warn};
+use reqwest::{blocking::Client, header, Proxy, StatusCode, Url};
+
+/// The extension of files being downloaded, next to where they are moved once verified.
+pub const PARTIAL_EXT: &str = "partial";
+
+/// Where parameter files are fetched from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// An IPFS HTTP gateway, e.g. `https://ipfs.io/ipfs`. Files are fetched from `<url>/<cid>`.
+    Gateway(Url),
+    /// A plain HTTP mirror of the parameter cache. Files are fetched from `<url>/<filename>`.
+    Mirror(Url),
+}
+
+impl Source {
+    /// Returns the URL of the file with the given name and cid.
+    pub fn file_url(&self, filename: &str, cid: &str) -> Result<Url> {
+        let (base, path) = match self {
+            Source::Gateway(url) => (url, cid),
+            Source::Mirror(url) => (url, filename),
+        };
+
+        // Joining replaces the last path segment of the base, unless it ends with a slash.
+        let mut base = base.clone();
+        if !base.path().ends_with('/') {
+            let path = format!("{}/", base.path());
+            base.set_path(&path);
+        }
+
+        base.join(path)
+            .with_context(|| format!("invalid URL for {} under {}", path, base))
+    }
+}
+
+/// Fetches parameter files over HTTP(S), trying each source in turn.
+pub struct HttpFetcher {
+    client: Client,
+    sources: Vec<Source>,
+}
+
+impl HttpFetcher {
+    pub fn new(sources: Vec<Source>) -> Result<Self> {
+        ensure!(!sources.is_empty(), "no gateways or mirrors to fetch from");
+
+        let client = Client::builder()
+            .proxy(Proxy::custom(move |url| env_proxy::for_url(&url).to_url()))
+            .build()?;
+
+        Ok(HttpFetcher { client, sources })
+    }
+
+    /// Fetches the file with the given name and cid to `path`. The download is written to a
+    /// `.partial` file next to `path`, which is resumed with a Range request if it is left over
+    /// from an interrupted download, or by the next source if one fails midway. It is moved to
+    /// `path` once its BLAKE2b digest matches `digest`, and removed if it does not.
+    pub fn fetch(&self, filename: &str, cid: &str, digest: &str, path: &Path) -> Result<()> {
+        let partial = partial_path(path);
+        let mut last_err = anyhow!("no sources");
+
+        for source in &self.sources {
+            let url = source.file_url(filename, cid)?;
+            info!("fetching {}", url);
+            if let Err(err) = self.download(&url, &partial) {
+                warn!("failed to fetch {}: {:?}", url, err);
+                last_err = err;
+                continue;
+            }
+
+            let actual_digest = get_digest_for_file(&partial)?;
+            if actual_digest == digest {
+                fs::rename(&partial, path)
+                    .with_context(|| format!("could not move {:?} to {:?}", partial, path))?;
+                return Ok(());
+            }
+
+            warn!(
+                "{} has digest {}, expected {}, discarding it",
+                url, actual_digest, digest
+            );
+            fs::remove_file(&partial).with_context(|| format!("could not remove {:?}", partial))?;
+            last_err = anyhow!("digest mismatch: {} != {}", actual_digest, digest);
+        }
+
+        Err(last_err).with_context(|| format!("failed to fetch {}", filename))
+    }
+
+    /// Downloads `url` into `partial`, resuming after the bytes it already holds.
+    fn download(&self, url: &Url, partial: &Path) -> Result<()> {
+        let offset = match fs::metadata(partial) {
+            Ok(metadata) => metadata.len(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => {
+                return Err(err).with_context(|| format!("could not stat {:?}", partial));
+            }
+        };
+
+        let mut request = self.client.get(url.clone());
+        if offset > 0 {
+            trace!("resuming {:?} at byte {}", partial, offset);
+            request = request.header(header::RANGE, format!("bytes={}-", offset));
+        }
+        let mut response = request.send()?;
+
+        let mut file = match response.status() {
+            StatusCode::PARTIAL_CONTENT if offset > 0 => {
+                let content_range = response
+                    .headers()
+                    .get(header::CONTENT_RANGE)
+                    .and_then(|val| val.to_str().ok())
+                    .unwrap_or_default();
+                ensure!(
+                    content_range.starts_with(&format!("bytes {}-", offset)),
+                    "unexpected content range {:?} for offset {}",
+                    content_range,
+                    offset
+                );
+                OpenOptions::new()
+                    .append(true)
+                    .open(partial)
+                    .with_context(|| format!("could not open {:?}", partial))?
+            }
+            // Nothing is left after the offset, the digest tells whether the file is complete.
+            StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => return Ok(()),
+            // The server ignored the range, if any, and sends the whole file.
+            StatusCode::OK => {
+                File::create(partial).with_context(|| format!("could not create {:?}", partial))?
+            }
+            status => bail!("unexpected response status {}", status),
+        };
+
+        io::copy(&mut response, &mut file)
+            .with_context(|| format!("could not write to {:?}", partial))?;
+        file.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Returns the path a file is downloaded to before it is verified and moved to `path`.
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".");
+    partial.push(PARTIAL_EXT);
+
+    PathBuf::from(partial)
+}
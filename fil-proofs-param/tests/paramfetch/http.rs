@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use fil_proofs_param::fetch::{partial_path, HttpFetcher, Source};
+use reqwest::Url;
+use tempfile::tempdir;
+
+use super::rand_bytes_with_blake2b;
+
+/// A minimal HTTP server serving `files` at `/<name>`, which honours `Range: bytes=<start>-`
+/// headers unless `ranges` is false. It records the range header of each request.
+struct TestServer {
+    url: Url,
+    ranges_requested: Arc<Mutex<Vec<Option<String>>>>,
+}
+
+impl TestServer {
+    fn start(files: BTreeMap<String, Vec<u8>>, ranges: bool) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind listener");
+        let url = Url::parse(&format!(
+            "http://{}/",
+            listener.local_addr().expect("no local address")
+        ))
+        .expect("invalid url");
+        let ranges_requested = Arc::new(Mutex::new(Vec::new()));
+
+        let requested = ranges_requested.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.expect("failed to accept connection");
+                serve(stream, &files, ranges, &requested);
+            }
+        });
+
+        TestServer {
+            url,
+            ranges_requested,
+        }
+    }
+
+    fn ranges_requested(&self) -> Vec<Option<String>> {
+        self.ranges_requested.lock().expect("poisoned").clone()
+    }
+}
+
+fn serve(
+    mut stream: TcpStream,
+    files: &BTreeMap<String, Vec<u8>>,
+    ranges: bool,
+    requested: &Mutex<Vec<Option<String>>>,
+) {
+    let mut reader = BufReader::new(stream.try_clone().expect("failed to clone stream"));
+    let mut request_line = String::new();
+    reader
+        .read_line(&mut request_line)
+        .expect("failed to read request");
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .expect("invalid request")
+        .trim_start_matches('/')
+        .to_string();
+
+    let mut range = None;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).expect("failed to read header");
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap_or_default();
+        if name.eq_ignore_ascii_case("range") {
+            range = parts.next().map(|value| value.trim().to_string());
+        }
+    }
+    requested.lock().expect("poisoned").push(range.clone());
+
+    let (status, headers, body): (&str, String, &[u8]) = match files.get(&path) {
+        None => ("404 Not Found", String::new(), &[][..]),
+        Some(data) => match range.as_ref().filter(|_| ranges) {
+            None => ("200 OK", String::new(), &data[..]),
+            Some(range) => {
+                let start: usize = range
+                    .trim_start_matches("bytes=")
+                    .trim_end_matches('-')
+                    .parse()
+                    .expect("invalid range");
+                if start >= data.len() {
+                    (
+                        "416 Range Not Satisfiable",
+                        format!("Content-Range: bytes */{}\r\n", data.len()),
+                        &[][..],
+                    )
+                } else {
+                    (
+                        "206 Partial Content",
+                        format!(
+                            "Content-Range: bytes {}-{}/{}\r\n",
+                            start,
+                            data.len() - 1,
+                            data.len()
+                        ),
+                        &data[start..],
+                    )
+                }
+            }
+        },
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        headers,
+        body.len()
+    )
+    .expect("failed to write response");
+    stream.write_all(body).expect("failed to write body");
+}
+
+fn files(entries: &[(&str, &[u8])]) -> BTreeMap<String, Vec<u8>> {
+    entries
+        .iter()
+        .map(|(name, data)| (name.to_string(), data.to_vec()))
+        .collect()
+}
+
+#[test]
+fn fetches_file_from_mirror() {
+    let (bytes, digest) = rand_bytes_with_blake2b().expect("failed to create bytes");
+    let server = TestServer::start(files(&[("aaa.vk", &bytes[..])]), true);
+    let dir = tempdir().expect("failed to create temp dir");
+    let path = dir.path().join("aaa.vk");
+
+    let fetcher = HttpFetcher::new(vec![Source::Mirror(server.url.clone())])
+        .expect("failed to create fetcher");
+    fetcher
+        .fetch("aaa.vk", "", &digest, &path)
+        .expect("failed to fetch");
+
+    assert_eq!(fs::read(&path).expect("failed to read file"), bytes);
+    assert!(!partial_path(&path).exists());
+    assert_eq!(server.ranges_requested(), vec![None]);
+}
+
+#[test]
+fn fetches_file_by_cid_from_gateway() {
+    let (bytes, digest) = rand_bytes_with_blake2b().expect("failed to create bytes");
+    let server = TestServer::start(files(&[("ipfs/QmAaa", &bytes[..])]), true);
+    let dir = tempdir().expect("failed to create temp dir");
+    let path = dir.path().join("aaa.vk");
+
+    let gateway = server.url.join("ipfs").expect("invalid url");
+    let fetcher =
+        HttpFetcher::new(vec![Source::Gateway(gateway)]).expect("failed to create fetcher");
+    fetcher
+        .fetch("aaa.vk", "QmAaa", &digest, &path)
+        .expect("failed to fetch");
+
+    assert_eq!(fs::read(&path).expect("failed to read file"), bytes);
+}
+
+#[test]
+fn resumes_partial_download() {
+    let (bytes, digest) = rand_bytes_with_blake2b().expect("failed to create bytes");
+    let server = TestServer::start(files(&[("aaa.vk", &bytes[..])]), true);
+    let dir = tempdir().expect("failed to create temp dir");
+    let path = dir.path().join("aaa.vk");
+    fs::write(partial_path(&path), &bytes[..10]).expect("failed to write partial file");
+
+    let fetcher = HttpFetcher::new(vec![Source::Mirror(server.url.clone())])
+        .expect("failed to create fetcher");
+    fetcher
+        .fetch("aaa.vk", "", &digest, &path)
+        .expect("failed to fetch");
+
+    assert_eq!(fs::read(&path).expect("failed to read file"), bytes);
+    assert_eq!(
+        server.ranges_requested(),
+        vec![Some("bytes=10-".to_string())]
+    );
+}
+
+#[test]
+fn restarts_download_if_range_is_ignored() {
+    let (bytes, digest) = rand_bytes_with_blake2b().expect("failed to create bytes");
+    let server = TestServer::start(files(&[("aaa.vk", &bytes[..])]), false);
+    let dir = tempdir().expect("failed to create temp dir");
+    let path = dir.path().join("aaa.vk");
+    fs::write(partial_path(&path), b"stale").expect("failed to write partial file");
+
+    let fetcher = HttpFetcher::new(vec![Source::Mirror(server.url.clone())])
+        .expect("failed to create fetcher");
+    fetcher
+        .fetch("aaa.vk", "", &digest, &path)
+        .expect("failed to fetch");
+
+    assert_eq!(fs::read(&path).expect("failed to read file"), bytes);
+}
+
+#[test]
+fn falls_back_to_next_mirror() {
+    let (bytes, digest) = rand_bytes_with_blake2b().expect("failed to create bytes");
+    let empty = TestServer::start(BTreeMap::new(), true);
+    let server = TestServer::start(files(&[("aaa.vk", &bytes[..])]), true);
+    let dir = tempdir().expect("failed to create temp dir");
+    let path = dir.path().join("aaa.vk");
+
+    let fetcher = HttpFetcher::new(vec![
+        Source::Mirror(empty.url.clone()),
+        Source::Mirror(server.url.clone()),
+    ])
+    .expect("failed to create fetcher");
+    fetcher
+        .fetch("aaa.vk", "", &digest, &path)
+        .expect("failed to fetch");
+
+    assert_eq!(fs::read(&path).expect("failed to read file"), bytes);
+    assert_eq!(empty.ranges_requested(), vec![None]);
+}
+
+#[test]
+fn rejects_file_with_wrong_digest() {
+    let (bytes, _) = rand_bytes_with_blake2b().expect("failed to create bytes");
+    let server = TestServer::start(files(&[("aaa.vk", &bytes[..])]), true);
+    let dir = tempdir().expect("failed to create temp dir");
+    let path = dir.path().join("aaa.vk");
+
+    let fetcher = HttpFetcher::new(vec![Source::Mirror(server.url.clone())])
+        .expect("failed to create fetcher");
+    let result = fetcher.fetch("aaa.vk", "", "obviouslywrong", &path);
+
+    assert!(result.is_err());
+    assert!(!path.exists());
+    assert!(!partial_path(&path).exists());
+}
@@ -10,6 +10,7 @@ use storage_proofs_core::parameter_cache::{ParameterData, ParameterMap};
 
 use crate::support::tmp_manifest;
 
+mod http;
 mod session;
 
 use session::ParamFetchSessionBuilder;
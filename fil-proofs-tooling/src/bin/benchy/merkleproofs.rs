@@ -57,7 +57,7 @@ fn generate_proofs<R: Rng, Tree: MerkleTreeTrait>(
             .gen_cached_proof(challenge, Some(rows_to_discard))
             .expect("failed to generate proof");
         if validate {
-            ensure!(proof.validate(challenge), "failed to validate proof");
+            ensure!(proof.validate(challenge)?, "failed to validate proof");
         }
     }
 
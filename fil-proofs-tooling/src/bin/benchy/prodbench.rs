@@ -1,5 +1,7 @@
-use std::fs::remove_file;
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, remove_file};
 use std::str::FromStr;
+use std::time::Duration;
 
 use bellperson::{bls::Bls12, util_cs::bench_cs::BenchCS, Circuit};
 use fil_proofs_tooling::{
@@ -18,11 +20,9 @@ use log::info;
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
-#[cfg(feature = "measurements")]
-use storage_proofs_core::measurements::{Operation, OP_MEASUREMENTS};
 use storage_proofs_core::{
-    api_version::ApiVersion, compound_proof::CompoundProof, parameter_cache::CacheableParameters,
-    proof::ProofScheme,
+    api_version::ApiVersion, compound_proof::CompoundProof, measurements::Operation,
+    metrics::METRICS, parameter_cache::CacheableParameters, proof::ProofScheme, settings::SETTINGS,
 };
 use storage_proofs_porep::stacked::{LayerChallenges, SetupParams, StackedCompound, StackedDrg};
 
@@ -71,6 +71,10 @@ pub struct ProdbenchOutputs {
     encoding_wall_time_ms: u64,
     generate_tree_c_cpu_time_ms: u64,
     generate_tree_c_wall_time_ms: u64,
+    generate_trees_pipelined_cpu_time_ms: u64,
+    generate_trees_pipelined_wall_time_ms: u64,
+    /// Sector bytes per second of wall time the pipelined CPU tree builder processed.
+    generate_trees_pipelined_throughput_bytes_per_sec: u64,
     porep_commit_time_cpu_time_ms: u64,
     porep_commit_time_wall_time_ms: u64,
     porep_proof_gen_cpu_time_ms: u64,
@@ -93,37 +97,51 @@ pub struct ProdbenchOutputs {
     add_piece_wall_time_ms: u64,
     generate_piece_commitment_cpu_time_ms: u64,
     generate_piece_commitment_wall_time_ms: u64,
+    /// Whether layers, tree stores and replicas were written with direct I/O.
+    direct_io: bool,
+    store_write_bytes: u64,
+    /// Bytes per second of wall time spent writing layers and tree stores.
+    store_write_throughput_bytes_per_sec: u64,
+    /// How much the page cache grew while replicating, a measure of how much of the working
+    /// set of other processes it evicted.
+    replication_page_cache_growth_bytes: i64,
     #[serde(flatten)]
     circuits: CircuitOutputs,
 }
 
-#[cfg(not(feature = "measurements"))]
-fn augment_with_op_measurements(mut _output: &mut ProdbenchOutputs) {}
-
-#[cfg(feature = "measurements")]
 fn augment_with_op_measurements(mut output: &mut ProdbenchOutputs) {
-    // drop the tx side of the channel, causing the iterator to yield None
-    // see also: https://doc.rust-lang.org/src/std/sync/mpsc/mod.rs.html#368
-    OP_MEASUREMENTS
-        .0
-        .lock()
-        .expect("failed to acquire mutex")
-        .take();
-
-    let measurements = OP_MEASUREMENTS
-        .1
-        .lock()
-        .expect("failed to acquire lock on rx side of perf channel");
-
-    for m in measurements.iter() {
-        let cpu_time = m.cpu_time.as_millis() as u64;
-        let wall_time = m.wall_time.as_millis() as u64;
-
-        match m.op {
+    // Merge the metrics of all sectors, reporting the mean time of each operation.
+    let mut totals: BTreeMap<Operation, (Duration, Duration, u64, u64)> = BTreeMap::new();
+    for m in METRICS.snapshot() {
+        let total = totals.entry(m.op).or_default();
+        total.0 += m.cpu_time.sum;
+        total.1 += m.wall_time.sum;
+        total.2 += m.wall_time.count;
+        total.3 += m.bytes;
+    }
+
+    for (op, (cpu_time, wall_time, count, bytes)) in totals {
+        if count == 0 {
+            continue;
+        }
+        let throughput = if wall_time.as_secs_f64() > 0.0 {
+            (bytes as f64 / wall_time.as_secs_f64()) as u64
+        } else {
+            0
+        };
+        let cpu_time = (cpu_time.as_millis() / u128::from(count)) as u64;
+        let wall_time = (wall_time.as_millis() / u128::from(count)) as u64;
+
+        match op {
             Operation::GenerateTreeC => {
                 output.generate_tree_c_cpu_time_ms = cpu_time;
                 output.generate_tree_c_wall_time_ms = wall_time;
             }
+            Operation::GenerateTreesPipelined => {
+                output.generate_trees_pipelined_cpu_time_ms = cpu_time;
+                output.generate_trees_pipelined_wall_time_ms = wall_time;
+                output.generate_trees_pipelined_throughput_bytes_per_sec = throughput;
+            }
             Operation::GenerateTreeRLast => {
                 output.tree_r_last_cpu_time_ms = cpu_time;
                 output.tree_r_last_wall_time_ms = wall_time;
@@ -152,11 +170,27 @@ fn augment_with_op_measurements(mut output: &mut ProdbenchOutputs) {
                 output.generate_piece_commitment_cpu_time_ms = cpu_time;
                 output.generate_piece_commitment_wall_time_ms = wall_time;
             }
+            Operation::StoreWrite => {
+                output.store_write_bytes = bytes;
+                output.store_write_throughput_bytes_per_sec = throughput;
+            }
             _ => {}
         }
     }
 }
 
+/// Returns the size of the page cache in bytes, including dirty pages, as reported by
+/// `/proc/meminfo`.
+fn page_cache_bytes() -> Option<i64> {
+    let meminfo = read_to_string("/proc/meminfo").ok()?;
+    meminfo
+        .lines()
+        .find(|line| line.starts_with("Cached:"))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|kib| kib.parse::<i64>().ok())
+        .map(|kib| kib * 1024)
+}
+
 fn configure_global_config(inputs: &ProdbenchInputs) {
     LAYERS
         .write()
@@ -188,6 +222,7 @@ pub fn run(
 
     assert!(inputs.num_sectors > 0, "Missing num_sectors");
 
+    let page_cache_before = page_cache_bytes();
     let (cfg, repls) = create_replicas::<DefaultOctLCTree>(
         sector_size,
         inputs.num_sectors as usize,
@@ -195,6 +230,10 @@ pub fn run(
         arbitrary_porep_id,
         inputs.api_version(),
     );
+    outputs.direct_io = SETTINGS.use_direct_io;
+    if let (Some(before), Some(after)) = (page_cache_before, page_cache_bytes()) {
+        outputs.replication_page_cache_growth_bytes = after - before;
+    }
 
     if only_add_piece || only_replicate {
         augment_with_op_measurements(&mut outputs);
@@ -1,41 +1,106 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use bellperson::{bls::Bls12, util_cs::bench_cs::BenchCS, Circuit};
 use dialoguer::{theme::ColorfulTheme, MultiSelect};
 use filecoin_proofs::{
     parameters::{public_params, window_post_public_params, winning_post_public_params},
-    with_shape, DefaultPieceHasher, PaddedBytesAmount, PoRepConfig, PoRepProofPartitions,
-    PoStConfig, PoStType, SectorSize, POREP_PARTITIONS, PUBLISHED_SECTOR_SIZES,
-    WINDOW_POST_CHALLENGE_COUNT, WINDOW_POST_SECTOR_COUNT, WINNING_POST_CHALLENGE_COUNT,
-    WINNING_POST_SECTOR_COUNT,
+    register_sector_size, supported_sector_sizes, with_shape, CustomSectorSize, DefaultPieceHasher,
+    PaddedBytesAmount, PoRepConfig, PoRepProofPartitions, PoStConfig, PoStType, SectorSize,
+    POREP_PARTITIONS, WINDOW_POST_CHALLENGE_COUNT, WINDOW_POST_SECTOR_COUNT,
+    WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT,
 };
 use humansize::{file_size_opts, FileSize};
 use log::{info, warn};
 use storage_proofs_core::{
-    api_version::ApiVersion, compound_proof::CompoundProof, merkle::MerkleTreeTrait,
+    api_version::ApiVersion, compound_proof::CompoundProof, merkle::MerkleTreeTrait, r1cs::R1csCS,
 };
 use storage_proofs_porep::stacked::{StackedCompound, StackedDrg};
 use storage_proofs_post::fallback::{FallbackPoSt, FallbackPoStCircuit, FallbackPoStCompound};
 use structopt::StructOpt;
 
+use crate::profile::{render, render_diff, CircuitProfile, ProfileCS, ProfileNode};
+
+mod profile;
+
 struct CircuitInfo {
     constraints: usize,
     inputs: usize,
+    profile: Option<ProfileNode>,
 }
 
-fn circuit_info<C: Circuit<Bls12>>(circuit: C) -> CircuitInfo {
+/// What to do with each circuit, besides counting its constraints.
+struct Synthesis<'a> {
+    r1cs_dir: Option<&'a Path>,
+    profile: bool,
+    collapse: bool,
+}
+
+/// Synthesizes the blank circuit returned by `circuit`, once for each requested output.
+fn circuit_info<C: Circuit<Bls12>, F: Fn() -> C>(
+    circuit: F,
+    name: &str,
+    sector_size: SectorSize,
+    synthesis: &Synthesis<'_>,
+) -> CircuitInfo {
+    let profile = if synthesis.profile {
+        let mut cs = ProfileCS::new(name, synthesis.collapse);
+        circuit().synthesize(&mut cs).expect("failed to synthesize");
+        Some(cs.into_profile())
+    } else {
+        None
+    };
+
+    if let Some(r1cs_dir) = synthesis.r1cs_dir {
+        let r1cs_path = r1cs_dir.join(format!("{}-{}.r1cs", name, u64::from(sector_size)));
+        let info = export_r1cs(circuit(), &r1cs_path);
+        return CircuitInfo { profile, ..info };
+    }
+
+    if let Some(profile) = profile {
+        return CircuitInfo {
+            constraints: profile.constraints,
+            inputs: profile.inputs,
+            profile: Some(profile),
+        };
+    }
+
     let mut cs_blank = BenchCS::new();
-    circuit
+    circuit()
         .synthesize(&mut cs_blank)
         .expect("failed to synthesize");
 
     CircuitInfo {
         constraints: cs_blank.num_constraints(),
         inputs: cs_blank.num_inputs(),
+        profile: None,
     }
 }
 
-fn get_porep_info<Tree: 'static + MerkleTreeTrait>(porep_config: PoRepConfig) -> CircuitInfo {
+/// Records the constraints of the blank `circuit` and writes them to `r1cs_path`.
+fn export_r1cs<C: Circuit<Bls12>>(circuit: C, r1cs_path: &Path) -> CircuitInfo {
+    let mut cs = R1csCS::new();
+    circuit.synthesize(&mut cs).expect("failed to synthesize");
+
+    let file = File::create(r1cs_path).expect("failed to create r1cs file");
+    cs.write_r1cs(BufWriter::new(file))
+        .expect("failed to write r1cs file");
+    info!("wrote {:?}", r1cs_path);
+    println!("Wrote {}", r1cs_path.display());
+
+    CircuitInfo {
+        constraints: cs.num_constraints(),
+        inputs: cs.num_inputs(),
+        profile: None,
+    }
+}
+
+fn get_porep_info<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    synthesis: &Synthesis<'_>,
+) -> CircuitInfo {
     info!("PoRep info");
 
     let public_params = public_params(
@@ -46,40 +111,52 @@ fn get_porep_info<Tree: 'static + MerkleTreeTrait>(porep_config: PoRepConfig) ->
     )
     .expect("failed to get public params from config");
 
-    let circuit = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
-        StackedDrg<Tree, DefaultPieceHasher>,
-        _,
-    >>::blank_circuit(&public_params);
+    let circuit = || {
+        <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
+            StackedDrg<Tree, DefaultPieceHasher>,
+            _,
+        >>::blank_circuit(&public_params)
+    };
 
-    circuit_info(circuit)
+    circuit_info(circuit, "porep", porep_config.sector_size, synthesis)
 }
 
-fn get_winning_post_info<Tree: 'static + MerkleTreeTrait>(post_config: &PoStConfig) -> CircuitInfo {
+fn get_winning_post_info<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    synthesis: &Synthesis<'_>,
+) -> CircuitInfo {
     info!("Winning PoSt info");
 
     let post_public_params = winning_post_public_params::<Tree>(post_config)
         .expect("failed to get public params from config");
 
-    let circuit: FallbackPoStCircuit<Tree> = <FallbackPoStCompound<Tree> as CompoundProof<
-        FallbackPoSt<Tree>,
-        FallbackPoStCircuit<Tree>,
-    >>::blank_circuit(&post_public_params);
+    let circuit = || -> FallbackPoStCircuit<Tree> {
+        <FallbackPoStCompound<Tree> as CompoundProof<
+            FallbackPoSt<Tree>,
+            FallbackPoStCircuit<Tree>,
+        >>::blank_circuit(&post_public_params)
+    };
 
-    circuit_info(circuit)
+    circuit_info(circuit, "winning-post", post_config.sector_size, synthesis)
 }
 
-fn get_window_post_info<Tree: 'static + MerkleTreeTrait>(post_config: &PoStConfig) -> CircuitInfo {
+fn get_window_post_info<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    synthesis: &Synthesis<'_>,
+) -> CircuitInfo {
     info!("Window PoSt info");
 
     let post_public_params = window_post_public_params::<Tree>(post_config)
         .expect("failed to get public params from config");
 
-    let circuit: FallbackPoStCircuit<Tree> = <FallbackPoStCompound<Tree> as CompoundProof<
-        FallbackPoSt<Tree>,
-        FallbackPoStCircuit<Tree>,
-    >>::blank_circuit(&post_public_params);
+    let circuit = || -> FallbackPoStCircuit<Tree> {
+        <FallbackPoStCompound<Tree> as CompoundProof<
+            FallbackPoSt<Tree>,
+            FallbackPoStCircuit<Tree>,
+        >>::blank_circuit(&post_public_params)
+    };
 
-    circuit_info(circuit)
+    circuit_info(circuit, "window-post", post_config.sector_size, synthesis)
 }
 
 #[derive(Debug, StructOpt)]
@@ -93,11 +170,152 @@ struct Opt {
     porep: bool,
     #[structopt(short = "z", long, use_delimiter = true)]
     constraints_for_sector_sizes: Vec<u64>,
+    /// Register an unpublished sector size, so that its circuits can be counted, e.g.
+    /// `sector_size=65536,base_arity=8,sub_arity=4,top_arity=0,layers=2,porep_partitions=1,
+    /// porep_minimum_challenges=2,window_post_sector_count=2`. Can be given more than once.
+    #[structopt(
+        long = "register-sector-size",
+        value_name = "FIELDS",
+        number_of_values = 1
+    )]
+    register_sector_sizes: Vec<CustomSectorSize>,
     #[structopt(default_value = "1.0.0", long)]
     api_version: String,
+    /// Also write the constraints of every selected circuit to this directory, in the binary
+    /// R1CS format (e.g. `porep-2048.r1cs`).
+    #[structopt(long, parse(from_os_str))]
+    r1cs_dir: Option<PathBuf>,
+    /// Print the constraints of every selected circuit broken down by namespace.
+    #[structopt(long)]
+    profile: bool,
+    /// Write the namespace breakdown of every selected circuit to this file, as JSON.
+    #[structopt(long, parse(from_os_str))]
+    profile_json: Option<PathBuf>,
+    /// How many namespace levels to print in the breakdown and diffs.
+    #[structopt(long, default_value = "4")]
+    depth: usize,
+    /// Merge namespaces which only differ in a trailing number (e.g. per challenge or per
+    /// layer) into one.
+    #[structopt(long)]
+    collapse: bool,
+    /// Print how the namespace breakdown differs from the one of this API version.
+    #[structopt(long)]
+    compare_api_version: Option<String>,
+    /// Print how the namespace breakdowns in two files written by `--profile-json` (e.g. on two
+    /// different commits) differ, then exit.
+    #[structopt(long, number_of_values = 2, parse(from_os_str))]
+    diff: Vec<PathBuf>,
+}
+
+/// Collects the namespace breakdowns of the circuits and prints them as requested.
+struct Profiles {
+    print: bool,
+    depth: usize,
+    compare_api_version: Option<ApiVersion>,
+    profiles: Vec<CircuitProfile>,
+}
+
+impl Profiles {
+    /// Prints the breakdown in `info` and its difference to the breakdown under the API version
+    /// to compare against, which `info_for` computes.
+    fn record<F: Fn(ApiVersion) -> CircuitInfo>(
+        &mut self,
+        circuit: &str,
+        sector_size: u64,
+        api_version: ApiVersion,
+        partitions: Option<usize>,
+        info: CircuitInfo,
+        info_for: F,
+    ) {
+        let profile = match info.profile {
+            Some(profile) => profile,
+            None => return,
+        };
+
+        if self.print {
+            print!("{}", render(&profile, self.depth));
+        }
+
+        if let Some(compare_api_version) = self.compare_api_version {
+            let other = info_for(compare_api_version)
+                .profile
+                .expect("profiling is enabled");
+            println!(
+                "{} differences from API version {} to {}:",
+                circuit, compare_api_version, api_version
+            );
+            print!("{}", render_diff(&other, &profile, self.depth));
+        }
+
+        self.profiles.push(CircuitProfile {
+            circuit: circuit.to_string(),
+            sector_size,
+            api_version: api_version.to_string(),
+            partitions,
+            profile,
+        });
+    }
 }
 
-fn winning_post_info(sector_size: u64, api_version: ApiVersion) -> CircuitInfo {
+fn read_profiles(path: &Path) -> Vec<CircuitProfile> {
+    let file = File::open(path).expect("failed to open profile file");
+    serde_json::from_reader(BufReader::new(file)).expect("failed to parse profile file")
+}
+
+/// Prints the differences between the breakdowns of the same circuits in two files written by
+/// `--profile-json`.
+fn diff_profiles(old_path: &Path, new_path: &Path, depth: usize) {
+    let old_profiles = read_profiles(old_path);
+    let new_profiles = read_profiles(new_path);
+
+    for new in &new_profiles {
+        let old = old_profiles
+            .iter()
+            .find(|old| old.circuit == new.circuit && old.sector_size == new.sector_size);
+        let human_size = new
+            .sector_size
+            .file_size(file_size_opts::BINARY)
+            .expect("failed to format sector size");
+
+        match old {
+            Some(old) => {
+                println!(
+                    "{} {} (API version {} -> {}):",
+                    human_size, new.circuit, old.api_version, new.api_version
+                );
+                print!("{}", render_diff(&old.profile, &new.profile, depth));
+            }
+            None => println!(
+                "{} {} only exists in {}",
+                human_size,
+                new.circuit,
+                new_path.display()
+            ),
+        }
+    }
+
+    for old in &old_profiles {
+        if !new_profiles
+            .iter()
+            .any(|new| new.circuit == old.circuit && new.sector_size == old.sector_size)
+        {
+            println!(
+                "{} {} only exists in {}",
+                old.sector_size
+                    .file_size(file_size_opts::BINARY)
+                    .expect("failed to format sector size"),
+                old.circuit,
+                old_path.display()
+            );
+        }
+    }
+}
+
+fn winning_post_info(
+    sector_size: u64,
+    api_version: ApiVersion,
+    synthesis: &Synthesis<'_>,
+) -> CircuitInfo {
     with_shape!(
         sector_size,
         get_winning_post_info,
@@ -108,11 +326,16 @@ fn winning_post_info(sector_size: u64, api_version: ApiVersion) -> CircuitInfo {
             typ: PoStType::Winning,
             priority: true,
             api_version,
-        }
+        },
+        synthesis,
     )
 }
 
-fn window_post_info(sector_size: u64, api_version: ApiVersion) -> CircuitInfo {
+fn window_post_info(
+    sector_size: u64,
+    api_version: ApiVersion,
+    synthesis: &Synthesis<'_>,
+) -> CircuitInfo {
     with_shape!(
         sector_size,
         get_window_post_info,
@@ -127,11 +350,16 @@ fn window_post_info(sector_size: u64, api_version: ApiVersion) -> CircuitInfo {
             typ: PoStType::Window,
             priority: true,
             api_version,
-        }
+        },
+        synthesis,
     )
 }
 
-fn porep_info(sector_size: u64, api_version: ApiVersion) -> (CircuitInfo, usize) {
+fn porep_info(
+    sector_size: u64,
+    api_version: ApiVersion,
+    synthesis: &Synthesis<'_>,
+) -> (CircuitInfo, usize) {
     let partitions = PoRepProofPartitions(
         *POREP_PARTITIONS
             .read()
@@ -147,7 +375,8 @@ fn porep_info(sector_size: u64, api_version: ApiVersion) -> (CircuitInfo, usize)
             partitions,
             porep_id: [0; 32],
             api_version,
-        }
+        },
+        synthesis,
     );
     (info, partitions.into())
 }
@@ -161,9 +390,19 @@ pub fn main() {
 
     let opts = Opt::from_args();
 
+    if let [old_path, new_path] = &opts.diff[..] {
+        diff_profiles(old_path, new_path, opts.depth);
+        return;
+    }
+
+    for custom in &opts.register_sector_sizes {
+        register_sector_size(*custom).expect("failed to register sector size");
+    }
+    let supported_sizes = supported_sector_sizes();
+
     // Display interactive menu if no sizes are given
     let sizes: Vec<u64> = if opts.constraints_for_sector_sizes.is_empty() {
-        let sector_sizes = PUBLISHED_SECTOR_SIZES
+        let sector_sizes = supported_sizes
             .iter()
             .map(|sector_size| {
                 // Right aligning the numbers makes them easier to read
@@ -183,7 +422,7 @@ pub fn main() {
             .expect("interaction failed");
 
         // Extract the selected sizes
-        PUBLISHED_SECTOR_SIZES
+        supported_sizes
             .iter()
             .enumerate()
             .filter_map(|(index, size)| {
@@ -198,7 +437,7 @@ pub fn main() {
         opts.constraints_for_sector_sizes
             .into_iter()
             .filter(|size| {
-                if PUBLISHED_SECTOR_SIZES.contains(size) {
+                if supported_sizes.contains(size) {
                     return true;
                 }
 
@@ -219,6 +458,30 @@ pub fn main() {
     let count_porep = opts.porep;
     let api_version = ApiVersion::from_str(&opts.api_version)
         .expect("Failed to parse api_version from semver string");
+    let compare_api_version = opts.compare_api_version.as_ref().map(|api_version| {
+        ApiVersion::from_str(api_version).expect("Failed to parse api_version from semver string")
+    });
+    let r1cs_dir = opts.r1cs_dir.as_deref();
+    if let Some(r1cs_dir) = r1cs_dir {
+        std::fs::create_dir_all(r1cs_dir).expect("failed to create r1cs directory");
+    }
+
+    let synthesis = Synthesis {
+        r1cs_dir,
+        profile: opts.profile || opts.profile_json.is_some() || compare_api_version.is_some(),
+        collapse: opts.collapse,
+    };
+    // Comparisons only need the breakdown under the other API version.
+    let compare_synthesis = Synthesis {
+        r1cs_dir: None,
+        ..synthesis
+    };
+    let mut profiles = Profiles {
+        print: opts.profile,
+        depth: opts.depth,
+        compare_api_version,
+        profiles: Vec::new(),
+    };
 
     for sector_size in sizes {
         let human_size = sector_size
@@ -227,27 +490,58 @@ pub fn main() {
         println!("Getting circuit info for sector size: {}", human_size);
 
         if count_winning {
-            let info = winning_post_info(sector_size, api_version);
+            let info = winning_post_info(sector_size, api_version, &synthesis);
             println!(
                 "{} Winning PoSt constraints: {}, public inputs: {}, partitions: 1",
                 human_size, info.constraints, info.inputs
             );
+            profiles.record(
+                "winning-post",
+                sector_size,
+                api_version,
+                Some(1),
+                info,
+                |api_version| winning_post_info(sector_size, api_version, &compare_synthesis),
+            );
         }
 
         if count_window {
-            let info = window_post_info(sector_size, api_version);
+            let info = window_post_info(sector_size, api_version, &synthesis);
             println!(
                 "{} Window PoSt constraints (per partition): {}, public inputs (per partition): {}, partitions: <depends on input size>",
                 human_size, info.constraints, info.inputs
             );
+            profiles.record(
+                "window-post",
+                sector_size,
+                api_version,
+                None,
+                info,
+                |api_version| window_post_info(sector_size, api_version, &compare_synthesis),
+            );
         }
 
         if count_porep {
-            let (info, partitions) = porep_info(sector_size, api_version);
+            let (info, partitions) = porep_info(sector_size, api_version, &synthesis);
             println!(
                 "{} PoRep constraints: {}, public inputs: {}, partitions: {}",
                 human_size, info.constraints, info.inputs, partitions
             );
+            profiles.record(
+                "porep",
+                sector_size,
+                api_version,
+                Some(partitions),
+                info,
+                |api_version| porep_info(sector_size, api_version, &compare_synthesis).0,
+            );
         }
     }
+
+    if let Some(profile_json) = opts.profile_json {
+        let file = File::create(&profile_json).expect("failed to create profile file");
+        serde_json::to_writer_pretty(BufWriter::new(file), &profiles.profiles)
+            .expect("failed to write profile file");
+        println!("Wrote {}", profile_json.display());
+    }
 }
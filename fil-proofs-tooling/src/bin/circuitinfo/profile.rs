@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use bellperson::{
+    bls::{Bls12, Fr},
+    ConstraintSystem, Index, LinearCombination, SynthesisError, Variable,
+};
+use serde::{Deserialize, Serialize};
+
+/// The constraints, inputs and aux variables allocated within a bellperson namespace, including
+/// those of all of its sub-namespaces.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProfileNode {
+    pub name: String,
+    /// How often the namespace was entered, e.g. once per challenge when numbered namespaces are
+    /// collapsed.
+    pub count: usize,
+    pub constraints: usize,
+    pub inputs: usize,
+    pub aux: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<ProfileNode>,
+}
+
+/// The profile of a single circuit, as written by `--profile-json`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CircuitProfile {
+    pub circuit: String,
+    pub sector_size: u64,
+    pub api_version: String,
+    /// The number of partitions, if it is fixed for the circuit. Every partition has the same
+    /// constraints, so the profile is that of a single partition.
+    pub partitions: Option<usize>,
+    pub profile: ProfileNode,
+}
+
+struct Node {
+    name: String,
+    count: usize,
+    constraints: usize,
+    inputs: usize,
+    aux: usize,
+    children: Vec<usize>,
+    index: HashMap<String, usize>,
+}
+
+impl Node {
+    fn new(name: String) -> Self {
+        Node {
+            name,
+            count: 1,
+            constraints: 0,
+            inputs: 0,
+            aux: 0,
+            children: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+}
+
+/// A constraint system which counts constraints and variables per namespace. Like `BenchCS`, it
+/// never evaluates assignments, so it is meant to be used with blank circuits.
+pub struct ProfileCS {
+    nodes: Vec<Node>,
+    /// The path of the current namespace, as indices into `nodes`, starting with the root.
+    stack: Vec<usize>,
+    collapse: bool,
+}
+
+impl ProfileCS {
+    /// Creates a constraint system whose root namespace is called `name`. If `collapse` is set,
+    /// sibling namespaces which only differ in a trailing number (e.g. `challenge_0` and
+    /// `challenge_1`) are counted as one.
+    pub fn new(name: &str, collapse: bool) -> Self {
+        let mut root = Node::new(name.to_string());
+        // The input for the constant one.
+        root.inputs = 1;
+
+        ProfileCS {
+            nodes: vec![root],
+            stack: vec![0],
+            collapse,
+        }
+    }
+
+    pub fn into_profile(self) -> ProfileNode {
+        fn build(nodes: &[Node], i: usize) -> ProfileNode {
+            let node = &nodes[i];
+            ProfileNode {
+                name: node.name.clone(),
+                count: node.count,
+                constraints: node.constraints,
+                inputs: node.inputs,
+                aux: node.aux,
+                children: node
+                    .children
+                    .iter()
+                    .map(|child| build(nodes, *child))
+                    .collect(),
+            }
+        }
+
+        build(&self.nodes, 0)
+    }
+
+    /// Applies `f` to the current namespace and all of its parents.
+    fn update<F: Fn(&mut Node)>(&mut self, f: F) {
+        for i in &self.stack {
+            f(&mut self.nodes[*i]);
+        }
+    }
+}
+
+impl ConstraintSystem<Bls12> for ProfileCS {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _annotation: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.update(|node| node.aux += 1);
+
+        Ok(Variable::new_unchecked(Index::Aux(self.nodes[0].aux - 1)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _annotation: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.update(|node| node.inputs += 1);
+
+        Ok(Variable::new_unchecked(Index::Input(
+            self.nodes[0].inputs - 1,
+        )))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _annotation: A, _a: LA, _b: LB, _c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LB: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LC: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+    {
+        self.update(|node| node.constraints += 1);
+    }
+
+    fn push_namespace<NR, N>(&mut self, name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+        let mut name: String = name_fn().into();
+        if self.collapse {
+            name = collapse_name(&name);
+        }
+
+        let parent = *self.stack.last().expect("root is never popped");
+        let child = match self.nodes[parent].index.get(&name) {
+            Some(child) => {
+                self.nodes[*child].count += 1;
+                *child
+            }
+            None => {
+                let child = self.nodes.len();
+                self.nodes.push(Node::new(name.clone()));
+                self.nodes[parent].children.push(child);
+                self.nodes[parent].index.insert(name, child);
+                child
+            }
+        };
+
+        self.stack.push(child);
+    }
+
+    fn pop_namespace(&mut self) {
+        assert!(self.stack.len() > 1, "cannot pop the root namespace");
+        self.stack.pop();
+    }
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+/// Replaces a trailing number, separated by an underscore or a space, with `*`.
+fn collapse_name(name: &str) -> String {
+    let prefix = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    if prefix.len() < name.len() && (prefix.ends_with('_') || prefix.ends_with(' ')) {
+        format!("{}*", prefix)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Renders `node` and its children as an indented tree, down to `max_depth` levels below it.
+pub fn render(node: &ProfileNode, max_depth: usize) -> String {
+    fn render_node(
+        out: &mut String,
+        node: &ProfileNode,
+        total: usize,
+        depth: usize,
+        max_depth: usize,
+    ) {
+        let percent = if total == 0 {
+            0.0
+        } else {
+            node.constraints as f64 * 100.0 / total as f64
+        };
+        let count = if node.count > 1 {
+            format!(" (x{})", node.count)
+        } else {
+            String::new()
+        };
+        writeln!(
+            out,
+            "{:indent$}{}{}: {} constraints ({:.2}%), {} inputs, {} aux",
+            "",
+            node.name,
+            count,
+            node.constraints,
+            percent,
+            node.inputs,
+            node.aux,
+            indent = depth * 2
+        )
+        .expect("writing to a string cannot fail");
+
+        if depth < max_depth {
+            for child in &node.children {
+                render_node(out, child, total, depth + 1, max_depth);
+            }
+        }
+    }
+
+    let mut out = String::new();
+    render_node(&mut out, node, node.constraints, 0, max_depth);
+    out
+}
+
+/// Renders the namespaces whose counts differ between `old` and `new`, down to `max_depth`
+/// levels below the root. Namespaces which only exist on one side are shown with zero counts
+/// on the other.
+pub fn render_diff(old: &ProfileNode, new: &ProfileNode, max_depth: usize) -> String {
+    fn delta(old: usize, new: usize) -> String {
+        format!("{} -> {} ({:+})", old, new, new as i64 - old as i64)
+    }
+
+    fn diff_node(
+        out: &mut String,
+        name: &str,
+        old: Option<&ProfileNode>,
+        new: Option<&ProfileNode>,
+        depth: usize,
+        max_depth: usize,
+    ) {
+        let empty = ProfileNode::default();
+        let (o, n) = (old.unwrap_or(&empty), new.unwrap_or(&empty));
+        if o.constraints == n.constraints && o.inputs == n.inputs && o.aux == n.aux {
+            return;
+        }
+
+        writeln!(
+            out,
+            "{:indent$}{}: constraints {}, inputs {}, aux {}",
+            "",
+            name,
+            delta(o.constraints, n.constraints),
+            delta(o.inputs, n.inputs),
+            delta(o.aux, n.aux),
+            indent = depth * 2
+        )
+        .expect("writing to a string cannot fail");
+
+        if depth >= max_depth {
+            return;
+        }
+
+        let mut names: Vec<&str> = n.children.iter().map(|c| c.name.as_str()).collect();
+        for child in &o.children {
+            if !names.contains(&child.name.as_str()) {
+                names.push(&child.name);
+            }
+        }
+
+        for child_name in names {
+            let old_child = o.children.iter().find(|c| c.name == child_name);
+            let new_child = n.children.iter().find(|c| c.name == child_name);
+            diff_node(out, child_name, old_child, new_child, depth + 1, max_depth);
+        }
+    }
+
+    let mut out = String::new();
+    diff_node(&mut out, &new.name, Some(old), Some(new), 0, max_depth);
+    if out.is_empty() {
+        out.push_str("no differences\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ff::Field;
+
+    fn synthesize(cs: &mut ProfileCS, challenges: usize) {
+        let input = cs
+            .alloc_input(|| "input", || Ok(Fr::one()))
+            .expect("alloc_input failure");
+        for i in 0..challenges {
+            let mut cs = cs.namespace(|| format!("challenge_{}", i));
+            let x = cs.alloc(|| "x", || Ok(Fr::one())).expect("alloc failure");
+            let mut cs = cs.namespace(|| "por");
+            cs.enforce(
+                || "x = input",
+                |lc| lc + x,
+                |lc| lc + ProfileCS::one(),
+                |lc| lc + input,
+            );
+            cs.enforce(|| "x * x = x", |lc| lc + x, |lc| lc + x, |lc| lc + x);
+        }
+    }
+
+    #[test]
+    fn test_profile_namespaces() {
+        let mut cs = ProfileCS::new("test", false);
+        synthesize(&mut cs, 3);
+        let profile = cs.into_profile();
+
+        assert_eq!(profile.constraints, 6);
+        assert_eq!(profile.inputs, 2);
+        assert_eq!(profile.aux, 3);
+        assert_eq!(profile.children.len(), 3);
+        assert_eq!(profile.children[1].name, "challenge_1");
+        assert_eq!(profile.children[1].constraints, 2);
+        assert_eq!(profile.children[1].aux, 1);
+        assert_eq!(profile.children[1].children[0].name, "por");
+        assert_eq!(profile.children[1].children[0].aux, 0);
+    }
+
+    #[test]
+    fn test_profile_collapse() {
+        let mut cs = ProfileCS::new("test", true);
+        synthesize(&mut cs, 3);
+        let profile = cs.into_profile();
+
+        assert_eq!(profile.children.len(), 1);
+        assert_eq!(profile.children[0].name, "challenge_*");
+        assert_eq!(profile.children[0].count, 3);
+        assert_eq!(profile.children[0].constraints, 6);
+        assert_eq!(profile.children[0].children[0].count, 3);
+
+        assert_eq!(
+            collapse_name("merkle hash, height 12"),
+            "merkle hash, height *"
+        );
+        assert_eq!(collapse_name("sha256"), "sha256");
+    }
+
+    #[test]
+    fn test_profile_diff() {
+        let mut old = ProfileCS::new("test", true);
+        synthesize(&mut old, 2);
+        let old = old.into_profile();
+
+        let mut new = ProfileCS::new("test", true);
+        synthesize(&mut new, 3);
+        let new = new.into_profile();
+
+        assert_eq!(render_diff(&old, &old, 4), "no differences\n");
+
+        let diff = render_diff(&old, &new, 4);
+        assert!(diff
+            .starts_with("test: constraints 4 -> 6 (+2), inputs 2 -> 2 (+0), aux 2 -> 3 (+1)\n"));
+        assert!(diff.contains("  challenge_*: constraints 4 -> 6 (+2)"));
+    }
+}
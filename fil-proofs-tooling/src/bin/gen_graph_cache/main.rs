@@ -1,20 +1,23 @@
 use std::collections::BTreeMap;
-use std::fs::File;
-use std::io::BufWriter;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
-use clap::{value_t, App, Arg};
-use filecoin_hashers::sha256::Sha256Hasher;
+use anyhow::{bail, ensure, Context, Result};
+use clap::{value_t, App, Arg, ArgMatches, SubCommand};
 use filecoin_proofs::{
-    with_shape, DRG_DEGREE, EXP_DEGREE, SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB, SECTOR_SIZE_512_MIB,
-    SECTOR_SIZE_64_GIB, SECTOR_SIZE_8_MIB,
+    DefaultTreeHasher, DRG_DEGREE, EXP_DEGREE, SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB,
+    SECTOR_SIZE_512_MIB, SECTOR_SIZE_64_GIB, SECTOR_SIZE_8_MIB,
 };
 use serde::{Deserialize, Serialize};
-use storage_proofs_core::{api_version::ApiVersion, merkle::MerkleTreeTrait, proof::ProofScheme};
-use storage_proofs_porep::stacked::{LayerChallenges, SetupParams, StackedDrg};
+use storage_proofs_core::api_version::ApiVersion;
+use storage_proofs_porep::stacked::{
+    get_parent_cache_data, parent_cache_digest, parent_cache_id, parent_cache_path, ParentCache,
+    ParentCacheStatus, StackedBucketGraph,
+};
 
 const PARENT_CACHE_JSON_OUTPUT: &str = "./parent_cache.json";
+const ARTIFACT_MANIFEST: &str = "manifest.json";
 
 pub type ParentCacheSummaryMap = BTreeMap<String, ParentCacheSummary>;
 
@@ -24,70 +27,20 @@ pub struct ParentCacheSummary {
     pub digest: String,
 }
 
-fn gen_graph_cache<Tree: 'static + MerkleTreeTrait>(
-    sector_size: usize,
-    porep_id: [u8; 32],
-    api_version: ApiVersion,
-    parent_cache_summary_map: &mut ParentCacheSummaryMap,
-) -> Result<()> {
-    let nodes = (sector_size / 32) as usize;
+/// Exported parent caches, keyed by their parent cache id.
+pub type ParentCacheArtifactMap = BTreeMap<String, ParentCacheArtifact>;
 
-    // Note that layers and challenge_count don't affect the graph, so
-    // we just use dummy values of 1 for the setup params.
-    let layers = 1;
-    let challenge_count = 1;
-    let layer_challenges = LayerChallenges::new(layers, challenge_count);
-
-    let sp = SetupParams {
-        nodes,
-        degree: DRG_DEGREE,
-        expansion_degree: EXP_DEGREE,
-        porep_id,
-        layer_challenges,
-        api_version,
-    };
-
-    let pp = StackedDrg::<Tree, Sha256Hasher>::setup(&sp).expect("failed to setup DRG");
-    let parent_cache = pp.graph.parent_cache()?;
-
-    let data = ParentCacheSummary {
-        digest: parent_cache.digest,
-        sector_size: parent_cache.sector_size,
-    };
-    parent_cache_summary_map.insert(
-        parent_cache
-            .path
-            .file_stem()
-            .expect("file_stem failure")
-            .to_str()
-            .expect("file stem to_str failure")
-            .to_string(),
-        data,
-    );
-
-    Ok(())
+/// A parent cache exported with `export`. The file is named after the digest of its content.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParentCacheArtifact {
+    pub sector_size: u64,
+    pub porep_id: String,
+    pub api_version: String,
+    pub digest: String,
+    pub file: String,
 }
 
-fn main() -> Result<()> {
-    fil_logger::init();
-
-    let matches = App::new("gen_graph_cache")
-        .version("0.1")
-        .about("Generates and/or verifies parent graph cache files")
-        .arg(
-            Arg::with_name("json")
-                .long("json")
-                .help("Creates a new json output file.")
-                .default_value("false"),
-        )
-        .arg(
-            Arg::with_name("size")
-                .long("size")
-                .help("Generate and/or verify the graph cache files for a single sector size")
-                .default_value("0"),
-        )
-        .get_matches();
-
+fn sector_sizes_and_porep_ids() -> Vec<(u64, [u8; 32], ApiVersion)> {
     // NOTE: The porep_ids below are tied to the versioned values provided in
     // filecoin-proofs-api:src/registry [porep_id()] that matches the specified
     // sector size and must be updated when that value is updated for the proper
@@ -95,7 +48,7 @@ fn main() -> Result<()> {
     //
     // If this value changes, previously existing cache files will no longer be
     // used and new cache files will be generated.
-    let sector_sizes_and_porep_ids: Vec<(u64, [u8; 32], ApiVersion)> = vec![
+    vec![
         (
             SECTOR_SIZE_2_KIB,
             [
@@ -176,59 +129,393 @@ fn main() -> Result<()> {
             ],
             ApiVersion::V1_1_0,
         ),
-    ];
+    ]
+}
+
+fn graph(
+    sector_size: u64,
+    porep_id: [u8; 32],
+    api_version: ApiVersion,
+) -> Result<StackedBucketGraph<DefaultTreeHasher>> {
+    let nodes = (sector_size / 32) as usize;
+
+    StackedBucketGraph::<DefaultTreeHasher>::new_stacked(
+        nodes,
+        DRG_DEGREE,
+        EXP_DEGREE,
+        porep_id,
+        api_version,
+    )
+}
+
+fn artifact_file_name(digest: &str) -> String {
+    format!("sdr-parent-{}.cache", digest)
+}
+
+/// Copies `src` to `dst` through a temporary file, so that `dst` is never seen partially
+/// written.
+fn copy_atomically(src: &Path, dst: &Path) -> Result<()> {
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("could not create {}", parent.display()))?;
+    }
+    let tmp = dst.with_extension("tmp");
+    fs::copy(src, &tmp)
+        .with_context(|| format!("could not copy {} to {}", src.display(), tmp.display()))?;
+    fs::rename(&tmp, dst)
+        .with_context(|| format!("could not rename {} to {}", tmp.display(), dst.display()))?;
+
+    Ok(())
+}
+
+fn describe(status: &ParentCacheStatus) -> String {
+    match status {
+        ParentCacheStatus::Missing => "missing".to_string(),
+        ParentCacheStatus::Partial { len, expected_len } => {
+            format!("partial ({} of {} bytes)", len, expected_len)
+        }
+        ParentCacheStatus::Verified => "verified".to_string(),
+        ParentCacheStatus::Corrupted { digest } => format!("corrupted (digest {})", digest),
+        ParentCacheStatus::Unverifiable { digest } => {
+            format!("not in manifest (digest {})", digest)
+        }
+    }
+}
+
+fn gen_graph_cache(
+    sector_size: u64,
+    porep_id: [u8; 32],
+    api_version: ApiVersion,
+    parent_cache_summary_map: &mut ParentCacheSummaryMap,
+) -> Result<()> {
+    let parent_cache = graph(sector_size, porep_id, api_version)?.parent_cache()?;
+
+    let data = ParentCacheSummary {
+        digest: parent_cache.digest,
+        sector_size: parent_cache.sector_size,
+    };
+    parent_cache_summary_map.insert(parent_cache_id(&parent_cache.path), data);
+
+    Ok(())
+}
+
+fn list_graph_cache(sector_size: u64, porep_id: [u8; 32], api_version: ApiVersion) -> Result<()> {
+    let path = parent_cache_path(&graph(sector_size, porep_id, api_version)?);
+    let expected = get_parent_cache_data(&path)
+        .map(|pcd| pcd.digest.clone())
+        .unwrap_or_else(|| "not in manifest".to_string());
+    let state = match fs::metadata(&path) {
+        Ok(metadata) => format!("{} bytes", metadata.len()),
+        Err(_) => "missing".to_string(),
+    };
+
+    println!(
+        "{} {} porep_id={} {} digest={} [{}]",
+        sector_size,
+        api_version,
+        hex_encode(&porep_id),
+        path.display(),
+        expected,
+        state
+    );
+
+    Ok(())
+}
 
+fn verify_graph_cache(
+    sector_size: u64,
+    porep_id: [u8; 32],
+    api_version: ApiVersion,
+) -> Result<bool> {
+    let (path, status) = ParentCache::status(&graph(sector_size, porep_id, api_version)?)?;
+    println!("{}: {}", path.display(), describe(&status));
+
+    Ok(status == ParentCacheStatus::Verified)
+}
+
+fn repair_graph_cache(sector_size: u64, porep_id: [u8; 32], api_version: ApiVersion) -> Result<()> {
+    let graph = graph(sector_size, porep_id, api_version)?;
+    let before = ParentCache::repair(&graph)?;
+    let (path, after) = ParentCache::status(&graph)?;
+    println!(
+        "{}: {} -> {}",
+        path.display(),
+        describe(&before),
+        describe(&after)
+    );
+    ensure!(
+        after == ParentCacheStatus::Verified,
+        "failed to repair {}",
+        path.display()
+    );
+
+    Ok(())
+}
+
+fn export_graph_cache(
+    sector_size: u64,
+    porep_id: [u8; 32],
+    api_version: ApiVersion,
+    output_dir: &Path,
+    artifacts: &mut ParentCacheArtifactMap,
+) -> Result<()> {
+    let (path, status) = ParentCache::status(&graph(sector_size, porep_id, api_version)?)?;
+    ensure!(
+        status == ParentCacheStatus::Verified,
+        "{} is {}, run repair first",
+        path.display(),
+        describe(&status)
+    );
+
+    let digest = get_parent_cache_data(&path)
+        .expect("verified caches are in the manifest")
+        .digest
+        .clone();
+    let file = artifact_file_name(&digest);
+    let artifact_path = output_dir.join(&file);
+    if !artifact_path.exists() || parent_cache_digest(&artifact_path)? != digest {
+        copy_atomically(&path, &artifact_path)?;
+    }
+    println!("{} -> {}", path.display(), artifact_path.display());
+
+    artifacts.insert(
+        parent_cache_id(&path),
+        ParentCacheArtifact {
+            sector_size,
+            porep_id: hex_encode(&porep_id),
+            api_version: api_version.to_string(),
+            digest,
+            file,
+        },
+    );
+
+    Ok(())
+}
+
+fn install_graph_cache(
+    sector_size: u64,
+    porep_id: [u8; 32],
+    api_version: ApiVersion,
+    input_dir: &Path,
+    artifacts: &ParentCacheArtifactMap,
+) -> Result<()> {
+    let (path, status) = ParentCache::status(&graph(sector_size, porep_id, api_version)?)?;
+    if status == ParentCacheStatus::Verified {
+        println!("{}: already verified", path.display());
+        return Ok(());
+    }
+
+    let id = parent_cache_id(&path);
+    let artifact = artifacts
+        .get(&id)
+        .with_context(|| format!("{} is not in the artifact manifest", id))?;
+    let expected = get_parent_cache_data(&path)
+        .with_context(|| format!("{} is not in the parent cache manifest", id))?;
+    ensure!(
+        artifact.digest == expected.digest,
+        "artifact digest {} of {} does not match {}",
+        artifact.digest,
+        id,
+        expected.digest
+    );
+
+    let artifact_path = input_dir.join(&artifact.file);
+    let digest = parent_cache_digest(&artifact_path)?;
+    ensure!(
+        digest == expected.digest,
+        "{} is corrupted (digest {})",
+        artifact_path.display(),
+        digest
+    );
+
+    copy_atomically(&artifact_path, &path)?;
+    println!("{} -> {}", artifact_path.display(), path.display());
+
+    Ok(())
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+fn read_artifacts(dir: &Path) -> Result<ParentCacheArtifactMap> {
+    let path = dir.join(ARTIFACT_MANIFEST);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let file = File::open(&path).with_context(|| format!("could not open {}", path.display()))?;
+
+    serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("could not parse {}", path.display()))
+}
+
+fn write_artifacts(dir: &Path, artifacts: &ParentCacheArtifactMap) -> Result<()> {
+    let path = dir.join(ARTIFACT_MANIFEST);
+    let file =
+        File::create(&path).with_context(|| format!("could not create {}", path.display()))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), artifacts)?;
+    println!("Wrote {:?}", path);
+
+    Ok(())
+}
+
+fn run(matches: &ArgMatches<'_>) -> Result<()> {
+    let sector_sizes_and_porep_ids = sector_sizes_and_porep_ids();
     let supported_sector_sizes = sector_sizes_and_porep_ids
         .iter()
         .map(|vals| vals.0)
         .collect::<Vec<u64>>();
-    let mut parent_cache_summary_map: ParentCacheSummaryMap = BTreeMap::new();
 
     let size = value_t!(matches, "size", u64).expect("failed to get size");
-    let json = value_t!(matches, "json", bool).expect("failed to get json");
-
-    if size == 0 {
-        println!(
-            "gen_graph_cache: sizes {:?}, output json {}",
-            supported_sector_sizes, json
-        );
-    } else if supported_sector_sizes.contains(&size) {
-        println!("gen_graph_cache: size {}, output json {}", size, json);
-    } else {
-        println!(
+    if size != 0 && !supported_sector_sizes.contains(&size) {
+        bail!(
             "Unsupported sector size {} (must be one of {:?})",
-            size, supported_sector_sizes
+            size,
+            supported_sector_sizes
         );
-        return Ok(());
     }
 
-    for (sector_size, porep_id, api_version) in sector_sizes_and_porep_ids {
-        // 'size' 0 indicates no size was specified, so we run all sizes.
-        if size != 0 && size != sector_size {
-            continue;
+    // 'size' 0 indicates no size was specified, so we run all sizes.
+    let selected = sector_sizes_and_porep_ids
+        .into_iter()
+        .filter(|(sector_size, _, _)| size == 0 || size == *sector_size);
+
+    match matches.subcommand() {
+        ("list", _) => {
+            for (sector_size, porep_id, api_version) in selected {
+                list_graph_cache(sector_size, porep_id, api_version)?;
+            }
+        }
+        ("verify", _) => {
+            let mut failed = 0;
+            for (sector_size, porep_id, api_version) in selected {
+                if !verify_graph_cache(sector_size, porep_id, api_version)? {
+                    failed += 1;
+                }
+            }
+            ensure!(failed == 0, "{} parent caches failed verification", failed);
+        }
+        ("repair", _) => {
+            for (sector_size, porep_id, api_version) in selected {
+                repair_graph_cache(sector_size, porep_id, api_version)?;
+            }
         }
+        ("export", Some(m)) => {
+            let output_dir = PathBuf::from(m.value_of("output-dir").expect("required argument"));
+            fs::create_dir_all(&output_dir)
+                .with_context(|| format!("could not create {}", output_dir.display()))?;
 
-        with_shape!(
-            sector_size as u64,
-            gen_graph_cache,
-            sector_size as usize,
-            porep_id,
-            api_version,
-            &mut parent_cache_summary_map,
-        )?;
-    }
+            let mut artifacts = read_artifacts(&output_dir)?;
+            for (sector_size, porep_id, api_version) in selected {
+                export_graph_cache(
+                    sector_size,
+                    porep_id,
+                    api_version,
+                    &output_dir,
+                    &mut artifacts,
+                )?;
+            }
+            write_artifacts(&output_dir, &artifacts)?;
+        }
+        ("install", Some(m)) => {
+            let input_dir = PathBuf::from(m.value_of("input-dir").expect("required argument"));
+            let artifacts = read_artifacts(&input_dir)?;
+            for (sector_size, porep_id, api_version) in selected {
+                install_graph_cache(sector_size, porep_id, api_version, &input_dir, &artifacts)?;
+            }
+        }
+        _ => {
+            let json = value_t!(matches, "json", bool).expect("failed to get json");
+            if size == 0 {
+                println!(
+                    "gen_graph_cache: sizes {:?}, output json {}",
+                    supported_sector_sizes, json
+                );
+            } else {
+                println!("gen_graph_cache: size {}, output json {}", size, json);
+            }
 
-    // Output all json to PARENT_CACHE_JSON_OUTPUT in the current
-    // directory.
-    if json {
-        let json_output_path = Path::new(PARENT_CACHE_JSON_OUTPUT);
-        let json_file = File::create(&json_output_path)?;
-        let writer = BufWriter::new(json_file);
-        serde_json::to_writer_pretty(writer, &parent_cache_summary_map)?;
-        println!("Wrote {:?}", json_output_path);
-    } else {
-        println!("{:?}", parent_cache_summary_map);
+            let mut parent_cache_summary_map: ParentCacheSummaryMap = BTreeMap::new();
+            for (sector_size, porep_id, api_version) in selected {
+                gen_graph_cache(
+                    sector_size,
+                    porep_id,
+                    api_version,
+                    &mut parent_cache_summary_map,
+                )?;
+            }
+
+            // Output all json to PARENT_CACHE_JSON_OUTPUT in the current
+            // directory.
+            if json {
+                let json_output_path = Path::new(PARENT_CACHE_JSON_OUTPUT);
+                let json_file = File::create(&json_output_path)?;
+                let writer = BufWriter::new(json_file);
+                serde_json::to_writer_pretty(writer, &parent_cache_summary_map)?;
+                println!("Wrote {:?}", json_output_path);
+            } else {
+                println!("{:?}", parent_cache_summary_map);
+            }
+        }
     }
 
     Ok(())
 }
+
+fn main() -> Result<()> {
+    fil_logger::init();
+
+    let matches = App::new("gen_graph_cache")
+        .version("0.1")
+        .about("Generates, verifies and manages parent graph cache files")
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Creates a new json output file.")
+                .default_value("false"),
+        )
+        .arg(
+            Arg::with_name("size")
+                .long("size")
+                .help("Generate and/or verify the graph cache files for a single sector size")
+                .default_value("0")
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("Lists the parent cache files and their expected digests"),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Checks the parent cache files against the parent cache manifest"),
+        )
+        .subcommand(
+            SubCommand::with_name("repair")
+                .about("Regenerates missing, partial and corrupted parent cache files"),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Copies verified parent cache files to content-addressed names")
+                .arg(
+                    Arg::with_name("output-dir")
+                        .long("output-dir")
+                        .help("Directory to write the files and their manifest to")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("install")
+                .about("Verifies exported parent cache files and installs them")
+                .arg(
+                    Arg::with_name("input-dir")
+                        .long("input-dir")
+                        .help("Directory written by export")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .get_matches();
+
+    run(&matches)
+}
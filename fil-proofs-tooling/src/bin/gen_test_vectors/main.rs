@@ -0,0 +1,101 @@
+use std::fs::{create_dir_all, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{ensure, Context, Result};
+use filecoin_proofs::{
+    register_sector_size, supported_sector_sizes, test_vectors::generate_test_vector,
+    CustomSectorSize, PUBLISHED_SECTOR_SIZES,
+};
+use log::info;
+use storage_proofs_core::api_version::ApiVersion;
+use structopt::StructOpt;
+use tempfile::tempdir;
+
+/// Generates the JSON test vectors checked by filecoin-proofs/tests/test_vectors.rs.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "gen_test_vectors")]
+struct Opt {
+    /// The directory the vectors are written to.
+    #[structopt(
+        long,
+        default_value = "filecoin-proofs/tests/vectors",
+        parse(from_os_str)
+    )]
+    output_dir: PathBuf,
+    /// The sector sizes to generate vectors for, all published sizes if none are given.
+    #[structopt(short = "z", long, use_delimiter = true)]
+    sector_sizes: Vec<u64>,
+    /// Register an unpublished sector size, so that vectors can be generated for it, e.g.
+    /// `sector_size=65536,base_arity=8,sub_arity=4,top_arity=0,layers=2,porep_partitions=1,
+    /// porep_minimum_challenges=2,window_post_sector_count=2`. Can be given more than once.
+    #[structopt(
+        long = "register-sector-size",
+        value_name = "FIELDS",
+        number_of_values = 1
+    )]
+    register_sector_sizes: Vec<CustomSectorSize>,
+    /// The API versions to generate vectors for.
+    #[structopt(long, use_delimiter = true, default_value = "1.0.0,1.1.0")]
+    api_versions: Vec<String>,
+    /// Include sealing (labels, comm_c, comm_r_last and comm_r) for sectors up to this size.
+    #[structopt(long, default_value = "2048")]
+    seal_up_to: u64,
+}
+
+fn main() -> Result<()> {
+    fil_logger::init();
+
+    let opts = Opt::from_args();
+
+    for custom in &opts.register_sector_sizes {
+        register_sector_size(*custom)?;
+    }
+    let supported_sizes = supported_sector_sizes();
+
+    let sector_sizes = if opts.sector_sizes.is_empty() {
+        PUBLISHED_SECTOR_SIZES.to_vec()
+    } else {
+        opts.sector_sizes
+    };
+    for sector_size in &sector_sizes {
+        ensure!(
+            supported_sizes.contains(sector_size),
+            "{} is neither a published nor a registered sector size",
+            sector_size
+        );
+    }
+    let api_versions = opts
+        .api_versions
+        .iter()
+        .map(|api_version| ApiVersion::from_str(api_version))
+        .collect::<Result<Vec<_>>>()?;
+
+    create_dir_all(&opts.output_dir)
+        .with_context(|| format!("could not create {:?}", opts.output_dir))?;
+
+    for sector_size in sector_sizes {
+        for api_version in &api_versions {
+            info!("generating vector for {} {}", sector_size, api_version);
+
+            let work_dir = tempdir()?;
+            let seal = sector_size <= opts.seal_up_to;
+            let vector = generate_test_vector(
+                sector_size,
+                *api_version,
+                if seal { Some(work_dir.path()) } else { None },
+            )?;
+
+            let path = opts
+                .output_dir
+                .join(format!("{}-{}.json", sector_size, api_version));
+            let file =
+                File::create(&path).with_context(|| format!("could not create {:?}", path))?;
+            serde_json::to_writer_pretty(BufWriter::new(file), &vector)?;
+            println!("Wrote {}", path.display());
+        }
+    }
+
+    Ok(())
+}
@@ -2,7 +2,6 @@ use std::fmt::{self, Debug, Formatter};
 use std::hash::Hasher as StdHasher;
 use std::panic::panic_any;
 
-use anyhow::ensure;
 use bellperson::{
     bls::{Bls12, Fr, FrRepr},
     gadgets::{
@@ -19,7 +18,8 @@ use merkletree::{
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
 
-use crate::types::{Domain, HashFunction, Hasher};
+use crate::error::DomainError;
+use crate::types::{ensure_len, fr_from_bytes, Domain, HashFunction, Hasher};
 
 #[derive(Default, Copy, Clone, PartialEq, Eq, Debug)]
 pub struct Blake2sHasher {}
@@ -64,7 +64,11 @@ impl StdHasher for Blake2sFunction {
 
     #[inline]
     fn finish(&self) -> u64 {
-        unreachable!("unused by Function -- should never be called")
+        // Unused by Function, but returns the leading bytes of the digest rather than panicking.
+        let digest = self.0.finalize();
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&digest.as_bytes()[..8]);
+        u64::from_le_bytes(bytes)
     }
 }
 
@@ -123,11 +127,16 @@ impl Element for Blake2sDomain {
         32
     }
 
+    /// merkletree passes exactly `byte_len` bytes per stored node, so other lengths are a bug in
+    /// the caller and panic. Any 32 bytes are accepted, as nodes are hashed as bytes.
     fn from_slice(bytes: &[u8]) -> Self {
-        match Blake2sDomain::try_from_bytes(bytes) {
-            Ok(res) => res,
-            Err(err) => panic_any(err),
+        if let Err(err) = ensure_len(bytes, Blake2sDomain::byte_len()) {
+            panic_any(err);
         }
+
+        let mut res = Blake2sDomain::default();
+        res.0.copy_from_slice(bytes);
+        res
     }
 
     fn copy_to_slice(&self, bytes: &mut [u8]) {
@@ -149,20 +158,23 @@ impl Domain for Blake2sDomain {
         self.0.to_vec()
     }
 
-    fn try_from_bytes(raw: &[u8]) -> anyhow::Result<Self> {
-        ensure!(
-            raw.len() == 32 && u32::from(raw[31]) <= Fr::NUM_BITS,
-            "invalid amount of bytes"
-        );
+    fn try_from_bytes(raw: &[u8]) -> Result<Self, DomainError> {
+        fr_from_bytes(raw)?;
 
         let mut res = Blake2sDomain::default();
-        res.0.copy_from_slice(&raw[0..32]);
+        res.0.copy_from_slice(raw);
         Ok(res)
     }
 
-    fn write_bytes(&self, dest: &mut [u8]) -> anyhow::Result<()> {
-        ensure!(dest.len() >= 32, "too many bytes");
-        dest[0..32].copy_from_slice(&self.0[..]);
+    fn write_bytes(&self, dest: &mut [u8]) -> Result<(), DomainError> {
+        if dest.len() < Blake2sDomain::byte_len() {
+            return Err(DomainError::InvalidLength {
+                expected: Blake2sDomain::byte_len(),
+                actual: dest.len(),
+            });
+        }
+
+        dest[0..Blake2sDomain::byte_len()].copy_from_slice(&self.0[..]);
         Ok(())
     }
 
@@ -0,0 +1,18 @@
+use thiserror::Error;
+
+/// Errors returned when converting bytes into domain elements, or hashing them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum DomainError {
+    #[error("invalid number of bytes {actual}, expected {expected}")]
+    InvalidLength { expected: usize, actual: usize },
+    #[error("input of {0} bytes is not a whole number of domain elements")]
+    UnalignedInput(usize),
+    #[error("bytes are not a valid field element")]
+    NotInField,
+    #[error("unsupported arity {0}")]
+    UnsupportedArity(usize),
+    #[error("hash_md needs more than one element, got {0}")]
+    NotEnoughElements(usize),
+    #[error("batch of {len} elements is not a whole number of preimages of arity {arity}")]
+    IncompleteBatch { len: usize, arity: usize },
+}
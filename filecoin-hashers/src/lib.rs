@@ -13,6 +13,8 @@ mod poseidon_types;
 #[cfg(feature = "sha256")]
 pub mod sha256;
 
+mod error;
 mod types;
 
+pub use self::error::*;
 pub use self::types::*;
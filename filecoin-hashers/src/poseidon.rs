@@ -4,14 +4,13 @@ use std::mem::size_of;
 use std::panic::panic_any;
 use std::slice;
 
-use anyhow::ensure;
 use bellperson::{
     bls::{Bls12, Fr, FrRepr},
     gadgets::{boolean::Boolean, num::AllocatedNum},
     ConstraintSystem, SynthesisError,
 };
 use ff::{Field, PrimeField, PrimeFieldRepr, ScalarEngine};
-use generic_array::typenum::{marker_traits::Unsigned, U2};
+use generic_array::typenum::{marker_traits::Unsigned, U11, U16, U2, U24, U36, U4, U8};
 use merkletree::{
     hash::{Algorithm as LightAlgorithm, Hashable},
     merkle::Element,
@@ -20,9 +19,11 @@ use neptune::{circuit::poseidon_hash, poseidon::Poseidon};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
 
+use crate::error::DomainError;
 use crate::types::{
-    Domain, HashFunction, Hasher, PoseidonArity, PoseidonMDArity, POSEIDON_CONSTANTS_16,
-    POSEIDON_CONSTANTS_2, POSEIDON_CONSTANTS_4, POSEIDON_CONSTANTS_8, POSEIDON_MD_CONSTANTS,
+    ensure_batch, ensure_len, fr_from_bytes, Domain, HashFunction, Hasher, PoseidonArity,
+    PoseidonMDArity, POSEIDON_CONSTANTS_16, POSEIDON_CONSTANTS_2, POSEIDON_CONSTANTS_4,
+    POSEIDON_CONSTANTS_8, POSEIDON_MD_CONSTANTS,
 };
 
 #[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -134,20 +135,22 @@ impl Domain for PoseidonDomain {
         out
     }
 
-    fn try_from_bytes(raw: &[u8]) -> anyhow::Result<Self> {
-        ensure!(
-            raw.len() == PoseidonDomain::byte_len(),
-            "invalid amount of bytes"
-        );
-        let mut res: FrRepr = Default::default();
-        res.read_le(raw)?;
+    fn try_from_bytes(raw: &[u8]) -> Result<Self, DomainError> {
+        fr_from_bytes(raw).map(Into::into)
+    }
 
-        Ok(PoseidonDomain(res))
+    fn write_bytes(&self, dest: &mut [u8]) -> Result<(), DomainError> {
+        let actual = dest.len();
+        self.0
+            .write_le(dest)
+            .map_err(|_| DomainError::InvalidLength {
+                expected: PoseidonDomain::byte_len(),
+                actual,
+            })
     }
 
-    fn write_bytes(&self, dest: &mut [u8]) -> anyhow::Result<()> {
-        self.0.write_le(dest)?;
-        Ok(())
+    fn try_to_fr(&self) -> Result<Fr, DomainError> {
+        Fr::from_repr(self.0).map_err(|_| DomainError::NotInField)
     }
 
     fn random<R: RngCore>(rng: &mut R) -> Self {
@@ -161,11 +164,21 @@ impl Element for PoseidonDomain {
         32
     }
 
+    /// merkletree passes exactly `byte_len` bytes per stored node, so other lengths are a bug in
+    /// the caller and panic. The bytes are kept as they are even if they are outside the field;
+    /// the infallible hashing methods reduce them and the `try_*` methods reject them.
     fn from_slice(bytes: &[u8]) -> Self {
-        match PoseidonDomain::try_from_bytes(bytes) {
-            Ok(res) => res,
-            Err(err) => panic_any(err),
+        if let Err(err) = ensure_len(bytes, PoseidonDomain::byte_len()) {
+            panic_any(err);
         }
+
+        let mut res = FrRepr::default();
+        for (limb, chunk) in res.0.iter_mut().zip(bytes.chunks_exact(size_of::<u64>())) {
+            let mut limb_bytes = [0u8; 8];
+            limb_bytes.copy_from_slice(chunk);
+            *limb = u64::from_le_bytes(limb_bytes);
+        }
+        PoseidonDomain(res)
     }
 
     fn copy_to_slice(&self, bytes: &mut [u8]) {
@@ -174,33 +187,64 @@ impl Element for PoseidonDomain {
 }
 
 impl StdHasher for PoseidonFunction {
+    /// `StdHasher` cannot report errors, so input that is not a supported number of elements
+    /// panics here and elements outside the field are reduced into it. Use
+    /// `HashFunction::try_hash` for input that is not known to be well-formed.
     #[inline]
     fn write(&mut self, msg: &[u8]) {
-        self.0 = Fr::from_repr(shared_hash(msg).0).expect("from_repr failure");
+        self.0 = shared_hash(msg);
     }
 
     #[inline]
     fn finish(&self) -> u64 {
-        unimplemented!()
+        self.0.into_repr().0[0]
     }
 }
 
-fn shared_hash(data: &[u8]) -> PoseidonDomain {
-    // FIXME: We shouldn't unwrap here, but doing otherwise will require an interface change.
-    // We could truncate so `bytes_into_frs` cannot fail, then ensure `data` is always `fr_safe`.
+/// Maps `repr` into the field by subtracting the modulus, which takes at most two steps for a
+/// 256-bit value.
+///
+/// merkletree reads stored nodes back through `Element::from_slice` and hashes them through
+/// infallible methods, so a corrupted store must not be able to panic there. The infallible
+/// hashing methods reduce their input with this instead: such a store yields a wrong root, which
+/// the `try_*` methods and `Domain::try_from_bytes` report as `DomainError::NotInField`.
+fn reduce(repr: FrRepr) -> Fr {
+    let modulus = Fr::char();
+    let mut repr = repr;
+    while repr >= modulus {
+        repr.sub_noborrow(&modulus);
+    }
+    Fr::from_repr(repr).expect("reduced repr is in the field")
+}
+
+fn shared_hash(data: &[u8]) -> Fr {
+    if data.len() % PoseidonDomain::byte_len() != 0 {
+        panic_any(DomainError::UnalignedInput(data.len()));
+    }
     let preimage = data
-        .chunks(32)
-        .map(|ref chunk| {
-            <Bls12 as ScalarEngine>::Fr::from_repr(PoseidonDomain::from_slice(chunk).0)
-                .expect("from_repr failure")
-        })
+        .chunks(PoseidonDomain::byte_len())
+        .map(|chunk| reduce(PoseidonDomain::from_slice(chunk).0))
         .collect::<Vec<_>>();
 
-    shared_hash_frs(&preimage).into()
+    try_shared_hash_frs(&preimage).unwrap_or_else(|err| panic_any(err))
+}
+
+fn try_shared_hash(data: &[u8]) -> Result<Fr, DomainError> {
+    if data.len() % PoseidonDomain::byte_len() != 0 {
+        return Err(DomainError::UnalignedInput(data.len()));
+    }
+    let preimage = data
+        .chunks(PoseidonDomain::byte_len())
+        .map(fr_from_bytes)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    try_shared_hash_frs(&preimage)
 }
 
-fn shared_hash_frs(preimage: &[<Bls12 as ScalarEngine>::Fr]) -> <Bls12 as ScalarEngine>::Fr {
-    match preimage.len() {
+fn try_shared_hash_frs(
+    preimage: &[<Bls12 as ScalarEngine>::Fr],
+) -> Result<<Bls12 as ScalarEngine>::Fr, DomainError> {
+    let hash = match preimage.len() {
         2 => {
             let mut p = Poseidon::new_with_preimage(&preimage, &POSEIDON_CONSTANTS_2);
             p.hash()
@@ -218,37 +262,96 @@ fn shared_hash_frs(preimage: &[<Bls12 as ScalarEngine>::Fr]) -> <Bls12 as Scalar
             p.hash()
         }
 
-        _ => panic_any(format!(
-            "Unsupported arity for Poseidon hasher: {}",
-            preimage.len()
-        )),
-    }
+        arity => return Err(DomainError::UnsupportedArity(arity)),
+    };
+
+    Ok(hash)
+}
+
+/// The number of preimages callers should pass to `hash_batch` at once: enough to amortize the
+/// setup of the hasher, few enough to spread a tree row over all threads.
+pub const BATCH_SIZE: usize = 1024;
+
+/// Hashes each group of `arity` consecutive elements of `preimages`, returning one hash per group.
+/// The results are the same as hashing each group on its own.
+///
+/// One hasher state is reset and refilled for all groups, rather than set up for each. neptune
+/// only provides scalar field arithmetic, so the permutations run one after another; callers
+/// parallelize by hashing several batches at once.
+pub fn hash_batch(preimages: &[Fr], arity: usize) -> Result<Vec<Fr>, DomainError> {
+    ensure_batch(preimages.len(), arity)?;
+    let hashes = match arity {
+        2 => hash_batch_with::<U2>(preimages),
+        4 => hash_batch_with::<U4>(preimages),
+        8 => hash_batch_with::<U8>(preimages),
+        11 => hash_batch_with::<U11>(preimages),
+        16 => hash_batch_with::<U16>(preimages),
+        24 => hash_batch_with::<U24>(preimages),
+        36 => hash_batch_with::<U36>(preimages),
+        arity => return Err(DomainError::UnsupportedArity(arity)),
+    };
+
+    Ok(hashes)
+}
+
+fn hash_batch_with<A: PoseidonArity>(preimages: &[Fr]) -> Vec<Fr> {
+    let mut p = Poseidon::new(A::PARAMETERS());
+    preimages
+        .chunks_exact(A::to_usize())
+        .map(|preimage| {
+            p.reset();
+            for element in preimage {
+                // The preimage holds exactly `arity` elements, so the state cannot be full.
+                p.input(*element).expect("input failure");
+            }
+            p.hash()
+        })
+        .collect()
 }
 
 impl HashFunction<PoseidonDomain> for PoseidonFunction {
     fn hash(data: &[u8]) -> PoseidonDomain {
-        shared_hash(data)
+        shared_hash(data).into()
     }
 
     fn hash2(a: &PoseidonDomain, b: &PoseidonDomain) -> PoseidonDomain {
-        let mut p =
-            Poseidon::new_with_preimage(&[(*a).into(), (*b).into()][..], &*POSEIDON_CONSTANTS_2);
-        let fr: <Bls12 as ScalarEngine>::Fr = p.hash();
-        fr.into()
+        let (a, b) = (reduce(a.0).into(), reduce(b.0).into());
+        Self::try_hash2(&a, &b).unwrap_or_else(|err| panic_any(err))
     }
 
     fn hash_md(input: &[PoseidonDomain]) -> PoseidonDomain {
-        assert!(input.len() > 1, "hash_md needs more than one element.");
+        let input = input
+            .iter()
+            .map(|element| reduce(element.0).into())
+            .collect::<Vec<_>>();
+        Self::try_hash_md(&input).unwrap_or_else(|err| panic_any(err))
+    }
+
+    fn try_hash(data: &[u8]) -> Result<PoseidonDomain, DomainError> {
+        try_shared_hash(data).map(Into::into)
+    }
+
+    fn try_hash2(a: &PoseidonDomain, b: &PoseidonDomain) -> Result<PoseidonDomain, DomainError> {
+        let preimage = [a.try_to_fr()?, b.try_to_fr()?];
+        let mut p = Poseidon::new_with_preimage(&preimage[..], &*POSEIDON_CONSTANTS_2);
+        let fr: <Bls12 as ScalarEngine>::Fr = p.hash();
+        Ok(fr.into())
+    }
+
+    fn try_hash_md(input: &[PoseidonDomain]) -> Result<PoseidonDomain, DomainError> {
+        if input.len() < 2 {
+            return Err(DomainError::NotEnoughElements(input.len()));
+        }
         let arity = PoseidonMDArity::to_usize();
 
         let mut p = Poseidon::new(&*POSEIDON_MD_CONSTANTS);
 
         let fr_input = input
             .iter()
-            .map(|x| <Bls12 as ScalarEngine>::Fr::from_repr(x.0).expect("from_repr failure"))
-            .collect::<Vec<_>>();
+            .map(Domain::try_to_fr)
+            .collect::<Result<Vec<_>, _>>()?;
 
-        fr_input[1..]
+        let hash = fr_input[1..]
             .chunks(arity - 1)
             .fold(fr_input[0], |acc, elts| {
                 p.reset();
@@ -257,8 +360,41 @@ impl HashFunction<PoseidonDomain> for PoseidonFunction {
                     let _ = p.input(*elt).expect("input failure");
                 });
                 p.hash()
-            })
-            .into()
+            });
+
+        Ok(hash.into())
+    }
+
+    fn try_multi_node(
+        parts: &[PoseidonDomain],
+        _height: usize,
+    ) -> Result<PoseidonDomain, DomainError> {
+        let preimage = parts
+            .iter()
+            .map(Domain::try_to_fr)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        try_shared_hash_frs(&preimage).map(Into::into)
+    }
+
+    fn try_multi_node_batch(
+        parts: &[PoseidonDomain],
+        arity: usize,
+        _height: usize,
+    ) -> Result<Vec<PoseidonDomain>, DomainError> {
+        // `hash_batch` supports more arities than tree nodes are hashed with.
+        if !matches!(arity, 2 | 4 | 8 | 16) {
+            return Err(DomainError::UnsupportedArity(arity));
+        }
+        let preimages = parts
+            .iter()
+            .map(Domain::try_to_fr)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(hash_batch(&preimages, arity)?
+            .into_iter()
+            .map(Into::into)
+            .collect())
     }
 
     fn hash_leaf_circuit<CS: ConstraintSystem<Bls12>>(
@@ -353,28 +489,15 @@ impl LightAlgorithm<PoseidonDomain> for PoseidonFunction {
         right: PoseidonDomain,
         _height: usize,
     ) -> PoseidonDomain {
-        shared_hash_frs(&[
-            <Bls12 as ScalarEngine>::Fr::from_repr(left.0).expect("from_repr failure"),
-            <Bls12 as ScalarEngine>::Fr::from_repr(right.0).expect("from_repr failure"),
-        ])
-        .into()
+        <Self as HashFunction<PoseidonDomain>>::hash2(&left, &right)
     }
 
     fn multi_node(&mut self, parts: &[PoseidonDomain], _height: usize) -> PoseidonDomain {
-        match parts.len() {
-            1 | 2 | 4 | 8 | 16 => shared_hash_frs(
-                &parts
-                    .iter()
-                    .enumerate()
-                    .map(|(i, x)| {
-                        <Bls12 as ScalarEngine>::Fr::from_repr(x.0)
-                            .unwrap_or_else(|_| panic_any(format!("from_repr failure at {}", i)))
-                    })
-                    .collect::<Vec<_>>(),
-            )
-            .into(),
-            arity => panic_any(format!("unsupported arity {}", arity)),
-        }
+        let preimage = parts.iter().map(|part| reduce(part.0)).collect::<Vec<_>>();
+
+        try_shared_hash_frs(&preimage)
+            .unwrap_or_else(|err| panic_any(err))
+            .into()
     }
 }
 
@@ -392,6 +515,7 @@ impl From<FrRepr> for PoseidonDomain {
     }
 }
 
+// Panics if `val` is not a valid field element; see `Domain::try_to_fr`.
 impl From<PoseidonDomain> for Fr {
     #[inline]
     fn from(val: PoseidonDomain) -> Self {
@@ -407,6 +531,15 @@ mod tests {
 
     use bellperson::util_cs::test_cs::TestConstraintSystem;
     use merkletree::{merkle::MerkleTree, store::VecStore};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use crate::types::POSEIDON_CONSTANTS_11;
+
+    const TEST_SEED: [u8; 16] = [
+        0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06, 0xbc,
+        0xe5,
+    ];
 
     #[test]
     fn test_path() {
@@ -495,6 +628,52 @@ mod tests {
         assert_eq!(t.read_at(6).expect("read_at failure"), root);
     }
 
+    #[test]
+    fn test_hash_batch() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        let preimages: Vec<Fr> = (0..16 * 11).map(|_| Fr::random(rng)).collect();
+
+        for &arity in &[2, 4, 8, 16] {
+            let expected: Vec<Fr> = preimages
+                .chunks(arity)
+                .map(|preimage| try_shared_hash_frs(preimage).expect("hash failure"))
+                .collect();
+            assert_eq!(
+                hash_batch(&preimages, arity).expect("hash_batch failure"),
+                expected
+            );
+
+            let nodes: Vec<PoseidonDomain> = preimages.iter().copied().map(Into::into).collect();
+            let expected: Vec<PoseidonDomain> = nodes
+                .chunks(arity)
+                .map(|children| PoseidonFunction::default().multi_node(children, 0))
+                .collect();
+            assert_eq!(
+                PoseidonFunction::try_multi_node_batch(&nodes, arity, 0)
+                    .expect("try_multi_node_batch failure"),
+                expected
+            );
+        }
+
+        let expected: Vec<Fr> = preimages
+            .chunks(11)
+            .map(|preimage| Poseidon::new_with_preimage(preimage, &*POSEIDON_CONSTANTS_11).hash())
+            .collect();
+        assert_eq!(
+            hash_batch(&preimages, 11).expect("hash_batch failure"),
+            expected
+        );
+
+        assert_eq!(
+            hash_batch(&preimages[..5], 2),
+            Err(DomainError::IncompleteBatch { len: 5, arity: 2 })
+        );
+        assert_eq!(
+            hash_batch(&preimages[..6], 3),
+            Err(DomainError::UnsupportedArity(3))
+        );
+    }
+
     #[test]
     fn test_as_ref() {
         let cases: Vec<[u64; 4]> = vec![
@@ -2,7 +2,6 @@ use std::fmt::{self, Debug, Formatter};
 use std::hash::Hasher as StdHasher;
 use std::panic::panic_any;
 
-use anyhow::ensure;
 use bellperson::{
     bls::{Bls12, Fr, FrRepr},
     gadgets::{boolean::Boolean, multipack, num::AllocatedNum, sha256::sha256 as sha256_circuit},
@@ -17,7 +16,8 @@ use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
-use crate::types::{Domain, HashFunction, Hasher};
+use crate::error::DomainError;
+use crate::types::{ensure_len, fr_from_bytes, Domain, HashFunction, Hasher};
 
 #[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Sha256Hasher {}
@@ -42,7 +42,11 @@ impl StdHasher for Sha256Function {
 
     #[inline]
     fn finish(&self) -> u64 {
-        unreachable!("unused by Function -- should never be called")
+        // Unused by Function, but returns the leading bytes of the digest rather than panicking.
+        let digest = self.0.clone().finalize();
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        u64::from_le_bytes(bytes)
     }
 }
 
@@ -114,22 +118,21 @@ impl Domain for Sha256Domain {
         self.0.to_vec()
     }
 
-    fn try_from_bytes(raw: &[u8]) -> anyhow::Result<Self> {
-        ensure!(
-            raw.len() == Sha256Domain::byte_len(),
-            "invalid number of bytes"
-        );
+    fn try_from_bytes(raw: &[u8]) -> Result<Self, DomainError> {
+        fr_from_bytes(raw)?;
 
         let mut res = Sha256Domain::default();
-        res.0.copy_from_slice(&raw[0..Sha256Domain::byte_len()]);
+        res.0.copy_from_slice(raw);
         Ok(res)
     }
 
-    fn write_bytes(&self, dest: &mut [u8]) -> anyhow::Result<()> {
-        ensure!(
-            dest.len() >= Sha256Domain::byte_len(),
-            "invalid number of bytes"
-        );
+    fn write_bytes(&self, dest: &mut [u8]) -> Result<(), DomainError> {
+        if dest.len() < Sha256Domain::byte_len() {
+            return Err(DomainError::InvalidLength {
+                expected: Sha256Domain::byte_len(),
+                actual: dest.len(),
+            });
+        }
 
         dest[0..Sha256Domain::byte_len()].copy_from_slice(&self.0[..]);
         Ok(())
@@ -146,11 +149,16 @@ impl Element for Sha256Domain {
         32
     }
 
+    /// merkletree passes exactly `byte_len` bytes per stored node, so other lengths are a bug in
+    /// the caller and panic. Any 32 bytes are accepted, as nodes are hashed as bytes.
     fn from_slice(bytes: &[u8]) -> Self {
-        match Sha256Domain::try_from_bytes(bytes) {
-            Ok(res) => res,
-            Err(err) => panic_any(err),
+        if let Err(err) = ensure_len(bytes, Sha256Domain::byte_len()) {
+            panic_any(err);
         }
+
+        let mut res = Sha256Domain::default();
+        res.0.copy_from_slice(bytes);
+        res
     }
 
     fn copy_to_slice(&self, bytes: &mut [u8]) {
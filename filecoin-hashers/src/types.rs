@@ -9,6 +9,7 @@ use bellperson::{
     gadgets::{boolean::Boolean, num::AllocatedNum},
     ConstraintSystem, SynthesisError,
 };
+use ff::{PrimeField, PrimeFieldRepr};
 use merkletree::{
     hash::{Algorithm as LightAlgorithm, Hashable as LightHashable},
     merkle::Element,
@@ -16,6 +17,13 @@ use merkletree::{
 use rand::RngCore;
 use serde::{de::DeserializeOwned, Serialize};
 
+use crate::error::DomainError;
+
+/// A node of a tree, stored as the LittleEndian bytes of a field element.
+///
+/// Stored nodes are read back through `Element::from_slice`, which only checks the length of its
+/// input and panics if it is wrong, so the value may lie outside the field. Untrusted bytes go
+/// through `try_from_bytes` instead, and `try_to_fr` rejects nodes that were read back unchecked.
 pub trait Domain:
     Ord
     + Copy
@@ -36,9 +44,15 @@ pub trait Domain:
 {
     #[allow(clippy::wrong_self_convention)]
     fn into_bytes(&self) -> Vec<u8>;
-    fn try_from_bytes(raw: &[u8]) -> anyhow::Result<Self>;
+    /// Reads an element from its LittleEndian bytes, rejecting values outside the field.
+    fn try_from_bytes(raw: &[u8]) -> Result<Self, DomainError>;
     /// Write itself into the given slice, LittleEndian bytes.
-    fn write_bytes(&self, _: &mut [u8]) -> anyhow::Result<()>;
+    fn write_bytes(&self, _: &mut [u8]) -> Result<(), DomainError>;
+
+    /// Fallible version of `Into<Fr>`, for elements that were not checked on construction.
+    fn try_to_fr(&self) -> Result<Fr, DomainError> {
+        fr_from_bytes(AsRef::<[u8]>::as_ref(self))
+    }
 
     fn random<R: RngCore>(rng: &mut R) -> Self;
 }
@@ -55,6 +69,43 @@ pub trait HashFunction<T: Domain>: Clone + Debug + Send + Sync + LightAlgorithm<
             .fold(input[0], |acc, elt| Self::hash2(&acc, elt))
     }
 
+    /// Fallible version of `hash`, for input that is not known to be well-formed.
+    fn try_hash(data: &[u8]) -> Result<T, DomainError> {
+        Ok(Self::hash(data))
+    }
+
+    /// Fallible version of `hash2`.
+    fn try_hash2(a: &T, b: &T) -> Result<T, DomainError> {
+        Ok(Self::hash2(a, b))
+    }
+
+    /// Fallible version of `hash_md`.
+    fn try_hash_md(input: &[T]) -> Result<T, DomainError> {
+        if input.len() < 2 {
+            return Err(DomainError::NotEnoughElements(input.len()));
+        }
+        Ok(Self::hash_md(input))
+    }
+
+    /// Fallible version of `multi_node`, as used to hash the children of a tree node.
+    fn try_multi_node(parts: &[T], height: usize) -> Result<T, DomainError> {
+        Ok(Self::default().multi_node(parts, height))
+    }
+
+    /// Hashes each group of `arity` consecutive nodes in `parts` like `multi_node`, returning one
+    /// node per group. Implementations may hash the groups together, the results are the same.
+    fn try_multi_node_batch(
+        parts: &[T],
+        arity: usize,
+        height: usize,
+    ) -> Result<Vec<T>, DomainError> {
+        ensure_batch(parts.len(), arity)?;
+        parts
+            .chunks(arity)
+            .map(|children| Self::try_multi_node(children, height))
+            .collect()
+    }
+
     fn hash_leaf(data: &dyn LightHashable<Self>) -> T {
         let mut a = Self::default();
         data.hash(&mut a);
@@ -122,3 +173,221 @@ pub trait Hasher: Clone + Debug + Eq + Default + Send + Sync {
 
     fn name() -> String;
 }
+
+/// Checks that `raw` holds exactly `expected` bytes.
+pub(crate) fn ensure_len(raw: &[u8], expected: usize) -> Result<(), DomainError> {
+    if raw.len() != expected {
+        return Err(DomainError::InvalidLength {
+            expected,
+            actual: raw.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Checks that a batch of `len` elements splits into whole preimages of `arity`.
+pub(crate) fn ensure_batch(len: usize, arity: usize) -> Result<(), DomainError> {
+    if arity == 0 || len % arity != 0 {
+        return Err(DomainError::IncompleteBatch { len, arity });
+    }
+    Ok(())
+}
+
+/// Reads a field element from its 32 LittleEndian bytes.
+pub(crate) fn fr_from_bytes(raw: &[u8]) -> Result<Fr, DomainError> {
+    ensure_len(raw, 32)?;
+    let mut repr = FrRepr::default();
+    repr.read_le(raw).map_err(|_| DomainError::NotInField)?;
+
+    Fr::from_repr(repr).map_err(|_| DomainError::NotInField)
+}
+
+#[cfg(all(test, feature = "blake2s", feature = "poseidon", feature = "sha256"))]
+mod tests {
+    use super::*;
+
+    use proptest::{collection::vec, prelude::any, proptest};
+
+    use crate::{blake2s::Blake2sHasher, poseidon::PoseidonHasher, sha256::Sha256Hasher};
+
+    fn modulus_bytes() -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(32);
+        Fr::char().write_le(&mut bytes).expect("write_le failure");
+        bytes
+    }
+
+    fn check_try_from_bytes<H: Hasher>() {
+        let mut below_modulus = modulus_bytes();
+        below_modulus[0] -= 1;
+        let domain = H::Domain::try_from_bytes(&below_modulus).expect("try_from_bytes failure");
+        assert_eq!(domain.into_bytes(), below_modulus);
+        assert!(domain.try_to_fr().is_ok());
+
+        assert_eq!(
+            H::Domain::try_from_bytes(&modulus_bytes()),
+            Err(DomainError::NotInField)
+        );
+        assert_eq!(
+            H::Domain::try_from_bytes(&[0u8; 31]),
+            Err(DomainError::InvalidLength {
+                expected: 32,
+                actual: 31
+            })
+        );
+        assert_eq!(
+            H::Domain::default().write_bytes(&mut [0u8; 16]),
+            Err(DomainError::InvalidLength {
+                expected: 32,
+                actual: 16
+            })
+        );
+
+        // Stored nodes are read back without validation, so the error surfaces on conversion.
+        let stored = H::Domain::from_slice(&modulus_bytes());
+        assert_eq!(stored.try_to_fr(), Err(DomainError::NotInField));
+    }
+
+    #[test]
+    fn test_try_from_bytes() {
+        check_try_from_bytes::<PoseidonHasher>();
+        check_try_from_bytes::<Sha256Hasher>();
+        check_try_from_bytes::<Blake2sHasher>();
+    }
+
+    #[test]
+    fn test_poseidon_rejects_malformed_input() {
+        type Function = <PoseidonHasher as Hasher>::Function;
+        type PoseidonDomain = <PoseidonHasher as Hasher>::Domain;
+
+        let valid = PoseidonDomain::default();
+        let invalid = PoseidonDomain::from_slice(&modulus_bytes());
+
+        assert_eq!(
+            Function::try_hash(&[0u8; 33]),
+            Err(DomainError::UnalignedInput(33))
+        );
+        assert_eq!(
+            Function::try_hash(&[0u8; 96]),
+            Err(DomainError::UnsupportedArity(3))
+        );
+        assert_eq!(
+            Function::try_hash2(&valid, &invalid),
+            Err(DomainError::NotInField)
+        );
+        assert_eq!(
+            Function::try_hash_md(&[valid]),
+            Err(DomainError::NotEnoughElements(1))
+        );
+        assert_eq!(
+            Function::try_multi_node(&[valid; 3], 0),
+            Err(DomainError::UnsupportedArity(3))
+        );
+        assert_eq!(
+            Function::try_multi_node(&[valid, valid, valid, invalid], 0),
+            Err(DomainError::NotInField)
+        );
+    }
+
+    fn fuzz_bytes<H: Hasher>(bytes: &[u8]) {
+        if let Ok(domain) = H::Domain::try_from_bytes(bytes) {
+            assert_eq!(domain.into_bytes(), bytes);
+            assert!(domain.try_to_fr().is_ok());
+        }
+        let _ = H::Function::try_hash(bytes);
+        let _ = H::Domain::default().write_bytes(&mut vec![0u8; bytes.len()]);
+    }
+
+    fn fuzz_nodes<H: Hasher>(nodes: &[[u8; 32]]) {
+        let nodes: Vec<H::Domain> = nodes
+            .iter()
+            .map(|node| H::Domain::from_slice(node))
+            .collect();
+        for node in &nodes {
+            assert_eq!(
+                node.try_to_fr().is_ok(),
+                H::Domain::try_from_bytes(AsRef::<[u8]>::as_ref(node)).is_ok()
+            );
+        }
+        if nodes.len() >= 2 {
+            let _ = H::Function::try_hash2(&nodes[0], &nodes[1]);
+        }
+        let _ = H::Function::try_hash_md(&nodes);
+        let _ = H::Function::try_multi_node(&nodes, 0);
+        for arity in 0..=nodes.len() {
+            let _ = H::Function::try_multi_node_batch(&nodes, arity, 0);
+        }
+    }
+
+    // Nodes are read back from a store through `Element::from_slice` and hashed through the
+    // infallible `LightAlgorithm` methods, which must not panic whatever the store holds.
+    fn fuzz_store_nodes<H: Hasher>(bytes: &[u8]) {
+        let nodes: Vec<H::Domain> = bytes
+            .chunks_exact(H::Domain::byte_len())
+            .map(H::Domain::from_slice)
+            .collect();
+        let mut hasher = H::Function::default();
+        for pair in nodes.chunks_exact(2) {
+            hasher.reset();
+            let _ = hasher.node(pair[0], pair[1], 0);
+            let _ = H::Function::hash2(&pair[0], &pair[1]);
+        }
+        for arity in &[2, 4, 8] {
+            for parts in nodes.chunks_exact(*arity) {
+                hasher.reset();
+                let _ = hasher.multi_node(parts, 0);
+            }
+        }
+        if nodes.len() >= 2 {
+            let _ = H::Function::hash_md(&nodes);
+        }
+    }
+
+    #[test]
+    fn test_store_nodes_outside_field() {
+        let mut bytes = modulus_bytes();
+        bytes.extend_from_slice(&[0xff; 32]);
+        for _ in 0..3 {
+            bytes.extend(modulus_bytes());
+            bytes.extend_from_slice(&[0xff; 32]);
+        }
+        fuzz_store_nodes::<PoseidonHasher>(&bytes);
+        fuzz_store_nodes::<Sha256Hasher>(&bytes);
+        fuzz_store_nodes::<Blake2sHasher>(&bytes);
+
+        // Elements outside the field are hashed as their reduced value.
+        type Function = <PoseidonHasher as Hasher>::Function;
+        type PoseidonDomain = <PoseidonHasher as Hasher>::Domain;
+        let zero = PoseidonDomain::default();
+        let modulus = PoseidonDomain::from_slice(&modulus_bytes());
+        assert_eq!(
+            Function::default().node(modulus, zero, 0),
+            Function::try_hash2(&zero, &zero).expect("try_hash2 failure")
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn fuzz_store_bytes(bytes in vec(any::<u8>(), 0..512)) {
+            fuzz_store_nodes::<PoseidonHasher>(&bytes);
+            fuzz_store_nodes::<Sha256Hasher>(&bytes);
+            fuzz_store_nodes::<Blake2sHasher>(&bytes);
+        }
+
+        #[test]
+        fn fuzz_domain_bytes(bytes in vec(any::<u8>(), 0..100), node in any::<[u8; 32]>()) {
+            fuzz_bytes::<PoseidonHasher>(&bytes);
+            fuzz_bytes::<PoseidonHasher>(&node);
+            fuzz_bytes::<Sha256Hasher>(&bytes);
+            fuzz_bytes::<Sha256Hasher>(&node);
+            fuzz_bytes::<Blake2sHasher>(&bytes);
+            fuzz_bytes::<Blake2sHasher>(&node);
+        }
+
+        #[test]
+        fn fuzz_domain_nodes(nodes in vec(any::<[u8; 32]>(), 0..20)) {
+            fuzz_nodes::<PoseidonHasher>(&nodes);
+            fuzz_nodes::<Sha256Hasher>(&nodes);
+            fuzz_nodes::<Blake2sHasher>(&nodes);
+        }
+    }
+}
@@ -10,6 +10,7 @@ use storage_proofs_core::{cache_key::CacheKey, merkle::MerkleTreeTrait};
 use storage_proofs_porep::stacked::StackedDrg;
 
 use crate::{
+    api::spans::api_span,
     constants::DefaultPieceHasher,
     types::{Commitment, PaddedBytesAmount, PoRepConfig},
 };
@@ -19,6 +20,7 @@ pub fn fauxrep<R: AsRef<Path>, S: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
     cache_path: R,
     out_path: S,
 ) -> Result<Commitment> {
+    let _span = api_span("fauxrep").porep_config(&porep_config).entered();
     let mut rng = thread_rng();
     fauxrep_aux::<_, R, S, Tree>(&mut rng, porep_config, cache_path, out_path)
 }
@@ -29,6 +31,9 @@ pub fn fauxrep_aux<R: Rng, S: AsRef<Path>, T: AsRef<Path>, Tree: 'static + Merkl
     cache_path: S,
     out_path: T,
 ) -> Result<Commitment> {
+    let _span = api_span("fauxrep_aux")
+        .porep_config(&porep_config)
+        .entered();
     let sector_bytes = PaddedBytesAmount::from(porep_config).0;
 
     {
@@ -62,6 +67,7 @@ pub fn fauxrep2<R: AsRef<Path>, S: AsRef<Path>, Tree: 'static + MerkleTreeTrait>
     cache_path: R,
     existing_p_aux_path: S,
 ) -> Result<Commitment> {
+    let _span = api_span("fauxrep2").entered();
     let mut rng = thread_rng();
 
     let fake_comm_c = <Tree::Hasher as Hasher>::Domain::random(&mut rng);
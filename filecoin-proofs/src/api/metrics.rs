@@ -0,0 +1,36 @@
+use storage_proofs_core::metrics::METRICS;
+#[cfg(feature = "prover")]
+use storage_proofs_core::{
+    metrics::{scoped_labels, LabelsGuard, MetricLabels},
+    sector::SectorId,
+};
+
+#[cfg(feature = "prover")]
+use crate::types::SectorSize;
+
+pub use storage_proofs_core::{
+    measurements::Operation,
+    metrics::{HistogramSnapshot, OpSnapshot, Sample},
+};
+
+/// Returns the metrics recorded by this process so far, one entry per operation and sector size.
+pub fn get_metrics() -> Vec<OpSnapshot> {
+    METRICS.snapshot()
+}
+
+/// Renders the metrics recorded by this process so far in the Prometheus text exposition
+/// format, e.g. to be served by a metrics endpoint of the caller.
+pub fn get_metrics_text() -> String {
+    METRICS.render_text()
+}
+
+/// Labels all metrics recorded on the current thread with the given sector, until the returned
+/// guard is dropped. The metrics are aggregated by sector size, the sector id is only recorded
+/// with the last sample of each operation.
+#[cfg(feature = "prover")]
+pub(crate) fn label_sector(sector_size: SectorSize, sector_id: Option<SectorId>) -> LabelsGuard {
+    scoped_labels(MetricLabels::new(
+        u64::from(sector_size),
+        sector_id.map(u64::from),
+    ))
+}
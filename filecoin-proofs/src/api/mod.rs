@@ -1,53 +1,95 @@
+#[cfg(feature = "prover")]
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufReader, BufWriter, Read, Write};
+#[cfg(feature = "prover")]
 use std::path::{Path, PathBuf};
 
 use anyhow::{ensure, Context, Result};
+#[cfg(feature = "prover")]
 use bincode::deserialize;
+#[cfg(feature = "prover")]
 use filecoin_hashers::Hasher;
-use fr32::{write_unpadded, Fr32Reader};
+#[cfg(feature = "prover")]
+use fr32::write_unpadded;
+use fr32::Fr32Reader;
 use log::{info, trace};
+#[cfg(feature = "prover")]
 use memmap::MmapOptions;
+#[cfg(feature = "prover")]
 use merkletree::store::{DiskStore, LevelCacheStore, StoreConfig};
+#[cfg(feature = "prover")]
+use storage_proofs_core::{
+    cache_key::CacheKey, merkle::get_base_tree_count, sector::SectorId,
+    util::default_rows_to_discard,
+};
 use storage_proofs_core::{
-    cache_key::CacheKey,
     measurements::{measure_op, Operation},
-    merkle::get_base_tree_count,
     pieces::generate_piece_commitment_bytes_from_source,
-    sector::SectorId,
-    util::default_rows_to_discard,
 };
+#[cfg(feature = "prover")]
 use storage_proofs_porep::{
     stacked::{generate_replica_id, PersistentAux, StackedDrg, TemporaryAux},
     PoRep,
 };
+#[cfg(feature = "prover")]
 use typenum::Unsigned;
 
+#[cfg(feature = "prover")]
+use crate::api::unsealed::verify_unsealed_range;
+#[cfg(feature = "prover")]
 use crate::{
+    api::metrics::label_sector,
+    constants::{DefaultBinaryTree, DefaultOctTree, DefaultPieceDomain},
+    parameters::public_params,
+    types::{
+        Commitment, MerkleTreeTrait, PoRepConfig, PoRepProofPartitions, ProverId,
+        SealPreCommitPhase1Output, Ticket, UnpaddedByteIndex,
+    },
+};
+use crate::{
+    api::spans::api_span,
     commitment_reader::CommitmentReader,
     constants::{
-        DefaultBinaryTree, DefaultOctTree, DefaultPieceDomain, DefaultPieceHasher,
+        DefaultPieceHasher,
         MINIMUM_RESERVED_BYTES_FOR_PIECE_IN_FULLY_ALIGNED_SECTOR as MINIMUM_PIECE_SIZE,
     },
-    parameters::public_params,
     pieces::{get_piece_alignment, sum_piece_bytes_with_alignment},
-    types::{
-        Commitment, MerkleTreeTrait, PaddedBytesAmount, PieceInfo, PoRepConfig,
-        PoRepProofPartitions, ProverId, SealPreCommitPhase1Output, Ticket, UnpaddedByteIndex,
-        UnpaddedBytesAmount,
-    },
+    types::{PaddedBytesAmount, PieceInfo, UnpaddedBytesAmount},
 };
 
+#[cfg(feature = "prover")]
 mod fake_seal;
+mod metrics;
+#[cfg(feature = "prover")]
+mod post_prover;
 mod post_util;
+#[cfg(feature = "prover")]
+mod regenerate;
+#[cfg(feature = "prover")]
+mod resources;
 mod seal;
+mod spans;
+#[cfg(feature = "prover")]
+mod unsealed;
+mod update;
 mod util;
 mod window_post;
 mod winning_post;
 
+#[cfg(feature = "prover")]
 pub use fake_seal::*;
+pub use metrics::*;
+#[cfg(feature = "prover")]
+pub use post_prover::*;
 pub use post_util::*;
+#[cfg(feature = "prover")]
+pub use regenerate::*;
+#[cfg(feature = "prover")]
+pub use resources::*;
 pub use seal::*;
+#[cfg(feature = "prover")]
+pub use unsealed::*;
+pub use update::*;
 pub use util::*;
 pub use window_post::*;
 pub use winning_post::*;
@@ -69,6 +111,7 @@ pub use winning_post::*;
 /// * `ticket` - the ticket that was used to generate the sector's replica-id.
 /// * `offset` - the byte index in the unsealed sector of the first byte that we want to read.
 /// * `num_bytes` - the number of bytes that we want to read.
+#[cfg(feature = "prover")]
 #[allow(clippy::too_many_arguments)]
 pub fn get_unsealed_range<T: Into<PathBuf> + AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
@@ -82,6 +125,11 @@ pub fn get_unsealed_range<T: Into<PathBuf> + AsRef<Path>, Tree: 'static + Merkle
     offset: UnpaddedByteIndex,
     num_bytes: UnpaddedBytesAmount,
 ) -> Result<UnpaddedBytesAmount> {
+    let _span = api_span("get_unsealed_range")
+        .porep_config(&porep_config)
+        .prover_id(&prover_id)
+        .sector_id(sector_id)
+        .entered();
     info!("get_unsealed_range:start");
 
     let f_out = File::create(&output_path)
@@ -123,8 +171,83 @@ pub fn get_unsealed_range<T: Into<PathBuf> + AsRef<Path>, Tree: 'static + Merkle
 /// * `ticket` - the ticket that was used to generate the sector's replica-id.
 /// * `offset` - the byte index in the unsealed sector of the first byte that we want to read.
 /// * `num_bytes` - the number of bytes that we want to read.
+#[cfg(feature = "prover")]
 #[allow(clippy::too_many_arguments)]
 pub fn unseal_range<P, R, W, Tree>(
+    porep_config: PoRepConfig,
+    cache_path: P,
+    sealed_sector: R,
+    unsealed_output: W,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+    ticket: Ticket,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+) -> Result<UnpaddedBytesAmount>
+where
+    P: Into<PathBuf> + AsRef<Path>,
+    R: Read,
+    W: Write,
+    Tree: 'static + MerkleTreeTrait,
+{
+    unseal_range_checked::<_, _, _, Tree>(
+        porep_config,
+        cache_path,
+        sealed_sector,
+        unsealed_output,
+        prover_id,
+        sector_id,
+        comm_d,
+        ticket,
+        offset,
+        num_bytes,
+        false,
+    )
+}
+
+/// Like `unseal_range`, but verifies the unsealed bytes against `comm_d` before writing them to
+/// `unsealed_output`, using the comm_d tree in `cache_path` if it is available. Fails with
+/// `Error::UnsealedDataMismatch` if they do not match, e.g. because the replica is corrupt or
+/// the ticket is wrong.
+#[cfg(feature = "prover")]
+#[allow(clippy::too_many_arguments)]
+pub fn unseal_range_verified<P, R, W, Tree>(
+    porep_config: PoRepConfig,
+    cache_path: P,
+    sealed_sector: R,
+    unsealed_output: W,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+    ticket: Ticket,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+) -> Result<UnpaddedBytesAmount>
+where
+    P: Into<PathBuf> + AsRef<Path>,
+    R: Read,
+    W: Write,
+    Tree: 'static + MerkleTreeTrait,
+{
+    unseal_range_checked::<_, _, _, Tree>(
+        porep_config,
+        cache_path,
+        sealed_sector,
+        unsealed_output,
+        prover_id,
+        sector_id,
+        comm_d,
+        ticket,
+        offset,
+        num_bytes,
+        true,
+    )
+}
+
+#[cfg(feature = "prover")]
+#[allow(clippy::too_many_arguments)]
+fn unseal_range_checked<P, R, W, Tree>(
     porep_config: PoRepConfig,
     cache_path: P,
     mut sealed_sector: R,
@@ -135,6 +258,7 @@ pub fn unseal_range<P, R, W, Tree>(
     ticket: Ticket,
     offset: UnpaddedByteIndex,
     num_bytes: UnpaddedBytesAmount,
+    verify: bool,
 ) -> Result<UnpaddedBytesAmount>
 where
     P: Into<PathBuf> + AsRef<Path>,
@@ -142,7 +266,13 @@ where
     W: Write,
     Tree: 'static + MerkleTreeTrait,
 {
+    let _span = api_span("unseal_range")
+        .porep_config(&porep_config)
+        .prover_id(&prover_id)
+        .sector_id(sector_id)
+        .entered();
     info!("unseal_range:start");
+    let _metric_labels = label_sector(porep_config.sector_size, Some(sector_id));
     ensure!(comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
 
     let comm_d =
@@ -167,6 +297,7 @@ where
         replica_id,
         offset,
         num_bytes,
+        if verify { Some(comm_d) } else { None },
     )?;
 
     info!("unseal_range:finish");
@@ -191,6 +322,7 @@ where
 /// * `ticket` - the ticket that was used to generate the sector's replica-id.
 /// * `offset` - the byte index in the unsealed sector of the first byte that we want to read.
 /// * `num_bytes` - the number of bytes that we want to read.
+#[cfg(feature = "prover")]
 #[allow(clippy::too_many_arguments)]
 pub fn unseal_range_mapped<P, W, Tree>(
     porep_config: PoRepConfig,
@@ -209,7 +341,86 @@ where
     W: Write,
     Tree: 'static + MerkleTreeTrait,
 {
+    unseal_range_mapped_checked::<_, _, Tree>(
+        porep_config,
+        cache_path,
+        sealed_path,
+        unsealed_output,
+        prover_id,
+        sector_id,
+        comm_d,
+        ticket,
+        offset,
+        num_bytes,
+        false,
+    )
+}
+
+/// Like `unseal_range_mapped`, but verifies the unsealed bytes against `comm_d` before writing
+/// them to `unsealed_output`, using the comm_d tree in `cache_path` if it is available. Fails
+/// with `Error::UnsealedDataMismatch` if they do not match, e.g. because the replica is corrupt
+/// or the ticket is wrong.
+#[cfg(feature = "prover")]
+#[allow(clippy::too_many_arguments)]
+pub fn unseal_range_mapped_verified<P, W, Tree>(
+    porep_config: PoRepConfig,
+    cache_path: P,
+    sealed_path: PathBuf,
+    unsealed_output: W,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+    ticket: Ticket,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+) -> Result<UnpaddedBytesAmount>
+where
+    P: Into<PathBuf> + AsRef<Path>,
+    W: Write,
+    Tree: 'static + MerkleTreeTrait,
+{
+    unseal_range_mapped_checked::<_, _, Tree>(
+        porep_config,
+        cache_path,
+        sealed_path,
+        unsealed_output,
+        prover_id,
+        sector_id,
+        comm_d,
+        ticket,
+        offset,
+        num_bytes,
+        true,
+    )
+}
+
+#[cfg(feature = "prover")]
+#[allow(clippy::too_many_arguments)]
+fn unseal_range_mapped_checked<P, W, Tree>(
+    porep_config: PoRepConfig,
+    cache_path: P,
+    sealed_path: PathBuf,
+    unsealed_output: W,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+    ticket: Ticket,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+    verify: bool,
+) -> Result<UnpaddedBytesAmount>
+where
+    P: Into<PathBuf> + AsRef<Path>,
+    W: Write,
+    Tree: 'static + MerkleTreeTrait,
+{
+    let _span = api_span("unseal_range_mapped")
+        .porep_config(&porep_config)
+        .prover_id(&prover_id)
+        .sector_id(sector_id)
+        .entered();
     info!("unseal_range_mapped:start");
+    let _metric_labels = label_sector(porep_config.sector_size, Some(sector_id));
     ensure!(comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
 
     let comm_d =
@@ -237,6 +448,7 @@ where
         replica_id,
         offset,
         num_bytes,
+        if verify { Some(comm_d) } else { None },
     );
     info!("unseal_range_mapped:finish");
 
@@ -260,6 +472,8 @@ where
 /// * `ticket` - the ticket that was used to generate the sector's replica-id.
 /// * `offset` - the byte index in the unsealed sector of the first byte that we want to read.
 /// * `num_bytes` - the number of bytes that we want to read.
+/// * `verify_comm_d` - the comm_d to verify the unsealed bytes against, if any.
+#[cfg(feature = "prover")]
 #[allow(clippy::too_many_arguments)]
 fn unseal_range_inner<P, W, Tree>(
     porep_config: PoRepConfig,
@@ -269,6 +483,7 @@ fn unseal_range_inner<P, W, Tree>(
     replica_id: <Tree::Hasher as Hasher>::Domain,
     offset: UnpaddedByteIndex,
     num_bytes: UnpaddedBytesAmount,
+    verify_comm_d: Option<DefaultPieceDomain>,
 ) -> Result<UnpaddedBytesAmount>
 where
     P: Into<PathBuf> + AsRef<Path>,
@@ -277,6 +492,41 @@ where
 {
     info!("unseal_range_inner:start");
 
+    extract_sector::<_, Tree>(porep_config, cache_path.as_ref(), data, replica_id)?;
+
+    let offset_padded: PaddedBytesAmount = UnpaddedBytesAmount::from(offset).into();
+    let num_bytes_padded: PaddedBytesAmount = num_bytes.into();
+    let start: usize = offset_padded.into();
+    let end = start + usize::from(num_bytes_padded);
+    if let Some(comm_d) = verify_comm_d {
+        verify_unsealed_range(cache_path.as_ref(), &comm_d, data, start, end)?;
+    }
+    let unsealed = &data[start..end];
+
+    // If the call to `extract_range` was successful, the `unsealed` vector must
+    // have a length which equals `num_bytes_padded`. The byte at its 0-index
+    // byte will be the the byte at index `offset_padded` in the sealed sector.
+    let written = write_unpadded(unsealed, &mut unsealed_output, 0, num_bytes.into())
+        .context("write_unpadded failed")?;
+
+    let amount = UnpaddedBytesAmount(written as u64);
+
+    info!("unseal_range_inner:finish");
+    Ok(amount)
+}
+
+/// Decodes the sealed sector `data` in place, using the comm_d tree in `cache_path`.
+#[cfg(feature = "prover")]
+fn extract_sector<P, Tree>(
+    porep_config: PoRepConfig,
+    cache_path: P,
+    data: &mut [u8],
+    replica_id: <Tree::Hasher as Hasher>::Domain,
+) -> Result<()>
+where
+    P: Into<PathBuf> + AsRef<Path>,
+    Tree: 'static + MerkleTreeTrait,
+{
     let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
     let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
     let config = StoreConfig::new(
@@ -294,24 +544,9 @@ where
         porep_config.api_version,
     )?;
 
-    let offset_padded: PaddedBytesAmount = UnpaddedBytesAmount::from(offset).into();
-    let num_bytes_padded: PaddedBytesAmount = num_bytes.into();
-
     StackedDrg::<Tree, DefaultPieceHasher>::extract_all(&pp, &replica_id, data, Some(config))?;
-    let start: usize = offset_padded.into();
-    let end = start + usize::from(num_bytes_padded);
-    let unsealed = &data[start..end];
 
-    // If the call to `extract_range` was successful, the `unsealed` vector must
-    // have a length which equals `num_bytes_padded`. The byte at its 0-index
-    // byte will be the the byte at index `offset_padded` in the sealed sector.
-    let written = write_unpadded(unsealed, &mut unsealed_output, 0, num_bytes.into())
-        .context("write_unpadded failed")?;
-
-    let amount = UnpaddedBytesAmount(written as u64);
-
-    info!("unseal_range_inner:finish");
-    Ok(amount)
+    Ok(())
 }
 
 /// Generates a piece commitment for the provided byte source. Returns an error
@@ -326,6 +561,7 @@ pub fn generate_piece_commitment<T: Read>(
     source: T,
     piece_size: UnpaddedBytesAmount,
 ) -> Result<PieceInfo> {
+    let _span = api_span("generate_piece_commitment").entered();
     trace!("generate_piece_commitment:start");
 
     let result = measure_op(Operation::GeneratePieceCommitment, || {
@@ -376,6 +612,7 @@ where
     R: Read,
     W: Write,
 {
+    let _span = api_span("add_piece").entered();
     info!("add_piece:start");
 
     let result = measure_op(Operation::AddPiece, || {
@@ -461,10 +698,12 @@ where
     R: Read,
     W: Write,
 {
+    let _span = api_span("write_and_preprocess").entered();
     add_piece(source, target, piece_size, Default::default())
 }
 
 // Verifies if a DiskStore specified by a config (or set of 'required_configs' is consistent).
+#[cfg(feature = "prover")]
 fn verify_store(config: &StoreConfig, arity: usize, required_configs: usize) -> Result<()> {
     let store_path = StoreConfig::data_path(&config.path, &config.id);
     if !Path::new(&store_path).exists() {
@@ -527,6 +766,7 @@ fn verify_store(config: &StoreConfig, arity: usize, required_configs: usize) ->
 }
 
 // Verifies if a LevelCacheStore specified by a config is consistent.
+#[cfg(feature = "prover")]
 fn verify_level_cache_store<Tree: MerkleTreeTrait>(config: &StoreConfig) -> Result<()> {
     let store_path = StoreConfig::data_path(&config.path, &config.id);
     if !Path::new(&store_path).exists() {
@@ -595,6 +835,7 @@ fn verify_level_cache_store<Tree: MerkleTreeTrait>(config: &StoreConfig) -> Resu
 }
 
 // Checks for the existence of the tree d store, the replica, and all generated labels.
+#[cfg(feature = "prover")]
 pub fn validate_cache_for_precommit_phase2<R, T, Tree: MerkleTreeTrait>(
     cache_path: R,
     replica_path: T,
@@ -604,6 +845,7 @@ where
     R: AsRef<Path>,
     T: AsRef<Path>,
 {
+    let _span = api_span("validate_cache_for_precommit_phase2").entered();
     info!("validate_cache_for_precommit_phase2:start");
 
     ensure!(
@@ -640,6 +882,7 @@ where
 // Checks for the existence of the replica data and t_aux, which in
 // turn allows us to verify the tree d, tree r, tree c, and the
 // labels.
+#[cfg(feature = "prover")]
 pub fn validate_cache_for_commit<R, T, Tree: MerkleTreeTrait>(
     cache_path: R,
     replica_path: T,
@@ -648,6 +891,7 @@ where
     R: AsRef<Path>,
     T: AsRef<Path>,
 {
+    let _span = api_span("validate_cache_for_commit").entered();
     info!("validate_cache_for_precommit:start");
 
     // Verify that the replica exists and is not empty.
@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{ensure, Context, Result};
+use bellperson::{bls::Bls12, groth16::MappedParameters};
+use filecoin_hashers::Hasher;
+use log::info;
+use storage_proofs_core::{
+    compound_proof::{self, CompoundProof},
+    merkle::{MerkleTreeTrait, MerkleTreeWrapper},
+    sector::SectorId,
+};
+use storage_proofs_post::fallback::{
+    self, FallbackPoSt, FallbackPoStCompound, PrivateSector, PublicSector,
+};
+
+use crate::{
+    api::{as_safe_commitment, get_partitions_for_window_post},
+    caches::get_post_params,
+    parameters::{window_post_setup_params, winning_post_setup_params},
+    types::{ChallengeSeed, PoStConfig, PrivateReplicaInfo, ProverId, SnarkProof},
+    PoStType,
+};
+
+/// A sector held open by a `PoStProver`.
+struct ProverSector<Tree: MerkleTreeTrait> {
+    replica: PrivateReplicaInfo<Tree>,
+    comm_r: <Tree::Hasher as Hasher>::Domain,
+    tree: MerkleTreeWrapper<
+        Tree::Hasher,
+        Tree::Store,
+        Tree::Arity,
+        Tree::SubTreeArity,
+        Tree::TopTreeArity,
+    >,
+}
+
+/// A long-lived PoSt prover for a single `PoStConfig` and prover.
+///
+/// The Groth and public parameters are set up when the prover is created, and each sector's
+/// tree_r_last is opened once, when the sector is added, so that repeated calls to
+/// `generate_winning_post` or `generate_window_post` only pay for the proving itself.
+pub struct PoStProver<Tree: 'static + MerkleTreeTrait> {
+    post_config: PoStConfig,
+    prover_id: ProverId,
+    prover_id_safe: <Tree::Hasher as Hasher>::Domain,
+    groth_params: Arc<MappedParameters<Bls12>>,
+    // The compound public params are tied to the lifetime of the private inputs, so only their
+    // vanilla part is kept, together with `post_config.priority`.
+    vanilla_params: fallback::PublicParams,
+    sectors: BTreeMap<SectorId, ProverSector<Tree>>,
+}
+
+impl<Tree: 'static + MerkleTreeTrait> PoStProver<Tree> {
+    /// Creates a prover without any sectors, loading the Groth parameters and setting up the
+    /// public parameters for `post_config`.
+    pub fn new(post_config: &PoStConfig, prover_id: ProverId) -> Result<Self> {
+        info!("PoStProver::new:start");
+
+        let prover_id_safe = as_safe_commitment(&prover_id, "prover_id")?;
+        let groth_params = get_post_params::<Tree>(post_config)?;
+
+        let vanilla_params = match post_config.typ {
+            PoStType::Winning => winning_post_setup_params(post_config)?,
+            PoStType::Window => window_post_setup_params(post_config),
+        };
+        let setup_params = compound_proof::SetupParams {
+            vanilla_params,
+            partitions: None,
+            priority: post_config.priority,
+        };
+        let pub_params: compound_proof::PublicParams<'_, FallbackPoSt<'_, Tree>> =
+            FallbackPoStCompound::setup(&setup_params)?;
+
+        info!("PoStProver::new:finish");
+        Ok(PoStProver {
+            post_config: post_config.clone(),
+            prover_id,
+            prover_id_safe,
+            groth_params,
+            vanilla_params: pub_params.vanilla_params,
+            sectors: BTreeMap::new(),
+        })
+    }
+
+    pub fn post_config(&self) -> &PoStConfig {
+        &self.post_config
+    }
+
+    pub fn prover_id(&self) -> ProverId {
+        self.prover_id
+    }
+
+    /// Opens the tree_r_last of `replica` and adds it to the prover, replacing any sector
+    /// already held under `sector_id`.
+    pub fn add_sector(
+        &mut self,
+        sector_id: SectorId,
+        replica: PrivateReplicaInfo<Tree>,
+    ) -> Result<()> {
+        let comm_r = replica
+            .safe_comm_r()
+            .with_context(|| format!("add_sector: safe_comm_r failed: {:?}", sector_id))?;
+        let tree = replica
+            .merkle_tree(self.post_config.sector_size)
+            .with_context(|| format!("add_sector: merkle_tree failed: {:?}", sector_id))?;
+
+        self.sectors.insert(
+            sector_id,
+            ProverSector {
+                replica,
+                comm_r,
+                tree,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Removes a sector from the prover, closing its tree. Returns the sector's replica info,
+    /// if it was held.
+    pub fn remove_sector(&mut self, sector_id: &SectorId) -> Option<PrivateReplicaInfo<Tree>> {
+        self.sectors.remove(sector_id).map(|sector| sector.replica)
+    }
+
+    pub fn contains_sector(&self, sector_id: &SectorId) -> bool {
+        self.sectors.contains_key(sector_id)
+    }
+
+    /// The ids of all sectors held by the prover, in ascending order.
+    pub fn sector_ids(&self) -> Vec<SectorId> {
+        self.sectors.keys().copied().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sectors.is_empty()
+    }
+
+    /// Generates a Winning proof-of-spacetime over the challenged `sector_ids`, which must all
+    /// have been added to the prover.
+    ///
+    /// `sector_ids` must be selected with `generate_winning_post_sector_challenge`, exactly as
+    /// for `generate_winning_post`.
+    pub fn generate_winning_post(
+        &self,
+        randomness: &ChallengeSeed,
+        sector_ids: &[SectorId],
+    ) -> Result<SnarkProof> {
+        info!("PoStProver::generate_winning_post:start");
+        ensure!(
+            self.post_config.typ == PoStType::Winning,
+            "invalid post config type"
+        );
+        ensure!(
+            sector_ids.len() == self.post_config.sector_count,
+            "invalid amount of sectors"
+        );
+
+        let randomness_safe: <Tree::Hasher as Hasher>::Domain =
+            as_safe_commitment(randomness, "randomness")?;
+
+        let param_sector_count = self.vanilla_params.sector_count;
+        let pub_params: compound_proof::PublicParams<'_, FallbackPoSt<'_, Tree>> =
+            compound_proof::PublicParams {
+                vanilla_params: self.vanilla_params.clone(),
+                partitions: None,
+                priority: self.post_config.priority,
+            };
+
+        let sectors = sector_ids
+            .iter()
+            .map(|sector_id| self.sector(sector_id))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut pub_sectors = Vec::with_capacity(param_sector_count);
+        let mut priv_sectors = Vec::with_capacity(param_sector_count);
+
+        for _ in 0..param_sector_count {
+            for (sector_id, sector) in sector_ids.iter().zip(sectors.iter()) {
+                pub_sectors.push(PublicSector::<<Tree::Hasher as Hasher>::Domain> {
+                    id: *sector_id,
+                    comm_r: sector.comm_r,
+                });
+                priv_sectors.push(PrivateSector {
+                    tree: &sector.tree,
+                    comm_c: sector.replica.safe_comm_c(),
+                    comm_r_last: sector.replica.safe_comm_r_last(),
+                });
+            }
+        }
+
+        let pub_inputs = fallback::PublicInputs::<<Tree::Hasher as Hasher>::Domain> {
+            randomness: randomness_safe,
+            prover_id: self.prover_id_safe,
+            sectors: pub_sectors,
+            k: None,
+        };
+
+        let priv_inputs = fallback::PrivateInputs::<Tree> {
+            sectors: &priv_sectors,
+        };
+
+        let proof = FallbackPoStCompound::<Tree>::prove(
+            &pub_params,
+            &pub_inputs,
+            &priv_inputs,
+            &self.groth_params,
+        )?;
+        let proof = proof.to_vec()?;
+
+        info!("PoStProver::generate_winning_post:finish");
+
+        Ok(proof)
+    }
+
+    /// Generates a Window proof-of-spacetime over all sectors held by the prover, equivalent to
+    /// calling `generate_window_post` with the same set of replicas.
+    pub fn generate_window_post(&self, randomness: &ChallengeSeed) -> Result<SnarkProof> {
+        info!("PoStProver::generate_window_post:start");
+        ensure!(
+            self.post_config.typ == PoStType::Window,
+            "invalid post config type"
+        );
+        ensure!(!self.sectors.is_empty(), "no sectors to prove");
+
+        let randomness_safe = as_safe_commitment(randomness, "randomness")?;
+
+        let partitions = get_partitions_for_window_post(self.sectors.len(), &self.post_config);
+
+        let sector_count = self.vanilla_params.sector_count;
+        let pub_params: compound_proof::PublicParams<'_, FallbackPoSt<'_, Tree>> =
+            compound_proof::PublicParams {
+                vanilla_params: self.vanilla_params.clone(),
+                partitions,
+                priority: self.post_config.priority,
+            };
+
+        let mut pub_sectors = Vec::with_capacity(sector_count);
+        let mut priv_sectors = Vec::with_capacity(sector_count);
+
+        for (sector_id, sector) in self.sectors.iter() {
+            pub_sectors.push(PublicSector {
+                id: *sector_id,
+                comm_r: sector.comm_r,
+            });
+            priv_sectors.push(PrivateSector {
+                tree: &sector.tree,
+                comm_c: sector.replica.safe_comm_c(),
+                comm_r_last: sector.replica.safe_comm_r_last(),
+            });
+        }
+
+        let pub_inputs = fallback::PublicInputs {
+            randomness: randomness_safe,
+            prover_id: self.prover_id_safe,
+            sectors: pub_sectors,
+            k: None,
+        };
+
+        let priv_inputs = fallback::PrivateInputs::<Tree> {
+            sectors: &priv_sectors,
+        };
+
+        let proof = FallbackPoStCompound::prove(
+            &pub_params,
+            &pub_inputs,
+            &priv_inputs,
+            &self.groth_params,
+        )?;
+
+        info!("PoStProver::generate_window_post:finish");
+
+        proof.to_vec()
+    }
+
+    fn sector(&self, sector_id: &SectorId) -> Result<&ProverSector<Tree>> {
+        self.sectors
+            .get(sector_id)
+            .with_context(|| format!("sector {:?} has not been added to the prover", sector_id))
+    }
+}
@@ -1,28 +1,45 @@
 use std::collections::BTreeMap;
+#[cfg(feature = "prover")]
 use std::fs;
+#[cfg(feature = "prover")]
 use std::path::Path;
 
-use anyhow::{anyhow, ensure, Context, Result};
+#[cfg(feature = "prover")]
+use anyhow::Context;
+use anyhow::{anyhow, ensure, Result};
+#[cfg(feature = "prover")]
 use bincode::deserialize;
 use filecoin_hashers::Hasher;
 use log::{info, trace};
+#[cfg(feature = "prover")]
+use storage_proofs_core::cache_key::CacheKey;
 use storage_proofs_core::{
-    cache_key::CacheKey, merkle::MerkleTreeTrait, proof::ProofScheme, sector::SectorId,
+    compound_proof::{self, CompoundProof},
+    merkle::MerkleTreeTrait,
+    multi_proof::MultiProof,
+    proof::ProofScheme,
+    sector::SectorId,
+};
+use storage_proofs_post::fallback::{
+    self, generate_leaf_challenge, FallbackPoSt, FallbackPoStCompound, SectorProof,
 };
-use storage_proofs_post::fallback::{self, generate_leaf_challenge, FallbackPoSt, SectorProof};
 
+#[cfg(feature = "prover")]
 use crate::{
-    api::as_safe_commitment,
+    api::metrics::label_sector,
     constants::DefaultPieceHasher,
-    types::{
-        ChallengeSeed, FallbackPoStSectorProof, PoStConfig, PrivateReplicaInfo, ProverId,
-        TemporaryAux, VanillaProof,
-    },
+    types::{PrivateReplicaInfo, TemporaryAux},
+};
+use crate::{
+    api::{as_safe_commitment, spans::api_span},
+    types::{ChallengeSeed, FallbackPoStSectorProof, PoStConfig, ProverId, VanillaProof},
     PoStType,
 };
 
 // Ensure that any associated cached data persisted is discarded.
+#[cfg(feature = "prover")]
 pub fn clear_cache<Tree: MerkleTreeTrait>(cache_dir: &Path) -> Result<()> {
+    let _span = api_span("clear_cache").entered();
     info!("clear_cache:start");
 
     let t_aux = {
@@ -41,9 +58,11 @@ pub fn clear_cache<Tree: MerkleTreeTrait>(cache_dir: &Path) -> Result<()> {
 }
 
 // Ensure that any associated cached data persisted is discarded.
+#[cfg(feature = "prover")]
 pub fn clear_caches<Tree: MerkleTreeTrait>(
     replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
 ) -> Result<()> {
+    let _span = api_span("clear_caches").entered();
     info!("clear_caches:start");
 
     for replica in replicas.values() {
@@ -61,8 +80,12 @@ pub fn generate_fallback_sector_challenges<Tree: 'static + MerkleTreeTrait>(
     post_config: &PoStConfig,
     randomness: &ChallengeSeed,
     pub_sectors: &[SectorId],
-    _prover_id: ProverId,
+    prover_id: ProverId,
 ) -> Result<BTreeMap<SectorId, Vec<u64>>> {
+    let _span = api_span("generate_fallback_sector_challenges")
+        .post_config(post_config)
+        .prover_id(&prover_id)
+        .entered();
     info!("generate_sector_challenges:start");
     ensure!(
         post_config.typ == PoStType::Window || post_config.typ == PoStType::Winning,
@@ -122,13 +145,19 @@ pub fn generate_fallback_sector_challenges<Tree: 'static + MerkleTreeTrait>(
 
 /// Generates a single vanilla proof required for either Window proof-of-spacetime
 /// or Winning proof-of-spacetime.
+#[cfg(feature = "prover")]
 pub fn generate_single_vanilla_proof<Tree: 'static + MerkleTreeTrait>(
     post_config: &PoStConfig,
     sector_id: SectorId,
     replica: &PrivateReplicaInfo<Tree>,
     challenges: &[u64],
 ) -> Result<FallbackPoStSectorProof<Tree>> {
+    let _span = api_span("generate_single_vanilla_proof")
+        .post_config(post_config)
+        .sector_id(sector_id)
+        .entered();
     info!("generate_single_vanilla_proof:start: {:?}", sector_id);
+    let _metric_labels = label_sector(post_config.sector_size, Some(sector_id));
 
     let tree = &replica
         .merkle_tree(post_config.sector_size)
@@ -184,6 +213,9 @@ pub fn partition_vanilla_proofs<Tree: MerkleTreeTrait>(
     partition_count: usize,
     vanilla_proofs: &[FallbackPoStSectorProof<Tree>],
 ) -> Result<Vec<VanillaProof<Tree>>> {
+    let _span = api_span("partition_vanilla_proofs")
+        .post_config(post_config)
+        .entered();
     info!("partition_vanilla_proofs:start");
     ensure!(
         post_config.typ == PoStType::Window || post_config.typ == PoStType::Winning,
@@ -308,3 +340,75 @@ pub(crate) fn get_partitions_for_window_post(
         None
     }
 }
+
+/// Batch verifies `multi_proofs` against `pub_inputs` in a single pairing check. If the batch
+/// fails, it is split in halves until the invalid proofs are isolated. Returns whether each
+/// proof is valid.
+pub(crate) fn batch_verify_post<Tree: 'static + MerkleTreeTrait>(
+    pub_params: &compound_proof::PublicParams<'_, FallbackPoSt<'_, Tree>>,
+    pub_inputs: &[fallback::PublicInputs<<Tree::Hasher as Hasher>::Domain>],
+    multi_proofs: &[MultiProof<'_>],
+    requirements: &fallback::ChallengeRequirements,
+) -> Result<Vec<bool>> {
+    ensure!(
+        pub_inputs.len() == multi_proofs.len(),
+        "Inconsistent inputs"
+    );
+
+    let mut valid = vec![false; multi_proofs.len()];
+    batch_verify_post_inner::<Tree>(
+        pub_params,
+        pub_inputs,
+        multi_proofs,
+        requirements,
+        &mut valid,
+    )?;
+
+    Ok(valid)
+}
+
+fn batch_verify_post_inner<Tree: 'static + MerkleTreeTrait>(
+    pub_params: &compound_proof::PublicParams<'_, FallbackPoSt<'_, Tree>>,
+    pub_inputs: &[fallback::PublicInputs<<Tree::Hasher as Hasher>::Domain>],
+    multi_proofs: &[MultiProof<'_>],
+    requirements: &fallback::ChallengeRequirements,
+    valid: &mut [bool],
+) -> Result<()> {
+    if multi_proofs.is_empty() {
+        return Ok(());
+    }
+
+    if FallbackPoStCompound::<Tree>::batch_verify(
+        pub_params,
+        pub_inputs,
+        multi_proofs,
+        requirements,
+    )? {
+        valid.iter_mut().for_each(|v| *v = true);
+        return Ok(());
+    }
+    if multi_proofs.len() == 1 {
+        return Ok(());
+    }
+
+    trace!(
+        "batch of {} PoSt proofs failed, splitting",
+        multi_proofs.len()
+    );
+    let mid = multi_proofs.len() / 2;
+    let (valid_left, valid_right) = valid.split_at_mut(mid);
+    batch_verify_post_inner::<Tree>(
+        pub_params,
+        &pub_inputs[..mid],
+        &multi_proofs[..mid],
+        requirements,
+        valid_left,
+    )?;
+    batch_verify_post_inner::<Tree>(
+        pub_params,
+        &pub_inputs[mid..],
+        &multi_proofs[mid..],
+        requirements,
+        valid_right,
+    )
+}
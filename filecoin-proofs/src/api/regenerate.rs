@@ -0,0 +1,466 @@
+use std::fs::{self, metadata, File};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use filecoin_hashers::{Domain, HashFunction, Hasher};
+use generic_array::typenum::{Unsigned, U0};
+use log::{info, trace, warn};
+use memmap::MmapOptions;
+use merkletree::store::{ReplicaConfig, StoreConfig};
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSlice};
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    merkle::{
+        create_disk_tree, create_lc_tree, get_base_tree_count, split_config,
+        split_config_and_replica, DiskTree, LCTree, MerkleTreeTrait,
+    },
+    util::{default_rows_to_discard, NODE_SIZE},
+};
+use storage_proofs_porep::stacked::{PersistentAux, StackedDrg};
+
+use crate::{
+    api::{
+        as_safe_commitment, commitment_from_fr, get_base_tree_leafs, get_base_tree_size, get_p_aux,
+        persist_p_aux, spans::api_span,
+    },
+    constants::{DefaultPieceHasher, LAYERS},
+    parameters::public_params,
+    types::{Commitment, PaddedBytesAmount, PoRepConfig, PoRepProofPartitions, SectorSize},
+};
+
+/// Rebuilds the tree_r_last stores of the sealed replica at `replica_path` into `cache_path`,
+/// replacing any existing ones, and returns the rebuilt comm_r_last.
+///
+/// # Arguments
+///
+/// * `sector_size` - size of the sealed sector.
+/// * `cache_path` - directory where the tree_r_last stores will be written.
+/// * `replica_path` - path to the sealed replica.
+pub fn regenerate_tree_r_last<Tree: 'static + MerkleTreeTrait>(
+    sector_size: SectorSize,
+    cache_path: &Path,
+    replica_path: &Path,
+) -> Result<Commitment> {
+    let _span = api_span("regenerate_tree_r_last")
+        .sector_size(sector_size)
+        .entered();
+    info!("regenerate_tree_r_last:start");
+
+    let tree_count = get_base_tree_count::<Tree>();
+    let comm_r_last = build_tree_r_last_with_id::<Tree>(
+        sector_size,
+        cache_path,
+        replica_path,
+        &regenerated_id(CacheKey::CommRLastTree),
+    )?;
+    replace_stores(cache_path, CacheKey::CommRLastTree, tree_count)?;
+
+    info!("regenerate_tree_r_last:finish");
+    Ok(commitment_from_fr(comm_r_last.into()))
+}
+
+/// Rebuilds the tree_c stores in `cache_path` from the layer labels persisted there, replacing
+/// any existing ones, and returns the rebuilt comm_c.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration the sector was sealed with.
+/// * `cache_path` - directory holding the sector's layer labels.
+pub fn regenerate_tree_c<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: &Path,
+) -> Result<Commitment> {
+    let _span = api_span("regenerate_tree_c")
+        .porep_config(&porep_config)
+        .entered();
+    info!("regenerate_tree_c:start");
+
+    let comm_c = build_tree_c::<Tree>(
+        porep_config,
+        cache_path,
+        &regenerated_id(CacheKey::CommCTree),
+    )?;
+    replace_stores(
+        cache_path,
+        CacheKey::CommCTree,
+        get_base_tree_count::<Tree>(),
+    )?;
+
+    info!("regenerate_tree_c:finish");
+    Ok(commitment_from_fr(comm_c.into()))
+}
+
+/// Regenerates the cache files needed to generate PoSts over a sealed sector, and checks
+/// the result against the sector's known `comm_r`.
+///
+/// tree_r_last is always rebuilt from the replica. comm_c is taken from tree_c, which is
+/// rebuilt from the layer labels when they are still in the cache, and otherwise read from
+/// the existing tree_c stores or, as a last resort, from an existing `p_aux`. The trees are
+/// rebuilt next to the existing stores, which are only replaced, along with `p_aux`, once the
+/// resulting roots match `comm_r`.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration the sector was sealed with.
+/// * `cache_path` - the sector's cache directory.
+/// * `replica_path` - path to the sealed replica.
+/// * `comm_r` - the sector's replica commitment.
+pub fn regenerate_sector_cache<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: &Path,
+    replica_path: &Path,
+    comm_r: Commitment,
+) -> Result<()> {
+    let _span = api_span("regenerate_sector_cache")
+        .porep_config(&porep_config)
+        .entered();
+    info!("regenerate_sector_cache:start");
+
+    ensure!(
+        metadata(cache_path)?.is_dir(),
+        "cache_path must be a directory"
+    );
+    ensure!(
+        metadata(replica_path)?.is_file(),
+        "replica_path must be a file"
+    );
+    let comm_r_safe: <Tree::Hasher as Hasher>::Domain = as_safe_commitment(&comm_r, "comm_r")?;
+
+    let tree_count = get_base_tree_count::<Tree>();
+    let comm_r_last = build_tree_r_last_with_id::<Tree>(
+        porep_config.sector_size,
+        cache_path,
+        replica_path,
+        &regenerated_id(CacheKey::CommRLastTree),
+    )?;
+    let mut rebuilt = vec![CacheKey::CommRLastTree];
+    let (comm_c, tree_c_rebuilt) = recover_comm_c::<Tree>(porep_config, cache_path)?;
+    if tree_c_rebuilt {
+        rebuilt.push(CacheKey::CommCTree);
+    }
+
+    if <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &comm_r_last) != comm_r_safe {
+        for key in rebuilt {
+            remove_stores(cache_path, key, tree_count)?;
+        }
+        bail!("regenerated cache does not match comm_r");
+    }
+
+    for key in rebuilt {
+        replace_stores(cache_path, key, tree_count)?;
+    }
+    persist_p_aux::<Tree>(
+        &PersistentAux {
+            comm_c,
+            comm_r_last,
+        },
+        cache_path,
+    )?;
+
+    info!("regenerate_sector_cache:finish");
+    Ok(())
+}
+
+/// Checks that the PoSt-critical cache files of a sealed sector are consistent with its
+/// `comm_r`: `p_aux` must hash to `comm_r`, and the root of the tree_r_last stores on disk
+/// must match the comm_r_last recorded in `p_aux`. Returns `false` if any of them is missing.
+///
+/// # Arguments
+///
+/// * `sector_size` - size of the sealed sector.
+/// * `cache_path` - the sector's cache directory.
+/// * `replica_path` - path to the sealed replica.
+/// * `comm_r` - the sector's replica commitment.
+pub fn verify_sector_cache<Tree: 'static + MerkleTreeTrait>(
+    sector_size: SectorSize,
+    cache_path: &Path,
+    replica_path: &Path,
+    comm_r: Commitment,
+) -> Result<bool> {
+    let _span = api_span("verify_sector_cache")
+        .sector_size(sector_size)
+        .entered();
+    info!("verify_sector_cache:start");
+
+    let comm_r_safe: <Tree::Hasher as Hasher>::Domain = as_safe_commitment(&comm_r, "comm_r")?;
+    let (configs, replica_config) = tree_r_last_configs::<Tree>(
+        sector_size,
+        cache_path,
+        replica_path,
+        &CacheKey::CommRLastTree.to_string(),
+    )?;
+    let missing_store = configs
+        .iter()
+        .find(|config| !StoreConfig::data_path(&config.path, &config.id).exists());
+
+    let is_valid = if !cache_path.join(CacheKey::PAux.to_string()).exists() {
+        warn!("p_aux is missing");
+        false
+    } else if let Some(config) = missing_store {
+        warn!("tree_r_last store {} is missing", config.id);
+        false
+    } else {
+        let p_aux = get_p_aux::<Tree>(cache_path)?;
+        if <Tree::Hasher as Hasher>::Function::hash2(&p_aux.comm_c, &p_aux.comm_r_last)
+            != comm_r_safe
+        {
+            warn!("p_aux does not match comm_r");
+            false
+        } else {
+            let tree_r_last = create_lc_tree::<
+                LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+            >(
+                get_base_tree_size::<Tree>(sector_size)?,
+                &configs,
+                &replica_config,
+            )?;
+
+            if tree_r_last.root() != p_aux.comm_r_last {
+                warn!("tree_r_last does not match p_aux");
+                false
+            } else {
+                true
+            }
+        }
+    };
+
+    info!("verify_sector_cache:finish");
+    Ok(is_valid)
+}
+
+/// The id the stores of `key` are rebuilt with, before they replace the existing ones.
+fn regenerated_id(key: CacheKey) -> String {
+    format!("regenerated-{}", key)
+}
+
+/// Returns the configs of the sub-tree stores with the given id in `cache_path`.
+fn sub_tree_configs(cache_path: &Path, id: &str, tree_count: usize) -> Result<Vec<StoreConfig>> {
+    split_config(StoreConfig::new(cache_path, id, 0), tree_count)
+}
+
+/// Replaces the sub-tree stores of `key` in `cache_path` with the ones rebuilt next to them.
+/// The completion markers the tree builders write next to the stores are moved along, and stale
+/// ones are removed.
+fn replace_stores(cache_path: &Path, key: CacheKey, tree_count: usize) -> Result<()> {
+    let rebuilt = sub_tree_configs(cache_path, &regenerated_id(key), tree_count)?;
+    let existing = sub_tree_configs(cache_path, &key.to_string(), tree_count)?;
+    for (from, to) in rebuilt.iter().zip(existing.iter()) {
+        let from_path = StoreConfig::data_path(&from.path, &from.id);
+        let to_path = StoreConfig::data_path(&to.path, &to.id);
+        fs::rename(&from_path, &to_path)
+            .with_context(|| format!("could not replace {:?} with {:?}", to_path, from_path))?;
+
+        let from_marker = marker_path(&from_path);
+        let to_marker = marker_path(&to_path);
+        if from_marker.exists() {
+            fs::rename(&from_marker, &to_marker)
+                .with_context(|| format!("could not replace {:?}", to_marker))?;
+        } else if to_marker.exists() {
+            fs::remove_file(&to_marker)
+                .with_context(|| format!("could not remove {:?}", to_marker))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the sub-tree stores of `key` rebuilt in `cache_path`, and their completion markers.
+fn remove_stores(cache_path: &Path, key: CacheKey, tree_count: usize) -> Result<()> {
+    for config in sub_tree_configs(cache_path, &regenerated_id(key), tree_count)? {
+        let path = StoreConfig::data_path(&config.path, &config.id);
+        for path in &[marker_path(&path), path] {
+            if path.exists() {
+                fs::remove_file(path).with_context(|| format!("could not remove {:?}", path))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The completion marker the tree builders write next to a sub-tree store once it is complete.
+fn marker_path(store_path: &Path) -> PathBuf {
+    let mut path = store_path.as_os_str().to_owned();
+    path.push(".done");
+    path.into()
+}
+
+fn tree_r_last_configs<Tree: MerkleTreeTrait>(
+    sector_size: SectorSize,
+    cache_path: &Path,
+    replica_path: &Path,
+    id: &str,
+) -> Result<(Vec<StoreConfig>, ReplicaConfig)> {
+    let base_tree_size = get_base_tree_size::<Tree>(sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+
+    let mut config = StoreConfig::new(
+        cache_path,
+        id,
+        default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize()),
+    );
+    config.size = Some(base_tree_size);
+
+    split_config_and_replica(
+        config,
+        replica_path.to_path_buf(),
+        base_tree_leafs,
+        get_base_tree_count::<Tree>(),
+    )
+}
+
+/// Builds the tree_r_last stores over the replica at `replica_path`, replacing any existing
+/// ones in `cache_path`, and returns comm_r_last.
+pub(crate) fn build_tree_r_last<Tree: 'static + MerkleTreeTrait>(
+    sector_size: SectorSize,
+    cache_path: &Path,
+    replica_path: &Path,
+) -> Result<<Tree::Hasher as Hasher>::Domain> {
+    build_tree_r_last_with_id::<Tree>(
+        sector_size,
+        cache_path,
+        replica_path,
+        &CacheKey::CommRLastTree.to_string(),
+    )
+}
+
+/// Like `build_tree_r_last`, but writes the stores with the id `id`.
+fn build_tree_r_last_with_id<Tree: 'static + MerkleTreeTrait>(
+    sector_size: SectorSize,
+    cache_path: &Path,
+    replica_path: &Path,
+    id: &str,
+) -> Result<<Tree::Hasher as Hasher>::Domain> {
+    let base_tree_size = get_base_tree_size::<Tree>(sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+    let tree_count = get_base_tree_count::<Tree>();
+
+    let (configs, replica_config) =
+        tree_r_last_configs::<Tree>(sector_size, cache_path, replica_path, id)?;
+
+    let f_replica = File::open(replica_path)
+        .with_context(|| format!("could not open replica_path={:?}", replica_path))?;
+    let replica = unsafe {
+        MmapOptions::new()
+            .map(&f_replica)
+            .with_context(|| format!("could not mmap replica_path={:?}", replica_path))?
+    };
+    ensure!(
+        replica.len() == usize::from(PaddedBytesAmount::from(sector_size)),
+        "replica_path={:?} does not match the sector size",
+        replica_path
+    );
+
+    for (i, config) in configs.iter().enumerate() {
+        // Remove the tree_r_last store if it exists already
+        let tree_r_last_store_path = StoreConfig::data_path(&config.path, &config.id);
+        if tree_r_last_store_path.exists() {
+            fs::remove_file(&tree_r_last_store_path).with_context(|| {
+                format!(
+                    "could not remove tree_r_last store {:?}",
+                    tree_r_last_store_path
+                )
+            })?;
+        }
+
+        let offset = replica_config.offsets[i];
+        let data = &replica[offset..offset + base_tree_leafs * NODE_SIZE];
+
+        // The parallel iterator building the tree cannot return errors, so the nodes are
+        // validated up front and converted without further checks below.
+        data.par_chunks(NODE_SIZE)
+            .enumerate()
+            .try_for_each(|(j, node_bytes)| -> Result<()> {
+                <Tree::Hasher as Hasher>::Domain::try_from_bytes(node_bytes).with_context(
+                    || format!("invalid node {} of tree_r_last {}/{}", j, i + 1, tree_count),
+                )?;
+                Ok(())
+            })?;
+        let nodes = data
+            .par_chunks(NODE_SIZE)
+            .map(<Tree::Hasher as Hasher>::Domain::from_slice);
+
+        trace!("building base tree_r_last {}/{}", i + 1, tree_count);
+        LCTree::<Tree::Hasher, Tree::Arity, U0, U0>::from_par_iter_with_config(
+            nodes,
+            config.clone(),
+        )
+        .with_context(|| format!("failed tree_r_last {}/{}", i + 1, tree_count))?;
+    }
+
+    let tree_r_last = create_lc_tree::<
+        LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    >(base_tree_size, &configs, &replica_config)?;
+
+    Ok(tree_r_last.root())
+}
+
+/// Rebuilds tree_c from the layer labels in `cache_path`, writing the stores with the id `id`.
+fn build_tree_c<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: &Path,
+    id: &str,
+) -> Result<<Tree::Hasher as Hasher>::Domain> {
+    let pp = public_params::<Tree>(
+        PaddedBytesAmount::from(porep_config),
+        usize::from(PoRepProofPartitions::from(porep_config)),
+        porep_config.porep_id,
+        porep_config.api_version,
+    )?;
+    let config = StoreConfig::new(cache_path, CacheKey::CommCTree.to_string(), 0);
+
+    StackedDrg::<Tree, DefaultPieceHasher>::regenerate_tree_c_with_id(&pp, config, id)
+}
+
+/// Returns comm_c, and whether tree_c was rebuilt next to the existing stores to get it.
+fn recover_comm_c<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: &Path,
+) -> Result<(<Tree::Hasher as Hasher>::Domain, bool)> {
+    let layers = *LAYERS
+        .read()
+        .expect("LAYERS poisoned")
+        .get(&u64::from(porep_config.sector_size))
+        .context("unknown sector size")?;
+    let cache_path_buf = cache_path.to_path_buf();
+    let labels_present = (1..=layers).all(|layer| {
+        StoreConfig::data_path(&cache_path_buf, &CacheKey::label_layer(layer)).exists()
+    });
+    if labels_present {
+        info!("rebuilding tree_c from layer labels");
+        let comm_c = build_tree_c::<Tree>(
+            porep_config,
+            cache_path,
+            &regenerated_id(CacheKey::CommCTree),
+        )?;
+        return Ok((comm_c, true));
+    }
+
+    let base_tree_size = get_base_tree_size::<Tree>(porep_config.sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+    let mut tree_c_config = StoreConfig::new(
+        cache_path,
+        CacheKey::CommCTree.to_string(),
+        default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize()),
+    );
+    tree_c_config.size = Some(base_tree_size);
+    let configs = split_config(tree_c_config, get_base_tree_count::<Tree>())?;
+
+    if configs
+        .iter()
+        .all(|config| StoreConfig::data_path(&config.path, &config.id).exists())
+    {
+        info!("layer labels missing, reading comm_c from tree_c");
+        let tree_c = create_disk_tree::<
+            DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+        >(base_tree_size, &configs)?;
+        return Ok((tree_c.root(), false));
+    }
+
+    if cache_path.join(CacheKey::PAux.to_string()).exists() {
+        info!("layer labels and tree_c missing, reading comm_c from p_aux");
+        return Ok((get_p_aux::<Tree>(cache_path)?.comm_c, false));
+    }
+
+    bail!("cannot recover comm_c: layer labels, tree_c and p_aux are all missing")
+}
@@ -0,0 +1,404 @@
+use std::cmp::min;
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom};
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use bellperson::bls::Fr;
+use byteorder::{BigEndian, ReadBytesExt};
+use merkletree::{merkle::get_merkle_tree_cache_size, store::StoreConfig};
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    merkle::{get_base_tree_count, MerkleTreeTrait},
+    settings::SETTINGS,
+    util::{default_rows_to_discard, NODE_SIZE},
+};
+use storage_proofs_porep::stacked::{pipelined_tree_builder_memory, tree_builder_batch_size};
+use typenum::Unsigned;
+
+use crate::{
+    api::{get_base_tree_leafs, get_base_tree_size},
+    constants::{DefaultBinaryTree, DRG_DEGREE, EXP_DEGREE, LAYERS},
+    parameters::setup_params,
+    types::{PaddedBytesAmount, PoRepConfig, PoRepProofPartitions, PoStConfig, PoStType},
+};
+
+/// The size of a SHA-256 block, which the multicore SDR ring buffer holds per node besides the
+/// parent labels.
+const SHA_BLOCK_SIZE: usize = 64;
+
+/// The sizes of the uncompressed G1 and G2 points in Groth parameter files.
+const G1_SIZE: u64 = 96;
+const G2_SIZE: u64 = 192;
+
+/// A phase of sealing or proving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    PreCommit1,
+    PreCommit2,
+    Commit1,
+    Commit2,
+    WinningPoSt,
+    WindowPoSt,
+}
+
+/// A file written to the cache directory of a sector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheFile {
+    /// The name of the file within the cache directory.
+    pub name: String,
+    pub size: u64,
+}
+
+/// A Groth parameter or verifying key file a phase loads from the parameter cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterFile {
+    pub path: PathBuf,
+    /// The size of the file, or `None` if it is not in the parameter cache yet.
+    pub size: Option<u64>,
+}
+
+/// The resources a phase is expected to need.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhaseResources {
+    pub phase: Phase,
+    /// The expected peak memory in bytes. It covers the buffers and memory mapped windows the
+    /// phase works on, and for the phases which prove, the Groth parameters and the assignments
+    /// and evaluation domains of the circuits of all partitions. `None` if it cannot be computed,
+    /// which is the case for those phases if their Groth parameters are not in the parameter
+    /// cache, as the size of the circuits is read from them.
+    pub peak_memory: Option<u64>,
+    /// The files the phase writes to the cache directory of the sector.
+    pub cache_files: Vec<CacheFile>,
+    /// The Groth parameters and verifying key the phase loads, if it proves.
+    pub parameter_files: Vec<ParameterFile>,
+}
+
+impl PhaseResources {
+    /// Returns the scratch disk space the phase takes in the cache directory.
+    pub fn scratch_disk(&self) -> u64 {
+        self.cache_files.iter().map(|file| file.size).sum()
+    }
+}
+
+/// The resources sealing a sector is expected to need.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SealResources {
+    /// The resources of each phase, in the order they run.
+    pub phases: Vec<PhaseResources>,
+    /// The size of the parent cache, which is shared by all sectors of the same size.
+    pub parent_cache_size: u64,
+    /// The size of the sealed replica.
+    pub replica_size: u64,
+    /// The size of the cache directory after `clear_cache`, not counting `p_aux` and `t_aux`,
+    /// which take a few hundred bytes.
+    pub final_cache_size: u64,
+}
+
+impl SealResources {
+    /// Returns the largest size the cache directory reaches, before `clear_cache`.
+    pub fn peak_cache_size(&self) -> u64 {
+        self.phases.iter().map(PhaseResources::scratch_disk).sum()
+    }
+}
+
+/// Returns the resources sealing a sector with the given config is expected to need with the
+/// current settings, such as `rows_to_discard`, `use_multicore_sdr` and the tree builder
+/// settings. The sizes of the Groth parameters are taken from the parameter cache.
+pub fn seal_resources<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<SealResources> {
+    let sector_size = u64::from(porep_config.sector_size);
+    let layers = *LAYERS
+        .read()
+        .expect("LAYERS poisoned")
+        .get(&sector_size)
+        .with_context(|| format!("unknown sector size {}", sector_size))?;
+
+    let nodes_count = sector_size as usize / NODE_SIZE;
+    let degree = DRG_DEGREE + EXP_DEGREE;
+    let parent_cache_size = (nodes_count * degree * size_of::<u32>()) as u64;
+
+    let pre_commit1 = pre_commit1_resources(porep_config, nodes_count, layers, degree)?;
+    let pre_commit2 = pre_commit2_resources::<Tree>(porep_config, layers)?;
+    let final_cache_size = pre_commit2
+        .cache_files
+        .iter()
+        .filter(|file| file.name.contains(&CacheKey::CommRLastTree.to_string()))
+        .map(|file| file.size)
+        .sum();
+
+    let commit1 = commit1_resources::<Tree>(porep_config, nodes_count, layers, degree)?;
+
+    let parameter_files = vec![
+        parameter_file(porep_config.get_cache_params_path::<Tree>()?)?,
+        parameter_file(porep_config.get_cache_verifying_key_path::<Tree>()?)?,
+    ];
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+    let commit2 = PhaseResources {
+        phase: Phase::Commit2,
+        peak_memory: proving_memory(&parameter_files[0], partitions)?,
+        cache_files: Vec::new(),
+        parameter_files,
+    };
+
+    Ok(SealResources {
+        phases: vec![pre_commit1, pre_commit2, commit1, commit2],
+        parent_cache_size,
+        replica_size: sector_size,
+        final_cache_size,
+    })
+}
+
+/// Returns the resources proving a PoSt with the given config is expected to need. The sizes of
+/// the Groth parameters are taken from the parameter cache. For Window PoSt, the peak memory is
+/// that of proving a single partition of `sector_count` sectors.
+pub fn post_resources<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+) -> Result<PhaseResources> {
+    let parameter_files = vec![
+        parameter_file(post_config.get_cache_params_path::<Tree>()?)?,
+        parameter_file(post_config.get_cache_verifying_key_path::<Tree>()?)?,
+    ];
+
+    Ok(PhaseResources {
+        phase: match post_config.typ {
+            PoStType::Winning => Phase::WinningPoSt,
+            PoStType::Window => Phase::WindowPoSt,
+        },
+        peak_memory: proving_memory(&parameter_files[0], 1)?,
+        cache_files: Vec::new(),
+        parameter_files,
+    })
+}
+
+fn pre_commit1_resources(
+    porep_config: PoRepConfig,
+    nodes_count: usize,
+    layers: usize,
+    degree: usize,
+) -> Result<PhaseResources> {
+    let sector_size = u64::from(porep_config.sector_size);
+
+    // Labeling keeps the current and the previous layer in memory, next to a window of the parent
+    // cache. The multicore SDR also fills a ring buffer with the parents of upcoming nodes.
+    let parents_window = min(nodes_count, SETTINGS.sdr_parents_cache_size as usize);
+    let mut peak_memory = 2 * sector_size + (parents_window * degree * size_of::<u32>()) as u64;
+    if SETTINGS.use_multicore_sdr {
+        peak_memory +=
+            (SETTINGS.multicore_sdr_lookahead * (NODE_SIZE * degree + SHA_BLOCK_SIZE)) as u64;
+    }
+
+    let mut cache_files: Vec<CacheFile> = (1..=layers)
+        .map(|layer| CacheFile {
+            name: store_file_name(&CacheKey::label_layer(layer)),
+            size: sector_size,
+        })
+        .collect();
+    let tree_d_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
+    cache_files.push(CacheFile {
+        name: store_file_name(&CacheKey::CommDTree.to_string()),
+        size: (tree_d_size * NODE_SIZE) as u64,
+    });
+
+    Ok(PhaseResources {
+        phase: Phase::PreCommit1,
+        peak_memory: Some(peak_memory),
+        cache_files,
+        parameter_files: Vec::new(),
+    })
+}
+
+fn pre_commit2_resources<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    layers: usize,
+) -> Result<PhaseResources> {
+    let arity = Tree::Arity::to_usize();
+    let tree_count = get_base_tree_count::<Tree>();
+    let tree_len = get_base_tree_size::<Tree>(porep_config.sector_size)?;
+    let nodes_count = get_base_tree_leafs::<Tree>(tree_len)?;
+    let rows_to_discard = default_rows_to_discard(nodes_count, arity);
+    let cache_size = get_merkle_tree_cache_size(nodes_count, arity, rows_to_discard)?;
+
+    let tree_c_files = sub_tree_files(CacheKey::CommCTree, tree_count, tree_len);
+    let tree_r_last_files = sub_tree_files(CacheKey::CommRLastTree, tree_count, cache_size);
+    let cache_files = tree_c_files.chain(tree_r_last_files).collect();
+
+    // The sub-trees are built one at a time. The GPU builders hold two batches, one being read
+    // and one being built, and the sub-tree they write. The CPU builders hold the leaves of a
+    // sub-tree and the sub-tree itself. Batched Poseidon only changes how the column hashes are
+    // computed, not what is held. With direct I/O, the replica is not mapped, so the region the
+    // leaves of tree_r_last are encoded in is read into memory instead, a batch for the GPU and
+    // pipelined builders and the whole sub-tree for the CPU builder, which the leaves term
+    // already covers.
+    let direct_io_nodes = usize::from(SETTINGS.use_direct_io);
+    let gpu_tree_builder =
+        cfg!(feature = "gpu") && (SETTINGS.use_gpu_column_builder || SETTINGS.use_gpu_tree_builder);
+    let tree_memory = if SETTINGS.use_pipelined_tree_builder && !gpu_tree_builder {
+        let batch = tree_builder_batch_size(nodes_count, arity, layers);
+        pipelined_tree_builder_memory(nodes_count, arity, layers)
+            + (batch * direct_io_nodes * NODE_SIZE) as u64
+    } else {
+        let cpu_memory = ((nodes_count + tree_len) * NODE_SIZE) as u64;
+        let tree_c_memory = if cfg!(feature = "gpu") && SETTINGS.use_gpu_column_builder {
+            let batch = min(nodes_count, SETTINGS.max_gpu_column_batch_size as usize);
+            ((2 * batch * layers + tree_len) * NODE_SIZE) as u64
+        } else {
+            cpu_memory
+        };
+        let tree_r_last_memory = if cfg!(feature = "gpu") && SETTINGS.use_gpu_tree_builder {
+            let batch = min(nodes_count, SETTINGS.max_gpu_tree_batch_size as usize);
+            ((2 * batch * (1 + direct_io_nodes) + tree_len) * NODE_SIZE) as u64
+        } else {
+            cpu_memory
+        };
+
+        tree_c_memory.max(tree_r_last_memory)
+    };
+
+    // When an interrupted run is resumed with direct I/O, the replica region of each sub-tree of
+    // tree_r_last which is built again is restored from tree_d in one piece, before the trees
+    // are built.
+    let restore_memory = (direct_io_nodes * nodes_count * NODE_SIZE) as u64;
+
+    Ok(PhaseResources {
+        phase: Phase::PreCommit2,
+        peak_memory: Some(tree_memory.max(restore_memory)),
+        cache_files,
+        parameter_files: Vec::new(),
+    })
+}
+
+fn commit1_resources<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    nodes_count: usize,
+    layers: usize,
+    degree: usize,
+) -> Result<PhaseResources> {
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+    let challenges = partitions
+        * setup_params(
+            PaddedBytesAmount::from(porep_config),
+            partitions,
+            porep_config.porep_id,
+            porep_config.api_version,
+        )?
+        .layer_challenges
+        .challenges_count_all();
+
+    // The trees and labels are read from their stores on disk. What the phase holds are the nodes
+    // of them which the vanilla proofs of each challenge take: the paths to the challenged node
+    // in tree_d and tree_r_last, the columns of the node and its parents with their paths in
+    // tree_c, and the parent labels of the labeling and encoding proofs.
+    let tree_len = get_base_tree_size::<Tree>(porep_config.sector_size)?;
+    let tree_proof = merkle_proof_nodes::<Tree>(get_base_tree_leafs::<Tree>(tree_len)?);
+    let tree_d_proof = merkle_proof_nodes::<DefaultBinaryTree>(nodes_count);
+    let challenge_nodes =
+        tree_d_proof + tree_proof + (1 + degree) * (layers + tree_proof) + (layers + 1) * degree;
+
+    Ok(PhaseResources {
+        phase: Phase::Commit1,
+        peak_memory: Some((challenges * challenge_nodes * NODE_SIZE) as u64),
+        cache_files: Vec::new(),
+        parameter_files: Vec::new(),
+    })
+}
+
+/// Returns the number of nodes an inclusion proof in `Tree` holds, with `leafs` leaves per base
+/// tree: the leaf, the siblings on each level and the root.
+fn merkle_proof_nodes<Tree: MerkleTreeTrait>(leafs: usize) -> usize {
+    let arity = Tree::Arity::to_usize();
+    let mut nodes = 2;
+    let mut width = leafs;
+    while width > 1 {
+        nodes += arity - 1;
+        width /= arity;
+    }
+
+    for &arity in &[
+        Tree::SubTreeArity::to_usize(),
+        Tree::TopTreeArity::to_usize(),
+    ] {
+        if arity > 0 {
+            nodes += arity - 1;
+        }
+    }
+
+    nodes
+}
+
+/// Returns the memory proving `partitions` partitions with the given Groth parameters takes: the
+/// parameters, and for each partition the assignments of the circuit and its evaluation domains.
+/// `None` if the parameters are not in the parameter cache.
+fn proving_memory(parameters: &ParameterFile, partitions: usize) -> Result<Option<u64>> {
+    let size = match parameters.size {
+        Some(size) => size,
+        None => return Ok(None),
+    };
+    let (domain_size, aux) = read_circuit_size(&parameters.path)?;
+
+    // The prover keeps the evaluations of the A, B and C polynomials, over the constraints and
+    // then over the domain, and the values of the auxiliary variables.
+    let partition_memory = (3 * domain_size + aux) * size_of::<Fr>() as u64;
+
+    Ok(Some(size + partitions as u64 * partition_memory))
+}
+
+/// Reads the size of the evaluation domain and the number of auxiliary variables of a circuit
+/// from its Groth parameters. The file starts with the verifying key, followed by the `h` query,
+/// which has one element less than the domain, and the `l` query, which has one element per
+/// auxiliary variable. Each is prefixed with its length.
+fn read_circuit_size(path: &Path) -> Result<(u64, u64)> {
+    let read = || -> io::Result<(u64, u64)> {
+        let mut file = File::open(path)?;
+
+        // alpha_g1, beta_g1, beta_g2, gamma_g2, delta_g1 and delta_g2 of the verifying key.
+        file.seek(SeekFrom::Start(3 * G1_SIZE + 3 * G2_SIZE))?;
+        let ic = u64::from(file.read_u32::<BigEndian>()?);
+        file.seek(SeekFrom::Current((ic * G1_SIZE) as i64))?;
+        let h = u64::from(file.read_u32::<BigEndian>()?);
+        file.seek(SeekFrom::Current((h * G1_SIZE) as i64))?;
+        let l = u64::from(file.read_u32::<BigEndian>()?);
+
+        Ok((h + 1, l))
+    };
+
+    read().with_context(|| format!("could not read the circuit size from {:?}", path))
+}
+
+/// Returns the store files of a tree split into `tree_count` sub-trees, as named by
+/// `split_config`, each storing `len` nodes.
+fn sub_tree_files(key: CacheKey, tree_count: usize, len: usize) -> impl Iterator<Item = CacheFile> {
+    let id = key.to_string();
+    (0..tree_count).map(move |i| {
+        let name = if tree_count == 1 {
+            store_file_name(&id)
+        } else {
+            store_file_name(&format!("{}-{}", id, i))
+        };
+
+        CacheFile {
+            name,
+            size: (len * NODE_SIZE) as u64,
+        }
+    })
+}
+
+fn store_file_name(id: &str) -> String {
+    StoreConfig::data_path(&PathBuf::new(), id)
+        .display()
+        .to_string()
+}
+
+fn parameter_file(path: PathBuf) -> Result<ParameterFile> {
+    let size = match fs::metadata(&path) {
+        Ok(metadata) => Some(metadata.len()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => {
+            return Err(err).with_context(|| format!("could not stat {:?}", path));
+        }
+    };
+
+    Ok(ParameterFile { path, size })
+}
@@ -1,54 +1,77 @@
+#[cfg(feature = "prover")]
 use std::fs::{self, metadata, File, OpenOptions};
+#[cfg(feature = "prover")]
 use std::io::Write;
+#[cfg(feature = "prover")]
 use std::path::{Path, PathBuf};
 
-use anyhow::{ensure, Context, Result};
-use bellperson::bls::{Bls12, Fr};
+#[cfg(feature = "prover")]
+use anyhow::Context;
+use anyhow::{ensure, Result};
+#[cfg(feature = "prover")]
+use bellperson::bls::Bls12;
+use bellperson::bls::Fr;
 use bellperson::groth16;
+#[cfg(feature = "prover")]
 use bincode::{deserialize, serialize};
 use filecoin_hashers::{Domain, Hasher};
 use log::{info, trace};
+#[cfg(feature = "prover")]
 use memmap::MmapOptions;
+#[cfg(feature = "prover")]
 use merkletree::store::{DiskStore, Store, StoreConfig};
 use rayon::prelude::*;
 use sha2::{Digest, Sha256};
+#[cfg(feature = "prover")]
 use storage_proofs_core::{
     cache_key::CacheKey,
-    compound_proof::{self, CompoundProof},
     drgraph::Graph,
     measurements::{measure_op, Operation},
-    merkle::{create_base_merkle_tree, BinaryMerkleTree, MerkleTreeTrait},
-    multi_proof::MultiProof,
+    merkle::{create_base_merkle_tree, BinaryMerkleTree},
     parameter_cache::SRS_MAX_PROOFS_TO_AGGREGATE,
     proof::ProofScheme,
-    sector::SectorId,
     util::default_rows_to_discard,
     Data,
 };
+use storage_proofs_core::{
+    compound_proof::{self, CompoundProof},
+    merkle::MerkleTreeTrait,
+    multi_proof::MultiProof,
+    sector::SectorId,
+};
 use storage_proofs_porep::stacked::{
     self, generate_replica_id, ChallengeRequirements, StackedCompound, StackedDrg, Tau,
-    TemporaryAux, TemporaryAuxCache,
+};
+#[cfg(feature = "prover")]
+use storage_proofs_porep::stacked::{
+    sdr_topology, ReadPlanBuilder, ReadTarget, TemporaryAux, TemporaryAuxCache,
 };
 
 use crate::{
-    api::{as_safe_commitment, commitment_from_fr, get_base_tree_leafs, get_base_tree_size},
-    caches::{
-        get_stacked_params, get_stacked_srs_key, get_stacked_srs_verifier_key,
-        get_stacked_verifying_key,
-    },
-    constants::{
-        DefaultBinaryTree, DefaultPieceDomain, DefaultPieceHasher, POREP_MINIMUM_CHALLENGES,
-        SINGLE_PARTITION_PROOF_LEN,
-    },
+    api::{as_safe_commitment, spans::api_span},
+    caches::{get_stacked_srs_verifier_key, get_stacked_verifying_key},
+    constants::{DefaultPieceDomain, DefaultPieceHasher, POREP_MINIMUM_CHALLENGES},
     parameters::setup_params,
-    pieces::{self, verify_pieces},
+    pieces,
     types::{
         AggregateSnarkProof, Commitment, PaddedBytesAmount, PieceInfo, PoRepConfig,
-        PoRepProofPartitions, ProverId, SealCommitOutput, SealCommitPhase1Output,
-        SealPreCommitOutput, SealPreCommitPhase1Output, SectorSize, Ticket, BINARY_ARITY,
+        PoRepProofPartitions, ProverId, SectorSize, Ticket,
+    },
+};
+#[cfg(feature = "prover")]
+use crate::{
+    api::{commitment_from_fr, get_base_tree_leafs, get_base_tree_size, metrics::label_sector},
+    caches::{get_stacked_params, get_stacked_srs_key},
+    constants::{DefaultBinaryTree, SINGLE_PARTITION_PROOF_LEN},
+    pieces::verify_pieces,
+    types::{
+        SdrPlacement, SdrPlacementReport, SdrTopology, SealCommitOutput, SealCommitPartitionProof,
+        SealCommitPhase1Output, SealCommitReadPlan, SealPreCommitOutput, SealPreCommitPhase1Output,
+        BINARY_ARITY,
     },
 };
 
+#[cfg(feature = "prover")]
 #[allow(clippy::too_many_arguments)]
 pub fn seal_pre_commit_phase1<R, S, T, Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
@@ -65,7 +88,47 @@ where
     S: AsRef<Path>,
     T: AsRef<Path>,
 {
+    seal_pre_commit_phase1_with_placement(
+        porep_config,
+        cache_path,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_infos,
+        &SdrPlacement::Auto,
+    )
+    .map(|(out, _)| out)
+}
+
+/// Like `seal_pre_commit_phase1`, but runs the multicore SDR labelling on the cores or NUMA
+/// node given by `placement` and returns the placement decision alongside the output.
+#[cfg(feature = "prover")]
+#[allow(clippy::too_many_arguments)]
+pub fn seal_pre_commit_phase1_with_placement<R, S, T, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+    placement: &SdrPlacement,
+) -> Result<(SealPreCommitPhase1Output<Tree>, SdrPlacementReport)>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    let _span = api_span("seal_pre_commit_phase1")
+        .porep_config(&porep_config)
+        .prover_id(&prover_id)
+        .sector_id(sector_id)
+        .entered();
     info!("seal_pre_commit_phase1:start: {:?}", sector_id);
+    let _metric_labels = label_sector(porep_config.sector_size, Some(sector_id));
 
     // Sanity check all input path types.
     ensure!(
@@ -181,10 +244,11 @@ where
         &porep_config.porep_id,
     );
 
-    let labels = StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase1(
+    let (labels, report) = StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase1_with_placement(
         &compound_public_params.vanilla_params,
         &replica_id,
         config.clone(),
+        placement,
     )?;
 
     let out = SealPreCommitPhase1Output {
@@ -194,9 +258,17 @@ where
     };
 
     info!("seal_pre_commit_phase1:finish: {:?}", sector_id);
-    Ok(out)
+    Ok((out, report))
+}
+
+/// Returns the topology multicore SDR labelling jobs are placed on: the cores, NUMA nodes and
+/// core groups, and which of the groups are currently in use.
+#[cfg(feature = "prover")]
+pub fn get_sdr_topology() -> Result<SdrTopology> {
+    sdr_topology()
 }
 
+#[cfg(feature = "prover")]
 #[allow(clippy::too_many_arguments)]
 pub fn seal_pre_commit_phase2<R, S, Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
@@ -208,7 +280,11 @@ where
     R: AsRef<Path>,
     S: AsRef<Path>,
 {
+    let _span = api_span("seal_pre_commit_phase2")
+        .porep_config(&porep_config)
+        .entered();
     info!("seal_pre_commit_phase2:start");
+    let _metric_labels = label_sector(porep_config.sector_size, None);
 
     // Sanity check all input path types.
     ensure!(
@@ -321,6 +397,7 @@ where
     Ok(out)
 }
 
+#[cfg(feature = "prover")]
 #[allow(clippy::too_many_arguments)]
 pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
@@ -333,7 +410,13 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
     pre_commit: SealPreCommitOutput,
     piece_infos: &[PieceInfo],
 ) -> Result<SealCommitPhase1Output<Tree>> {
+    let _span = api_span("seal_commit_phase1")
+        .porep_config(&porep_config)
+        .prover_id(&prover_id)
+        .sector_id(sector_id)
+        .entered();
     info!("seal_commit_phase1:start: {:?}", sector_id);
+    let _metric_labels = label_sector(porep_config.sector_size, Some(sector_id));
 
     // Sanity check all input path types.
     ensure!(
@@ -449,6 +532,207 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
     Ok(out)
 }
 
+/// Returns the files and byte ranges which `seal_commit_phase1` reads from the cache directory
+/// and the replica for the given replica id and seed. Only these parts need to be present for
+/// `seal_commit_phase1_sparse`, see `seal_commit_phase1_write_sparse_cache`.
+///
+/// # Arguments
+///
+/// * `porep_config` - this sector's porep config that contains the number of bytes in the sector.
+/// * `cache_path` - path to the cache directory of the sector.
+/// * `replica_id` - the replica id of the sector, as returned by `seal_commit_phase1`.
+/// * `seed` - the seed used to derive the porep challenges.
+#[cfg(feature = "prover")]
+pub fn seal_commit_phase1_read_plan<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: T,
+    replica_id: <Tree::Hasher as Hasher>::Domain,
+    seed: Ticket,
+) -> Result<SealCommitReadPlan> {
+    let _span = api_span("seal_commit_phase1_read_plan")
+        .porep_config(&porep_config)
+        .entered();
+    info!("seal_commit_phase1_read_plan:start");
+
+    ensure!(
+        metadata(cache_path.as_ref())?.is_dir(),
+        "cache_path must be a directory"
+    );
+
+    let t_aux = {
+        let t_aux_path = cache_path.as_ref().join(CacheKey::TAux.to_string());
+        let t_aux_bytes = fs::read(&t_aux_path)
+            .with_context(|| format!("could not read file t_aux={:?}", t_aux_path))?;
+
+        let mut res: TemporaryAux<Tree, DefaultPieceHasher> = deserialize(&t_aux_bytes)?;
+        res.set_cache_path(cache_path.as_ref());
+        res
+    };
+
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+    let vanilla_params = setup_params(
+        PaddedBytesAmount::from(porep_config),
+        partitions,
+        porep_config.porep_id,
+        porep_config.api_version,
+    )?;
+    let pub_params = StackedDrg::<Tree, DefaultPieceHasher>::setup(&vanilla_params)?;
+
+    let public_inputs = stacked::PublicInputs {
+        replica_id,
+        tau: None,
+        k: None,
+        seed,
+    };
+
+    let mut builder = ReadPlanBuilder::new();
+    for key in &[CacheKey::PAux, CacheKey::TAux] {
+        let target = ReadTarget::Cache(PathBuf::from(key.to_string()));
+        let path = cache_path.as_ref().join(key.to_string());
+        let len = metadata(&path)
+            .with_context(|| format!("could not stat {:?}", path))?
+            .len();
+        builder.add_file(target.clone(), len)?;
+        builder.add_whole_file(&target)?;
+    }
+    stacked::add_commit_reads(
+        &mut builder,
+        &pub_params,
+        &public_inputs,
+        &t_aux,
+        partitions,
+    )?;
+
+    let plan = SealCommitReadPlan {
+        replica_id: commitment_from_fr(replica_id.into()),
+        seed,
+        reads: builder.build(),
+    };
+
+    info!(
+        "seal_commit_phase1_read_plan:finish: {} bytes",
+        plan.reads.total_bytes()
+    );
+    Ok(plan)
+}
+
+/// Writes a sparse copy of a sector's cache directory and replica, which only holds the ranges
+/// of `plan`. The plan itself is stored in the sparse cache directory, where it is picked up by
+/// `seal_commit_phase1_sparse`. The sparse files have their full size, so they should be
+/// transferred with a tool which preserves holes.
+///
+/// # Arguments
+///
+/// * `plan` - the read plan of the sector, as returned by `seal_commit_phase1_read_plan`.
+/// * `cache_path` - path to the cache directory of the sector.
+/// * `replica_path` - path to the sealed sector.
+/// * `sparse_cache_path` - path to the sparse cache directory to create.
+/// * `sparse_replica_path` - path to the sparse sealed sector to create.
+#[cfg(feature = "prover")]
+pub fn seal_commit_phase1_write_sparse_cache<R, S, T, U>(
+    plan: &SealCommitReadPlan,
+    cache_path: R,
+    replica_path: S,
+    sparse_cache_path: T,
+    sparse_replica_path: U,
+) -> Result<()>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+    U: AsRef<Path>,
+{
+    let _span = api_span("seal_commit_phase1_write_sparse_cache").entered();
+    info!("seal_commit_phase1_write_sparse_cache:start");
+
+    plan.reads.write_sparse_copy(
+        cache_path.as_ref(),
+        replica_path.as_ref(),
+        sparse_cache_path.as_ref(),
+        sparse_replica_path.as_ref(),
+    )?;
+
+    let plan_path = sparse_cache_path
+        .as_ref()
+        .join(CacheKey::CommitReadPlan.to_string());
+    let plan_file = File::create(&plan_path)
+        .with_context(|| format!("could not create file plan={:?}", plan_path))?;
+    serde_json::to_writer(plan_file, plan)
+        .with_context(|| format!("could not write to file plan={:?}", plan_path))?;
+
+    info!("seal_commit_phase1_write_sparse_cache:finish");
+    Ok(())
+}
+
+/// Like `seal_commit_phase1`, but works from a sparse cache directory and replica, as written
+/// by `seal_commit_phase1_write_sparse_cache`. The ticket and seed must be the ones the sparse
+/// cache was planned for.
+#[cfg(feature = "prover")]
+#[allow(clippy::too_many_arguments)]
+pub fn seal_commit_phase1_sparse<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    sparse_cache_path: T,
+    sparse_replica_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    seed: Ticket,
+    pre_commit: SealPreCommitOutput,
+    piece_infos: &[PieceInfo],
+) -> Result<SealCommitPhase1Output<Tree>> {
+    let _span = api_span("seal_commit_phase1_sparse")
+        .porep_config(&porep_config)
+        .prover_id(&prover_id)
+        .sector_id(sector_id)
+        .entered();
+    info!("seal_commit_phase1_sparse:start: {:?}", sector_id);
+
+    let plan: SealCommitReadPlan = {
+        let plan_path = sparse_cache_path
+            .as_ref()
+            .join(CacheKey::CommitReadPlan.to_string());
+        let plan_file = File::open(&plan_path)
+            .with_context(|| format!("could not read file plan={:?}", plan_path))?;
+        serde_json::from_reader(plan_file)
+            .with_context(|| format!("could not parse file plan={:?}", plan_path))?
+    };
+
+    let replica_id = generate_replica_id::<Tree::Hasher, _>(
+        &prover_id,
+        sector_id.into(),
+        &ticket,
+        DefaultPieceDomain::try_from_bytes(&pre_commit.comm_d)?,
+        &porep_config.porep_id,
+    );
+    ensure!(
+        plan.replica_id == commitment_from_fr(replica_id.into()),
+        "sparse cache was planned for a different replica id"
+    );
+    ensure!(
+        plan.seed == seed,
+        "sparse cache was planned for a different seed"
+    );
+    plan.reads
+        .verify_files(sparse_cache_path.as_ref(), sparse_replica_path.as_ref())
+        .context("sparse cache is incomplete")?;
+
+    let out = seal_commit_phase1::<_, Tree>(
+        porep_config,
+        sparse_cache_path,
+        sparse_replica_path,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit,
+        piece_infos,
+    )?;
+
+    info!("seal_commit_phase1_sparse:finish: {:?}", sector_id);
+    Ok(out)
+}
+
+#[cfg(feature = "prover")]
 #[allow(clippy::too_many_arguments)]
 pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
@@ -456,7 +740,13 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
     prover_id: ProverId,
     sector_id: SectorId,
 ) -> Result<SealCommitOutput> {
+    let _span = api_span("seal_commit_phase2")
+        .porep_config(&porep_config)
+        .prover_id(&prover_id)
+        .sector_id(sector_id)
+        .entered();
     info!("seal_commit_phase2:start: {:?}", sector_id);
+    let _metric_labels = label_sector(porep_config.sector_size, Some(sector_id));
 
     let SealCommitPhase1Output {
         vanilla_proofs,
@@ -544,6 +834,183 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
     Ok(out)
 }
 
+/// Generates the proof of a single partition of a seal commit. Partitions can be proven
+/// independently of each other (e.g. on different machines), and are put back together with
+/// `seal_commit_phase2_assemble`.
+///
+/// # Arguments
+///
+/// * `porep_config` - this sector's porep config that contains the number of bytes in the sector.
+/// * `phase1_output` - the output of `seal_commit_phase1` for this sector.
+/// * `partition` - the index of the partition to prove.
+#[cfg(feature = "prover")]
+pub fn seal_commit_phase2_partition<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    phase1_output: &SealCommitPhase1Output<Tree>,
+    partition: usize,
+) -> Result<SealCommitPartitionProof> {
+    let _span = api_span("seal_commit_phase2_partition")
+        .porep_config(&porep_config)
+        .entered();
+    info!("seal_commit_phase2_partition:start: {}", partition);
+
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+    ensure!(
+        partition < partitions,
+        "invalid partition {} (sector has {} partitions)",
+        partition,
+        partitions
+    );
+    ensure!(
+        phase1_output.vanilla_proofs.len() == partitions,
+        "invalid amount of vanilla proofs"
+    );
+
+    let public_inputs = seal_commit_phase2_public_inputs(phase1_output)?;
+
+    let groth_params = get_stacked_params::<Tree>(porep_config)?;
+
+    let compound_setup_params = compound_proof::SetupParams {
+        vanilla_params: setup_params(
+            PaddedBytesAmount::from(porep_config),
+            partitions,
+            porep_config.porep_id,
+            porep_config.api_version,
+        )?,
+        partitions: Some(partitions),
+        priority: false,
+    };
+
+    let compound_public_params = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
+        StackedDrg<'_, Tree, DefaultPieceHasher>,
+        _,
+    >>::setup(&compound_setup_params)?;
+
+    info!("snark_proof:start");
+    let groth_proof = StackedCompound::<Tree, DefaultPieceHasher>::partition_circuit_proof(
+        &public_inputs,
+        &phase1_output.vanilla_proofs[partition],
+        partition,
+        &compound_public_params.vanilla_params,
+        &groth_params,
+        compound_public_params.priority,
+    )?;
+    info!("snark_proof:finish");
+
+    let mut proof = Vec::with_capacity(SINGLE_PARTITION_PROOF_LEN);
+    groth_proof.write(&mut proof)?;
+
+    let out = SealCommitPartitionProof { partition, proof };
+
+    info!("seal_commit_phase2_partition:finish: {}", partition);
+    Ok(out)
+}
+
+/// Puts the proofs of all partitions of a seal commit, as generated by
+/// `seal_commit_phase2_partition`, together in partition order and verifies the result. The
+/// output is the same as that of `seal_commit_phase2`.
+///
+/// # Arguments
+///
+/// * `porep_config` - this sector's porep config that contains the number of bytes in the sector.
+/// * `phase1_output` - the output of `seal_commit_phase1` for this sector.
+/// * `partition_proofs` - the proofs of every partition, in any order.
+/// * `prover_id` - the prover_id used to seal this sector.
+/// * `sector_id` - the sector_id of this sector.
+#[cfg(feature = "prover")]
+pub fn seal_commit_phase2_assemble<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    phase1_output: &SealCommitPhase1Output<Tree>,
+    partition_proofs: &[SealCommitPartitionProof],
+    prover_id: ProverId,
+    sector_id: SectorId,
+) -> Result<SealCommitOutput> {
+    let _span = api_span("seal_commit_phase2_assemble")
+        .porep_config(&porep_config)
+        .prover_id(&prover_id)
+        .sector_id(sector_id)
+        .entered();
+    info!("seal_commit_phase2_assemble:start: {:?}", sector_id);
+
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+    ensure!(
+        partition_proofs.len() == partitions,
+        "invalid amount of partition proofs"
+    );
+
+    let mut ordered: Vec<Option<&SealCommitPartitionProof>> = vec![None; partitions];
+    for partition_proof in partition_proofs {
+        ensure!(
+            partition_proof.partition < partitions,
+            "invalid partition {}",
+            partition_proof.partition
+        );
+        ensure!(
+            partition_proof.proof.len() == SINGLE_PARTITION_PROOF_LEN,
+            "invalid proof length for partition {}",
+            partition_proof.partition
+        );
+        ensure!(
+            ordered[partition_proof.partition]
+                .replace(partition_proof)
+                .is_none(),
+            "duplicate proof for partition {}",
+            partition_proof.partition
+        );
+    }
+
+    let mut buf = Vec::with_capacity(SINGLE_PARTITION_PROOF_LEN * partitions);
+    for partition_proof in ordered.into_iter().flatten() {
+        buf.extend_from_slice(&partition_proof.proof);
+    }
+
+    verify_seal::<Tree>(
+        porep_config,
+        phase1_output.comm_r,
+        phase1_output.comm_d,
+        prover_id,
+        sector_id,
+        phase1_output.ticket,
+        phase1_output.seed,
+        &buf,
+    )
+    .context("post-seal verification sanity check failed")?;
+
+    let out = SealCommitOutput { proof: buf };
+
+    info!("seal_commit_phase2_assemble:finish: {:?}", sector_id);
+    Ok(out)
+}
+
+#[cfg(feature = "prover")]
+fn seal_commit_phase2_public_inputs<Tree: 'static + MerkleTreeTrait>(
+    phase1_output: &SealCommitPhase1Output<Tree>,
+) -> Result<stacked::PublicInputs<<Tree::Hasher as Hasher>::Domain, DefaultPieceDomain>> {
+    let SealCommitPhase1Output {
+        comm_d,
+        comm_r,
+        replica_id,
+        seed,
+        ..
+    } = phase1_output;
+
+    ensure!(*comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
+    ensure!(*comm_r != [0; 32], "Invalid all zero commitment (comm_r)");
+
+    let comm_r_safe = as_safe_commitment(comm_r, "comm_r")?;
+    let comm_d_safe = DefaultPieceDomain::try_from_bytes(comm_d)?;
+
+    Ok(stacked::PublicInputs {
+        replica_id: *replica_id,
+        tau: Some(stacked::Tau {
+            comm_d: comm_d_safe,
+            comm_r: comm_r_safe,
+        }),
+        k: None,
+        seed: *seed,
+    })
+}
+
 /// Given the specified arguments, this method returns the inputs that were used to
 /// generate the seal proof.  This can be useful for proof aggregation, as verification
 /// requires these inputs.
@@ -569,6 +1036,11 @@ pub fn get_seal_inputs<Tree: 'static + MerkleTreeTrait>(
     ticket: Ticket,
     seed: Ticket,
 ) -> Result<Vec<Vec<Fr>>> {
+    let _span = api_span("get_seal_inputs")
+        .porep_config(&porep_config)
+        .prover_id(&prover_id)
+        .sector_id(sector_id)
+        .entered();
     info!("get_seal_inputs:start");
 
     ensure!(comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
@@ -643,6 +1115,7 @@ fn get_aggregate_target_len(len: usize) -> usize {
 }
 
 /// Given a list of proofs and a target_len, make sure that the proofs list is padded to the target_len size.
+#[cfg(feature = "prover")]
 fn pad_proofs_to_target(proofs: &mut Vec<groth16::Proof<Bls12>>, target_len: usize) -> Result<()> {
     trace!(
         "pad_proofs_to_target target_len {}, proofs len {}",
@@ -719,12 +1192,16 @@ fn pad_inputs_to_target(
 /// * `porep_config` - this sector's porep config that contains the number of bytes in the sector.
 /// * `seeds` - an ordered list of seeds used to derive the PoRep challenges.
 /// * `commit_outputs` - an ordered list of seal proof outputs returned from 'seal_commit_phase2'.
+#[cfg(feature = "prover")]
 pub fn aggregate_seal_commit_proofs<Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
     comm_rs: &[[u8; 32]],
     seeds: &[[u8; 32]],
     commit_outputs: &[SealCommitOutput],
 ) -> Result<AggregateSnarkProof> {
+    let _span = api_span("aggregate_seal_commit_proofs")
+        .porep_config(&porep_config)
+        .entered();
     info!("aggregate_seal_commit_proofs:start");
 
     ensure!(
@@ -810,6 +1287,9 @@ pub fn verify_aggregate_seal_commit_proofs<Tree: 'static + MerkleTreeTrait>(
     seeds: &[[u8; 32]],
     commit_inputs: Vec<Vec<Fr>>,
 ) -> Result<bool> {
+    let _span = api_span("verify_aggregate_seal_commit_proofs")
+        .porep_config(&porep_config)
+        .entered();
     info!("verify_aggregate_seal_commit_proofs:start");
 
     let aggregate_proof =
@@ -893,6 +1373,9 @@ pub fn verify_aggregate_seal_commit_proofs<Tree: 'static + MerkleTreeTrait>(
 /// * `porep_config` - this sector's porep config that contains the number of bytes in the sector.
 /// * `piece_infos` - the piece info (commitment and byte length) for each piece in this sector.
 pub fn compute_comm_d(sector_size: SectorSize, piece_infos: &[PieceInfo]) -> Result<Commitment> {
+    let _span = api_span("compute_comm_d")
+        .sector_size(sector_size)
+        .entered();
     info!("compute_comm_d:start");
 
     let result = pieces::compute_comm_d(sector_size, piece_infos);
@@ -924,6 +1407,11 @@ pub fn verify_seal<Tree: 'static + MerkleTreeTrait>(
     seed: Ticket,
     proof_vec: &[u8],
 ) -> Result<bool> {
+    let _span = api_span("verify_seal")
+        .porep_config(&porep_config)
+        .prover_id(&prover_id)
+        .sector_id(sector_id)
+        .entered();
     info!("verify_seal:start: {:?}", sector_id);
     ensure!(comm_d_in != [0; 32], "Invalid all zero commitment (comm_d)");
     ensure!(comm_r_in != [0; 32], "Invalid all zero commitment (comm_r)");
@@ -1019,6 +1507,9 @@ pub fn verify_batch_seal<Tree: 'static + MerkleTreeTrait>(
     seeds: &[Ticket],
     proof_vecs: &[&[u8]],
 ) -> Result<bool> {
+    let _span = api_span("verify_batch_seal")
+        .porep_config(&porep_config)
+        .entered();
     info!("verify_batch_seal:start");
     ensure!(!comm_r_ins.is_empty(), "Cannot prove empty batch");
     let l = comm_r_ins.len();
@@ -0,0 +1,69 @@
+use storage_proofs_core::sector::SectorId;
+use tracing::{
+    field::{self, debug, display},
+    info_span,
+    span::EnteredSpan,
+    Span,
+};
+
+#[cfg(feature = "prover")]
+use crate::types::ProverId;
+use crate::types::{PoRepConfig, PoStConfig, SectorSize};
+
+/// The span of an API call. All log lines and spans of the call, including those of the threads
+/// working on its behalf, are nested in it, so they can be attributed to a sector when several
+/// are processed at once. Fields the call does not know about stay empty.
+pub(crate) struct ApiSpan(Span);
+
+/// Creates the span of the API call `phase`.
+pub(crate) fn api_span(phase: &'static str) -> ApiSpan {
+    ApiSpan(info_span!(
+        "filecoin_proofs",
+        phase,
+        sector_id = field::Empty,
+        prover_id = field::Empty,
+        sector_size = field::Empty,
+        porep_id = field::Empty,
+        partitions = field::Empty,
+        post_type = field::Empty,
+        api_version = field::Empty,
+    ))
+}
+
+impl ApiSpan {
+    pub fn sector_id(self, sector_id: SectorId) -> Self {
+        self.0.record("sector_id", &u64::from(sector_id));
+        self
+    }
+
+    #[cfg(feature = "prover")]
+    pub fn prover_id(self, prover_id: &ProverId) -> Self {
+        self.0.record("prover_id", &hex::encode(prover_id).as_str());
+        self
+    }
+
+    pub fn sector_size(self, sector_size: SectorSize) -> Self {
+        self.0.record("sector_size", &u64::from(sector_size));
+        self
+    }
+
+    pub fn porep_config(self, porep_config: &PoRepConfig) -> Self {
+        self.0
+            .record("porep_id", &hex::encode(porep_config.porep_id).as_str())
+            .record("partitions", &(usize::from(porep_config.partitions) as u64))
+            .record("api_version", &display(porep_config.api_version));
+        self.sector_size(porep_config.sector_size)
+    }
+
+    pub fn post_config(self, post_config: &PoStConfig) -> Self {
+        self.0
+            .record("post_type", &debug(&post_config.typ))
+            .record("api_version", &display(post_config.api_version));
+        self.sector_size(post_config.sector_size)
+    }
+
+    /// Enters the span on the current thread, until the returned guard is dropped.
+    pub fn entered(self) -> EnteredSpan {
+        self.0.entered()
+    }
+}
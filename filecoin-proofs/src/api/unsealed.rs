@@ -0,0 +1,626 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use bincode::{deserialize, serialize};
+use filecoin_hashers::{Domain, Hasher};
+use fr32::{write_unpadded, Fr32Reader};
+use log::{info, warn};
+use memmap::MmapOptions;
+use merkletree::store::StoreConfig;
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::{cache_key::CacheKey, error::Error, sector::SectorId, util::NODE_SIZE};
+use storage_proofs_porep::stacked::generate_replica_id;
+
+use crate::{
+    api::{
+        as_safe_commitment, ensure_piece_size, extract_sector, metrics::label_sector,
+        spans::api_span,
+    },
+    commitment_reader::CommitmentReader,
+    constants::{DefaultPieceDomain, DefaultPieceHasher},
+    pieces::piece_hash,
+    types::{
+        Commitment, MerkleTreeTrait, PaddedBytesAmount, PieceInfo, PoRepConfig, ProverId,
+        SectorSize, Ticket, UnpaddedByteIndex, UnpaddedBytesAmount,
+    },
+};
+
+/// The number of padded bytes holding 127 unpadded bytes. Ranges of unpadded bytes are tracked
+/// in whole chunks, as only those can be unpadded on their own.
+const FR32_CHUNK: u64 = 128;
+const FR32_CHUNK_UNPADDED: u64 = 127;
+
+/// The presence index stored next to the data file.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct PresenceIndex {
+    sector_size: u64,
+    /// Sorted, non-overlapping and non-adjacent ranges `[start, end)` of padded bytes holding
+    /// piece data.
+    ranges: Vec<(u64, u64)>,
+}
+
+impl PresenceIndex {
+    fn covers(&self, start: u64, end: u64) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(range_start, range_end)| range_start <= start && end <= range_end)
+    }
+
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(range_start, range_end)| range_start < end && start < range_end)
+    }
+
+    fn insert(&mut self, mut start: u64, mut end: u64) {
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        for &(range_start, range_end) in &self.ranges {
+            if range_end < start || end < range_start {
+                ranges.push((range_start, range_end));
+            } else {
+                start = start.min(range_start);
+                end = end.max(range_end);
+            }
+        }
+        ranges.push((start, end));
+        ranges.sort_unstable();
+        self.ranges = ranges;
+    }
+}
+
+/// Returns the range of padded bytes `[start, end)` holding the unpadded bytes
+/// `[offset, offset + num_bytes)`, extended to whole fr32 chunks.
+fn padded_chunk_range(offset: UnpaddedByteIndex, num_bytes: UnpaddedBytesAmount) -> (u64, u64) {
+    let start = offset.0 / FR32_CHUNK_UNPADDED * FR32_CHUNK;
+    let unpadded_end = offset.0 + num_bytes.0;
+    let end = (unpadded_end + FR32_CHUNK_UNPADDED - 1) / FR32_CHUNK_UNPADDED * FR32_CHUNK;
+
+    (start, end)
+}
+
+/// An unsealed sector file which records which ranges hold piece data.
+///
+/// The data file holds the fr32 padded sector bytes at their offsets, like the output of
+/// `add_piece`, so it can be sealed directly. It is sparse: ranges which were never written read
+/// as zeros. The presence of piece data is tracked in an index stored next to it, at
+/// `<path>.present`.
+#[derive(Debug)]
+pub struct UnsealedSector {
+    path: PathBuf,
+    file: File,
+    index: PresenceIndex,
+}
+
+impl UnsealedSector {
+    /// Creates an empty unsealed sector at `path`, replacing any existing file.
+    pub fn create<P: AsRef<Path>>(path: P, sector_size: SectorSize) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .with_context(|| format!("could not create unsealed sector {:?}", path))?;
+        file.set_len(u64::from(sector_size))?;
+
+        let sector = UnsealedSector {
+            path,
+            file,
+            index: PresenceIndex {
+                sector_size: u64::from(sector_size),
+                ranges: Vec::new(),
+            },
+        };
+        sector.persist_index()?;
+
+        Ok(sector)
+    }
+
+    /// Opens the unsealed sector at `path`, which must have been created with `create`.
+    pub fn open<P: AsRef<Path>>(path: P, sector_size: SectorSize) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let index_path = Self::index_path(&path);
+        let index: PresenceIndex = deserialize(
+            &fs::read(&index_path)
+                .with_context(|| format!("could not read presence index {:?}", index_path))?,
+        )?;
+        ensure!(
+            index.sector_size == u64::from(sector_size),
+            "unsealed sector {:?} has size {}, expected {}",
+            path,
+            index.sector_size,
+            u64::from(sector_size)
+        );
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("could not open unsealed sector {:?}", path))?;
+        ensure!(
+            file.metadata()?.len() == index.sector_size,
+            "unsealed sector {:?} is truncated",
+            path
+        );
+
+        Ok(UnsealedSector { path, file, index })
+    }
+
+    fn index_path(path: &Path) -> PathBuf {
+        let mut index_path = path.as_os_str().to_owned();
+        index_path.push(".present");
+        index_path.into()
+    }
+
+    /// The path of the data file, e.g. to seal it.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn sector_size(&self) -> SectorSize {
+        SectorSize(self.index.sector_size)
+    }
+
+    /// Writes the index, after the data it describes has been synced to disk.
+    fn persist_index(&self) -> Result<()> {
+        self.file.sync_data()?;
+
+        let index_path = Self::index_path(&self.path);
+        let tmp_path = index_path.with_extension("present.tmp");
+        fs::write(&tmp_path, serialize(&self.index)?)
+            .with_context(|| format!("could not write {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &index_path)
+            .with_context(|| format!("could not write {:?}", index_path))?;
+
+        Ok(())
+    }
+
+    /// Writes a piece of `piece_size` unpadded bytes read from `source` at `offset`, after
+    /// preprocessing it like `add_piece`. Pieces may be written in any order, but each must
+    /// start at a multiple of its padded size and must not overlap data already present.
+    pub fn write_piece<R: Read>(
+        &mut self,
+        source: R,
+        offset: UnpaddedByteIndex,
+        piece_size: UnpaddedBytesAmount,
+    ) -> Result<PieceInfo> {
+        let _span = api_span("write_piece")
+            .sector_size(self.sector_size())
+            .entered();
+        info!("write_piece:start");
+        ensure_piece_size(piece_size)?;
+
+        let start = u64::from(PaddedBytesAmount::from(UnpaddedBytesAmount::from(offset)));
+        let padded_piece_size = u64::from(PaddedBytesAmount::from(piece_size));
+        let end = start + padded_piece_size;
+        ensure!(
+            start % padded_piece_size == 0,
+            "piece at {:?} is not aligned to its padded size {}",
+            offset,
+            padded_piece_size
+        );
+        ensure!(
+            end <= self.index.sector_size,
+            "piece at {:?} does not fit into the sector",
+            offset
+        );
+        ensure!(
+            !self.index.overlaps(start, end),
+            "piece at {:?} overlaps data already present",
+            offset
+        );
+
+        let mut target = BufWriter::new(&self.file);
+        target.seek(SeekFrom::Start(start))?;
+
+        let fr32_reader = Fr32Reader::new(BufReader::new(source));
+        let mut commitment_reader = CommitmentReader::new(fr32_reader);
+        let n = io::copy(&mut commitment_reader, &mut target)
+            .context("failed to write and preprocess bytes")?;
+        target.flush()?;
+        drop(target);
+        ensure!(
+            n == padded_piece_size,
+            "write_piece: invalid bytes amount written"
+        );
+
+        let commitment = commitment_reader.finish()?;
+        let mut comm = [0u8; 32];
+        comm.copy_from_slice(commitment.as_ref());
+
+        self.index.insert(start, end);
+        self.persist_index()?;
+
+        info!("write_piece:finish");
+        PieceInfo::new(comm, piece_size)
+    }
+
+    /// Writes padded bytes at the padded offset `start`, e.g. unsealed from the replica, and
+    /// records them as present.
+    pub(crate) fn write_padded(&mut self, start: u64, data: &[u8]) -> Result<()> {
+        let end = start + data.len() as u64;
+        ensure!(
+            start % FR32_CHUNK == 0 && end % FR32_CHUNK == 0,
+            "padded range {}..{} is not aligned to whole chunks",
+            start,
+            end
+        );
+        ensure!(
+            end <= self.index.sector_size,
+            "padded range {}..{} does not fit into the sector",
+            start,
+            end
+        );
+
+        self.file.seek(SeekFrom::Start(start))?;
+        self.file.write_all(data)?;
+        self.index.insert(start, end);
+        self.persist_index()
+    }
+
+    /// Returns whether all unpadded bytes `[offset, offset + num_bytes)` are present, i.e. can
+    /// be read without unsealing.
+    pub fn is_present(&self, offset: UnpaddedByteIndex, num_bytes: UnpaddedBytesAmount) -> bool {
+        let (start, end) = padded_chunk_range(offset, num_bytes);
+        self.index.covers(start, end)
+    }
+
+    /// Returns the ranges of unpadded bytes which are present, in order.
+    pub fn present_ranges(&self) -> Vec<(UnpaddedByteIndex, UnpaddedBytesAmount)> {
+        self.index
+            .ranges
+            .iter()
+            .map(|&(start, end)| {
+                (
+                    UnpaddedByteIndex(start / FR32_CHUNK * FR32_CHUNK_UNPADDED),
+                    UnpaddedBytesAmount((end - start) / FR32_CHUNK * FR32_CHUNK_UNPADDED),
+                )
+            })
+            .collect()
+    }
+
+    /// Writes the unpadded bytes `[offset, offset + num_bytes)` to `target`. Fails if they are
+    /// not all present.
+    pub fn read_range<W: Write>(
+        &self,
+        offset: UnpaddedByteIndex,
+        num_bytes: UnpaddedBytesAmount,
+        mut target: W,
+    ) -> Result<UnpaddedBytesAmount> {
+        ensure!(
+            self.is_present(offset, num_bytes),
+            "range of {:?} at {:?} is not present",
+            num_bytes,
+            offset
+        );
+
+        let (start, end) = padded_chunk_range(offset, num_bytes);
+        let mut padded = vec![0u8; (end - start) as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut padded)?;
+
+        let chunk_offset = (start / FR32_CHUNK * FR32_CHUNK_UNPADDED) as usize;
+        let written = write_unpadded(
+            &padded,
+            &mut target,
+            usize::from(offset) - chunk_offset,
+            num_bytes.into(),
+        )
+        .context("write_unpadded failed")?;
+
+        Ok(UnpaddedBytesAmount(written as u64))
+    }
+}
+
+/// Unseals the sector at `sealed_path` and caches the bytes whose first (unpadded) byte begins
+/// at `offset` and ends at `offset` plus `num_bytes` in `unsealed`, from where they can be read
+/// with `UnsealedSector::read_range`. The range is extended to whole fr32 chunks and verified
+/// against `comm_d` before it is cached. Note that the entire sector is unsealed each time this
+/// function is called.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration containing the sector size.
+/// * `cache_path` - path to the directory in which the sector data's Merkle Tree is written.
+/// * `sealed_path` - path to the sealed sector file that we will unseal.
+/// * `unsealed` - the unsealed sector the range is cached in.
+/// * `prover_id` - the prover-id that sealed the sector.
+/// * `sector_id` - the sector-id of the sealed sector.
+/// * `comm_d` - the commitment to the sector's data.
+/// * `ticket` - the ticket that was used to generate the sector's replica-id.
+/// * `offset` - the byte index in the unsealed sector of the first byte that we want to cache.
+/// * `num_bytes` - the number of bytes that we want to cache.
+#[allow(clippy::too_many_arguments)]
+pub fn unseal_range_into<P, Tree>(
+    porep_config: PoRepConfig,
+    cache_path: P,
+    sealed_path: PathBuf,
+    unsealed: &mut UnsealedSector,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+    ticket: Ticket,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+) -> Result<UnpaddedBytesAmount>
+where
+    P: Into<PathBuf> + AsRef<Path>,
+    Tree: 'static + MerkleTreeTrait,
+{
+    let _span = api_span("unseal_range_into")
+        .porep_config(&porep_config)
+        .prover_id(&prover_id)
+        .sector_id(sector_id)
+        .entered();
+    info!("unseal_range_into:start");
+    let _metric_labels = label_sector(porep_config.sector_size, Some(sector_id));
+    ensure!(comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
+    ensure!(
+        unsealed.sector_size() == porep_config.sector_size,
+        "unsealed sector size does not match the porep config"
+    );
+
+    let (start, end) = padded_chunk_range(offset, num_bytes);
+    ensure!(
+        end <= u64::from(porep_config.sector_size),
+        "range of {:?} at {:?} does not fit into the sector",
+        num_bytes,
+        offset
+    );
+
+    let comm_d =
+        as_safe_commitment::<<DefaultPieceHasher as Hasher>::Domain, _>(&comm_d, "comm_d")?;
+
+    let replica_id = generate_replica_id::<Tree::Hasher, _>(
+        &prover_id,
+        sector_id.into(),
+        &ticket,
+        comm_d,
+        &porep_config.porep_id,
+    );
+
+    let mapped_file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&sealed_path)?;
+    let mut data = unsafe { MmapOptions::new().map_copy(&mapped_file)? };
+
+    extract_sector::<_, Tree>(porep_config, cache_path.as_ref(), &mut data, replica_id)?;
+    verify_unsealed_range(
+        cache_path.as_ref(),
+        &comm_d,
+        &data,
+        start as usize,
+        end as usize,
+    )?;
+    unsealed.write_padded(start, &data[start as usize..end as usize])?;
+
+    info!("unseal_range_into:finish");
+    Ok(num_bytes)
+}
+
+/// Returns the root of the binary tree over the padded `leaves`, whose number must be a power of
+/// two.
+fn subtree_root(leaves: &[u8]) -> Result<DefaultPieceDomain> {
+    // Subtrees larger than this are split and hashed in parallel.
+    const SEQUENTIAL_LEN: usize = 1 << 20;
+
+    if leaves.len() == NODE_SIZE {
+        return DefaultPieceDomain::try_from_bytes(leaves).map_err(Into::into);
+    }
+
+    if leaves.len() > SEQUENTIAL_LEN {
+        let (left, right) = leaves.split_at(leaves.len() / 2);
+        let (left, right) = rayon::join(|| subtree_root(left), || subtree_root(right));
+        return Ok(piece_hash(left?.as_ref(), right?.as_ref()));
+    }
+
+    let mut row: Vec<DefaultPieceDomain> = leaves
+        .chunks(NODE_SIZE * 2)
+        .map(|pair| piece_hash(&pair[..NODE_SIZE], &pair[NODE_SIZE..]))
+        .collect();
+    while row.len() > 1 {
+        row = row
+            .chunks(2)
+            .map(|pair| piece_hash(pair[0].as_ref(), pair[1].as_ref()))
+            .collect();
+    }
+
+    Ok(row[0])
+}
+
+/// Splits the leaves `[first, last)` into maximal aligned subtrees, as `(height, index)` pairs.
+fn aligned_subtrees(mut first: usize, last: usize) -> Vec<(usize, usize)> {
+    let mut subtrees = Vec::new();
+    while first < last {
+        let mut height = if first == 0 {
+            (last - first).next_power_of_two().trailing_zeros() as usize
+        } else {
+            first.trailing_zeros() as usize
+        };
+        while 1 << height > last - first {
+            height -= 1;
+        }
+        subtrees.push((height, first >> height));
+        first += 1 << height;
+    }
+
+    subtrees
+}
+
+/// The tree-d store in the cache directory, if it is complete and its root is `comm_d`.
+fn open_tree_d(cache_path: &Path, comm_d: &DefaultPieceDomain, leafs: usize) -> Option<File> {
+    let path = StoreConfig::data_path(cache_path, &CacheKey::CommDTree.to_string());
+    let mut tree_d = File::open(&path).ok()?;
+    let tree_len = 2 * leafs - 1;
+    if tree_d.metadata().ok()?.len() != (tree_len * NODE_SIZE) as u64 {
+        return None;
+    }
+
+    let mut root = [0u8; NODE_SIZE];
+    tree_d
+        .seek(SeekFrom::Start(((tree_len - 1) * NODE_SIZE) as u64))
+        .ok()?;
+    tree_d.read_exact(&mut root).ok()?;
+    if DefaultPieceDomain::try_from_bytes(&root).ok()? != *comm_d {
+        warn!(
+            "tree-d in {:?} does not match comm_d, not using it",
+            cache_path
+        );
+        return None;
+    }
+
+    Some(tree_d)
+}
+
+/// Verifies the padded bytes `[start, end)` of the decoded sector `data` against `comm_d`.
+///
+/// The range is split into aligned subtrees, whose roots are hashed from `data` and checked with
+/// inclusion paths read from the tree-d store in `cache_path`. Without a usable store, the
+/// inclusion paths would have to be hashed from `data`, so the whole sector is checked instead.
+/// Returns `Error::UnsealedDataMismatch` if the data does not match.
+pub(crate) fn verify_unsealed_range(
+    cache_path: &Path,
+    comm_d: &DefaultPieceDomain,
+    data: &[u8],
+    start: usize,
+    end: usize,
+) -> Result<()> {
+    info!("verify_unsealed_range:start");
+    ensure!(
+        data.len().is_power_of_two() && data.len() >= NODE_SIZE,
+        "invalid sector size {}",
+        data.len()
+    );
+    ensure!(
+        start < end && end <= data.len(),
+        "invalid range {}..{}",
+        start,
+        end
+    );
+
+    let leafs = data.len() / NODE_SIZE;
+    let height = leafs.trailing_zeros() as usize;
+    let mismatch = || Error::UnsealedDataMismatch(start as u64, end as u64);
+
+    let tree_d = match open_tree_d(cache_path, comm_d, leafs) {
+        Some(tree_d) => tree_d,
+        None => {
+            info!("tree-d not available, verifying the whole sector");
+            if subtree_root(data)? != *comm_d {
+                return Err(mismatch().into());
+            }
+
+            info!("verify_unsealed_range:finish");
+            return Ok(());
+        }
+    };
+
+    // Node `index` of the row at `level` is stored after the `2 * leafs - 2 * (leafs >> level)`
+    // nodes of the rows below it.
+    let read_node = |level: usize, index: usize| -> Result<DefaultPieceDomain> {
+        let pos = 2 * leafs - 2 * (leafs >> level) + index;
+        let mut node = [0u8; NODE_SIZE];
+        let mut tree_d = &tree_d;
+        tree_d.seek(SeekFrom::Start((pos * NODE_SIZE) as u64))?;
+        tree_d.read_exact(&mut node)?;
+        DefaultPieceDomain::try_from_bytes(&node).map_err(Into::into)
+    };
+
+    let first = start / NODE_SIZE;
+    let last = (end + NODE_SIZE - 1) / NODE_SIZE;
+    for (subtree_height, index) in aligned_subtrees(first, last) {
+        let leaves = &data[(index << subtree_height) * NODE_SIZE..][..NODE_SIZE << subtree_height];
+        let mut node = subtree_root(leaves)?;
+        let mut index = index;
+        for level in subtree_height..height {
+            let sibling = read_node(level, index ^ 1)?;
+            node = if index % 2 == 0 {
+                piece_hash(node.as_ref(), sibling.as_ref())
+            } else {
+                piece_hash(sibling.as_ref(), node.as_ref())
+            };
+            index >>= 1;
+        }
+
+        if node != *comm_d {
+            return Err(mismatch().into());
+        }
+    }
+
+    info!("verify_unsealed_range:finish");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::{
+        merkle::create_base_merkle_tree, util::default_rows_to_discard, TEST_SEED,
+    };
+    use tempfile::tempdir;
+
+    use crate::types::{DataTree, BINARY_ARITY};
+
+    #[test]
+    fn test_aligned_subtrees() {
+        assert_eq!(aligned_subtrees(0, 8), vec![(3, 0)]);
+        assert_eq!(
+            aligned_subtrees(3, 13),
+            vec![(0, 3), (2, 1), (2, 2), (0, 12)]
+        );
+    }
+
+    #[test]
+    fn test_verify_unsealed_range() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        let leafs = 64;
+        let mut data: Vec<u8> = (0..leafs * NODE_SIZE).map(|_| rng.gen()).collect();
+        for node in data.chunks_mut(NODE_SIZE) {
+            node[NODE_SIZE - 1] &= 0b0011_1111;
+        }
+
+        let cache_dir = tempdir().expect("tempdir failure");
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(leafs, BINARY_ARITY),
+        );
+        let tree_d = create_base_merkle_tree::<DataTree>(Some(config), leafs, &data)
+            .expect("failed to build tree-d");
+        let comm_d = tree_d.root();
+        drop(tree_d);
+
+        verify_unsealed_range(cache_dir.path(), &comm_d, &data, 100, 900)
+            .expect("valid range failed to verify");
+
+        data[500] ^= 1;
+        let err = verify_unsealed_range(cache_dir.path(), &comm_d, &data, 100, 900)
+            .expect_err("corrupt range verified");
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::UnsealedDataMismatch(100, 900))
+        ));
+        // Only the requested range is checked when the tree is available.
+        verify_unsealed_range(cache_dir.path(), &comm_d, &data, 0, 256)
+            .expect("valid range failed to verify");
+
+        // Without the tree, the whole sector is checked against comm_d.
+        std::fs::remove_file(StoreConfig::data_path(
+            cache_dir.path(),
+            &CacheKey::CommDTree.to_string(),
+        ))
+        .expect("failed to remove tree-d");
+        assert!(verify_unsealed_range(cache_dir.path(), &comm_d, &data, 0, 256).is_err());
+        data[500] ^= 1;
+        verify_unsealed_range(cache_dir.path(), &comm_d, &data, 0, 256)
+            .expect("valid sector failed to verify");
+    }
+}
@@ -0,0 +1,490 @@
+#[cfg(feature = "prover")]
+use std::fs::{self, metadata, File, OpenOptions};
+#[cfg(feature = "prover")]
+use std::path::Path;
+
+use anyhow::Result;
+#[cfg(feature = "prover")]
+use anyhow::{ensure, Context};
+use bincode::deserialize;
+#[cfg(feature = "prover")]
+use bincode::serialize;
+#[cfg(feature = "prover")]
+use filecoin_hashers::{Domain, HashFunction, Hasher};
+#[cfg(feature = "prover")]
+use generic_array::typenum::Unsigned;
+use log::info;
+#[cfg(feature = "prover")]
+use memmap::{Mmap, MmapOptions};
+#[cfg(feature = "prover")]
+use merkletree::store::{DiskStore, StoreConfig};
+#[cfg(feature = "prover")]
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSlice, ParallelSliceMut};
+#[cfg(feature = "prover")]
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    merkle::{
+        create_base_merkle_tree, create_tree, get_base_tree_count, split_config_and_replica,
+        BinaryMerkleTree, MerkleTreeWrapper,
+    },
+    util::{default_rows_to_discard, NODE_SIZE},
+};
+use storage_proofs_core::{merkle::MerkleTreeTrait, proof::ProofScheme};
+#[cfg(feature = "prover")]
+use storage_proofs_porep::stacked::PersistentAux;
+#[cfg(feature = "prover")]
+use storage_proofs_update::{decode, encode, phi, PrivateInputs, Rhos};
+use storage_proofs_update::{EmptySectorUpdate, PublicInputs};
+
+use crate::{
+    api::{as_safe_commitment, spans::api_span},
+    constants::{DefaultPieceDomain, DefaultPieceHasher},
+    parameters::empty_sector_update_public_params,
+    types::{ChallengeSeed, Commitment, EmptySectorUpdateProof, PoRepConfig},
+};
+#[cfg(feature = "prover")]
+use crate::{
+    api::{
+        build_tree_r_last, commitment_from_fr, get_base_tree_leafs, get_base_tree_size, get_p_aux,
+        persist_p_aux,
+    },
+    constants::DefaultBinaryTree,
+    pieces::verify_pieces,
+    types::{EmptySectorUpdateEncoded, PaddedBytesAmount, PieceInfo, SectorSize, BINARY_ARITY},
+};
+
+/// Encodes the data in `staged_data_path` into a copy of the CC replica at `sector_key_path`,
+/// writing the updated replica to `new_replica_path` and its trees and `p_aux` to
+/// `new_cache_path`. The sector key's column commitment is reused, so no SDR is performed.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration the sector key was sealed with.
+/// * `new_replica_path` - path where the updated replica will be written.
+/// * `new_cache_path` - directory where the updated replica's cache files will be written.
+/// * `sector_key_path` - path to the existing CC replica (the sector key).
+/// * `sector_key_cache_path` - cache directory of the sector key.
+/// * `staged_data_path` - path to the staged (fr32 padded) data holding the new pieces.
+/// * `piece_infos` - the piece info (commitment and byte length) for each piece in the sector.
+#[cfg(feature = "prover")]
+#[allow(clippy::too_many_arguments)]
+pub fn encode_into<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    new_replica_path: &Path,
+    new_cache_path: &Path,
+    sector_key_path: &Path,
+    sector_key_cache_path: &Path,
+    staged_data_path: &Path,
+    piece_infos: &[PieceInfo],
+) -> Result<EmptySectorUpdateEncoded> {
+    let _span = api_span("encode_into")
+        .porep_config(&porep_config)
+        .entered();
+    info!("encode_into:start");
+
+    ensure!(
+        metadata(staged_data_path)?.is_file(),
+        "staged_data_path must be a file"
+    );
+    ensure!(
+        metadata(sector_key_path)?.is_file(),
+        "sector_key_path must be a file"
+    );
+    ensure!(
+        metadata(new_cache_path)?.is_dir(),
+        "new_cache_path must be a directory"
+    );
+
+    let sector_bytes = usize::from(PaddedBytesAmount::from(porep_config));
+    let nodes_count = sector_bytes / NODE_SIZE;
+
+    let p_aux_old = get_p_aux::<Tree>(sector_key_cache_path)?;
+    let comm_r_old =
+        <Tree::Hasher as Hasher>::Function::hash2(&p_aux_old.comm_c, &p_aux_old.comm_r_last);
+
+    // The staged data is zero-padded to the sector size below, a longer file would be truncated.
+    let staged_len = fs::metadata(staged_data_path)
+        .with_context(|| {
+            format!(
+                "could not stat staged_data_path={:?}",
+                staged_data_path.display()
+            )
+        })?
+        .len();
+    ensure!(
+        staged_len <= sector_bytes as u64,
+        "staged_data_path={:?} is {} bytes long, more than the sector size of {} bytes",
+        staged_data_path.display(),
+        staged_len,
+        sector_bytes
+    );
+
+    // Copy the staged data to the output location, where it will be encoded in place.
+    fs::copy(staged_data_path, new_replica_path).with_context(|| {
+        format!(
+            "could not copy staged_data_path={:?} to new_replica_path={:?}",
+            staged_data_path.display(),
+            new_replica_path.display()
+        )
+    })?;
+    let f_data = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(new_replica_path)
+        .with_context(|| {
+            format!(
+                "could not open new_replica_path={:?}",
+                new_replica_path.display()
+            )
+        })?;
+    // Zero-pad the data to the requested size by extending the underlying file if needed.
+    f_data.set_len(sector_bytes as u64)?;
+    let mut data = unsafe {
+        MmapOptions::new().map_mut(&f_data).with_context(|| {
+            format!(
+                "could not mmap new_replica_path={:?}",
+                new_replica_path.display()
+            )
+        })?
+    };
+
+    info!("building merkle tree for the new data");
+    let tree_d_root = {
+        let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
+        let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
+        let config = StoreConfig::new(
+            new_cache_path,
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(base_tree_leafs, BINARY_ARITY),
+        );
+
+        let data_tree = create_base_merkle_tree::<BinaryMerkleTree<DefaultPieceHasher>>(
+            Some(config),
+            base_tree_leafs,
+            &data,
+        )?;
+        data_tree.root()
+    };
+    let comm_d_new = commitment_from_fr(tree_d_root.into());
+
+    ensure!(
+        verify_pieces(&comm_d_new, piece_infos, porep_config.into())?,
+        "pieces and comm_d do not match"
+    );
+
+    let sector_key = map_file(sector_key_path, sector_bytes)?;
+    let pub_params = empty_sector_update_public_params::<Tree>(&porep_config)?;
+    let phi = phi::<Tree::Hasher, DefaultPieceDomain>(&tree_d_root, &comm_r_old);
+    let rhos = Rhos::new(&phi, pub_params.h, nodes_count)?;
+
+    info!("encoding new data into the sector key");
+    data.par_chunks_mut(NODE_SIZE)
+        .zip(sector_key.par_chunks(NODE_SIZE))
+        .enumerate()
+        .try_for_each(
+            |(i, (data_node_bytes, sector_key_node_bytes))| -> Result<()> {
+                let data_node = DefaultPieceDomain::try_from_bytes(data_node_bytes)?;
+                let sector_key_node =
+                    <Tree::Hasher as Hasher>::Domain::try_from_bytes(sector_key_node_bytes)?;
+                let encoded_node = encode(sector_key_node, data_node, &rhos.rho(i));
+                data_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&encoded_node));
+
+                Ok(())
+            },
+        )?;
+    data.flush()?;
+    drop(sector_key);
+    drop(data);
+
+    info!("building tree_r_last for the updated replica");
+    let comm_r_last_new =
+        build_tree_r_last::<Tree>(porep_config.sector_size, new_cache_path, new_replica_path)?;
+    let comm_r_new = <Tree::Hasher as Hasher>::Function::hash2(&p_aux_old.comm_c, &comm_r_last_new);
+
+    // The updated replica shares its column commitment with the sector key.
+    persist_p_aux::<Tree>(
+        &PersistentAux {
+            comm_c: p_aux_old.comm_c,
+            comm_r_last: comm_r_last_new,
+        },
+        new_cache_path,
+    )?;
+
+    let out = EmptySectorUpdateEncoded {
+        comm_r_new: commitment_from_fr(comm_r_new.into()),
+        comm_r_last_new: commitment_from_fr(comm_r_last_new.into()),
+        comm_d_new,
+    };
+
+    info!("encode_into:finish");
+    Ok(out)
+}
+
+/// Recovers the (fr32 padded) data encoded into the updated replica at `replica_path`, writing
+/// it to `out_data_path`.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration the sector key was sealed with.
+/// * `out_data_path` - path where the decoded data will be written.
+/// * `replica_path` - path to the updated replica.
+/// * `sector_key_path` - path to the sector key the replica was encoded into.
+/// * `sector_key_cache_path` - cache directory of the sector key.
+/// * `comm_d_new` - the data commitment of the encoded data.
+#[cfg(feature = "prover")]
+pub fn decode_from<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    out_data_path: &Path,
+    replica_path: &Path,
+    sector_key_path: &Path,
+    sector_key_cache_path: &Path,
+    comm_d_new: Commitment,
+) -> Result<()> {
+    let _span = api_span("decode_from")
+        .porep_config(&porep_config)
+        .entered();
+    info!("decode_from:start");
+
+    let sector_bytes = usize::from(PaddedBytesAmount::from(porep_config));
+    let nodes_count = sector_bytes / NODE_SIZE;
+
+    let p_aux_old = get_p_aux::<Tree>(sector_key_cache_path)?;
+    let comm_r_old =
+        <Tree::Hasher as Hasher>::Function::hash2(&p_aux_old.comm_c, &p_aux_old.comm_r_last);
+    let comm_d_new_safe: DefaultPieceDomain = as_safe_commitment(&comm_d_new, "comm_d_new")?;
+
+    let pub_params = empty_sector_update_public_params::<Tree>(&porep_config)?;
+    let phi = phi::<Tree::Hasher, DefaultPieceDomain>(&comm_d_new_safe, &comm_r_old);
+    let rhos = Rhos::new(&phi, pub_params.h, nodes_count)?;
+
+    let replica = map_file(replica_path, sector_bytes)?;
+    let sector_key = map_file(sector_key_path, sector_bytes)?;
+
+    let f_out = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(out_data_path)
+        .with_context(|| format!("could not open out_data_path={:?}", out_data_path.display()))?;
+    // Resizing an existing, longer file would truncate whatever it holds past the sector.
+    let out_len = f_out.metadata()?.len();
+    ensure!(
+        out_len <= sector_bytes as u64,
+        "out_data_path={:?} is {} bytes long, more than the sector size of {} bytes",
+        out_data_path.display(),
+        out_len,
+        sector_bytes
+    );
+    f_out.set_len(sector_bytes as u64)?;
+    let mut out_data = unsafe {
+        MmapOptions::new().map_mut(&f_out).with_context(|| {
+            format!("could not mmap out_data_path={:?}", out_data_path.display())
+        })?
+    };
+
+    out_data
+        .par_chunks_mut(NODE_SIZE)
+        .zip(
+            replica
+                .par_chunks(NODE_SIZE)
+                .zip(sector_key.par_chunks(NODE_SIZE)),
+        )
+        .enumerate()
+        .try_for_each(
+            |(i, (out_node_bytes, (replica_node_bytes, sector_key_node_bytes)))| -> Result<()> {
+                let replica_node =
+                    <Tree::Hasher as Hasher>::Domain::try_from_bytes(replica_node_bytes)?;
+                let sector_key_node =
+                    <Tree::Hasher as Hasher>::Domain::try_from_bytes(sector_key_node_bytes)?;
+                let data_node: DefaultPieceDomain =
+                    decode(sector_key_node, replica_node, &rhos.rho_inv(i));
+                out_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&data_node));
+
+                Ok(())
+            },
+        )?;
+    out_data.flush()?;
+
+    info!("decode_from:finish");
+    Ok(())
+}
+
+/// Generates a proof that the replica at `replica_path` is the sector key at `sector_key_path`
+/// with the data committed to by `comm_d_new` encoded into it.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration the sector key was sealed with.
+/// * `comm_r_old` - replica commitment of the sector key.
+/// * `comm_r_new` - replica commitment of the updated replica.
+/// * `comm_d_new` - data commitment of the encoded data.
+/// * `randomness` - verifier randomness used to derive the challenges.
+/// * `sector_key_path` - path to the sector key.
+/// * `sector_key_cache_path` - cache directory of the sector key.
+/// * `replica_path` - path to the updated replica.
+/// * `replica_cache_path` - cache directory of the updated replica.
+#[cfg(feature = "prover")]
+#[allow(clippy::too_many_arguments)]
+pub fn generate_empty_sector_update_proof<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+    randomness: &ChallengeSeed,
+    sector_key_path: &Path,
+    sector_key_cache_path: &Path,
+    replica_path: &Path,
+    replica_cache_path: &Path,
+) -> Result<EmptySectorUpdateProof> {
+    let _span = api_span("generate_empty_sector_update_proof")
+        .porep_config(&porep_config)
+        .entered();
+    info!("generate_empty_sector_update_proof:start");
+
+    let comm_r_old_safe = as_safe_commitment(&comm_r_old, "comm_r_old")?;
+    let comm_r_new_safe = as_safe_commitment(&comm_r_new, "comm_r_new")?;
+    let comm_d_new_safe: DefaultPieceDomain = as_safe_commitment(&comm_d_new, "comm_d_new")?;
+    let randomness_safe = as_safe_commitment(randomness, "randomness")?;
+
+    let p_aux_new = get_p_aux::<Tree>(replica_cache_path)?;
+
+    let tree_r_old = open_tree_r_last::<Tree>(
+        porep_config.sector_size,
+        sector_key_cache_path,
+        sector_key_path,
+    )?;
+    let tree_r_new =
+        open_tree_r_last::<Tree>(porep_config.sector_size, replica_cache_path, replica_path)?;
+    let tree_d_new = {
+        let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
+        let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
+        let mut config = StoreConfig::new(
+            replica_cache_path,
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(base_tree_leafs, BINARY_ARITY),
+        );
+        config.size = Some(base_tree_size);
+
+        let store: DiskStore<DefaultPieceDomain> =
+            DiskStore::new_from_disk(base_tree_size, BINARY_ARITY, &config)?;
+        BinaryMerkleTree::<DefaultPieceHasher>::from_data_store(store, base_tree_leafs)?
+    };
+
+    let pub_params = empty_sector_update_public_params::<Tree>(&porep_config)?;
+    let pub_inputs = PublicInputs {
+        comm_r_old: comm_r_old_safe,
+        comm_d_new: comm_d_new_safe,
+        comm_r_new: comm_r_new_safe,
+        randomness: randomness_safe,
+    };
+    let priv_inputs = PrivateInputs::<Tree, DefaultPieceHasher> {
+        comm_c: p_aux_new.comm_c,
+        tree_r_old: &tree_r_old,
+        tree_r_new: &tree_r_new,
+        tree_d_new: &tree_d_new,
+    };
+
+    let proof = EmptySectorUpdate::<Tree, DefaultPieceHasher>::prove(
+        &pub_params,
+        &pub_inputs,
+        &priv_inputs,
+    )?;
+
+    let out = EmptySectorUpdateProof(serialize(&proof)?);
+
+    info!("generate_empty_sector_update_proof:finish");
+    Ok(out)
+}
+
+/// Verifies a proof produced by `generate_empty_sector_update_proof`.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration the sector key was sealed with.
+/// * `proof` - the sector update proof.
+/// * `comm_r_old` - replica commitment of the sector key.
+/// * `comm_r_new` - replica commitment of the updated replica.
+/// * `comm_d_new` - data commitment of the encoded data.
+/// * `randomness` - verifier randomness used to derive the challenges.
+pub fn verify_empty_sector_update_proof<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    proof: &EmptySectorUpdateProof,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+    randomness: &ChallengeSeed,
+) -> Result<bool> {
+    let _span = api_span("verify_empty_sector_update_proof")
+        .porep_config(&porep_config)
+        .entered();
+    info!("verify_empty_sector_update_proof:start");
+
+    let comm_r_old_safe = as_safe_commitment(&comm_r_old, "comm_r_old")?;
+    let comm_r_new_safe = as_safe_commitment(&comm_r_new, "comm_r_new")?;
+    let comm_d_new_safe: DefaultPieceDomain = as_safe_commitment(&comm_d_new, "comm_d_new")?;
+    let randomness_safe = as_safe_commitment(randomness, "randomness")?;
+
+    let pub_params = empty_sector_update_public_params::<Tree>(&porep_config)?;
+    let pub_inputs = PublicInputs {
+        comm_r_old: comm_r_old_safe,
+        comm_d_new: comm_d_new_safe,
+        comm_r_new: comm_r_new_safe,
+        randomness: randomness_safe,
+    };
+    let proof: <EmptySectorUpdate<'_, Tree, DefaultPieceHasher> as ProofScheme<'_>>::Proof =
+        deserialize(&proof.0)?;
+
+    let is_valid =
+        EmptySectorUpdate::<Tree, DefaultPieceHasher>::verify(&pub_params, &pub_inputs, &proof)?;
+
+    info!("verify_empty_sector_update_proof:finish");
+    Ok(is_valid)
+}
+
+#[cfg(feature = "prover")]
+fn map_file(path: &Path, expected_len: usize) -> Result<Mmap> {
+    let f = File::open(path).with_context(|| format!("could not open path={:?}", path))?;
+    ensure!(
+        f.metadata()?.len() as usize == expected_len,
+        "{:?} is not {} bytes long",
+        path,
+        expected_len
+    );
+
+    unsafe { MmapOptions::new().map(&f) }.with_context(|| format!("could not mmap path={:?}", path))
+}
+
+#[cfg(feature = "prover")]
+fn open_tree_r_last<Tree: 'static + MerkleTreeTrait>(
+    sector_size: SectorSize,
+    cache_path: &Path,
+    replica_path: &Path,
+) -> Result<
+    MerkleTreeWrapper<
+        Tree::Hasher,
+        Tree::Store,
+        Tree::Arity,
+        Tree::SubTreeArity,
+        Tree::TopTreeArity,
+    >,
+> {
+    let base_tree_size = get_base_tree_size::<Tree>(sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+
+    let mut config = StoreConfig::new(
+        cache_path,
+        CacheKey::CommRLastTree.to_string(),
+        default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize()),
+    );
+    config.size = Some(base_tree_size);
+
+    let tree_count = get_base_tree_count::<Tree>();
+    let (configs, replica_config) = split_config_and_replica(
+        config,
+        replica_path.to_path_buf(),
+        base_tree_leafs,
+        tree_count,
+    )?;
+
+    create_tree::<Tree>(base_tree_size, &configs, Some(&replica_config))
+}
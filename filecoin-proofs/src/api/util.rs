@@ -1,11 +1,23 @@
+#[cfg(feature = "prover")]
+use std::fs::{self, File};
+#[cfg(feature = "prover")]
+use std::io::Write;
 use std::mem::size_of;
+#[cfg(feature = "prover")]
+use std::path::Path;
 
 use anyhow::{Context, Result};
 use bellperson::bls::Fr;
+#[cfg(feature = "prover")]
+use bincode::{deserialize, serialize};
 use filecoin_hashers::{Domain, Hasher};
 use fr32::{bytes_into_fr, fr_into_bytes};
 use merkletree::merkle::{get_merkle_tree_leafs, get_merkle_tree_len};
+#[cfg(feature = "prover")]
+use storage_proofs_core::cache_key::CacheKey;
 use storage_proofs_core::merkle::{get_base_tree_count, MerkleTreeTrait};
+#[cfg(feature = "prover")]
+use storage_proofs_porep::stacked::PersistentAux;
 use typenum::Unsigned;
 
 use crate::types::{Commitment, SectorSize};
@@ -38,3 +50,30 @@ pub fn get_base_tree_size<Tree: MerkleTreeTrait>(sector_size: SectorSize) -> Res
 pub fn get_base_tree_leafs<Tree: MerkleTreeTrait>(base_tree_size: usize) -> Result<usize> {
     get_merkle_tree_leafs(base_tree_size, Tree::Arity::to_usize())
 }
+
+#[cfg(feature = "prover")]
+pub(crate) fn get_p_aux<Tree: MerkleTreeTrait>(
+    cache_path: &Path,
+) -> Result<PersistentAux<<Tree::Hasher as Hasher>::Domain>> {
+    let p_aux_path = cache_path.join(CacheKey::PAux.to_string());
+    let p_aux_bytes = fs::read(&p_aux_path)
+        .with_context(|| format!("could not read file p_aux={:?}", p_aux_path))?;
+
+    deserialize(&p_aux_bytes).map_err(Into::into)
+}
+
+#[cfg(feature = "prover")]
+pub(crate) fn persist_p_aux<Tree: MerkleTreeTrait>(
+    p_aux: &PersistentAux<<Tree::Hasher as Hasher>::Domain>,
+    cache_path: &Path,
+) -> Result<()> {
+    let p_aux_path = cache_path.join(CacheKey::PAux.to_string());
+    let mut f_p_aux = File::create(&p_aux_path)
+        .with_context(|| format!("could not create file p_aux={:?}", p_aux_path))?;
+    let p_aux_bytes = serialize(p_aux)?;
+    f_p_aux
+        .write_all(&p_aux_bytes)
+        .with_context(|| format!("could not write to file p_aux={:?}", p_aux_path))?;
+
+    Ok(())
+}
@@ -2,35 +2,43 @@ use std::collections::BTreeMap;
 
 use anyhow::{ensure, Context, Result};
 use filecoin_hashers::Hasher;
-use log::info;
+use log::{info, warn};
 use storage_proofs_core::{
     compound_proof::{self, CompoundProof},
     merkle::MerkleTreeTrait,
     multi_proof::MultiProof,
     sector::SectorId,
 };
-use storage_proofs_post::fallback::{
-    self, FallbackPoSt, FallbackPoStCompound, PrivateSector, PublicSector,
-};
+#[cfg(feature = "prover")]
+use storage_proofs_post::fallback::PrivateSector;
+use storage_proofs_post::fallback::{self, FallbackPoSt, FallbackPoStCompound, PublicSector};
 
+#[cfg(feature = "prover")]
+use crate::{
+    api::partition_vanilla_proofs,
+    caches::get_post_params,
+    types::{FallbackPoStSectorProof, PrivateReplicaInfo, SnarkProof},
+};
 use crate::{
-    api::{as_safe_commitment, get_partitions_for_window_post, partition_vanilla_proofs},
-    caches::{get_post_params, get_post_verifying_key},
+    api::{as_safe_commitment, batch_verify_post, get_partitions_for_window_post, spans::api_span},
+    caches::get_post_verifying_key,
     parameters::window_post_setup_params,
-    types::{
-        ChallengeSeed, FallbackPoStSectorProof, PoStConfig, PrivateReplicaInfo, ProverId,
-        PublicReplicaInfo, SnarkProof,
-    },
+    types::{ChallengeSeed, PoStConfig, ProverId, PublicReplicaInfo},
     PoStType,
 };
 
 /// Generates a Window proof-of-spacetime with provided vanilla proofs.
+#[cfg(feature = "prover")]
 pub fn generate_window_post_with_vanilla<Tree: 'static + MerkleTreeTrait>(
     post_config: &PoStConfig,
     randomness: &ChallengeSeed,
     prover_id: ProverId,
     vanilla_proofs: Vec<FallbackPoStSectorProof<Tree>>,
 ) -> Result<SnarkProof> {
+    let _span = api_span("generate_window_post_with_vanilla")
+        .post_config(post_config)
+        .prover_id(&prover_id)
+        .entered();
     info!("generate_window_post_with_vanilla:start");
     ensure!(
         post_config.typ == PoStType::Window,
@@ -93,12 +101,17 @@ pub fn generate_window_post_with_vanilla<Tree: 'static + MerkleTreeTrait>(
 }
 
 /// Generates a Window proof-of-spacetime.
+#[cfg(feature = "prover")]
 pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
     post_config: &PoStConfig,
     randomness: &ChallengeSeed,
     replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
     prover_id: ProverId,
 ) -> Result<SnarkProof> {
+    let _span = api_span("generate_window_post")
+        .post_config(post_config)
+        .prover_id(&prover_id)
+        .entered();
     info!("generate_window_post:start");
     ensure!(
         post_config.typ == PoStType::Window,
@@ -180,6 +193,10 @@ pub fn verify_window_post<Tree: 'static + MerkleTreeTrait>(
     prover_id: ProverId,
     proof: &[u8],
 ) -> Result<bool> {
+    let _span = api_span("verify_window_post")
+        .post_config(post_config)
+        .prover_id(&prover_id)
+        .entered();
     info!("verify_window_post:start");
 
     ensure!(
@@ -242,3 +259,110 @@ pub fn verify_window_post<Tree: 'static + MerkleTreeTrait>(
 
     Ok(true)
 }
+
+/// Verifies many window proofs-of-spacetime made under the same `post_config` in batched
+/// pairing checks, one per partition count.
+///
+/// The entry at index `i` is made of `randomness[i]`, `prover_ids[i]`, `replicas[i]` and
+/// `proofs[i]`, as they would be passed to `verify_window_post`. Returns whether each entry is
+/// valid. Entries which are malformed (e.g. non-canonical randomness or a truncated proof) are
+/// reported as invalid rather than failing the whole batch.
+pub fn verify_window_post_batch<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &[ChallengeSeed],
+    prover_ids: &[ProverId],
+    replicas: &[&BTreeMap<SectorId, PublicReplicaInfo>],
+    proofs: &[&[u8]],
+) -> Result<Vec<bool>> {
+    let _span = api_span("verify_window_post_batch")
+        .post_config(post_config)
+        .entered();
+    info!("verify_window_post_batch:start");
+
+    ensure!(
+        post_config.typ == PoStType::Window,
+        "invalid post config type"
+    );
+    ensure!(!proofs.is_empty(), "Cannot verify empty batch");
+    let l = proofs.len();
+    ensure!(l == randomness.len(), "Inconsistent inputs");
+    ensure!(l == prover_ids.len(), "Inconsistent inputs");
+    ensure!(l == replicas.len(), "Inconsistent inputs");
+
+    let verifying_key = get_post_verifying_key::<Tree>(&post_config)?;
+
+    // Proofs over different numbers of sectors have different numbers of partitions, and
+    // can only be batched with each other if those match.
+    let mut batches: BTreeMap<Option<usize>, Vec<_>> = BTreeMap::new();
+    let mut valid = vec![false; l];
+
+    for i in 0..l {
+        let partitions = get_partitions_for_window_post(replicas[i].len(), &post_config);
+        let entry = window_post_public_inputs::<Tree>(&randomness[i], &prover_ids[i], replicas[i])
+            .and_then(|pub_inputs| {
+                let multi_proof =
+                    MultiProof::new_from_reader(partitions, proofs[i], &verifying_key)?;
+                Ok((pub_inputs, multi_proof))
+            });
+
+        match entry {
+            Ok(entry) => batches.entry(partitions).or_default().push((i, entry)),
+            Err(err) => warn!("verify_window_post_batch: invalid entry {}: {:?}", i, err),
+        }
+    }
+
+    for (partitions, batch) in batches {
+        let setup_params = compound_proof::SetupParams {
+            vanilla_params: window_post_setup_params(&post_config),
+            partitions,
+            priority: false,
+        };
+        let pub_params: compound_proof::PublicParams<'_, FallbackPoSt<'_, Tree>> =
+            FallbackPoStCompound::setup(&setup_params)?;
+
+        let (indices, entries): (Vec<usize>, Vec<_>) = batch.into_iter().unzip();
+        let (pub_inputs, multi_proofs): (Vec<_>, Vec<_>) = entries.into_iter().unzip();
+
+        let batch_valid = batch_verify_post::<Tree>(
+            &pub_params,
+            &pub_inputs,
+            &multi_proofs,
+            &fallback::ChallengeRequirements {
+                minimum_challenge_count: post_config.challenge_count * post_config.sector_count,
+            },
+        )?;
+        for (i, is_valid) in indices.into_iter().zip(batch_valid) {
+            valid[i] = is_valid;
+        }
+    }
+
+    info!("verify_window_post_batch:finish");
+
+    Ok(valid)
+}
+
+fn window_post_public_inputs<Tree: 'static + MerkleTreeTrait>(
+    randomness: &ChallengeSeed,
+    prover_id: &ProverId,
+    replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+) -> Result<fallback::PublicInputs<<Tree::Hasher as Hasher>::Domain>> {
+    let pub_sectors = replicas
+        .iter()
+        .map(|(sector_id, replica)| {
+            let comm_r = replica.safe_comm_r().with_context(|| {
+                format!("verify_window_post: safe_comm_r failed: {:?}", sector_id)
+            })?;
+            Ok(PublicSector {
+                id: *sector_id,
+                comm_r,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(fallback::PublicInputs {
+        randomness: as_safe_commitment(randomness, "randomness")?,
+        prover_id: as_safe_commitment(prover_id, "prover_id")?,
+        sectors: pub_sectors,
+        k: None,
+    })
+}
@@ -1,35 +1,44 @@
 use anyhow::{ensure, Context, Result};
 use filecoin_hashers::Hasher;
-use log::info;
+use log::{info, warn};
 use storage_proofs_core::{
     compound_proof::{self, CompoundProof},
     merkle::MerkleTreeTrait,
     multi_proof::MultiProof,
     sector::SectorId,
 };
+#[cfg(feature = "prover")]
+use storage_proofs_post::fallback::PrivateSector;
 use storage_proofs_post::fallback::{
-    self, generate_sector_challenges, FallbackPoSt, FallbackPoStCompound, PrivateSector,
-    PublicSector,
+    self, generate_sector_challenges, FallbackPoSt, FallbackPoStCompound, PublicSector,
 };
 
+#[cfg(feature = "prover")]
 use crate::{
-    api::{as_safe_commitment, partition_vanilla_proofs},
-    caches::{get_post_params, get_post_verifying_key},
+    api::partition_vanilla_proofs,
+    caches::get_post_params,
+    types::{FallbackPoStSectorProof, PrivateReplicaInfo, SnarkProof},
+};
+use crate::{
+    api::{as_safe_commitment, batch_verify_post, spans::api_span},
+    caches::get_post_verifying_key,
     parameters::winning_post_setup_params,
-    types::{
-        ChallengeSeed, Commitment, FallbackPoStSectorProof, PoStConfig, PrivateReplicaInfo,
-        ProverId, PublicReplicaInfo, SnarkProof,
-    },
+    types::{ChallengeSeed, Commitment, PoStConfig, ProverId, PublicReplicaInfo},
     PoStType,
 };
 
 /// Generates a Winning proof-of-spacetime with provided vanilla proofs.
+#[cfg(feature = "prover")]
 pub fn generate_winning_post_with_vanilla<Tree: 'static + MerkleTreeTrait>(
     post_config: &PoStConfig,
     randomness: &ChallengeSeed,
     prover_id: ProverId,
     vanilla_proofs: Vec<FallbackPoStSectorProof<Tree>>,
 ) -> Result<SnarkProof> {
+    let _span = api_span("generate_winning_post_with_vanilla")
+        .post_config(post_config)
+        .prover_id(&prover_id)
+        .entered();
     info!("generate_winning_post_with_vanilla:start");
     ensure!(
         post_config.typ == PoStType::Winning,
@@ -95,12 +104,17 @@ pub fn generate_winning_post_with_vanilla<Tree: 'static + MerkleTreeTrait>(
 }
 
 /// Generates a Winning proof-of-spacetime.
+#[cfg(feature = "prover")]
 pub fn generate_winning_post<Tree: 'static + MerkleTreeTrait>(
     post_config: &PoStConfig,
     randomness: &ChallengeSeed,
     replicas: &[(SectorId, PrivateReplicaInfo<Tree>)],
     prover_id: ProverId,
 ) -> Result<SnarkProof> {
+    let _span = api_span("generate_winning_post")
+        .post_config(post_config)
+        .prover_id(&prover_id)
+        .entered();
     info!("generate_winning_post:start");
     ensure!(
         post_config.typ == PoStType::Winning,
@@ -193,6 +207,10 @@ pub fn generate_winning_post_sector_challenge<Tree: MerkleTreeTrait>(
     sector_set_size: u64,
     prover_id: Commitment,
 ) -> Result<Vec<u64>> {
+    let _span = api_span("generate_winning_post_sector_challenge")
+        .post_config(post_config)
+        .prover_id(&prover_id)
+        .entered();
     info!("generate_winning_post_sector_challenge:start");
     ensure!(sector_set_size != 0, "empty sector set is invalid");
     ensure!(
@@ -229,6 +247,10 @@ pub fn verify_winning_post<Tree: 'static + MerkleTreeTrait>(
     prover_id: ProverId,
     proof: &[u8],
 ) -> Result<bool> {
+    let _span = api_span("verify_winning_post")
+        .post_config(post_config)
+        .prover_id(&prover_id)
+        .entered();
     info!("verify_winning_post:start");
 
     ensure!(
@@ -302,3 +324,122 @@ pub fn verify_winning_post<Tree: 'static + MerkleTreeTrait>(
 
     Ok(true)
 }
+
+/// Verifies many winning proofs-of-spacetime made under the same `post_config` in a single
+/// batched pairing check.
+///
+/// The entry at index `i` is made of `randomness[i]`, `prover_ids[i]`, `replicas[i]` and
+/// `proofs[i]`, as they would be passed to `verify_winning_post`. Returns whether each entry is
+/// valid. Entries which are malformed (e.g. non-canonical randomness or a truncated proof) are
+/// reported as invalid rather than failing the whole batch.
+pub fn verify_winning_post_batch<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &[ChallengeSeed],
+    prover_ids: &[ProverId],
+    replicas: &[&[(SectorId, PublicReplicaInfo)]],
+    proofs: &[&[u8]],
+) -> Result<Vec<bool>> {
+    let _span = api_span("verify_winning_post_batch")
+        .post_config(post_config)
+        .entered();
+    info!("verify_winning_post_batch:start");
+
+    ensure!(
+        post_config.typ == PoStType::Winning,
+        "invalid post config type"
+    );
+    ensure!(!proofs.is_empty(), "Cannot verify empty batch");
+    let l = proofs.len();
+    ensure!(l == randomness.len(), "Inconsistent inputs");
+    ensure!(l == prover_ids.len(), "Inconsistent inputs");
+    ensure!(l == replicas.len(), "Inconsistent inputs");
+
+    let vanilla_params = winning_post_setup_params(&post_config)?;
+    let param_sector_count = vanilla_params.sector_count;
+
+    let setup_params = compound_proof::SetupParams {
+        vanilla_params,
+        partitions: None,
+        priority: false,
+    };
+    let pub_params: compound_proof::PublicParams<'_, FallbackPoSt<'_, Tree>> =
+        FallbackPoStCompound::setup(&setup_params)?;
+    let verifying_key = get_post_verifying_key::<Tree>(&post_config)?;
+
+    let mut indices = Vec::with_capacity(l);
+    let mut pub_inputs = Vec::with_capacity(l);
+    let mut multi_proofs = Vec::with_capacity(l);
+
+    for i in 0..l {
+        let entry = winning_post_public_inputs::<Tree>(
+            post_config,
+            param_sector_count,
+            &randomness[i],
+            &prover_ids[i],
+            replicas[i],
+        )
+        .and_then(|entry_pub_inputs| {
+            let multi_proof = MultiProof::new_from_reader(None, proofs[i], &verifying_key)?;
+            Ok((entry_pub_inputs, multi_proof))
+        });
+
+        match entry {
+            Ok((entry_pub_inputs, multi_proof)) => {
+                indices.push(i);
+                pub_inputs.push(entry_pub_inputs);
+                multi_proofs.push(multi_proof);
+            }
+            Err(err) => warn!("verify_winning_post_batch: invalid entry {}: {:?}", i, err),
+        }
+    }
+
+    let mut valid = vec![false; l];
+    let batch_valid = batch_verify_post::<Tree>(
+        &pub_params,
+        &pub_inputs,
+        &multi_proofs,
+        &fallback::ChallengeRequirements {
+            minimum_challenge_count: post_config.challenge_count * post_config.sector_count,
+        },
+    )?;
+    for (i, is_valid) in indices.into_iter().zip(batch_valid) {
+        valid[i] = is_valid;
+    }
+
+    info!("verify_winning_post_batch:finish");
+
+    Ok(valid)
+}
+
+fn winning_post_public_inputs<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    param_sector_count: usize,
+    randomness: &ChallengeSeed,
+    prover_id: &ProverId,
+    replicas: &[(SectorId, PublicReplicaInfo)],
+) -> Result<fallback::PublicInputs<<Tree::Hasher as Hasher>::Domain>> {
+    ensure!(
+        post_config.sector_count == replicas.len(),
+        "invalid amount of replicas provided"
+    );
+
+    let mut pub_sectors = Vec::with_capacity(param_sector_count);
+    for _ in 0..param_sector_count {
+        for (sector_id, replica) in replicas.iter() {
+            let comm_r = replica.safe_comm_r().with_context(|| {
+                format!("verify_winning_post: safe_comm_r failed: {:?}", sector_id)
+            })?;
+            pub_sectors.push(PublicSector {
+                id: *sector_id,
+                comm_r,
+            });
+        }
+    }
+
+    Ok(fallback::PublicInputs {
+        randomness: as_safe_commitment(randomness, "randomness")?,
+        prover_id: as_safe_commitment(prover_id, "prover_id")?,
+        sectors: pub_sectors,
+        k: None,
+    })
+}
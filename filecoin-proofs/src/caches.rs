@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 
 use anyhow::Result;
 use bellperson::{
@@ -54,52 +54,74 @@ lazy_static! {
 /// consisting of sector size and pow2 num proofs to aggregate).
 #[derive(Debug, Default)]
 pub struct SRSCache<G> {
-    data: HashMap<String, OnceCell<Arc<G>>>,
+    identifier: String,
+    data: RwLock<HashMap<String, Arc<OnceCell<Arc<G>>>>>,
 }
 
 impl<G> SRSCache<G> {
     /// Initializes the cache by pre-populating the internal map with
     /// all supported keys that could be looked up at a later time.
     pub fn with_defaults(identifier: &str) -> Self {
-        let mut data = HashMap::new();
+        let cache = Self {
+            identifier: identifier.to_string(),
+            data: Default::default(),
+        };
+        for sector_size in &PUBLISHED_SECTOR_SIZES {
+            cache.add_sector_size(*sector_size);
+        }
+
+        cache
+    }
+
+    /// Adds the keys of all supported numbers of proofs to aggregate for `sector_size`.
+    pub fn add_sector_size(&self, sector_size: u64) {
+        let mut data = self.data.write().expect("SRS cache poisoned");
         let mut num_proofs_to_aggregate = PROOFS_TESTS_MIN_SNARKS;
 
         loop {
-            for sector_size in &PUBLISHED_SECTOR_SIZES {
-                let key = format!(
-                    "STACKED[{}-{}]-{}",
-                    sector_size, num_proofs_to_aggregate, identifier,
-                );
-                trace!("inserting placeholder srs key with hash key {}", key);
-                data.insert(key, OnceCell::new());
-            }
+            let key = format!(
+                "STACKED[{}-{}]-{}",
+                sector_size, num_proofs_to_aggregate, self.identifier,
+            );
+            trace!("inserting placeholder srs key with hash key {}", key);
+            data.entry(key).or_default();
 
             num_proofs_to_aggregate <<= 1;
             if num_proofs_to_aggregate > PROOFS_TESTS_MAX_SNARKS {
                 break;
             }
         }
-
-        Self { data }
     }
 
     /// Returns `None` for non existent entries, `Some(v)` for existing ones, where `v` is either
     /// the result of running `generator` or already existing one.
-    pub fn get_or_init<F>(&self, key: &str, generator: F) -> Result<Option<&Arc<G>>>
+    pub fn get_or_init<F>(&self, key: &str, generator: F) -> Result<Option<Arc<G>>>
     where
         F: FnOnce() -> Result<G>,
     {
-        if let Some(cell) = self.data.get(key) {
+        let cell = self
+            .data
+            .read()
+            .expect("SRS cache poisoned")
+            .get(key)
+            .cloned();
+        if let Some(cell) = cell {
             trace!("generating or waiting on specialize for {}", key);
             let result =
                 cell.get_or_try_init(|| -> Result<Arc<G>> { Ok(Arc::new(generator()?)) })?;
-            return Ok(Some(result));
+            return Ok(Some(result.clone()));
         }
 
         Ok(None)
     }
 }
 
+/// Makes the SRS keys of `sector_size` cacheable, used for sector sizes registered at runtime.
+pub(crate) fn add_srs_sector_size(sector_size: u64) {
+    SRS_KEY_MEMORY_CACHE.add_sector_size(sector_size);
+    SRS_VERIFIER_KEY_MEMORY_CACHE.add_sector_size(sector_size);
+}
+
 pub fn cache_lookup<F, G>(
     cache_ref: &Mutex<Cache<G>>,
     identifier: String,
@@ -142,7 +164,7 @@ where
 {
     trace!("srs_cache_lookup looking up {}", identifier);
     if let Some(entry) = cache_ref.get_or_init(&identifier, generator)? {
-        return Ok(entry.clone());
+        return Ok(entry);
     }
 
     panic!("unknown identifier {}", identifier);
@@ -1,19 +1,22 @@
 use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::RwLock;
 
 pub use storage_proofs_core::drgraph::BASE_DEGREE as DRG_DEGREE;
 pub use storage_proofs_porep::stacked::EXP_DEGREE;
 
+use anyhow::{anyhow, bail, ensure, Context, Error, Result};
 use filecoin_hashers::{poseidon::PoseidonHasher, sha256::Sha256Hasher, Hasher};
 use lazy_static::lazy_static;
+use log::info;
 use storage_proofs_core::{
     merkle::{BinaryMerkleTree, LCTree, OctLCMerkleTree, OctMerkleTree},
     util::NODE_SIZE,
     MAX_LEGACY_POREP_REGISTERED_PROOF_ID,
 };
-use typenum::{U0, U2, U8};
+use typenum::{U0, U2, U4, U8};
 
-use crate::types::UnpaddedBytesAmount;
+use crate::{caches, types::UnpaddedBytesAmount};
 
 pub const SECTOR_SIZE_2_KIB: u64 = 1 << 11;
 pub const SECTOR_SIZE_4_KIB: u64 = 1 << 12;
@@ -31,6 +34,8 @@ pub const WINNING_POST_SECTOR_COUNT: usize = 1;
 
 pub const WINDOW_POST_CHALLENGE_COUNT: usize = 10;
 
+pub const EMPTY_SECTOR_UPDATE_CHALLENGE_COUNT: usize = 80;
+
 pub const MAX_LEGACY_REGISTERED_SEAL_PROOF_ID: u64 = MAX_LEGACY_POREP_REGISTERED_PROOF_ID;
 
 /// Sector sizes for which parameters have been published.
@@ -119,6 +124,7 @@ lazy_static! {
         .copied()
         .collect()
     );
+    static ref CUSTOM_SECTOR_SIZES: RwLock<HashMap<u64, CustomSectorSize>> = Default::default();
 }
 
 /// The size of a single snark proof.
@@ -148,8 +154,10 @@ pub type DefaultOctLCTree = OctLCMerkleTree<DefaultTreeHasher>;
 // Generic shapes
 pub type SectorShapeBase = LCTree<DefaultTreeHasher, U8, U0, U0>;
 pub type SectorShapeSub2 = LCTree<DefaultTreeHasher, U8, U2, U0>;
+pub type SectorShapeSub4 = LCTree<DefaultTreeHasher, U8, U4, U0>;
 pub type SectorShapeSub8 = LCTree<DefaultTreeHasher, U8, U8, U0>;
 pub type SectorShapeTop2 = LCTree<DefaultTreeHasher, U8, U8, U2>;
+pub type SectorShapeTop4 = LCTree<DefaultTreeHasher, U8, U8, U4>;
 
 // Specific size constants by shape
 pub type SectorShape2KiB = SectorShapeBase;
@@ -170,25 +178,264 @@ pub fn is_sector_shape_base(sector_size: u64) -> bool {
     matches!(
         sector_size,
         SECTOR_SIZE_2_KIB | SECTOR_SIZE_8_MIB | SECTOR_SIZE_512_MIB
-    )
+    ) || registered_sector_shape(sector_size) == Some(SectorShape::Base)
 }
 
 pub fn is_sector_shape_sub2(sector_size: u64) -> bool {
     matches!(
         sector_size,
         SECTOR_SIZE_4_KIB | SECTOR_SIZE_16_MIB | SECTOR_SIZE_1_GIB
-    )
+    ) || registered_sector_shape(sector_size) == Some(SectorShape::Sub2)
+}
+
+pub fn is_sector_shape_sub4(sector_size: u64) -> bool {
+    registered_sector_shape(sector_size) == Some(SectorShape::Sub4)
 }
 
 pub fn is_sector_shape_sub8(sector_size: u64) -> bool {
     matches!(sector_size, SECTOR_SIZE_16_KIB | SECTOR_SIZE_32_GIB)
+        || registered_sector_shape(sector_size) == Some(SectorShape::Sub8)
 }
 
 pub fn is_sector_shape_top2(sector_size: u64) -> bool {
     matches!(sector_size, SECTOR_SIZE_32_KIB | SECTOR_SIZE_64_GIB)
+        || registered_sector_shape(sector_size) == Some(SectorShape::Top2)
+}
+
+pub fn is_sector_shape_top4(sector_size: u64) -> bool {
+    registered_sector_shape(sector_size) == Some(SectorShape::Top4)
+}
+
+/// The tree shapes a sector size can be registered with, see `register_sector_size`. Each one
+/// corresponds to one of the `SectorShape*` types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectorShape {
+    Base,
+    Sub2,
+    Sub4,
+    Sub8,
+    Top2,
+    Top4,
+}
+
+impl SectorShape {
+    /// Returns the shape with the given base, sub and top tree arities, where 0 means there is no
+    /// such tree.
+    pub fn from_arities(base_arity: usize, sub_arity: usize, top_arity: usize) -> Result<Self> {
+        let shape = match (base_arity, sub_arity, top_arity) {
+            (8, 0, 0) => SectorShape::Base,
+            (8, 2, 0) => SectorShape::Sub2,
+            (8, 4, 0) => SectorShape::Sub4,
+            (8, 8, 0) => SectorShape::Sub8,
+            (8, 8, 2) => SectorShape::Top2,
+            (8, 8, 4) => SectorShape::Top4,
+            _ => bail!(
+                "unsupported tree shape: base arity {}, sub arity {}, top arity {}",
+                base_arity,
+                sub_arity,
+                top_arity
+            ),
+        };
+
+        Ok(shape)
+    }
+
+    /// Returns the base, sub and top tree arities of the shape.
+    pub fn arities(self) -> (usize, usize, usize) {
+        match self {
+            SectorShape::Base => (8, 0, 0),
+            SectorShape::Sub2 => (8, 2, 0),
+            SectorShape::Sub4 => (8, 4, 0),
+            SectorShape::Sub8 => (8, 8, 0),
+            SectorShape::Top2 => (8, 8, 2),
+            SectorShape::Top4 => (8, 8, 4),
+        }
+    }
+}
+
+/// The parameters of a sector size which is not published, e.g. a small size for tests and
+/// research. See `register_sector_size`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CustomSectorSize {
+    /// The sector size in bytes, a power of two of at least 2KiB.
+    pub sector_size: u64,
+    pub base_arity: usize,
+    /// The arity of the sub tree, or 0 if there is none.
+    pub sub_arity: usize,
+    /// The arity of the top tree, or 0 if there is none.
+    pub top_arity: usize,
+    pub layers: usize,
+    pub porep_partitions: u8,
+    /// The minimum number of PoRep challenges, split across the partitions.
+    pub porep_minimum_challenges: u64,
+    /// The number of sectors proven in a single Window PoSt partition.
+    pub window_post_sector_count: usize,
+}
+
+/// Parses the comma-separated `field=value` pairs of all fields, e.g.
+/// `sector_size=65536,base_arity=8,sub_arity=4,top_arity=0,layers=2,porep_partitions=1,
+/// porep_minimum_challenges=2,window_post_sector_count=2`. This is how the tools take sector
+/// sizes to register.
+impl FromStr for CustomSectorSize {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut fields = HashMap::new();
+        for pair in s.split(',') {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next().unwrap_or_default().trim();
+            let value = parts
+                .next()
+                .ok_or_else(|| anyhow!("expected field=value, got {:?}", pair))?
+                .trim();
+            ensure!(
+                fields.insert(key, value).is_none(),
+                "field {} given more than once",
+                key
+            );
+        }
+
+        fn field<T: FromStr>(fields: &mut HashMap<&str, &str>, key: &str) -> Result<T>
+        where
+            T::Err: std::error::Error + Send + Sync + 'static,
+        {
+            let value = fields
+                .remove(key)
+                .ok_or_else(|| anyhow!("missing field {}", key))?;
+            value
+                .parse()
+                .with_context(|| format!("invalid value for {}: {:?}", key, value))
+        }
+
+        let custom = CustomSectorSize {
+            sector_size: field(&mut fields, "sector_size")?,
+            base_arity: field(&mut fields, "base_arity")?,
+            sub_arity: field(&mut fields, "sub_arity")?,
+            top_arity: field(&mut fields, "top_arity")?,
+            layers: field(&mut fields, "layers")?,
+            porep_partitions: field(&mut fields, "porep_partitions")?,
+            porep_minimum_challenges: field(&mut fields, "porep_minimum_challenges")?,
+            window_post_sector_count: field(&mut fields, "window_post_sector_count")?,
+        };
+        if let Some(key) = fields.keys().next() {
+            bail!("unknown field {}", key);
+        }
+
+        Ok(custom)
+    }
+}
+
+/// Registers a sector size for which no parameters have been published. Afterwards, it can be
+/// used like any published size for sealing, PoSt and aggregation. Parameters for it are
+/// generated on first use, like for published sizes whose parameters are missing.
+///
+/// Registering the same size again with identical parameters does nothing.
+pub fn register_sector_size(custom: CustomSectorSize) -> Result<()> {
+    let sector_size = custom.sector_size;
+    ensure!(
+        !PUBLISHED_SECTOR_SIZES.contains(&sector_size),
+        "sector size {} is published and cannot be registered",
+        sector_size
+    );
+    ensure!(
+        sector_size.is_power_of_two() && sector_size >= SECTOR_SIZE_2_KIB,
+        "sector size {} must be a power of two of at least {}",
+        sector_size,
+        SECTOR_SIZE_2_KIB
+    );
+
+    let shape = SectorShape::from_arities(custom.base_arity, custom.sub_arity, custom.top_arity)?;
+    // The base trees must be complete oct trees.
+    let base_tree_count = (custom.sub_arity.max(1) * custom.top_arity.max(1)) as u64;
+    let base_tree_nodes = sector_size / NODE_SIZE as u64 / base_tree_count;
+    ensure!(
+        base_tree_nodes > 1 && base_tree_nodes.trailing_zeros() % 3 == 0,
+        "sector size {} does not fit the tree shape {:?}",
+        sector_size,
+        shape
+    );
+
+    ensure!(custom.layers > 0, "layers must be greater than 0");
+    ensure!(
+        custom.porep_partitions > 0,
+        "porep partitions must be greater than 0"
+    );
+    ensure!(
+        custom.porep_minimum_challenges > 0,
+        "porep minimum challenges must be greater than 0"
+    );
+    ensure!(
+        custom.window_post_sector_count > 0,
+        "window post sector count must be greater than 0"
+    );
+
+    let mut custom_sector_sizes = CUSTOM_SECTOR_SIZES
+        .write()
+        .expect("CUSTOM_SECTOR_SIZES poisoned");
+    if let Some(registered) = custom_sector_sizes.get(&sector_size) {
+        ensure!(
+            *registered == custom,
+            "sector size {} is already registered with different parameters",
+            sector_size
+        );
+        return Ok(());
+    }
+
+    POREP_MINIMUM_CHALLENGES
+        .write()
+        .expect("POREP_MINIMUM_CHALLENGES poisoned")
+        .insert(sector_size, custom.porep_minimum_challenges);
+    POREP_PARTITIONS
+        .write()
+        .expect("POREP_PARTITIONS poisoned")
+        .insert(sector_size, custom.porep_partitions);
+    LAYERS
+        .write()
+        .expect("LAYERS poisoned")
+        .insert(sector_size, custom.layers);
+    WINDOW_POST_SECTOR_COUNT
+        .write()
+        .expect("WINDOW_POST_SECTOR_COUNT poisoned")
+        .insert(sector_size, custom.window_post_sector_count);
+    caches::add_srs_sector_size(sector_size);
+    custom_sector_sizes.insert(sector_size, custom);
+
+    info!("registered sector size {}: {:?}", sector_size, custom);
+
+    Ok(())
+}
+
+/// Returns the shape of a sector size registered with `register_sector_size`.
+pub fn registered_sector_shape(sector_size: u64) -> Option<SectorShape> {
+    CUSTOM_SECTOR_SIZES
+        .read()
+        .expect("CUSTOM_SECTOR_SIZES poisoned")
+        .get(&sector_size)
+        .map(|custom| {
+            SectorShape::from_arities(custom.base_arity, custom.sub_arity, custom.top_arity)
+                .expect("registered sector size with invalid shape")
+        })
+}
+
+/// Returns the published sector sizes, followed by the registered ones in ascending order.
+pub fn supported_sector_sizes() -> Vec<u64> {
+    let mut custom_sector_sizes: Vec<u64> = CUSTOM_SECTOR_SIZES
+        .read()
+        .expect("CUSTOM_SECTOR_SIZES poisoned")
+        .keys()
+        .copied()
+        .collect();
+    custom_sector_sizes.sort_unstable();
+
+    PUBLISHED_SECTOR_SIZES
+        .iter()
+        .copied()
+        .chain(custom_sector_sizes)
+        .collect()
 }
 
 /// Calls a function with the type hint of the sector shape matching the provided sector.
+/// Sector sizes registered with `register_sector_size` use the matching generic shape.
 /// Panics if provided with an unknown sector size.
 #[macro_export]
 macro_rules! with_shape {
@@ -227,7 +474,27 @@ macro_rules! with_shape {
             _x if $size == $crate::constants::SECTOR_SIZE_64_GIB => {
               $f::<$crate::constants::SectorShape64GiB>($($args),*)
             },
-            _ => panic!("unsupported sector size: {}", $size),
+            _ => match $crate::constants::registered_sector_shape($size) {
+                Some($crate::constants::SectorShape::Base) => {
+                  $f::<$crate::constants::SectorShapeBase>($($args),*)
+                },
+                Some($crate::constants::SectorShape::Sub2) => {
+                  $f::<$crate::constants::SectorShapeSub2>($($args),*)
+                },
+                Some($crate::constants::SectorShape::Sub4) => {
+                  $f::<$crate::constants::SectorShapeSub4>($($args),*)
+                },
+                Some($crate::constants::SectorShape::Sub8) => {
+                  $f::<$crate::constants::SectorShapeSub8>($($args),*)
+                },
+                Some($crate::constants::SectorShape::Top2) => {
+                  $f::<$crate::constants::SectorShapeTop2>($($args),*)
+                },
+                Some($crate::constants::SectorShape::Top4) => {
+                  $f::<$crate::constants::SectorShapeTop4>($($args),*)
+                },
+                None => panic!("unsupported sector size: {}", $size),
+            },
         }
     };
     ($size:expr, $f:ident, $($args:expr),*) => {
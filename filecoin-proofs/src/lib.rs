@@ -8,6 +8,8 @@ pub mod constants;
 pub mod param;
 pub mod parameters;
 pub mod pieces;
+#[cfg(feature = "prover")]
+pub mod test_vectors;
 pub mod types;
 
 mod api;
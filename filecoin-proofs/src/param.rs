@@ -19,8 +19,12 @@ pub fn get_full_path_for_file_within_cache(filename: &str) -> PathBuf {
 
 // Produces a BLAKE2b checksum for a file within the cache
 pub fn get_digest_for_file_within_cache(filename: &str) -> Result<String> {
-    let path = get_full_path_for_file_within_cache(filename);
-    let mut file = File::open(&path).with_context(|| format!("could not open path={:?}", path))?;
+    get_digest_for_file(&get_full_path_for_file_within_cache(filename))
+}
+
+// Produces a BLAKE2b checksum for a file, as found in parameters.json
+pub fn get_digest_for_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path).with_context(|| format!("could not open path={:?}", path))?;
     let mut hasher = Blake2b::new();
 
     io::copy(&mut file, &mut hasher)?;
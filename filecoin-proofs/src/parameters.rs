@@ -2,10 +2,14 @@ use anyhow::{ensure, Result};
 use storage_proofs_core::{api_version::ApiVersion, proof::ProofScheme};
 use storage_proofs_porep::stacked::{self, LayerChallenges, StackedDrg};
 use storage_proofs_post::fallback::{self, FallbackPoSt};
+use storage_proofs_update::{self as update, EmptySectorUpdate};
 
 use crate::{
-    constants::{DefaultPieceHasher, DRG_DEGREE, EXP_DEGREE, LAYERS, POREP_MINIMUM_CHALLENGES},
-    types::{MerkleTreeTrait, PaddedBytesAmount, PoStConfig},
+    constants::{
+        DefaultPieceHasher, DRG_DEGREE, EMPTY_SECTOR_UPDATE_CHALLENGE_COUNT, EXP_DEGREE, LAYERS,
+        POREP_MINIMUM_CHALLENGES,
+    },
+    types::{MerkleTreeTrait, PaddedBytesAmount, PoRepConfig, PoStConfig},
 };
 
 type WinningPostSetupParams = fallback::SetupParams;
@@ -14,6 +18,9 @@ pub type WinningPostPublicParams = fallback::PublicParams;
 type WindowPostSetupParams = fallback::SetupParams;
 pub type WindowPostPublicParams = fallback::PublicParams;
 
+type EmptySectorUpdateSetupParams = update::SetupParams;
+pub type EmptySectorUpdatePublicParams = update::PublicParams;
+
 pub fn public_params<Tree: 'static + MerkleTreeTrait>(
     sector_bytes: PaddedBytesAmount,
     partitions: usize,
@@ -74,6 +81,23 @@ pub fn window_post_setup_params(post_config: &PoStConfig) -> WindowPostSetupPara
     }
 }
 
+pub fn empty_sector_update_public_params<Tree: 'static + MerkleTreeTrait>(
+    porep_config: &PoRepConfig,
+) -> Result<EmptySectorUpdatePublicParams> {
+    EmptySectorUpdate::<Tree, DefaultPieceHasher>::setup(&empty_sector_update_setup_params(
+        porep_config,
+    ))
+}
+
+pub fn empty_sector_update_setup_params(
+    porep_config: &PoRepConfig,
+) -> EmptySectorUpdateSetupParams {
+    update::SetupParams {
+        sector_bytes: u64::from(PaddedBytesAmount::from(*porep_config)),
+        challenge_count: EMPTY_SECTOR_UPDATE_CHALLENGE_COUNT,
+    }
+}
+
 pub fn setup_params(
     sector_bytes: PaddedBytesAmount,
     partitions: usize,
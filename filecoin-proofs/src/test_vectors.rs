@@ -0,0 +1,474 @@
+//! Reproducible test vectors for the sealing and PoSt derivations, so that other
+//! implementations can check themselves bit-exactly against this one.
+//!
+//! All inputs are drawn from an `XorShiftRng` seeded with `TEST_SEED`, so generating the vector
+//! for the same sector size and `ApiVersion` always yields the same result. Byte strings
+//! (commitments, ids, labels, ...) are hex encoded, in the byte order the library serializes them
+//! in (i.e. little-endian for field elements).
+//!
+//! The derivations which only depend on the sector size are included for every sector size. The
+//! ones which require sealing a sector (labels, comm_c, comm_r_last and comm_r) are only included
+//! when a working directory is passed to `generate_test_vector`, which is only practical for small
+//! sectors.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use filecoin_hashers::Domain;
+use fr32::bytes_into_fr;
+use log::info;
+use merkletree::store::StoreConfig;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::{
+    api_version::ApiVersion, cache_key::CacheKey, drgraph::Graph, merkle::MerkleTreeTrait,
+    sector::SectorId, TEST_SEED,
+};
+use storage_proofs_porep::stacked::generate_replica_id;
+
+use crate::{
+    api::{
+        add_piece, compute_comm_d, generate_fallback_sector_challenges,
+        generate_winning_post_sector_challenge, get_p_aux, seal_pre_commit_phase1,
+        seal_pre_commit_phase2,
+    },
+    constants::{
+        POREP_PARTITIONS, SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB, SECTOR_SIZE_512_MIB,
+        SECTOR_SIZE_64_GIB, SECTOR_SIZE_8_MIB, WINDOW_POST_CHALLENGE_COUNT,
+        WINDOW_POST_SECTOR_COUNT, WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT,
+    },
+    parameters::public_params,
+    types::{
+        PaddedBytesAmount, PoRepConfig, PoRepProofPartitions, PoStConfig, PoStType, SectorSize,
+        UnpaddedBytesAmount,
+    },
+    with_shape,
+};
+
+/// The number of nodes whose parents and labels are sampled.
+const SAMPLED_NODES: usize = 8;
+
+/// The number of sectors the PoSt challenges are derived for.
+const POST_SECTORS: u64 = 4;
+
+/// The size of the sector set winning PoSt sectors are selected from.
+const WINNING_POST_SECTOR_SET_SIZE: u64 = 100;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestVector {
+    pub sector_size: u64,
+    pub api_version: String,
+    pub porep_id: String,
+    pub prover_id: String,
+    pub sector_id: u64,
+    pub ticket: String,
+    pub seed: String,
+    /// comm_d of the sealed data, or of an all-zero sector if the vector does not include sealing.
+    pub comm_d: String,
+    pub replica_id: String,
+    pub parents: Vec<NodeParents>,
+    /// The challenged nodes of every partition.
+    pub porep_challenges: Vec<Vec<usize>>,
+    pub winning_post: PoStVector,
+    pub window_post: PoStVector,
+    pub seal: Option<SealVector>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeParents {
+    pub node: usize,
+    pub drg: Vec<u32>,
+    pub expander: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoStVector {
+    pub randomness: String,
+    /// The indices of the challenged sectors within a sector set of `sector_set_size` (winning
+    /// PoSt only).
+    pub sector_set_size: Option<u64>,
+    pub selected_sectors: Vec<u64>,
+    pub challenges: Vec<SectorChallenges>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectorChallenges {
+    pub sector_id: u64,
+    pub challenges: Vec<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealVector {
+    /// The labels of the sampled nodes, per layer (starting at 1).
+    pub labels: Vec<Vec<NodeLabel>>,
+    pub comm_c: String,
+    pub comm_r_last: String,
+    pub comm_r: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeLabel {
+    pub node: usize,
+    pub label: String,
+}
+
+/// The porep_id the vector for `sector_size` and `api_version` is generated with: that of the
+/// registered seal proof for production sector sizes, and the arbitrary ids also used by the
+/// API tests for all others.
+pub fn test_vector_porep_id(sector_size: u64, api_version: ApiVersion) -> [u8; 32] {
+    let registered_proof = match sector_size {
+        SECTOR_SIZE_2_KIB => Some(0u64),
+        SECTOR_SIZE_8_MIB => Some(1),
+        SECTOR_SIZE_512_MIB => Some(2),
+        SECTOR_SIZE_32_GIB => Some(3),
+        SECTOR_SIZE_64_GIB => Some(4),
+        _ => None,
+    };
+
+    match (registered_proof, api_version) {
+        (Some(registered_proof), ApiVersion::V1_0_0) => {
+            let mut porep_id = [0u8; 32];
+            porep_id[..8].copy_from_slice(&registered_proof.to_le_bytes());
+            porep_id
+        }
+        (Some(registered_proof), ApiVersion::V1_1_0) => {
+            let mut porep_id = [0u8; 32];
+            porep_id[..8].copy_from_slice(&(registered_proof + 5).to_le_bytes());
+            porep_id
+        }
+        (None, ApiVersion::V1_0_0) => [127; 32],
+        (None, ApiVersion::V1_1_0) => [128; 32],
+    }
+}
+
+/// Generates the test vector for `sector_size` and `api_version`. Sealing derivations are only
+/// included if `work_dir` is given, in which case the sector is sealed in it.
+pub fn generate_test_vector(
+    sector_size: u64,
+    api_version: ApiVersion,
+    work_dir: Option<&Path>,
+) -> Result<TestVector> {
+    with_shape!(
+        sector_size,
+        generate_test_vector_inner,
+        sector_size,
+        api_version,
+        work_dir,
+    )
+}
+
+fn generate_test_vector_inner<Tree: 'static + MerkleTreeTrait>(
+    sector_size: u64,
+    api_version: ApiVersion,
+    work_dir: Option<&Path>,
+) -> Result<TestVector> {
+    info!(
+        "generate_test_vector:start: {} {}",
+        sector_size, api_version
+    );
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let porep_id = test_vector_porep_id(sector_size, api_version);
+    let porep_config = PoRepConfig {
+        sector_size: SectorSize(sector_size),
+        partitions: PoRepProofPartitions(
+            *POREP_PARTITIONS
+                .read()
+                .expect("POREP_PARTITIONS poisoned")
+                .get(&sector_size)
+                .context("unknown sector size")?,
+        ),
+        porep_id,
+        api_version,
+    };
+
+    let prover_id = random_commitment(rng);
+    let sector_id = rng.gen::<u64>();
+    let ticket = random_commitment(rng);
+    let seed = random_commitment(rng);
+    let winning_randomness = random_commitment(rng);
+    let window_randomness = random_commitment(rng);
+
+    let nodes = (sector_size / 32) as usize;
+    let mut sampled_nodes = vec![0, 1, nodes / 2, nodes - 1];
+    while sampled_nodes.len() < SAMPLED_NODES {
+        sampled_nodes.push(rng.gen_range(0, nodes));
+    }
+
+    let pub_params = public_params::<Tree>(
+        PaddedBytesAmount(sector_size),
+        usize::from(porep_config.partitions),
+        porep_id,
+        api_version,
+    )?;
+
+    let seal = match work_dir {
+        Some(work_dir) => Some(seal::<Tree>(
+            rng,
+            porep_config,
+            prover_id,
+            sector_id,
+            ticket,
+            pub_params.layer_challenges.layers(),
+            &sampled_nodes,
+            work_dir,
+        )?),
+        None => None,
+    };
+
+    let comm_d = match &seal {
+        Some((comm_d, _)) => *comm_d,
+        None => compute_comm_d(SectorSize(sector_size), &[])?,
+    };
+
+    let replica_id =
+        generate_replica_id::<Tree::Hasher, _>(&prover_id, sector_id, &ticket, comm_d, &porep_id);
+
+    let parents = sampled_nodes
+        .iter()
+        .map(|node| {
+            let mut drg = vec![0u32; pub_params.graph.base_graph().degree()];
+            pub_params.graph.base_parents(*node, &mut drg)?;
+            let mut expander = vec![0u32; pub_params.graph.expansion_degree()];
+            pub_params.graph.expanded_parents(*node, &mut expander)?;
+
+            Ok(NodeParents {
+                node: *node,
+                drg,
+                expander,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let porep_challenges = (0..usize::from(porep_config.partitions))
+        .map(|k| {
+            pub_params
+                .layer_challenges
+                .derive(nodes, &replica_id, &seed, k as u8)
+        })
+        .collect();
+
+    let winning_post = winning_post_vector::<Tree>(
+        sector_size,
+        api_version,
+        prover_id,
+        sector_id,
+        winning_randomness,
+    )?;
+    let window_post = window_post_vector::<Tree>(
+        sector_size,
+        api_version,
+        prover_id,
+        sector_id,
+        window_randomness,
+    )?;
+
+    let vector = TestVector {
+        sector_size,
+        api_version: api_version.to_string(),
+        porep_id: hex::encode(porep_id),
+        prover_id: hex::encode(prover_id),
+        sector_id,
+        ticket: hex::encode(ticket),
+        seed: hex::encode(seed),
+        comm_d: hex::encode(comm_d),
+        replica_id: hex::encode(replica_id.into_bytes()),
+        parents,
+        porep_challenges,
+        winning_post,
+        window_post,
+        seal: seal.map(|(_, seal)| seal),
+    };
+
+    info!(
+        "generate_test_vector:finish: {} {}",
+        sector_size, api_version
+    );
+    Ok(vector)
+}
+
+/// A random 32 byte value which is a valid field element.
+fn random_commitment<R: Rng>(rng: &mut R) -> [u8; 32] {
+    let mut bytes: [u8; 32] = rng.gen();
+    bytes[31] &= 0b0011_1111;
+    bytes
+}
+
+/// Seals a sector of random data in `work_dir`, returning its comm_d and the seal vector.
+#[allow(clippy::too_many_arguments)]
+fn seal<Tree: 'static + MerkleTreeTrait>(
+    rng: &mut XorShiftRng,
+    porep_config: PoRepConfig,
+    prover_id: [u8; 32],
+    sector_id: u64,
+    ticket: [u8; 32],
+    layers: usize,
+    sampled_nodes: &[usize],
+    work_dir: &Path,
+) -> Result<([u8; 32], SealVector)> {
+    let sector_size = u64::from(porep_config.sector_size);
+    let cache_path = work_dir.join("cache");
+    let staged_path = work_dir.join("staged");
+    let sealed_path = work_dir.join("sealed");
+    fs::create_dir_all(&cache_path)?;
+
+    let piece_size = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+    let piece_bytes: Vec<u8> = (0..u64::from(piece_size)).map(|_| rng.gen()).collect();
+
+    let staged_file = File::create(&staged_path)?;
+    let (piece_info, _) = add_piece(&piece_bytes[..], staged_file, piece_size, &[])?;
+    File::create(&sealed_path)?;
+
+    let phase1_output = seal_pre_commit_phase1::<_, _, _, Tree>(
+        porep_config,
+        &cache_path,
+        &staged_path,
+        &sealed_path,
+        prover_id,
+        SectorId::from(sector_id),
+        ticket,
+        &[piece_info],
+    )?;
+    let pre_commit_output =
+        seal_pre_commit_phase2(porep_config, phase1_output, &cache_path, &sealed_path)?;
+
+    let labels = (1..=layers)
+        .map(|layer| read_labels(&cache_path, layer, sampled_nodes))
+        .collect::<Result<Vec<_>>>()?;
+
+    let p_aux = get_p_aux::<Tree>(&cache_path)?;
+
+    let seal = SealVector {
+        labels,
+        comm_c: hex::encode(p_aux.comm_c.into_bytes()),
+        comm_r_last: hex::encode(p_aux.comm_r_last.into_bytes()),
+        comm_r: hex::encode(pre_commit_output.comm_r),
+    };
+
+    Ok((pre_commit_output.comm_d, seal))
+}
+
+/// Reads the labels of `nodes` in `layer` from the layer's store in `cache_path`.
+fn read_labels(cache_path: &Path, layer: usize, nodes: &[usize]) -> Result<Vec<NodeLabel>> {
+    let path = StoreConfig::data_path(&cache_path.to_path_buf(), &CacheKey::label_layer(layer));
+    let mut file = OpenOptions::new()
+        .read(true)
+        .open(&path)
+        .with_context(|| format!("could not open labels at {:?}", path))?;
+
+    nodes
+        .iter()
+        .map(|node| {
+            let mut label = [0u8; 32];
+            file.seek(SeekFrom::Start((*node * 32) as u64))?;
+            file.read_exact(&mut label)?;
+            // Every label must be a valid field element.
+            bytes_into_fr(&label)?;
+
+            Ok(NodeLabel {
+                node: *node,
+                label: hex::encode(label),
+            })
+        })
+        .collect()
+}
+
+fn winning_post_vector<Tree: 'static + MerkleTreeTrait>(
+    sector_size: u64,
+    api_version: ApiVersion,
+    prover_id: [u8; 32],
+    sector_id: u64,
+    randomness: [u8; 32],
+) -> Result<PoStVector> {
+    let post_config = PoStConfig {
+        sector_size: SectorSize(sector_size),
+        challenge_count: WINNING_POST_CHALLENGE_COUNT,
+        sector_count: WINNING_POST_SECTOR_COUNT,
+        typ: PoStType::Winning,
+        priority: false,
+        api_version,
+    };
+
+    let selected_sectors = generate_winning_post_sector_challenge::<Tree>(
+        &post_config,
+        &randomness,
+        WINNING_POST_SECTOR_SET_SIZE,
+        prover_id,
+    )?;
+    let sector_ids: Vec<SectorId> = selected_sectors
+        .iter()
+        .map(|index| SectorId::from(sector_id.wrapping_add(*index)))
+        .collect();
+
+    let challenges = generate_fallback_sector_challenges::<Tree>(
+        &post_config,
+        &randomness,
+        &sector_ids,
+        prover_id,
+    )?;
+
+    Ok(PoStVector {
+        randomness: hex::encode(randomness),
+        sector_set_size: Some(WINNING_POST_SECTOR_SET_SIZE),
+        selected_sectors,
+        challenges: sector_challenges(challenges),
+    })
+}
+
+fn window_post_vector<Tree: 'static + MerkleTreeTrait>(
+    sector_size: u64,
+    api_version: ApiVersion,
+    prover_id: [u8; 32],
+    sector_id: u64,
+    randomness: [u8; 32],
+) -> Result<PoStVector> {
+    let post_config = PoStConfig {
+        sector_size: SectorSize(sector_size),
+        challenge_count: WINDOW_POST_CHALLENGE_COUNT,
+        sector_count: *WINDOW_POST_SECTOR_COUNT
+            .read()
+            .expect("WINDOW_POST_SECTOR_COUNT poisoned")
+            .get(&sector_size)
+            .context("unknown sector size")?,
+        typ: PoStType::Window,
+        priority: false,
+        api_version,
+    };
+
+    let sector_ids: Vec<SectorId> = (0..POST_SECTORS)
+        .map(|i| SectorId::from(sector_id.wrapping_add(i)))
+        .collect();
+
+    let challenges = generate_fallback_sector_challenges::<Tree>(
+        &post_config,
+        &randomness,
+        &sector_ids,
+        prover_id,
+    )?;
+    ensure!(
+        challenges.len() == sector_ids.len(),
+        "missing window post challenges"
+    );
+
+    Ok(PoStVector {
+        randomness: hex::encode(randomness),
+        sector_set_size: None,
+        selected_sectors: Vec::new(),
+        challenges: sector_challenges(challenges),
+    })
+}
+
+fn sector_challenges(challenges: BTreeMap<SectorId, Vec<u64>>) -> Vec<SectorChallenges> {
+    challenges
+        .into_iter()
+        .map(|(sector_id, challenges)| SectorChallenges {
+            sector_id: u64::from(sector_id),
+            challenges,
+        })
+        .collect()
+}
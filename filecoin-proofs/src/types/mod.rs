@@ -1,6 +1,11 @@
 pub use merkletree::store::StoreConfig;
 pub use storage_proofs_core::merkle::{MerkleProof, MerkleTreeTrait};
-pub use storage_proofs_porep::stacked::{Labels, PersistentAux, TemporaryAux};
+pub use storage_proofs_porep::stacked::{
+    CoreGroupInfo, Labels, PersistentAux, SdrPlacement, SdrPlacementReport, SdrTopology,
+    TemporaryAux,
+};
+#[cfg(feature = "prover")]
+pub use storage_proofs_porep::stacked::{FileReads, ReadPlan, ReadTarget};
 
 use filecoin_hashers::Hasher;
 use serde::{Deserialize, Serialize};
@@ -44,6 +49,16 @@ pub const OCT_ARITY: usize = 8;
 /// Arity for binary trees, used for comm_d.
 pub const BINARY_ARITY: usize = 2;
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmptySectorUpdateEncoded {
+    pub comm_r_new: Commitment,
+    pub comm_r_last_new: Commitment,
+    pub comm_d_new: Commitment,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmptySectorUpdateProof(pub Vec<u8>);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SealPreCommitOutput {
     pub comm_r: Commitment,
@@ -71,6 +86,24 @@ pub struct SealCommitOutput {
     pub proof: Vec<u8>,
 }
 
+/// The proof of a single partition of a seal commit, as returned by
+/// `seal_commit_phase2_partition`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SealCommitPartitionProof {
+    pub partition: usize,
+    pub proof: Vec<u8>,
+}
+
+/// The files and byte ranges which `seal_commit_phase1` reads for a replica id and seed, as
+/// returned by `seal_commit_phase1_read_plan`.
+#[cfg(feature = "prover")]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealCommitReadPlan {
+    pub replica_id: Commitment,
+    pub seed: Ticket,
+    pub reads: ReadPlan,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SealPreCommitPhase1Output<Tree: MerkleTreeTrait> {
     #[serde(bound(
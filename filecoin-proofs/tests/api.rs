@@ -1,5 +1,5 @@
 use std::collections::BTreeMap;
-use std::fs::{read_dir, remove_file};
+use std::fs::{read, read_dir, remove_file, File};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Once;
@@ -11,24 +11,32 @@ use bincode::serialize;
 use ff::Field;
 use filecoin_hashers::Hasher;
 use filecoin_proofs::{
-    add_piece, aggregate_seal_commit_proofs, clear_cache, compute_comm_d, fauxrep_aux,
-    generate_fallback_sector_challenges, generate_piece_commitment, generate_single_vanilla_proof,
-    generate_window_post, generate_window_post_with_vanilla, generate_winning_post,
+    add_piece, aggregate_seal_commit_proofs, clear_cache, compute_comm_d, decode_from, encode_into,
+    fauxrep_aux, generate_empty_sector_update_proof, generate_fallback_sector_challenges,
+    generate_piece_commitment, generate_single_vanilla_proof, generate_window_post,
+    generate_window_post_with_vanilla, generate_winning_post,
     generate_winning_post_sector_challenge, generate_winning_post_with_vanilla, get_seal_inputs,
-    seal_commit_phase1, seal_commit_phase2, seal_pre_commit_phase1, seal_pre_commit_phase2,
-    unseal_range, validate_cache_for_commit, validate_cache_for_precommit_phase2,
-    verify_aggregate_seal_commit_proofs, verify_seal, verify_window_post, verify_winning_post,
-    Commitment, DefaultTreeDomain, MerkleTreeTrait, PaddedBytesAmount, PieceInfo, PoRepConfig,
-    PoRepProofPartitions, PoStConfig, PoStType, PrivateReplicaInfo, ProverId, PublicReplicaInfo,
-    SealCommitOutput, SealPreCommitOutput, SealPreCommitPhase1Output, SectorShape16KiB,
-    SectorShape2KiB, SectorShape32KiB, SectorShape4KiB, SectorSize, UnpaddedByteIndex,
-    UnpaddedBytesAmount, POREP_PARTITIONS, SECTOR_SIZE_16_KIB, SECTOR_SIZE_2_KIB,
+    regenerate_sector_cache, register_sector_size, seal_commit_phase1,
+    seal_commit_phase1_read_plan, seal_commit_phase1_sparse, seal_commit_phase1_write_sparse_cache,
+    seal_commit_phase2, seal_commit_phase2_assemble, seal_commit_phase2_partition,
+    seal_pre_commit_phase1, seal_pre_commit_phase2, unseal_range, unseal_range_into,
+    unseal_range_verified, validate_cache_for_commit, validate_cache_for_precommit_phase2,
+    verify_aggregate_seal_commit_proofs, verify_empty_sector_update_proof, verify_seal,
+    verify_sector_cache, verify_window_post, verify_window_post_batch, verify_winning_post,
+    verify_winning_post_batch, Commitment, CustomSectorSize, DefaultTreeDomain, MerkleTreeTrait,
+    PaddedBytesAmount, PieceInfo, PoRepConfig, PoRepProofPartitions, PoStConfig, PoStProver,
+    PoStType, PrivateReplicaInfo, ProverId, PublicReplicaInfo, SealCommitOutput,
+    SealPreCommitOutput, SealPreCommitPhase1Output, SectorShape16KiB, SectorShape2KiB,
+    SectorShape32KiB, SectorShape4KiB, SectorShapeSub4, SectorSize, UnpaddedByteIndex,
+    UnpaddedBytesAmount, UnsealedSector, POREP_PARTITIONS, SECTOR_SIZE_16_KIB, SECTOR_SIZE_2_KIB,
     SECTOR_SIZE_32_KIB, SECTOR_SIZE_4_KIB, WINDOW_POST_CHALLENGE_COUNT, WINDOW_POST_SECTOR_COUNT,
     WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT,
 };
 use rand::{random, Rng, SeedableRng};
 use rand_xorshift::XorShiftRng;
-use storage_proofs_core::{api_version::ApiVersion, is_legacy_porep_id, sector::SectorId};
+use storage_proofs_core::{
+    api_version::ApiVersion, error::Error, is_legacy_porep_id, sector::SectorId,
+};
 use tempfile::{tempdir, NamedTempFile, TempDir};
 
 // Use a fixed PoRep ID, so that the parents cache can be re-used between some tests.
@@ -116,6 +124,58 @@ fn test_seal_lifecycle_32kib_top_8_8_2() -> Result<()> {
     )
 }
 
+const SECTOR_SIZE_64_KIB: u64 = 1 << 16;
+
+// 64KiB is not a published sector size, it is registered with the canonical 8-4-0 shape.
+fn register_sector_size_64kib() {
+    register_sector_size(CustomSectorSize {
+        sector_size: SECTOR_SIZE_64_KIB,
+        base_arity: 8,
+        sub_arity: 4,
+        top_arity: 0,
+        layers: 2,
+        porep_partitions: 1,
+        porep_minimum_challenges: 2,
+        window_post_sector_count: 2,
+    })
+    .expect("failed to register 64KiB sector size");
+}
+
+#[test]
+fn test_seal_lifecycle_64kib_custom_sub_8_4() -> Result<()> {
+    register_sector_size_64kib();
+
+    seal_lifecycle::<SectorShapeSub4>(
+        SECTOR_SIZE_64_KIB,
+        &ARBITRARY_POREP_ID_V1_1_0,
+        ApiVersion::V1_1_0,
+    )
+}
+
+#[test]
+#[ignore]
+fn test_seal_proof_aggregation_2_64kib_custom_sub_8_4() -> Result<()> {
+    register_sector_size_64kib();
+
+    let verified = aggregate_proofs::<SectorShapeSub4>(
+        SECTOR_SIZE_64_KIB,
+        &ARBITRARY_POREP_ID_V1_1_0,
+        ApiVersion::V1_1_0,
+        2,
+    )?;
+    assert!(verified);
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_window_post_64kib_custom_sub_8_4() -> Result<()> {
+    register_sector_size_64kib();
+
+    window_post::<SectorShapeSub4>(SECTOR_SIZE_64_KIB, 2, 2, false, ApiVersion::V1_1_0)
+}
+
 // These tests are good to run, but take a long time.
 
 //#[test]
@@ -766,12 +826,36 @@ fn winning_post<Tree: 'static + MerkleTreeTrait>(
         prover_id,
         vanilla_proofs,
     )?;
+
+    let valid =
+        verify_winning_post::<Tree>(&config, &randomness, &pub_replicas[..], prover_id, &proof)?;
+    assert!(valid, "proof did not verify");
+
+    // 3)
+    let mut prover = PoStProver::<Tree>::new(&config, prover_id)?;
+    prover.add_sector(sector_id, private_replica_info)?;
+
+    let proof = prover.generate_winning_post(&randomness, &[sector_id])?;
     /////////////////////////////////////////////
 
     let valid =
         verify_winning_post::<Tree>(&config, &randomness, &pub_replicas[..], prover_id, &proof)?;
     assert!(valid, "proof did not verify");
 
+    // Batch verification reports which entries are invalid.
+    let other_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut other_randomness = [0u8; 32];
+    other_randomness.copy_from_slice(AsRef::<[u8]>::as_ref(&other_fr));
+
+    let valid = verify_winning_post_batch::<Tree>(
+        &config,
+        &[randomness, other_randomness, randomness],
+        &[prover_id, prover_id, prover_id],
+        &[&pub_replicas[..], &pub_replicas[..], &pub_replicas[..]],
+        &[&proof[..], &proof[..], &proof[..1]],
+    )?;
+    assert_eq!(valid, vec![true, false, false]);
+
     Ok(())
 }
 
@@ -1140,11 +1224,281 @@ fn window_post<Tree: 'static + MerkleTreeTrait>(
 
     let proof =
         generate_window_post_with_vanilla::<Tree>(&config, &randomness, prover_id, vanilla_proofs)?;
+
+    let valid = verify_window_post::<Tree>(&config, &randomness, &pub_replicas, prover_id, &proof)?;
+    assert!(valid, "proof did not verify");
+
+    // 3)
+    let mut prover = PoStProver::<Tree>::new(&config, prover_id)?;
+    for (sector_id, replica) in priv_replicas.iter() {
+        prover.add_sector(*sector_id, replica.clone())?;
+    }
+    assert_eq!(prover.len(), priv_replicas.len());
+
+    let proof = prover.generate_window_post(&randomness)?;
     /////////////////////////////////////////////
 
     let valid = verify_window_post::<Tree>(&config, &randomness, &pub_replicas, prover_id, &proof)?;
     assert!(valid, "proof did not verify");
 
+    // Batch verification reports which entries are invalid.
+    let other_fr: <Tree::Hasher as Hasher>::Domain = Fr::random(rng).into();
+    let mut other_randomness = [0u8; 32];
+    other_randomness.copy_from_slice(AsRef::<[u8]>::as_ref(&other_fr));
+
+    let valid = verify_window_post_batch::<Tree>(
+        &config,
+        &[randomness, other_randomness, randomness],
+        &[prover_id, prover_id, prover_id],
+        &[&pub_replicas, &pub_replicas, &pub_replicas],
+        &[&proof[..], &proof[..], &proof[..1]],
+    )?;
+    assert_eq!(valid, vec![true, false, false]);
+
+    // Sectors removed from the prover are no longer proven.
+    let (removed_sector_id, _) = priv_replicas.iter().next().expect("no replicas");
+    assert!(prover.remove_sector(removed_sector_id).is_some());
+    assert!(!prover.contains_sector(removed_sector_id));
+    let mut remaining_pub_replicas = pub_replicas.clone();
+    remaining_pub_replicas.remove(removed_sector_id);
+
+    if !remaining_pub_replicas.is_empty() {
+        let proof = prover.generate_window_post(&randomness)?;
+        let valid = verify_window_post::<Tree>(
+            &config,
+            &randomness,
+            &remaining_pub_replicas,
+            prover_id,
+            &proof,
+        )?;
+        assert!(valid, "proof did not verify");
+    }
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_empty_sector_update_2kib_base_8() -> Result<()> {
+    empty_sector_update::<SectorShape2KiB>(SECTOR_SIZE_2_KIB, ApiVersion::V1_1_0)
+}
+
+#[test]
+#[ignore]
+fn test_empty_sector_update_4kib_sub_8_2() -> Result<()> {
+    empty_sector_update::<SectorShape4KiB>(SECTOR_SIZE_4_KIB, ApiVersion::V1_1_0)
+}
+
+#[test]
+#[ignore]
+fn test_empty_sector_update_32kib_top_8_8_2() -> Result<()> {
+    empty_sector_update::<SectorShape32KiB>(SECTOR_SIZE_32_KIB, ApiVersion::V1_1_0)
+}
+
+fn empty_sector_update<Tree: 'static + MerkleTreeTrait>(
+    sector_size: u64,
+    api_version: ApiVersion,
+) -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let porep_id = match api_version {
+        ApiVersion::V1_0_0 => ARBITRARY_POREP_ID_V1_0_0,
+        ApiVersion::V1_1_0 => ARBITRARY_POREP_ID_V1_1_0,
+    };
+    let config = porep_config(sector_size, porep_id, api_version);
+
+    // The sealed sector (and its cache) serves as the sector key.
+    let (_, sector_key_file, comm_r_old, sector_key_cache_dir) =
+        create_seal::<_, Tree>(rng, sector_size, prover_id, true, &porep_id, api_version)?;
+
+    // Stage the new data.
+    let (mut piece_file, _) = generate_piece_file(sector_size)?;
+    let number_of_bytes_in_piece = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), number_of_bytes_in_piece)?;
+    piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(
+        &mut piece_file,
+        &mut staged_sector_file,
+        number_of_bytes_in_piece,
+        &[],
+    )?;
+    let piece_infos = vec![piece_info];
+
+    let new_replica_file = NamedTempFile::new()?;
+    let new_cache_dir = tempdir()?;
+    let encoded = encode_into::<Tree>(
+        config,
+        new_replica_file.path(),
+        new_cache_dir.path(),
+        sector_key_file.path(),
+        sector_key_cache_dir.path(),
+        staged_sector_file.path(),
+        &piece_infos,
+    )?;
+    assert_ne!(encoded.comm_r_new, comm_r_old);
+
+    let random_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut randomness = [0u8; 32];
+    randomness.copy_from_slice(AsRef::<[u8]>::as_ref(&random_fr));
+
+    let proof = generate_empty_sector_update_proof::<Tree>(
+        config,
+        comm_r_old,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+        &randomness,
+        sector_key_file.path(),
+        sector_key_cache_dir.path(),
+        new_replica_file.path(),
+        new_cache_dir.path(),
+    )?;
+    let valid = verify_empty_sector_update_proof::<Tree>(
+        config,
+        &proof,
+        comm_r_old,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+        &randomness,
+    )?;
+    assert!(valid, "empty sector update proof did not verify");
+
+    let decoded_file = NamedTempFile::new()?;
+    decode_from::<Tree>(
+        config,
+        decoded_file.path(),
+        new_replica_file.path(),
+        sector_key_file.path(),
+        sector_key_cache_dir.path(),
+        encoded.comm_d_new,
+    )?;
+    assert_eq!(read(decoded_file.path())?, read(staged_sector_file.path())?);
+
+    // Files longer than the sector are rejected instead of being truncated.
+    let mut oversized_file = NamedTempFile::new()?;
+    oversized_file.write_all(&vec![0u8; sector_size as usize + 1])?;
+    let rejected_replica_file = NamedTempFile::new()?;
+    assert!(encode_into::<Tree>(
+        config,
+        rejected_replica_file.path(),
+        new_cache_dir.path(),
+        sector_key_file.path(),
+        sector_key_cache_dir.path(),
+        oversized_file.path(),
+        &piece_infos,
+    )
+    .is_err());
+    assert!(decode_from::<Tree>(
+        config,
+        oversized_file.path(),
+        new_replica_file.path(),
+        sector_key_file.path(),
+        sector_key_cache_dir.path(),
+        encoded.comm_d_new,
+    )
+    .is_err());
+    assert_eq!(
+        oversized_file.as_file().metadata()?.len(),
+        sector_size + 1,
+        "decode_from truncated its output file"
+    );
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_regenerate_sector_cache_2kib_base_8() -> Result<()> {
+    regenerate_sector_cache_lifecycle::<SectorShape2KiB>(SECTOR_SIZE_2_KIB, ApiVersion::V1_1_0)
+}
+
+fn regenerate_sector_cache_lifecycle<Tree: 'static + MerkleTreeTrait>(
+    sector_size: u64,
+    api_version: ApiVersion,
+) -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let porep_id = match api_version {
+        ApiVersion::V1_0_0 => ARBITRARY_POREP_ID_V1_0_0,
+        ApiVersion::V1_1_0 => ARBITRARY_POREP_ID_V1_1_0,
+    };
+    let config = porep_config(sector_size, porep_id, api_version);
+
+    // Do not skip the proof, so that the layer labels are kept in the cache.
+    let (_, sealed_sector_file, comm_r, cache_dir) =
+        create_seal::<_, Tree>(rng, sector_size, prover_id, false, &porep_id, api_version)?;
+    assert!(verify_sector_cache::<Tree>(
+        config.sector_size,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        comm_r,
+    )?);
+
+    // Remove everything a PoSt depends on.
+    for entry in read_dir(cache_dir.path())? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+            .to_string();
+        if name.contains("tree-r-last") || name.contains("tree-c") || name.contains("p_aux") {
+            remove_file(&path)?;
+        }
+    }
+    assert!(!verify_sector_cache::<Tree>(
+        config.sector_size,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        comm_r,
+    )?);
+
+    regenerate_sector_cache::<Tree>(config, cache_dir.path(), sealed_sector_file.path(), comm_r)?;
+    assert!(verify_sector_cache::<Tree>(
+        config.sector_size,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        comm_r,
+    )?);
+
+    // Regenerating against the wrong comm_r must fail, and leave the existing cache intact.
+    let mut bad_comm_r = comm_r;
+    bad_comm_r[0] ^= 1;
+    assert!(regenerate_sector_cache::<Tree>(
+        config,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        bad_comm_r,
+    )
+    .is_err());
+    assert!(verify_sector_cache::<Tree>(
+        config.sector_size,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        comm_r,
+    )?);
+    for entry in read_dir(cache_dir.path())? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        assert!(
+            !name.contains("regenerated"),
+            "rebuilt store {:?} was left behind",
+            path
+        );
+    }
+
     Ok(())
 }
 
@@ -1314,6 +1668,47 @@ fn unseal<Tree: 'static + MerkleTreeTrait>(
     assert_eq!(contents.len(), 508);
     assert_eq!(&piece_bytes[508..508 + 508], &contents[..]);
 
+    let mut verified_contents = vec![];
+    unseal_range_verified::<_, _, _, Tree>(
+        config,
+        cache_dir_path,
+        File::open(sealed_sector_file.path())?,
+        &mut verified_contents,
+        prover_id,
+        sector_id,
+        comm_d,
+        ticket,
+        UnpaddedByteIndex(508),
+        UnpaddedBytesAmount(508),
+    )?;
+    assert_eq!(verified_contents, contents);
+
+    let unsealed_dir = tempdir()?;
+    let mut unsealed =
+        UnsealedSector::create(unsealed_dir.path().join("unsealed"), config.sector_size)?;
+    assert!(!unsealed.is_present(UnpaddedByteIndex(508), UnpaddedBytesAmount(508)));
+    unseal_range_into::<_, Tree>(
+        config,
+        cache_dir_path,
+        sealed_sector_file.path().to_path_buf(),
+        &mut unsealed,
+        prover_id,
+        sector_id,
+        comm_d,
+        ticket,
+        UnpaddedByteIndex(508),
+        UnpaddedBytesAmount(508),
+    )?;
+    assert!(unsealed.is_present(UnpaddedByteIndex(508), UnpaddedBytesAmount(508)));
+
+    let mut cached = vec![];
+    unsealed.read_range(
+        UnpaddedByteIndex(508),
+        UnpaddedBytesAmount(508),
+        &mut cached,
+    )?;
+    assert_eq!(&piece_bytes[508..508 + 508], &cached[..]);
+
     let computed_comm_d = compute_comm_d(config.sector_size, &piece_infos)?;
 
     assert_eq!(
@@ -1332,6 +1727,29 @@ fn unseal<Tree: 'static + MerkleTreeTrait>(
         &commit_output.proof,
     )?;
     assert!(verified, "failed to verify valid seal");
+
+    // Unsealing with the wrong ticket decodes garbage, which must not be served. This is done
+    // last, as it replaces the labels in the cache directory.
+    let mut wrong_ticket = ticket;
+    wrong_ticket[0] ^= 1;
+    let err = unseal_range_verified::<_, _, _, Tree>(
+        config,
+        cache_dir_path,
+        File::open(sealed_sector_file.path())?,
+        Vec::new(),
+        prover_id,
+        sector_id,
+        comm_d,
+        wrong_ticket,
+        UnpaddedByteIndex(508),
+        UnpaddedBytesAmount(508),
+    )
+    .expect_err("unsealing with the wrong ticket must fail verification");
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::UnsealedDataMismatch(_, _))
+    ));
+
     Ok(())
 }
 
@@ -1436,6 +1854,213 @@ fn create_seal<R: Rng, Tree: 'static + MerkleTreeTrait>(
     Ok((sector_id, sealed_sector_file, comm_r, cache_dir))
 }
 
+#[test]
+#[ignore]
+fn test_seal_commit_phase2_partitions_2kib_base_8() -> Result<()> {
+    init_logger();
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let (mut piece_file, _piece_bytes) = generate_piece_file(sector_size)?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir().expect("failed to create temp dir");
+
+    let config = porep_config(sector_size, ARBITRARY_POREP_ID_V1_1_0, ApiVersion::V1_1_0);
+    let ticket = rng.gen();
+    let seed = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+
+    let (piece_infos, phase1_output) = run_seal_pre_commit_phase1::<SectorShape2KiB>(
+        config,
+        prover_id,
+        sector_id,
+        ticket,
+        &cache_dir,
+        &mut piece_file,
+        &sealed_sector_file,
+    )?;
+    let pre_commit_output = seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    let phase1_output = seal_commit_phase1::<_, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit_output,
+        &piece_infos,
+    )?;
+
+    // Prove the partitions out of order, assembly must put them back in order.
+    let partitions = usize::from(PoRepProofPartitions::from(config));
+    let mut partition_proofs = (0..partitions)
+        .rev()
+        .map(|partition| {
+            seal_commit_phase2_partition::<SectorShape2KiB>(config, &phase1_output, partition)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    assert!(
+        seal_commit_phase2_partition::<SectorShape2KiB>(config, &phase1_output, partitions)
+            .is_err(),
+        "out of range partition must fail"
+    );
+
+    let output = seal_commit_phase2_assemble::<SectorShape2KiB>(
+        config,
+        &phase1_output,
+        &partition_proofs,
+        prover_id,
+        sector_id,
+    )?;
+    assert!(verify_seal::<SectorShape2KiB>(
+        config,
+        phase1_output.comm_r,
+        phase1_output.comm_d,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        &output.proof,
+    )?);
+
+    // Assembly must reject duplicate and missing partitions.
+    let duplicate = partition_proofs[0].clone();
+    partition_proofs.push(duplicate);
+    assert!(seal_commit_phase2_assemble::<SectorShape2KiB>(
+        config,
+        &phase1_output,
+        &partition_proofs,
+        prover_id,
+        sector_id,
+    )
+    .is_err());
+
+    partition_proofs.truncate(partitions - 1);
+    assert!(seal_commit_phase2_assemble::<SectorShape2KiB>(
+        config,
+        &phase1_output,
+        &partition_proofs,
+        prover_id,
+        sector_id,
+    )
+    .is_err());
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_seal_commit_phase1_sparse_2kib_base_8() -> Result<()> {
+    init_logger();
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let (mut piece_file, _piece_bytes) = generate_piece_file(sector_size)?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir().expect("failed to create temp dir");
+
+    let config = porep_config(sector_size, ARBITRARY_POREP_ID_V1_1_0, ApiVersion::V1_1_0);
+    let ticket = rng.gen();
+    let seed = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+
+    let (piece_infos, phase1_output) = run_seal_pre_commit_phase1::<SectorShape2KiB>(
+        config,
+        prover_id,
+        sector_id,
+        ticket,
+        &cache_dir,
+        &mut piece_file,
+        &sealed_sector_file,
+    )?;
+    let pre_commit_output = seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    let phase1_output = seal_commit_phase1::<_, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit_output.clone(),
+        &piece_infos,
+    )?;
+
+    let plan = seal_commit_phase1_read_plan::<_, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        phase1_output.replica_id,
+        seed,
+    )?;
+    assert!(plan.reads.total_bytes() > 0);
+
+    let sparse_dir = tempdir().expect("failed to create temp dir");
+    let sparse_cache_path = sparse_dir.path().join("cache");
+    let sparse_replica_path = sparse_dir.path().join("sealed");
+    seal_commit_phase1_write_sparse_cache(
+        &plan,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        &sparse_cache_path,
+        &sparse_replica_path,
+    )?;
+
+    let sparse_phase1_output = seal_commit_phase1_sparse::<_, SectorShape2KiB>(
+        config,
+        &sparse_cache_path,
+        &sparse_replica_path,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit_output.clone(),
+        &piece_infos,
+    )?;
+    assert_eq!(
+        serialize(&phase1_output)?,
+        serialize(&sparse_phase1_output)?
+    );
+
+    // The sparse cache only holds what the planned seed needs.
+    let other_seed = rng.gen();
+    assert!(seal_commit_phase1_sparse::<_, SectorShape2KiB>(
+        config,
+        &sparse_cache_path,
+        &sparse_replica_path,
+        prover_id,
+        sector_id,
+        ticket,
+        other_seed,
+        pre_commit_output,
+        &piece_infos,
+    )
+    .is_err());
+
+    Ok(())
+}
+
 fn create_seal_for_aggregation<R: Rng, Tree: 'static + MerkleTreeTrait>(
     rng: &mut R,
     sector_size: u64,
@@ -1,6 +1,8 @@
 use filecoin_proofs::{
-    with_shape, SECTOR_SIZE_16_MIB, SECTOR_SIZE_1_GIB, SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB,
-    SECTOR_SIZE_4_KIB, SECTOR_SIZE_512_MIB, SECTOR_SIZE_64_GIB, SECTOR_SIZE_8_MIB,
+    register_sector_size, registered_sector_shape, supported_sector_sizes, with_shape,
+    CustomSectorSize, SectorShape, LAYERS, POREP_PARTITIONS, SECTOR_SIZE_16_MIB, SECTOR_SIZE_1_GIB,
+    SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB, SECTOR_SIZE_4_KIB, SECTOR_SIZE_512_MIB,
+    SECTOR_SIZE_64_GIB, SECTOR_SIZE_8_MIB, WINDOW_POST_SECTOR_COUNT,
 };
 use generic_array::typenum::Unsigned;
 use storage_proofs_core::merkle::MerkleTreeTrait;
@@ -83,3 +85,123 @@ fn test_with_shape_macro_aux(sector_size: u64) {
         sector_size, arities, expected
     );
 }
+
+fn custom_sector_size(sector_size: u64) -> CustomSectorSize {
+    let (base_arity, sub_arity, top_arity) = canonical_shape(sector_size);
+    CustomSectorSize {
+        sector_size,
+        base_arity,
+        sub_arity,
+        top_arity,
+        layers: 2,
+        porep_partitions: 1,
+        porep_minimum_challenges: 2,
+        window_post_sector_count: 2,
+    }
+}
+
+#[test]
+fn test_register_sector_size() {
+    for sector_size in &[1 << 16, 1 << 17, 1 << 19, 1 << 28] {
+        let custom = custom_sector_size(*sector_size);
+        register_sector_size(custom).expect("failed to register sector size");
+        // Registering the same parameters again is allowed.
+        register_sector_size(custom).expect("failed to register sector size again");
+
+        test_with_shape_macro_aux(*sector_size);
+        assert!(supported_sector_sizes().contains(sector_size));
+        assert_eq!(
+            LAYERS.read().expect("LAYERS poisoned").get(sector_size),
+            Some(&custom.layers)
+        );
+        assert_eq!(
+            POREP_PARTITIONS
+                .read()
+                .expect("POREP_PARTITIONS poisoned")
+                .get(sector_size),
+            Some(&custom.porep_partitions)
+        );
+        assert_eq!(
+            WINDOW_POST_SECTOR_COUNT
+                .read()
+                .expect("WINDOW_POST_SECTOR_COUNT poisoned")
+                .get(sector_size),
+            Some(&custom.window_post_sector_count)
+        );
+    }
+
+    // Shapes other than the canonical one can be registered.
+    let sector_size = 1 << 22;
+    let custom = CustomSectorSize {
+        sub_arity: 8,
+        top_arity: 4,
+        ..custom_sector_size(sector_size)
+    };
+    register_sector_size(custom).expect("failed to register sector size");
+    assert_eq!(
+        registered_sector_shape(sector_size),
+        Some(SectorShape::Top4)
+    );
+    assert_eq!(with_shape!(sector_size, arities_to_usize), (8, 8, 4));
+}
+
+#[test]
+fn test_register_sector_size_invalid() {
+    // Published sizes cannot be changed.
+    assert!(register_sector_size(custom_sector_size(SECTOR_SIZE_2_KIB)).is_err());
+
+    // Sizes must be powers of two and fit the shape.
+    let custom = custom_sector_size(1 << 21);
+    assert!(register_sector_size(CustomSectorSize {
+        sector_size: 3 << 20,
+        ..custom
+    })
+    .is_err());
+    assert!(register_sector_size(CustomSectorSize {
+        sub_arity: 8,
+        ..custom
+    })
+    .is_err());
+    assert!(register_sector_size(CustomSectorSize {
+        base_arity: 4,
+        ..custom
+    })
+    .is_err());
+    assert!(register_sector_size(CustomSectorSize {
+        layers: 0,
+        ..custom
+    })
+    .is_err());
+    assert!(registered_sector_shape(1 << 21).is_none());
+
+    // A registered size cannot be changed.
+    register_sector_size(custom).expect("failed to register sector size");
+    assert!(register_sector_size(CustomSectorSize {
+        layers: 3,
+        ..custom
+    })
+    .is_err());
+}
+
+#[test]
+fn test_custom_sector_size_from_str() {
+    let fields = "sector_size=65536,base_arity=8,sub_arity=4,top_arity=0,layers=2,\
+        porep_partitions=1,porep_minimum_challenges=2,window_post_sector_count=2";
+    let custom: CustomSectorSize = fields.parse().expect("failed to parse custom sector size");
+    assert_eq!(custom, custom_sector_size(1 << 16));
+
+    // Every field is required exactly once, with a valid value.
+    assert!("sector_size=65536,base_arity=8,sub_arity=4,top_arity=0"
+        .parse::<CustomSectorSize>()
+        .is_err());
+    assert!(format!("{},layers=2", fields)
+        .parse::<CustomSectorSize>()
+        .is_err());
+    assert!(fields
+        .replace("layers=2", "layers=two")
+        .parse::<CustomSectorSize>()
+        .is_err());
+    assert!(format!("{},arity=8", fields)
+        .parse::<CustomSectorSize>()
+        .is_err());
+}
@@ -0,0 +1,118 @@
+use std::env;
+use std::fs::{remove_file, File};
+use std::io::{Seek, SeekFrom, Write};
+
+use anyhow::Result;
+use bincode::{deserialize, serialize};
+use filecoin_proofs::{
+    add_piece, generate_piece_commitment, seal_pre_commit_phase1, seal_pre_commit_phase2,
+    unseal_range, validate_cache_for_commit, PaddedBytesAmount, PoRepConfig, PoRepProofPartitions,
+    SealPreCommitPhase1Output, SectorShape2KiB, SectorSize, UnpaddedByteIndex, UnpaddedBytesAmount,
+    POREP_PARTITIONS, SECTOR_SIZE_2_KIB, TEST_SEED,
+};
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use storage_proofs_core::api_version::ApiVersion;
+use tempfile::{tempdir, NamedTempFile};
+
+// The settings are read once per process, so direct I/O is only enabled in this test binary.
+fn enable_direct_io() {
+    env::set_var("FIL_PROOFS_USE_DIRECT_IO", "1");
+}
+
+#[test]
+fn test_seal_pre_commit_phase2_direct_io() -> Result<()> {
+    enable_direct_io();
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let config = PoRepConfig {
+        sector_size: SectorSize(sector_size),
+        partitions: PoRepProofPartitions(
+            *POREP_PARTITIONS
+                .read()
+                .expect("POREP_PARTITIONS poisoned")
+                .get(&sector_size)
+                .expect("unknown sector size"),
+        ),
+        porep_id: [5; 32],
+        api_version: ApiVersion::V1_1_0,
+    };
+    let prover_id = [1; 32];
+    let sector_id = rng.gen::<u64>().into();
+    let ticket = rng.gen();
+
+    let number_of_bytes_in_piece = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+    let piece_bytes: Vec<u8> = (0..number_of_bytes_in_piece.0).map(|_| rng.gen()).collect();
+    let mut piece_file = NamedTempFile::new()?;
+    piece_file.write_all(&piece_bytes)?;
+    piece_file.seek(SeekFrom::Start(0))?;
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), number_of_bytes_in_piece)?;
+    piece_file.seek(SeekFrom::Start(0))?;
+
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(
+        &mut piece_file,
+        &mut staged_sector_file,
+        number_of_bytes_in_piece,
+        &[],
+    )?;
+
+    let cache_dir = tempdir()?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let phase1_output = seal_pre_commit_phase1::<_, _, _, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &[piece_info],
+    )?;
+    let phase1_output_bytes = serialize(&phase1_output)?;
+
+    let pre_commit_output = seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+    validate_cache_for_commit::<_, _, SectorShape2KiB>(
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+    let replica = std::fs::read(sealed_sector_file.path())?;
+
+    // Rebuilding tree_r_last after an interrupted run restores the replica region from tree_d
+    // and encodes it again.
+    remove_file(cache_dir.path().join("sc-02-data-tree-r-last.dat.done"))?;
+    let phase1_output: SealPreCommitPhase1Output<SectorShape2KiB> =
+        deserialize(&phase1_output_bytes)?;
+    let resumed_output = seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+    assert_eq!(resumed_output.comm_r, pre_commit_output.comm_r);
+    assert_eq!(std::fs::read(sealed_sector_file.path())?, replica);
+
+    // The replica written with direct I/O decodes to the piece.
+    let mut unsealed = Vec::new();
+    unseal_range::<_, _, _, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        File::open(sealed_sector_file.path())?,
+        &mut unsealed,
+        prover_id,
+        sector_id,
+        pre_commit_output.comm_d,
+        ticket,
+        UnpaddedByteIndex(0),
+        number_of_bytes_in_piece,
+    )?;
+    assert_eq!(unsealed, piece_bytes);
+
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use filecoin_proofs::{
+    post_resources, seal_resources, Phase, PhaseResources, PoRepConfig, PoRepProofPartitions,
+    PoStConfig, PoStType, SectorShape2KiB, SectorShape32GiB, SectorSize, POREP_PARTITIONS,
+    SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB, WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT,
+};
+use storage_proofs_core::api_version::ApiVersion;
+
+fn porep_config(sector_size: u64) -> PoRepConfig {
+    PoRepConfig {
+        sector_size: SectorSize(sector_size),
+        partitions: PoRepProofPartitions(
+            *POREP_PARTITIONS
+                .read()
+                .expect("POREP_PARTITIONS poisoned")
+                .get(&sector_size)
+                .expect("unknown sector size"),
+        ),
+        porep_id: [0; 32],
+        api_version: ApiVersion::V1_1_0,
+    }
+}
+
+#[test]
+fn test_seal_resources_2kib() {
+    let resources = seal_resources::<SectorShape2KiB>(porep_config(SECTOR_SIZE_2_KIB))
+        .expect("seal_resources failed");
+
+    let phases: Vec<Phase> = resources.phases.iter().map(|phase| phase.phase).collect();
+    assert_eq!(
+        phases,
+        vec![
+            Phase::PreCommit1,
+            Phase::PreCommit2,
+            Phase::Commit1,
+            Phase::Commit2
+        ]
+    );
+
+    // 64 nodes with 14 parents of 4 bytes each.
+    assert_eq!(resources.parent_cache_size, 3584);
+    assert_eq!(resources.replica_size, 2048);
+
+    let pre_commit1 = &resources.phases[0];
+    let files: Vec<(&str, u64)> = pre_commit1
+        .cache_files
+        .iter()
+        .map(|file| (file.name.as_str(), file.size))
+        .collect();
+    assert_eq!(
+        files,
+        vec![
+            ("sc-02-data-layer-1.dat", 2048),
+            ("sc-02-data-layer-2.dat", 2048),
+            ("sc-02-data-tree-d.dat", 127 * 32),
+        ]
+    );
+    assert!(
+        pre_commit1
+            .peak_memory
+            .expect("no pre commit 1 peak memory")
+            >= 2 * 2048
+    );
+
+    // A single oct tree over 64 nodes, of which tree_r_last only keeps the root.
+    let pre_commit2 = &resources.phases[1];
+    let files: Vec<(&str, u64)> = pre_commit2
+        .cache_files
+        .iter()
+        .map(|file| (file.name.as_str(), file.size))
+        .collect();
+    assert_eq!(
+        files,
+        vec![
+            ("sc-02-data-tree-c.dat", 73 * 32),
+            ("sc-02-data-tree-r-last.dat", 32),
+        ]
+    );
+    assert_eq!(resources.final_cache_size, 32);
+    assert_eq!(
+        resources.peak_cache_size(),
+        2 * 2048 + 127 * 32 + 73 * 32 + 32
+    );
+
+    // Two challenges, each with 8 nodes of tree_d, 16 of tree_r_last, 15 columns of 2 labels with
+    // 16 nodes of tree_c and 3 times 14 parent labels.
+    let commit1 = &resources.phases[2];
+    assert_eq!(commit1.peak_memory, Some(2 * 336 * 32));
+
+    let commit2 = &resources.phases[3];
+    assert_eq!(commit2.parameter_files.len(), 2);
+    assert!(commit2.parameter_files[0]
+        .path
+        .to_string_lossy()
+        .ends_with(".params"));
+    assert_proving_memory(commit2);
+}
+
+#[test]
+fn test_seal_resources_32gib() {
+    let resources = seal_resources::<SectorShape32GiB>(porep_config(SECTOR_SIZE_32_GIB))
+        .expect("seal_resources failed");
+
+    assert_eq!(resources.parent_cache_size, 56 << 30);
+
+    let pre_commit1 = &resources.phases[0];
+    assert_eq!(pre_commit1.cache_files.len(), 12);
+    assert_eq!(
+        pre_commit1.scratch_disk(),
+        11 * (32 << 30) + ((2 << 30) - 1) * 32
+    );
+
+    // Eight oct sub-trees over 2^27 nodes each, see `default_rows_to_discard`.
+    let pre_commit2 = &resources.phases[1];
+    assert_eq!(pre_commit2.cache_files.len(), 16);
+    assert_eq!(pre_commit2.cache_files[0].name, "sc-02-data-tree-c-0.dat");
+    assert_eq!(pre_commit2.cache_files[0].size, 153_391_689 * 32);
+    assert_eq!(
+        pre_commit2.cache_files[15].name,
+        "sc-02-data-tree-r-last-7.dat"
+    );
+    assert_eq!(resources.final_cache_size, 8 * 299_593 * 32);
+
+    // 10 partitions of 18 challenges, each with 32 nodes of tree_d, 72 of tree_r_last, 15
+    // columns of 11 labels with 72 nodes of tree_c and 12 times 14 parent labels.
+    let commit1 = &resources.phases[2];
+    assert_eq!(commit1.peak_memory, Some(180 * 1517 * 32));
+}
+
+#[test]
+fn test_post_resources() {
+    let post_config = PoStConfig {
+        sector_size: SectorSize(SECTOR_SIZE_2_KIB),
+        challenge_count: WINNING_POST_CHALLENGE_COUNT,
+        sector_count: WINNING_POST_SECTOR_COUNT,
+        typ: PoStType::Winning,
+        priority: false,
+        api_version: ApiVersion::V1_1_0,
+    };
+    let resources = post_resources::<SectorShape2KiB>(&post_config).expect("post_resources failed");
+
+    assert_eq!(resources.phase, Phase::WinningPoSt);
+    assert!(resources.cache_files.is_empty());
+    assert_eq!(resources.parameter_files.len(), 2);
+    assert_proving_memory(&resources);
+}
+
+/// The memory of proving is only known if the Groth parameters are in the parameter cache, and
+/// covers them.
+fn assert_proving_memory(resources: &PhaseResources) {
+    match resources.parameter_files[0].size {
+        Some(size) => assert!(resources.peak_memory.expect("no proving peak memory") > size),
+        None => assert_eq!(resources.peak_memory, None),
+    }
+}
@@ -0,0 +1,90 @@
+use std::fs::{read_dir, File};
+use std::io::BufReader;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Result;
+use filecoin_proofs::{
+    test_vectors::{generate_test_vector, TestVector},
+    SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB, SECTOR_SIZE_8_MIB,
+};
+use storage_proofs_core::api_version::ApiVersion;
+use tempfile::tempdir;
+
+/// The sector sizes whose vectors must be checked in, for both API versions.
+const CHECKED_IN_SECTOR_SIZES: [u64; 2] = [SECTOR_SIZE_2_KIB, SECTOR_SIZE_8_MIB];
+
+/// Checks the library against every vector in tests/vectors. The vectors are (re)generated with
+/// `cargo run --release --bin gen_test_vectors -- -z 2048,8388608`.
+#[test]
+fn test_checked_in_vectors() -> Result<()> {
+    let vectors_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vectors");
+
+    for sector_size in &CHECKED_IN_SECTOR_SIZES {
+        for api_version in &[ApiVersion::V1_0_0, ApiVersion::V1_1_0] {
+            let path = vectors_dir.join(format!("{}-{}.json", sector_size, api_version));
+            assert!(path.exists(), "missing vector {:?}", path);
+        }
+    }
+
+    let mut checked = 0;
+    for entry in read_dir(&vectors_dir)? {
+        let path = entry?.path();
+        if path.extension().map_or(true, |ext| ext != "json") {
+            continue;
+        }
+
+        let expected: TestVector = serde_json::from_reader(BufReader::new(File::open(&path)?))?;
+        let api_version = ApiVersion::from_str(&expected.api_version)?;
+
+        let work_dir = tempdir()?;
+        let actual = generate_test_vector(
+            expected.sector_size,
+            api_version,
+            expected.seal.as_ref().map(|_| work_dir.path()),
+        )?;
+
+        assert_eq!(actual, expected, "vector {:?} does not match", path);
+        checked += 1;
+    }
+    assert!(checked >= 2 * CHECKED_IN_SECTOR_SIZES.len());
+
+    Ok(())
+}
+
+#[test]
+fn test_vector_generation_2kib() -> Result<()> {
+    for api_version in &[ApiVersion::V1_0_0, ApiVersion::V1_1_0] {
+        let work_dir = tempdir()?;
+        let vector = generate_test_vector(SECTOR_SIZE_2_KIB, *api_version, Some(work_dir.path()))?;
+
+        let seal = vector.seal.as_ref().expect("sealing was requested");
+        assert_eq!(seal.labels.len(), 2);
+        assert!(seal.labels.iter().all(|labels| labels.len() == 8));
+        assert_eq!(vector.parents.len(), 8);
+        assert_eq!(vector.porep_challenges.len(), 1);
+        assert_eq!(vector.winning_post.challenges.len(), 1);
+        assert_eq!(vector.window_post.challenges.len(), 4);
+
+        // Generation is reproducible, and survives a round trip through JSON.
+        let work_dir = tempdir()?;
+        let again = generate_test_vector(SECTOR_SIZE_2_KIB, *api_version, Some(work_dir.path()))?;
+        assert_eq!(vector, again);
+
+        let json = serde_json::to_string(&vector)?;
+        assert_eq!(vector, serde_json::from_str::<TestVector>(&json)?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_vector_generation_without_seal() -> Result<()> {
+    let vector = generate_test_vector(SECTOR_SIZE_32_GIB, ApiVersion::V1_1_0, None)?;
+
+    assert!(vector.seal.is_none());
+    assert_eq!(vector.porep_challenges.len(), 10);
+    assert_eq!(&vector.porep_id[..16], "0800000000000000");
+
+    Ok(())
+}
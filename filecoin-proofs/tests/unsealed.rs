@@ -0,0 +1,102 @@
+use std::io::Cursor;
+
+use anyhow::Result;
+use filecoin_proofs::{
+    add_piece, generate_piece_commitment, SectorSize, UnpaddedByteIndex, UnpaddedBytesAmount,
+    UnsealedSector, TEST_SEED,
+};
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use tempfile::tempdir;
+
+#[test]
+fn test_unsealed_sector_out_of_order_pieces() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SectorSize(2048);
+
+    let piece_a: Vec<u8> = (0..254).map(|_| rng.gen()).collect();
+    let piece_b: Vec<u8> = (0..508).map(|_| rng.gen()).collect();
+
+    let dir = tempdir()?;
+    let path = dir.path().join("unsealed");
+    let mut sector = UnsealedSector::create(&path, sector_size)?;
+
+    // The second piece is written first, at its aligned offset.
+    let info_b = sector.write_piece(
+        Cursor::new(&piece_b),
+        UnpaddedByteIndex(508),
+        UnpaddedBytesAmount(508),
+    )?;
+    let info_a = sector.write_piece(
+        Cursor::new(&piece_a),
+        UnpaddedByteIndex(0),
+        UnpaddedBytesAmount(254),
+    )?;
+    assert_eq!(
+        info_a,
+        generate_piece_commitment(Cursor::new(&piece_a), UnpaddedBytesAmount(254))?
+    );
+    assert_eq!(
+        info_b,
+        generate_piece_commitment(Cursor::new(&piece_b), UnpaddedBytesAmount(508))?
+    );
+
+    // Misaligned and overlapping pieces are rejected.
+    assert!(sector
+        .write_piece(
+            Cursor::new(&piece_a),
+            UnpaddedByteIndex(127),
+            UnpaddedBytesAmount(254),
+        )
+        .is_err());
+    assert!(sector
+        .write_piece(
+            Cursor::new(&piece_a),
+            UnpaddedByteIndex(508),
+            UnpaddedBytesAmount(254),
+        )
+        .is_err());
+
+    // The data file matches the output of appending the pieces with `add_piece`.
+    let mut staged = Vec::new();
+    add_piece(
+        Cursor::new(&piece_a),
+        &mut staged,
+        UnpaddedBytesAmount(254),
+        &[],
+    )?;
+    add_piece(
+        Cursor::new(&piece_b),
+        &mut staged,
+        UnpaddedBytesAmount(508),
+        &[UnpaddedBytesAmount(254)],
+    )?;
+    let data = std::fs::read(sector.path())?;
+    assert_eq!(data.len(), 2048);
+    assert_eq!(&data[..staged.len()], &staged[..]);
+    assert!(data[staged.len()..].iter().all(|b| *b == 0));
+
+    // Presence survives reopening.
+    drop(sector);
+    let sector = UnsealedSector::open(&path, sector_size)?;
+    assert_eq!(
+        sector.present_ranges(),
+        vec![
+            (UnpaddedByteIndex(0), UnpaddedBytesAmount(254)),
+            (UnpaddedByteIndex(508), UnpaddedBytesAmount(508)),
+        ]
+    );
+    assert!(sector.is_present(UnpaddedByteIndex(100), UnpaddedBytesAmount(100)));
+    assert!(!sector.is_present(UnpaddedByteIndex(200), UnpaddedBytesAmount(100)));
+    assert!(!sector.is_present(UnpaddedByteIndex(1016), UnpaddedBytesAmount(1)));
+    assert!(UnsealedSector::open(&path, SectorSize(4096)).is_err());
+
+    let mut read = Vec::new();
+    sector.read_range(UnpaddedByteIndex(600), UnpaddedBytesAmount(300), &mut read)?;
+    assert_eq!(&read[..], &piece_b[92..392]);
+    assert!(sector
+        .read_range(UnpaddedByteIndex(200), UnpaddedBytesAmount(100), Vec::new())
+        .is_err());
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use anyhow::{ensure, Context};
+use anyhow::{bail, ensure, Context};
 use bellperson::{
     bls::{Bls12, Fr},
     groth16::{
@@ -23,6 +23,7 @@ use crate::{
     parameter_cache::{CacheableParameters, ParameterSetMetadata},
     partitions::partition_count,
     proof::ProofScheme,
+    r1cs::R1csCS,
 };
 
 #[derive(Clone)]
@@ -277,6 +278,87 @@ where
             .collect()
     }
 
+    /// partition_circuit_proof creates and synthesizes the circuit of the single partition `k`,
+    /// then generates a groth proof from it. The proof verifies against the public inputs of
+    /// partition `k`, so partitions can be proven independently and put together in partition
+    /// order into a proof that verifies like one from circuit_proofs. Groth proofs are
+    /// randomized, so the bytes differ from those circuit_proofs would return.
+    fn partition_circuit_proof(
+        pub_in: &S::PublicInputs,
+        vanilla_proof: &S::Proof,
+        k: usize,
+        pub_params: &S::PublicParams,
+        groth_params: &groth16::MappedParameters<Bls12>,
+        priority: bool,
+    ) -> Result<groth16::Proof<Bls12>> {
+        let mut rng = OsRng;
+
+        let circuit = Self::circuit(
+            &pub_in,
+            C::ComponentPrivateInputs::default(),
+            vanilla_proof,
+            &pub_params,
+            Some(k),
+        )?;
+
+        let mut groth_proofs = if priority {
+            create_random_proof_batch_in_priority(vec![circuit], groth_params, &mut rng)?
+        } else {
+            create_random_proof_batch(vec![circuit], groth_params, &mut rng)?
+        };
+        ensure!(
+            groth_proofs.len() == 1,
+            "expected a single groth proof for partition {}",
+            k
+        );
+
+        groth_proofs
+            .pop()
+            .with_context(|| format!("missing groth proof for partition {}", k))
+    }
+
+    /// blank_r1cs synthesizes the blank circuit into an R1csCS, recording its constraints
+    /// without an assignment, so that they can be exported with `R1csCS::write_r1cs`.
+    fn blank_r1cs(public_params: &S::PublicParams) -> Result<R1csCS> {
+        let mut cs = R1csCS::new();
+        Self::blank_circuit(public_params).synthesize(&mut cs)?;
+
+        Ok(cs)
+    }
+
+    /// partition_r1cs synthesizes the circuit of partition `k` from a real vanilla proof into an
+    /// R1csCS, recording its constraints together with a satisfying witness, so that both can be
+    /// exported with `R1csCS::write_r1cs` and `R1csCS::write_witness`.
+    fn partition_r1cs(
+        pub_in: &S::PublicInputs,
+        vanilla_proof: &S::Proof,
+        k: usize,
+        pub_params: &S::PublicParams,
+    ) -> Result<R1csCS> {
+        let circuit = Self::circuit(
+            &pub_in,
+            C::ComponentPrivateInputs::default(),
+            vanilla_proof,
+            &pub_params,
+            Some(k),
+        )?;
+
+        let mut cs = R1csCS::new();
+        circuit.synthesize(&mut cs)?;
+
+        ensure!(cs.has_witness(), "incomplete witness for partition {}", k);
+        ensure!(
+            cs.public_inputs()? == Self::generate_public_inputs(pub_in, pub_params, Some(k))?,
+            "public inputs of partition {} do not match the generated inputs",
+            k
+        );
+        if let Some(i) = cs.which_is_unsatisfied()? {
+            bail!("constraint {} of partition {} is not satisfied", i, k);
+        }
+
+        Ok(cs)
+    }
+
     /// Given a prover_srs key, a list of groth16 proofs, and an ordered list of seeds
     /// (used to derive the PoRep challenges) hashed pair-wise with the comm_rs using sha256, aggregate them all into
     /// an AggregateProof type.
@@ -0,0 +1,76 @@
+//! The context of a thread doing work for a sector: the labels its metrics are recorded with and
+//! the tracing span its log lines and spans are nested in.
+//!
+//! Neither is inherited by spawned threads or rayon workers. Threads doing work on behalf of
+//! another one capture its context with `ThreadContext::current` and enter it.
+
+use tracing::{span::Entered, Span};
+
+use crate::metrics::{scoped_labels, LabelsGuard, MetricLabels};
+
+/// The metric labels and tracing span of a thread.
+#[derive(Clone, Debug)]
+pub struct ThreadContext {
+    labels: MetricLabels,
+    span: Span,
+}
+
+/// Restores the previous context of the thread when dropped.
+#[must_use = "the context is left when the guard is dropped"]
+pub struct ContextGuard<'a> {
+    _labels: LabelsGuard,
+    _span: Entered<'a>,
+}
+
+impl ThreadContext {
+    /// Captures the context of the current thread.
+    pub fn current() -> Self {
+        ThreadContext {
+            labels: MetricLabels::current(),
+            span: Span::current(),
+        }
+    }
+
+    /// Enters the context on the current thread, until the returned guard is dropped.
+    pub fn enter(&self) -> ContextGuard<'_> {
+        ContextGuard {
+            _labels: scoped_labels(self.labels),
+            _span: self.span.enter(),
+        }
+    }
+
+    /// Runs `f` within the context on the current thread.
+    pub fn in_scope<T, F: FnOnce() -> T>(&self, f: F) -> T {
+        let _context = self.enter();
+        f()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    use tracing::info_span;
+
+    #[test]
+    fn test_thread_context_is_entered_on_other_threads() {
+        let labels = MetricLabels::new(2048, Some(7));
+        let _labels = scoped_labels(labels);
+        let span = info_span!("test");
+        let _span = span.enter();
+
+        let context = ThreadContext::current();
+        thread::spawn(move || {
+            assert_eq!(MetricLabels::current(), MetricLabels::default());
+            context.in_scope(|| {
+                assert_eq!(MetricLabels::current(), labels);
+                assert_eq!(Span::current().id(), context.span.id());
+            });
+            assert_eq!(MetricLabels::current(), MetricLabels::default());
+        })
+        .join()
+        .expect("thread failed");
+    }
+}
@@ -1,9 +1,15 @@
+#[cfg(feature = "prover")]
 use std::fs::OpenOptions;
 use std::ops::{Deref, DerefMut};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use anyhow::{ensure, Context, Result};
+#[cfg(not(feature = "prover"))]
+use anyhow::bail;
+#[cfg(feature = "prover")]
+use anyhow::Context;
+use anyhow::{ensure, Result};
 use log::info;
+#[cfg(feature = "prover")]
 use memmap::{MmapMut, MmapOptions};
 
 /// A wrapper around data either on disk or a slice in memory, that can be dropped and read back into memory,
@@ -18,6 +24,7 @@ pub struct Data<'a> {
 #[derive(Debug)]
 enum RawData<'a> {
     Slice(&'a mut [u8]),
+    #[cfg(feature = "prover")]
     Mmap(MmapMut),
 }
 
@@ -27,6 +34,7 @@ impl<'a> Deref for RawData<'a> {
     fn deref(&self) -> &Self::Target {
         match self {
             RawData::Slice(ref raw) => raw,
+            #[cfg(feature = "prover")]
             RawData::Mmap(ref raw) => raw,
         }
     }
@@ -36,6 +44,7 @@ impl<'a> DerefMut for RawData<'a> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         match self {
             RawData::Slice(ref mut raw) => raw,
+            #[cfg(feature = "prover")]
             RawData::Mmap(ref mut raw) => raw,
         }
     }
@@ -52,6 +61,7 @@ impl<'a> From<&'a mut [u8]> for Data<'a> {
     }
 }
 
+#[cfg(feature = "prover")]
 impl<'a> From<(MmapMut, PathBuf)> for Data<'a> {
     fn from(raw: (MmapMut, PathBuf)) -> Self {
         let len = raw.0.len();
@@ -108,6 +118,11 @@ impl<'a> Data<'a> {
         self.len == 0
     }
 
+    /// Returns the path of the file the data is read from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
     /// Recover the data.
     pub fn ensure_data(&mut self) -> Result<()> {
         match self.raw {
@@ -116,21 +131,22 @@ impl<'a> Data<'a> {
                 ensure!(self.path.is_some(), "Missing path");
                 let path = self.path.as_ref().expect("path as_ref failure");
 
-                info!("restoring {}", path.display());
-
-                let f_data = OpenOptions::new()
-                    .read(true)
-                    .write(true)
-                    .open(path)
-                    .with_context(|| format!("could not open path={:?}", path))?;
-                let data = unsafe {
-                    MmapOptions::new()
-                        .map_mut(&f_data)
-                        .with_context(|| format!("could not mmap path={:?}", path))?
-                };
-
-                self.len = data.len();
-                self.raw = Some(RawData::Mmap(data));
+                // Data on disk is only mapped back in by provers.
+                #[cfg(not(feature = "prover"))]
+                bail!(
+                    "cannot restore {} without the prover feature",
+                    path.display()
+                );
+
+                #[cfg(feature = "prover")]
+                {
+                    info!("restoring {}", path.display());
+
+                    let data = map_path(path)?;
+
+                    self.len = data.len();
+                    self.raw = Some(RawData::Mmap(data));
+                }
             }
         }
 
@@ -145,3 +161,19 @@ impl<'a> Data<'a> {
         }
     }
 }
+
+#[cfg(feature = "prover")]
+fn map_path(path: &Path) -> Result<MmapMut> {
+    let f_data = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("could not open path={:?}", path))?;
+    let data = unsafe {
+        MmapOptions::new()
+            .map_mut(&f_data)
+            .with_context(|| format!("could not mmap path={:?}", path))?
+    };
+
+    Ok(data)
+}
@@ -376,7 +376,7 @@ mod tests {
             .expect("failed to build tree");
         let proof = tree.gen_proof(2).expect("failed to gen proof");
 
-        assert!(proof.verify());
+        assert!(proof.verify().expect("failed to verify proof"));
     }
 
     #[test]
@@ -41,6 +41,8 @@ pub enum Error {
     FaultySectors(Vec<SectorId>),
     #[error("Invalid parameters file: {}", _0)]
     InvalidParameters(String),
+    #[error("unsealed data in padded bytes {}..{} does not match comm_d", _0, _1)]
+    UnsealedDataMismatch(u64, u64),
 }
 
 impl From<Box<dyn Any + Send>> for Error {
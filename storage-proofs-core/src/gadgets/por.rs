@@ -713,7 +713,7 @@ mod tests {
                 .expect("try_from_bytes failure");
             let priv_inputs = por::PrivateInputs::<ResTree<Tree>>::new(leaf_element, &tree);
             let p = tree.gen_proof(i).expect("gen_proof failure");
-            assert!(p.verify());
+            assert!(p.verify().expect("verify failure"));
 
             // create a non circuit proof
             let proof = PoR::<ResTree<Tree>>::prove(&pub_params, &pub_inputs, &priv_inputs)
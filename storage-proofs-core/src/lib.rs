@@ -14,6 +14,7 @@ use std::convert::TryInto;
 pub mod api_version;
 pub mod cache_key;
 pub mod compound_proof;
+pub mod context;
 pub mod crypto;
 pub mod data;
 pub mod drgraph;
@@ -21,14 +22,17 @@ pub mod error;
 pub mod gadgets;
 pub mod measurements;
 pub mod merkle;
+pub mod metrics;
 pub mod multi_proof;
 pub mod parameter_cache;
 pub mod partitions;
 pub mod pieces;
 pub mod por;
 pub mod proof;
+pub mod r1cs;
 pub mod sector;
 pub mod settings;
+#[cfg(feature = "prover")]
 pub mod test_helper;
 pub mod util;
 
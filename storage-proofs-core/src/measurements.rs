@@ -1,41 +1,15 @@
-#[cfg(feature = "measurements")]
-use std::sync::{
-    mpsc::{channel, Receiver, Sender},
-    Mutex,
-};
-use std::time::Duration;
+use serde::{Deserialize, Serialize};
 
-#[cfg(feature = "measurements")]
-use lazy_static::lazy_static;
-use serde::Serialize;
+use crate::metrics::METRICS;
 
-#[cfg(feature = "measurements")]
-lazy_static! {
-    pub static ref OP_MEASUREMENTS: (
-        Mutex<Option<Sender<OpMeasurement>>>,
-        Mutex<Receiver<OpMeasurement>>
-    ) = {
-        // create asynchronous channel with unlimited buffer
-        let (tx, rx) = channel();
-        (Mutex::new(Some(tx)), Mutex::new(rx))
-    };
-}
-
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "kebab-case")]
-pub struct OpMeasurement {
-    pub op: Operation,
-    pub cpu_time: Duration,
-    pub wall_time: Duration,
-}
-
-#[derive(Debug, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum Operation {
     AddPiece,
     GeneratePieceCommitment,
     GenerateTreeC,
     GenerateTreeRLast,
+    GenerateTreesPipelined,
     CommD,
     EncodeWindowTimeAll,
     WindowCommLeavesTime,
@@ -44,17 +18,46 @@ pub enum Operation {
     PostFinalizeTicket,
     PostReadChallengedRange,
     PostPartialTicketHash,
+    ParentCacheRead,
+    LabelLayer,
+    GpuTreeBatch,
+    ParamLoad,
+    StoreWrite,
 }
 
-#[cfg(feature = "measurements")]
+impl Operation {
+    /// The name of the operation, as used by the metrics text exporter.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::AddPiece => "add_piece",
+            Operation::GeneratePieceCommitment => "generate_piece_commitment",
+            Operation::GenerateTreeC => "generate_tree_c",
+            Operation::GenerateTreeRLast => "generate_tree_r_last",
+            Operation::GenerateTreesPipelined => "generate_trees_pipelined",
+            Operation::CommD => "comm_d",
+            Operation::EncodeWindowTimeAll => "encode_window_time_all",
+            Operation::WindowCommLeavesTime => "window_comm_leaves_time",
+            Operation::PorepCommitTime => "porep_commit_time",
+            Operation::PostInclusionProofs => "post_inclusion_proofs",
+            Operation::PostFinalizeTicket => "post_finalize_ticket",
+            Operation::PostReadChallengedRange => "post_read_challenged_range",
+            Operation::PostPartialTicketHash => "post_partial_ticket_hash",
+            Operation::ParentCacheRead => "parent_cache_read",
+            Operation::LabelLayer => "label_layer",
+            Operation::GpuTreeBatch => "gpu_tree_batch",
+            Operation::ParamLoad => "param_load",
+            Operation::StoreWrite => "store_write",
+        }
+    }
+}
+
+/// Runs `f` and records its execution as `op` in the metrics registry, see
+/// [`METRICS`](crate::metrics::METRICS).
 pub fn measure_op<T, F>(op: Operation, f: F) -> T
 where
     F: FnOnce() -> T,
 {
-    use std::time::Instant;
-
-    let cpu_time_start = cpu_time::ProcessTime::now();
-    let wall_start_time = Instant::now();
+    let _timer = METRICS.start(op);
 
     #[cfg(feature = "profile")]
     gperftools::profiler::PROFILER
@@ -70,28 +73,5 @@ where
         .stop()
         .unwrap();
 
-    let opt_tx = OP_MEASUREMENTS
-        .0
-        .lock()
-        .expect("acquire lock on tx side of perf channel");
-
-    if let Some(tx) = opt_tx.as_ref() {
-        tx.clone()
-            .send(OpMeasurement {
-                op,
-                cpu_time: cpu_time_start.elapsed(),
-                wall_time: wall_start_time.elapsed(),
-            })
-            .expect("failed to send to perf channel");
-    }
-
     x
 }
-
-#[cfg(not(feature = "measurements"))]
-pub fn measure_op<T, F>(_: Operation, f: F) -> T
-where
-    F: FnOnce() -> T,
-{
-    f()
-}
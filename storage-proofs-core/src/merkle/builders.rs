@@ -4,13 +4,14 @@ use std::io::Write;
 use std::mem::size_of;
 use std::path::PathBuf;
 
-use anyhow::ensure;
+use anyhow::{ensure, Context};
 use filecoin_hashers::{Domain, Hasher, PoseidonArity};
 use generic_array::typenum::{Unsigned, U0};
 use log::trace;
 use merkletree::{
     merkle::{
-        get_merkle_tree_leafs, is_merkle_tree_size_valid, FromIndexedParallelIterator, MerkleTree,
+        get_merkle_tree_leafs, is_merkle_tree_size_valid, Element, FromIndexedParallelIterator,
+        MerkleTree,
     },
     store::{DiskStore, ExternalReader, LevelCacheStore, ReplicaConfig, Store, StoreConfig},
 };
@@ -193,16 +194,17 @@ pub fn create_base_merkle_tree<Tree: MerkleTreeTrait>(
         "Invalid merkle tree size given the arity"
     );
 
+    // The parallel iterator building the tree cannot return errors, so the nodes are validated
+    // up front and converted without further checks below.
+    (0..size).into_par_iter().try_for_each(|i| -> Result<()> {
+        <Tree::Hasher as Hasher>::Domain::try_from_bytes(data_at_node(&data, i)?)
+            .with_context(|| format!("invalid node {}", i))?;
+        Ok(())
+    })?;
+
     let f = |i| {
-        // TODO Replace `expect()` with `context()` (problem is the parallel iterator)
         let d = data_at_node(&data, i).expect("data_at_node math failed");
-        // TODO/FIXME: This can panic. FOR NOW, let's leave this since we're experimenting with
-        // optimization paths. However, we need to ensure that bad input will not lead to a panic
-        // that isn't caught by the FPS API.
-        // Unfortunately, it's not clear how to perform this error-handling in the parallel
-        // iterator case.
-        <Tree::Hasher as Hasher>::Domain::try_from_bytes(d)
-            .expect("failed to convert node data to domain element")
+        <Tree::Hasher as Hasher>::Domain::from_slice(d)
     };
 
     let tree = match config {
@@ -258,7 +260,7 @@ pub fn create_base_lcmerkle_tree<H: Hasher, BaseTreeArity: 'static + PoseidonAri
 
     let f = |i| {
         let d = data_at_node(&data, i)?;
-        H::Domain::try_from_bytes(d)
+        H::Domain::try_from_bytes(d).map_err(Into::into)
     };
 
     let mut lc_tree: LCMerkleTree<H, BaseTreeArity> =
@@ -4,10 +4,13 @@ use std::fs::File;
 
 pub use merkletree::store::{DiskStore, ExternalReader, Store};
 
-use filecoin_hashers::Hasher;
+use anyhow::Context;
+use filecoin_hashers::{Domain, Hasher};
 use generic_array::typenum::{U0, U2, U4, U8};
 use merkletree::store::LevelCacheStore;
 
+use crate::{error::Result, util::NODE_SIZE};
+
 mod builders;
 mod proof;
 mod tree;
@@ -41,3 +44,11 @@ pub type OctTopMerkleTree<H> = DiskTree<H, U8, U8, U2>;
 pub type OctLCMerkleTree<H> = LCTree<H, U8, U0, U0>;
 pub type OctLCSubMerkleTree<H> = LCTree<H, U8, U2, U0>;
 pub type OctLCTopMerkleTree<H> = LCTree<H, U8, U8, U2>;
+
+/// Reads the node at `index` from `store`, returning an error instead of panicking later on if the
+/// stored bytes are not a valid domain element, e.g. because the file was corrupted.
+pub fn read_node<E: Domain, S: Store<E>>(store: &S, index: usize) -> Result<E> {
+    let mut buf = [0u8; NODE_SIZE];
+    store.read_into(index, &mut buf)?;
+    E::try_from_bytes(&buf).with_context(|| format!("invalid node {} in store", index))
+}
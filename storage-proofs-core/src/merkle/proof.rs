@@ -6,9 +6,8 @@ use std::slice::Iter;
 
 use anyhow::{ensure, Result};
 use bellperson::bls::Fr;
-use filecoin_hashers::{Hasher, PoseidonArity};
+use filecoin_hashers::{HashFunction, Hasher, PoseidonArity};
 use generic_array::typenum::{Unsigned, U0};
-use merkletree::hash::Algorithm;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::drgraph::graph_height;
@@ -58,26 +57,27 @@ pub trait MerkleProofTrait: Clone + Serialize + DeserializeOwned + Debug + Sync
             .map(|v| (v.0.iter().copied().map(Into::into).collect(), v.1))
             .collect::<Vec<_>>()
     }
-    fn verify(&self) -> bool;
+    /// Checks that the path leads from the leaf to the root. Fails if the proof is malformed.
+    fn verify(&self) -> Result<bool>;
 
     /// Validates the MerkleProof and that it corresponds to the supplied node.
     ///
     /// TODO: audit performance and usage in case verification is
     /// unnecessary based on how it's used.
-    fn validate(&self, node: usize) -> bool {
-        if !self.verify() {
-            return false;
+    fn validate(&self, node: usize) -> Result<bool> {
+        if !self.verify()? {
+            return Ok(false);
         }
 
-        node == self.path_index()
+        Ok(node == self.path_index())
     }
 
-    fn validate_data(&self, data: <Self::Hasher as Hasher>::Domain) -> bool {
-        if !self.verify() {
-            return false;
+    fn validate_data(&self, data: <Self::Hasher as Hasher>::Domain) -> Result<bool> {
+        if !self.verify()? {
+            return Ok(false);
         }
 
-        self.leaf() == data
+        Ok(self.leaf() == data)
     }
 
     fn leaf(&self) -> <Self::Hasher as Hasher>::Domain;
@@ -180,18 +180,24 @@ impl<H: Hasher, Arity: PoseidonArity> From<Vec<PathElement<H, Arity>>> for Inclu
 }
 
 impl<H: Hasher, Arity: PoseidonArity> InclusionPath<H, Arity> {
-    /// Calculate the root of this path, given the leaf as input.
-    pub fn root(&self, leaf: H::Domain) -> H::Domain {
-        let mut a = H::Function::default();
-        (0..self.path.len()).fold(leaf, |h, height| {
-            a.reset();
-
-            let index = self.path[height].index;
-            let mut nodes = self.path[height].hashes.clone();
-            nodes.insert(index, h);
-
-            a.multi_node(&nodes, height)
-        })
+    /// Calculate the root of this path, given the leaf as input. Fails if the path is malformed,
+    /// e.g. because it was deserialized from untrusted input.
+    pub fn root(&self, leaf: H::Domain) -> Result<H::Domain> {
+        self.path
+            .iter()
+            .enumerate()
+            .try_fold(leaf, |h, (height, element)| -> Result<H::Domain> {
+                ensure!(
+                    element.index <= element.hashes.len(),
+                    "invalid index {} in path element of {} hashes",
+                    element.index,
+                    element.hashes.len()
+                );
+                let mut nodes = element.hashes.clone();
+                nodes.insert(element.index, h);
+
+                Ok(H::Function::try_multi_node(&nodes, height)?)
+            })
     }
 
     pub fn len(&self) -> usize {
@@ -271,7 +277,7 @@ impl<
         }
     }
 
-    fn verify(&self) -> bool {
+    fn verify(&self) -> Result<bool> {
         forward_method!(self.data, verify)
     }
 
@@ -516,9 +522,9 @@ impl<H: Hasher, Arity: 'static + PoseidonArity> SingleProof<H, Arity> {
         Ok(proof_to_single(&p, 1, None))
     }
 
-    fn verify(&self) -> bool {
-        let calculated_root = self.path.root(self.leaf);
-        self.root == calculated_root
+    fn verify(&self) -> Result<bool> {
+        let calculated_root = self.path.root(self.leaf)?;
+        Ok(self.root == calculated_root)
     }
 
     fn leaf(&self) -> H::Domain {
@@ -568,11 +574,11 @@ impl<H: Hasher, Arity: 'static + PoseidonArity, SubTreeArity: 'static + Poseidon
         Ok(SubProof::new(base_proof, sub_proof, root, leaf))
     }
 
-    fn verify(&self) -> bool {
-        let sub_leaf = self.base_proof.root(self.leaf);
-        let calculated_root = self.sub_proof.root(sub_leaf);
+    fn verify(&self) -> Result<bool> {
+        let sub_leaf = self.base_proof.root(self.leaf)?;
+        let calculated_root = self.sub_proof.root(sub_leaf)?;
 
-        self.root == calculated_root
+        Ok(self.root == calculated_root)
     }
 
     fn leaf(&self) -> H::Domain {
@@ -649,12 +655,12 @@ impl<
         Ok(TopProof::new(base_proof, sub_proof, top_proof, root, leaf))
     }
 
-    fn verify(&self) -> bool {
-        let sub_leaf = self.base_proof.root(self.leaf);
-        let top_leaf = self.sub_proof.root(sub_leaf);
-        let calculated_root = self.top_proof.root(top_leaf);
+    fn verify(&self) -> Result<bool> {
+        let sub_leaf = self.base_proof.root(self.leaf)?;
+        let top_leaf = self.sub_proof.root(sub_leaf)?;
+        let calculated_root = self.top_proof.root(top_leaf)?;
 
-        self.root == calculated_root
+        Ok(self.root == calculated_root)
     }
 
     fn leaf(&self) -> H::Domain {
@@ -719,20 +725,46 @@ mod tests {
         for i in 0..nodes {
             let proof = tree.gen_proof(i).expect("gen_proof failure");
 
-            assert!(proof.verify(), "failed to validate");
+            assert!(
+                proof.verify().expect("verify failure"),
+                "failed to validate"
+            );
 
-            assert!(proof.validate(i), "failed to validate valid merkle path");
+            assert!(
+                proof.validate(i).expect("validate failure"),
+                "failed to validate valid merkle path"
+            );
             let data_slice = &data[i * node_size..(i + 1) * node_size].to_vec();
             assert!(
-                proof.validate_data(
-                    <Tree::Hasher as Hasher>::Domain::try_from_bytes(data_slice)
-                        .expect("try from bytes failure")
-                ),
+                proof
+                    .validate_data(
+                        <Tree::Hasher as Hasher>::Domain::try_from_bytes(data_slice)
+                            .expect("try from bytes failure")
+                    )
+                    .expect("validate_data failure"),
                 "failed to validate valid data"
             );
         }
     }
 
+    #[test]
+    fn inclusion_path_root_malformed() {
+        let mut rng = thread_rng();
+        let leaf = <PoseidonHasher as Hasher>::Domain::random(&mut rng);
+        let element = |index| PathElement::<PoseidonHasher, U2> {
+            hashes: vec![<PoseidonHasher as Hasher>::Domain::random(&mut thread_rng())],
+            index,
+            _arity: PhantomData,
+        };
+
+        let path: InclusionPath<PoseidonHasher, U2> = vec![element(1), element(0)].into();
+        assert!(path.root(leaf).is_ok());
+
+        // An index past the hashes of its element must not panic.
+        let path: InclusionPath<PoseidonHasher, U2> = vec![element(0), element(2)].into();
+        assert!(path.root(leaf).is_err());
+    }
+
     #[test]
     fn merklepath_poseidon_2() {
         merklepath::<
@@ -4,8 +4,8 @@ use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 
-use anyhow::Result;
-use filecoin_hashers::{Hasher, PoseidonArity};
+use anyhow::{Context, Result};
+use filecoin_hashers::{Domain, Hasher, PoseidonArity};
 use generic_array::typenum::U0;
 use merkletree::{
     hash::Hashable,
@@ -15,6 +15,7 @@ use merkletree::{
 use rayon::prelude::{IndexedParallelIterator, IntoParallelIterator};
 
 use crate::merkle::{LCTree, MerkleProof, MerkleProofTrait};
+use crate::util::NODE_SIZE;
 
 /// Trait used to abstract over the way Merkle Trees are constructed and stored.
 pub trait MerkleTreeTrait: Send + Sync + Debug {
@@ -163,6 +164,14 @@ impl<
         Ok(tree.into())
     }
 
+    /// Like `read_at`, but returns an error if the stored bytes are not a valid domain element.
+    /// Only base trees keep their nodes in a single store, so this fails for compound trees.
+    pub fn read_node(&self, index: usize) -> Result<H::Domain> {
+        let mut buf = [0u8; NODE_SIZE];
+        self.inner.read_into(index, &mut buf)?;
+        H::Domain::try_from_bytes(&buf).with_context(|| format!("invalid node {} in tree", index))
+    }
+
     pub fn new_with_config<I: IntoIterator<Item = H::Domain>>(
         data: I,
         config: StoreConfig,
@@ -0,0 +1,455 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use cpu_time::ProcessTime;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::measurements::Operation;
+
+lazy_static! {
+    /// The process wide metrics registry. It is always enabled, recording is a few atomic
+    /// operations per measured operation.
+    pub static ref METRICS: Registry = Registry::default();
+}
+
+thread_local! {
+    static CURRENT_LABELS: Cell<MetricLabels> = Cell::new(MetricLabels::default());
+}
+
+/// Upper bounds, in seconds, of the buckets of the duration histograms.
+pub const HISTOGRAM_BUCKETS: [f64; 12] = [
+    0.001, 0.01, 0.1, 0.5, 1.0, 5.0, 30.0, 60.0, 300.0, 900.0, 3600.0, 14400.0,
+];
+
+/// The labels every metric is recorded with.
+///
+/// Only the sector size is part of the key metrics are aggregated by, so the registry does not
+/// grow with the number of sectors processed. The sector id is recorded with the last sample of
+/// each operation instead.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct MetricLabels {
+    pub sector_size: Option<u64>,
+    pub sector_id: Option<u64>,
+}
+
+impl MetricLabels {
+    pub fn new(sector_size: u64, sector_id: Option<u64>) -> Self {
+        MetricLabels {
+            sector_size: Some(sector_size),
+            sector_id,
+        }
+    }
+
+    /// The labels metrics are aggregated by, i.e. these labels without the sector id.
+    fn key(self) -> Self {
+        MetricLabels {
+            sector_id: None,
+            ..self
+        }
+    }
+
+    /// The labels metrics recorded on the current thread are recorded with.
+    pub fn current() -> Self {
+        CURRENT_LABELS.with(|labels| labels.get())
+    }
+}
+
+/// Restores the previous labels of the thread when dropped.
+#[must_use = "the labels are reset when the guard is dropped"]
+pub struct LabelsGuard {
+    previous: MetricLabels,
+}
+
+impl Drop for LabelsGuard {
+    fn drop(&mut self) {
+        let previous = self.previous;
+        CURRENT_LABELS.with(|labels| labels.set(previous));
+    }
+}
+
+/// Records all metrics on the current thread with `labels`, until the returned guard is dropped.
+///
+/// Labels are not inherited by spawned threads, they have to be set again within them.
+pub fn scoped_labels(labels: MetricLabels) -> LabelsGuard {
+    let previous = CURRENT_LABELS.with(|current| current.replace(labels));
+    LabelsGuard { previous }
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Non-cumulative counts, one per bucket of `HISTOGRAM_BUCKETS`.
+    buckets: [AtomicU64; 12],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        if let Some(i) = HISTOGRAM_BUCKETS.iter().position(|bound| secs <= *bound) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = HISTOGRAM_BUCKETS
+            .iter()
+            .zip(self.buckets.iter())
+            .map(|(bound, count)| {
+                cumulative += count.load(Ordering::Relaxed);
+                (*bound, cumulative)
+            })
+            .collect();
+
+        HistogramSnapshot {
+            count: self.count.load(Ordering::Relaxed),
+            sum: Duration::from_micros(self.sum_micros.load(Ordering::Relaxed)),
+            buckets,
+        }
+    }
+}
+
+#[derive(Default)]
+struct OpMetrics {
+    count: AtomicU64,
+    in_flight: AtomicI64,
+    bytes: AtomicU64,
+    wall_time: Histogram,
+    cpu_time: Histogram,
+    last_sample: Mutex<Option<Sample>>,
+}
+
+/// A histogram of durations.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HistogramSnapshot {
+    pub count: u64,
+    pub sum: Duration,
+    /// Cumulative counts of the observations less than or equal to each upper bound, in
+    /// seconds. Observations above the last bound are only included in `count`.
+    pub buckets: Vec<(f64, u64)>,
+}
+
+impl HistogramSnapshot {
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            Duration::default()
+        } else {
+            Duration::from_nanos((self.sum.as_nanos() / u128::from(self.count)) as u64)
+        }
+    }
+}
+
+/// A single execution of an operation.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    /// The sector the execution was for, if it was labelled with one.
+    pub sector_id: Option<u64>,
+    pub wall_time: Duration,
+    pub cpu_time: Duration,
+}
+
+/// The metrics of one operation with one set of labels.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OpSnapshot {
+    pub op: Operation,
+    /// The labels the metrics are aggregated by, which never include the sector id.
+    pub labels: MetricLabels,
+    /// Counter of the completed executions.
+    pub count: u64,
+    /// Gauge of the currently running executions.
+    pub in_flight: i64,
+    /// Counter of the bytes processed, for operations which report them.
+    pub bytes: u64,
+    pub wall_time: HistogramSnapshot,
+    /// Process CPU time, i.e. including other threads running concurrently.
+    pub cpu_time: HistogramSnapshot,
+    /// The most recently completed execution, with the sector it was for.
+    pub last_sample: Option<Sample>,
+}
+
+/// Measures one execution of an operation, which is recorded once it is dropped.
+pub struct OpTimer {
+    metrics: Arc<OpMetrics>,
+    sector_id: Option<u64>,
+    wall_start: Instant,
+    cpu_start: ProcessTime,
+}
+
+impl Drop for OpTimer {
+    fn drop(&mut self) {
+        let sample = Sample {
+            sector_id: self.sector_id,
+            wall_time: self.wall_start.elapsed(),
+            cpu_time: self.cpu_start.elapsed(),
+        };
+        self.metrics.wall_time.observe(sample.wall_time);
+        self.metrics.cpu_time.observe(sample.cpu_time);
+        *self
+            .metrics
+            .last_sample
+            .lock()
+            .expect("metrics lock poisoned") = Some(sample);
+        self.metrics.count.fetch_add(1, Ordering::Relaxed);
+        self.metrics.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counters, gauges and histograms for every `Operation`, per set of labels.
+#[derive(Default)]
+pub struct Registry {
+    ops: RwLock<BTreeMap<(Operation, MetricLabels), Arc<OpMetrics>>>,
+}
+
+impl Registry {
+    fn get(&self, op: Operation) -> Arc<OpMetrics> {
+        let key = (op, MetricLabels::current().key());
+        if let Some(metrics) = self.ops.read().expect("metrics lock poisoned").get(&key) {
+            return metrics.clone();
+        }
+
+        self.ops
+            .write()
+            .expect("metrics lock poisoned")
+            .entry(key)
+            .or_default()
+            .clone()
+    }
+
+    /// Starts measuring an execution of `op` with the labels of the current thread.
+    pub fn start(&self, op: Operation) -> OpTimer {
+        let metrics = self.get(op);
+        metrics.in_flight.fetch_add(1, Ordering::Relaxed);
+
+        OpTimer {
+            metrics,
+            sector_id: MetricLabels::current().sector_id,
+            wall_start: Instant::now(),
+            cpu_start: ProcessTime::now(),
+        }
+    }
+
+    /// Adds `bytes` to the bytes processed by `op`.
+    pub fn add_bytes(&self, op: Operation, bytes: u64) {
+        self.get(op).bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Returns the current value of all metrics, ordered by operation and labels.
+    pub fn snapshot(&self) -> Vec<OpSnapshot> {
+        self.ops
+            .read()
+            .expect("metrics lock poisoned")
+            .iter()
+            .map(|((op, labels), metrics)| OpSnapshot {
+                op: *op,
+                labels: *labels,
+                count: metrics.count.load(Ordering::Relaxed),
+                in_flight: metrics.in_flight.load(Ordering::Relaxed),
+                bytes: metrics.bytes.load(Ordering::Relaxed),
+                wall_time: metrics.wall_time.snapshot(),
+                cpu_time: metrics.cpu_time.snapshot(),
+                last_sample: *metrics.last_sample.lock().expect("metrics lock poisoned"),
+            })
+            .collect()
+    }
+
+    /// Removes all recorded metrics.
+    pub fn reset(&self) {
+        self.ops.write().expect("metrics lock poisoned").clear();
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format. The last samples are left
+    /// out, so that the number of series does not grow with the number of sectors processed.
+    pub fn render_text(&self) -> String {
+        let snapshot = self.snapshot();
+        let mut out = String::new();
+
+        let scalars: [(&str, &str, fn(&OpSnapshot) -> String); 3] = [
+            ("fil_proofs_operations_total", "counter", |s| {
+                s.count.to_string()
+            }),
+            ("fil_proofs_operations_in_flight", "gauge", |s| {
+                s.in_flight.to_string()
+            }),
+            ("fil_proofs_operation_bytes_total", "counter", |s| {
+                s.bytes.to_string()
+            }),
+        ];
+        for (name, kind, value) in scalars.iter() {
+            writeln!(out, "# TYPE {} {}", name, kind).expect("writing to a string cannot fail");
+            for s in &snapshot {
+                writeln!(out, "{}{{{}}} {}", name, render_labels(s, None), value(s))
+                    .expect("writing to a string cannot fail");
+            }
+        }
+
+        let histograms: [(&str, fn(&OpSnapshot) -> &HistogramSnapshot); 2] = [
+            ("fil_proofs_operation_wall_seconds", |s| &s.wall_time),
+            ("fil_proofs_operation_cpu_seconds", |s| &s.cpu_time),
+        ];
+        for (name, histogram) in histograms.iter() {
+            writeln!(out, "# TYPE {} histogram", name).expect("writing to a string cannot fail");
+            for s in &snapshot {
+                let h = histogram(s);
+                for (bound, count) in &h.buckets {
+                    let le = bound.to_string();
+                    writeln!(
+                        out,
+                        "{}_bucket{{{}}} {}",
+                        name,
+                        render_labels(s, Some(&le)),
+                        count
+                    )
+                    .expect("writing to a string cannot fail");
+                }
+                writeln!(
+                    out,
+                    "{}_bucket{{{}}} {}",
+                    name,
+                    render_labels(s, Some("+Inf")),
+                    h.count
+                )
+                .expect("writing to a string cannot fail");
+                writeln!(
+                    out,
+                    "{}_sum{{{}}} {}",
+                    name,
+                    render_labels(s, None),
+                    h.sum.as_secs_f64()
+                )
+                .expect("writing to a string cannot fail");
+                writeln!(
+                    out,
+                    "{}_count{{{}}} {}",
+                    name,
+                    render_labels(s, None),
+                    h.count
+                )
+                .expect("writing to a string cannot fail");
+            }
+        }
+
+        out
+    }
+}
+
+fn render_labels(s: &OpSnapshot, le: Option<&str>) -> String {
+    let mut labels = format!("op=\"{}\"", s.op.name());
+    if let Some(sector_size) = s.labels.sector_size {
+        write!(labels, ",sector_size=\"{}\"", sector_size)
+            .expect("writing to a string cannot fail");
+    }
+    if let Some(le) = le {
+        write!(labels, ",le=\"{}\"", le).expect("writing to a string cannot fail");
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_labels_and_snapshot() {
+        let registry = Registry::default();
+
+        {
+            let _labels = scoped_labels(MetricLabels::new(2048, Some(7)));
+            drop(registry.start(Operation::LabelLayer));
+            drop(registry.start(Operation::LabelLayer));
+            registry.add_bytes(Operation::ParentCacheRead, 1024);
+
+            let _timer = registry.start(Operation::GpuTreeBatch);
+            let snapshot = registry.snapshot();
+            let batch = snapshot
+                .iter()
+                .find(|s| s.op == Operation::GpuTreeBatch)
+                .expect("missing gpu batch metrics");
+            assert_eq!(batch.in_flight, 1);
+            assert_eq!(batch.count, 0);
+        }
+        assert_eq!(MetricLabels::current(), MetricLabels::default());
+        drop(registry.start(Operation::LabelLayer));
+
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.len(), 4);
+
+        let labelled = snapshot
+            .iter()
+            .find(|s| s.op == Operation::LabelLayer && s.labels.sector_size == Some(2048))
+            .expect("missing labelled metrics");
+        assert_eq!(labelled.count, 2);
+        assert_eq!(labelled.in_flight, 0);
+        assert_eq!(labelled.wall_time.count, 2);
+        assert_eq!(labelled.wall_time.buckets.len(), HISTOGRAM_BUCKETS.len());
+        assert_eq!(labelled.labels.sector_id, None);
+        let sample = labelled.last_sample.expect("missing last sample");
+        assert_eq!(sample.sector_id, Some(7));
+
+        let unlabelled = snapshot
+            .iter()
+            .find(|s| s.op == Operation::LabelLayer && s.labels == MetricLabels::default())
+            .expect("missing unlabelled metrics");
+        assert_eq!(unlabelled.count, 1);
+        assert_eq!(
+            unlabelled
+                .last_sample
+                .expect("missing last sample")
+                .sector_id,
+            None
+        );
+
+        let text = registry.render_text();
+        assert!(text.contains("# TYPE fil_proofs_operations_total counter\n"));
+        assert!(text
+            .contains("fil_proofs_operations_total{op=\"label_layer\",sector_size=\"2048\"} 2\n"));
+        assert!(text.contains(
+            "fil_proofs_operation_bytes_total{op=\"parent_cache_read\",sector_size=\"2048\"} 1024\n"
+        ));
+        assert!(text.contains(
+            "fil_proofs_operation_wall_seconds_bucket{op=\"label_layer\",le=\"+Inf\"} 1\n"
+        ));
+
+        // Other sectors of the same size are aggregated into the same metrics.
+        {
+            let _labels = scoped_labels(MetricLabels::new(2048, Some(8)));
+            drop(registry.start(Operation::LabelLayer));
+        }
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.len(), 4);
+        let labelled = snapshot
+            .iter()
+            .find(|s| s.op == Operation::LabelLayer && s.labels.sector_size == Some(2048))
+            .expect("missing labelled metrics");
+        assert_eq!(labelled.count, 3);
+        assert_eq!(
+            labelled.last_sample.expect("missing last sample").sector_id,
+            Some(8)
+        );
+        assert!(!registry.render_text().contains("sector_id"));
+
+        registry.reset();
+        assert!(registry.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_histogram_mean() {
+        let histogram = HistogramSnapshot {
+            count: u64::from(u32::max_value()) + 2,
+            sum: Duration::from_secs(u64::from(u32::max_value()) + 2),
+            buckets: Vec::new(),
+        };
+        assert_eq!(histogram.mean(), Duration::from_secs(1));
+        assert_eq!(HistogramSnapshot::default().mean(), Duration::default());
+    }
+}
@@ -12,6 +12,7 @@ use fs2::FileExt;
 use itertools::Itertools;
 use lazy_static::lazy_static;
 use log::{info, trace};
+#[cfg(feature = "prover")]
 use memmap::MmapOptions;
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
@@ -19,6 +20,8 @@ use sha2::{Digest, Sha256};
 
 use crate::{
     error::{Error, Result},
+    measurements::{measure_op, Operation},
+    metrics::METRICS,
     settings::SETTINGS,
 };
 
@@ -455,12 +458,17 @@ pub fn read_cached_params(cache_entry_path: &Path) -> Result<groth16::MappedPara
         }
     }
 
-    with_exclusive_read_lock::<_, io::Error, _>(cache_entry_path, |_file| {
-        let mapped_params =
-            groth16::Parameters::build_mapped_parameters(cache_entry_path.to_path_buf(), false)?;
-        info!("read parameters from cache {:?} ", cache_entry_path);
+    measure_op(Operation::ParamLoad, || {
+        with_exclusive_read_lock::<_, io::Error, _>(cache_entry_path, |file| {
+            let mapped_params = groth16::Parameters::build_mapped_parameters(
+                cache_entry_path.to_path_buf(),
+                false,
+            )?;
+            METRICS.add_bytes(Operation::ParamLoad, file.as_ref().metadata()?.len());
+            info!("read parameters from cache {:?} ", cache_entry_path);
 
-        Ok(mapped_params)
+            Ok(mapped_params)
+        })
     })
     .map_err(Into::into)
 }
@@ -470,11 +478,14 @@ fn read_cached_verifying_key(cache_entry_path: &Path) -> io::Result<groth16::Ver
         "checking cache_path: {:?} for verifying key",
         cache_entry_path
     );
-    with_exclusive_read_lock(cache_entry_path, |mut file| {
-        let key = groth16::VerifyingKey::read(&mut file)?;
-        info!("read verifying key from cache {:?} ", cache_entry_path);
+    measure_op(Operation::ParamLoad, || {
+        with_exclusive_read_lock(cache_entry_path, |mut file| {
+            let key = groth16::VerifyingKey::read(&mut file)?;
+            METRICS.add_bytes(Operation::ParamLoad, file.as_ref().metadata()?.len());
+            info!("read verifying key from cache {:?} ", cache_entry_path);
 
-        Ok(key)
+            Ok(key)
+        })
     })
 }
 
@@ -538,20 +549,57 @@ fn read_cached_srs_key(cache_entry_path: &Path) -> Result<groth16::aggregate::Ge
         }
     }
 
-    with_exclusive_read_lock(cache_entry_path, |file| {
-        let srs_map = unsafe { MmapOptions::new().map(file.as_ref())? };
-        // NOTE: We do not currently support lengths higher than this,
-        // even though the SRS file can handle up to (2 << 19) + 1
-        // elements.  Specifying under that limit speeds up
-        // performance quite a bit.
-        let max_len = (2 << 14) + 1;
-        let key = groth16::aggregate::GenericSRS::read_mmap(&srs_map, max_len)?;
-        info!("read srs key from cache {:?} ", cache_entry_path);
-
-        Ok(key)
+    measure_op(Operation::ParamLoad, || {
+        with_exclusive_read_lock(cache_entry_path, |file| {
+            // NOTE: We do not currently support lengths higher than this,
+            // even though the SRS file can handle up to (2 << 19) + 1
+            // elements.  Specifying under that limit speeds up
+            // performance quite a bit.
+            let max_len = (2 << 14) + 1;
+            let key = read_srs_key(file, max_len)?;
+            METRICS.add_bytes(Operation::ParamLoad, file.as_ref().metadata()?.len());
+            info!("read srs key from cache {:?} ", cache_entry_path);
+
+            Ok(key)
+        })
     })
 }
 
+#[cfg(feature = "prover")]
+fn read_srs_key(
+    file: &mut LockedFile,
+    max_len: usize,
+) -> io::Result<groth16::aggregate::GenericSRS<Bls12>> {
+    let srs_map = unsafe { MmapOptions::new().map(file.as_ref())? };
+    groth16::aggregate::GenericSRS::read_mmap(&srs_map, max_len)
+}
+
+/// Reads the SRS key without mapping the file. Like `GenericSRS::read_mmap`, only the first
+/// `max_len` points of each of its four vectors are read, the rest is skipped.
+#[cfg(not(feature = "prover"))]
+fn read_srs_key(
+    file: &mut LockedFile,
+    max_len: usize,
+) -> io::Result<groth16::aggregate::GenericSRS<Bls12>> {
+    // The vectors are stored as a big endian u32 length followed by compressed points: the
+    // alpha and beta powers in G1 (48 bytes each), then those in G2 (96 bytes each).
+    let mut truncated = Vec::new();
+    for point_len in &[48, 48, 96, 96] {
+        let mut len_bytes = [0u8; 4];
+        file.read_exact(&mut len_bytes)?;
+        let vec_len = u32::from_be_bytes(len_bytes) as usize;
+        let read_len = std::cmp::min(vec_len, max_len);
+
+        truncated.extend_from_slice(&(read_len as u32).to_be_bytes());
+        let start = truncated.len();
+        truncated.resize(start + read_len * point_len, 0);
+        file.read_exact(&mut truncated[start..])?;
+        file.seek(SeekFrom::Current(((vec_len - read_len) * point_len) as i64))?;
+    }
+
+    groth16::aggregate::GenericSRS::read(&mut truncated.as_slice())
+}
+
 fn read_cached_metadata(cache_entry_path: &Path) -> io::Result<CacheEntryMetadata> {
     info!("checking cache_path: {:?} for metadata", cache_entry_path);
     with_exclusive_read_lock(cache_entry_path, |file| {
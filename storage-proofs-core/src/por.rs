@@ -143,8 +143,8 @@ impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for PoR<Tree> {
             }
         }
 
-        let data_valid = proof.proof.validate_data(proof.data);
-        let path_valid = proof.proof.validate(pub_inputs.challenge);
+        let data_valid = proof.proof.validate_data(proof.data)?;
+        let path_valid = proof.proof.validate(pub_inputs.challenge)?;
 
         Ok(data_valid && path_valid)
     }
@@ -0,0 +1,483 @@
+//! Export of circuits, and optionally their witnesses, in a portable binary R1CS format.
+//!
+//! The files written follow the `r1cs` (version 1) and `wtns` (version 2) binary formats used by
+//! circom and snarkjs, so that exported circuits can be audited, checked against independent
+//! provers and diffed between releases. All integers are little-endian.
+//!
+//! Wires are numbered as follows: wire `0` is the constant one, wires `1..num_inputs` are the
+//! public inputs of the circuit, in allocation order, and the private (auxiliary) variables
+//! follow, also in allocation order.
+//!
+//! `.r1cs`:
+//!
+//! ```text
+//! magic "r1cs" | version: u32 = 1 | sections: u32 = 3
+//! section 1 (header):      type: u32 = 1 | size: u64
+//!     field size: u32 = 32 | prime: [u8; 32] | wires: u32 | public outputs: u32 = 0
+//!     public inputs: u32 | private inputs: u32 = 0 | labels: u64 | constraints: u32
+//! section 2 (constraints): type: u32 = 2 | size: u64
+//!     per constraint, for each of A, B and C (with A * B = C):
+//!         terms: u32 | terms * (wire: u32, coefficient: [u8; 32])
+//! section 3 (wire labels): type: u32 = 3 | size: u64
+//!     per wire: label: u64 (the label of a wire is its index)
+//! ```
+//!
+//! `.wtns`:
+//!
+//! ```text
+//! magic "wtns" | version: u32 = 2 | sections: u32 = 2
+//! section 1 (header):  type: u32 = 1 | size: u64
+//!     field size: u32 = 32 | prime: [u8; 32] | values: u32
+//! section 2 (values):  type: u32 = 2 | size: u64
+//!     per wire: value: [u8; 32]
+//! ```
+//!
+//! Field elements (the prime, coefficients and values) are written in their canonical
+//! (non-Montgomery) little-endian representation.
+
+use std::io::Write;
+
+use anyhow::{ensure, Context};
+use bellperson::{
+    bls::{Bls12, Fr},
+    ConstraintSystem, Index, LinearCombination, SynthesisError, Variable,
+};
+use byteorder::{LittleEndian, WriteBytesExt};
+use ff::{Field, PrimeField, PrimeFieldRepr};
+use fr32::fr_into_bytes;
+
+use crate::error::Result;
+
+const FIELD_SIZE: usize = 32;
+
+const R1CS_MAGIC: &[u8; 4] = b"r1cs";
+const R1CS_VERSION: u32 = 1;
+const WTNS_MAGIC: &[u8; 4] = b"wtns";
+const WTNS_VERSION: u32 = 2;
+
+/// A linear combination, with its terms merged, sorted by wire and without zero coefficients.
+type Terms = Vec<(Index, Fr)>;
+
+/// A constraint system which records every variable and constraint of a circuit, so that it can
+/// be written out with `write_r1cs` and `write_witness`.
+///
+/// Synthesizing a blank circuit records the constraints only; synthesizing a circuit built from
+/// real vanilla proofs also records a satisfying assignment.
+pub struct R1csCS {
+    inputs: Vec<Option<Fr>>,
+    aux: Vec<Option<Fr>>,
+    constraints: Vec<(Terms, Terms, Terms)>,
+}
+
+impl Default for R1csCS {
+    fn default() -> Self {
+        R1csCS {
+            inputs: vec![Some(Fr::one())],
+            aux: Vec::new(),
+            constraints: Vec::new(),
+        }
+    }
+}
+
+impl R1csCS {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of public inputs, including the constant one.
+    pub fn num_inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn num_aux(&self) -> usize {
+        self.aux.len()
+    }
+
+    pub fn num_wires(&self) -> usize {
+        self.inputs.len() + self.aux.len()
+    }
+
+    pub fn num_constraints(&self) -> usize {
+        self.constraints.len()
+    }
+
+    /// Returns true if a value was assigned to every variable.
+    pub fn has_witness(&self) -> bool {
+        self.inputs
+            .iter()
+            .chain(self.aux.iter())
+            .all(Option::is_some)
+    }
+
+    /// The assigned public inputs, without the constant one.
+    pub fn public_inputs(&self) -> Result<Vec<Fr>> {
+        self.inputs[1..]
+            .iter()
+            .copied()
+            .enumerate()
+            .map(|(i, value)| value.with_context(|| format!("input {} is not assigned", i + 1)))
+            .collect()
+    }
+
+    /// Returns the index of the first constraint which the recorded witness does not satisfy,
+    /// if any.
+    pub fn which_is_unsatisfied(&self) -> Result<Option<usize>> {
+        ensure!(self.has_witness(), "no witness was recorded");
+
+        for (i, (a, b, c)) in self.constraints.iter().enumerate() {
+            let mut ab = self.eval(a);
+            ab.mul_assign(&self.eval(b));
+
+            if ab != self.eval(c) {
+                return Ok(Some(i));
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn is_satisfied(&self) -> Result<bool> {
+        Ok(self.which_is_unsatisfied()?.is_none())
+    }
+
+    /// Writes the constraint system in the `r1cs` format described in the module documentation.
+    pub fn write_r1cs<W: Write>(&self, mut writer: W) -> Result<()> {
+        let num_wires = self.num_wires();
+        let num_public_inputs = self.inputs.len() - 1;
+
+        writer.write_all(R1CS_MAGIC)?;
+        writer.write_u32::<LittleEndian>(R1CS_VERSION)?;
+        writer.write_u32::<LittleEndian>(3)?;
+
+        // Header
+        writer.write_u32::<LittleEndian>(1)?;
+        writer.write_u64::<LittleEndian>((4 + FIELD_SIZE + 4 * 4 + 8 + 4) as u64)?;
+        write_field_header(&mut writer)?;
+        writer.write_u32::<LittleEndian>(to_u32(num_wires, "wires")?)?;
+        writer.write_u32::<LittleEndian>(0)?;
+        writer.write_u32::<LittleEndian>(to_u32(num_public_inputs, "public inputs")?)?;
+        writer.write_u32::<LittleEndian>(0)?;
+        writer.write_u64::<LittleEndian>(num_wires as u64)?;
+        writer.write_u32::<LittleEndian>(to_u32(self.constraints.len(), "constraints")?)?;
+
+        // Constraints
+        let constraints_size: usize = self
+            .constraints
+            .iter()
+            .map(|(a, b, c)| {
+                [a, b, c]
+                    .iter()
+                    .map(|terms| 4 + terms.len() * (4 + FIELD_SIZE))
+                    .sum::<usize>()
+            })
+            .sum();
+        writer.write_u32::<LittleEndian>(2)?;
+        writer.write_u64::<LittleEndian>(constraints_size as u64)?;
+        for (a, b, c) in &self.constraints {
+            for terms in &[a, b, c] {
+                writer.write_u32::<LittleEndian>(to_u32(terms.len(), "terms")?)?;
+                for (index, coeff) in terms.iter() {
+                    writer.write_u32::<LittleEndian>(to_u32(self.wire(*index), "wire")?)?;
+                    writer.write_all(&fr_into_bytes(coeff))?;
+                }
+            }
+        }
+
+        // Wire labels
+        writer.write_u32::<LittleEndian>(3)?;
+        writer.write_u64::<LittleEndian>((num_wires * 8) as u64)?;
+        for wire in 0..num_wires {
+            writer.write_u64::<LittleEndian>(wire as u64)?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Writes the recorded witness in the `wtns` format described in the module documentation.
+    /// Fails if the circuit was synthesized without an assignment.
+    pub fn write_witness<W: Write>(&self, mut writer: W) -> Result<()> {
+        ensure!(self.has_witness(), "no witness was recorded");
+
+        let num_wires = self.num_wires();
+
+        writer.write_all(WTNS_MAGIC)?;
+        writer.write_u32::<LittleEndian>(WTNS_VERSION)?;
+        writer.write_u32::<LittleEndian>(2)?;
+
+        // Header
+        writer.write_u32::<LittleEndian>(1)?;
+        writer.write_u64::<LittleEndian>((4 + FIELD_SIZE + 4) as u64)?;
+        write_field_header(&mut writer)?;
+        writer.write_u32::<LittleEndian>(to_u32(num_wires, "wires")?)?;
+
+        // Values
+        writer.write_u32::<LittleEndian>(2)?;
+        writer.write_u64::<LittleEndian>((num_wires * FIELD_SIZE) as u64)?;
+        for value in self.inputs.iter().chain(self.aux.iter()).flatten() {
+            writer.write_all(&fr_into_bytes(value))?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    fn wire(&self, index: Index) -> usize {
+        match index {
+            Index::Input(i) => i,
+            Index::Aux(i) => self.inputs.len() + i,
+        }
+    }
+
+    fn eval(&self, terms: &[(Index, Fr)]) -> Fr {
+        let mut acc = Fr::zero();
+        for (index, coeff) in terms {
+            let value = match index {
+                Index::Input(i) => self.inputs[*i],
+                Index::Aux(i) => self.aux[*i],
+            };
+            let mut term = value.expect("witness is complete");
+            term.mul_assign(coeff);
+            acc.add_assign(&term);
+        }
+        acc
+    }
+}
+
+impl ConstraintSystem<Bls12> for R1csCS {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _annotation: A, f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.aux.push(f().ok());
+
+        Ok(Variable::new_unchecked(Index::Aux(self.aux.len() - 1)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _annotation: A, f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.inputs.push(f().ok());
+
+        Ok(Variable::new_unchecked(Index::Input(self.inputs.len() - 1)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _annotation: A, a: LA, b: LB, c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LB: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LC: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+    {
+        let a = terms(a(LinearCombination::zero()));
+        let b = terms(b(LinearCombination::zero()));
+        let c = terms(c(LinearCombination::zero()));
+
+        self.constraints.push((a, b, c));
+    }
+
+    fn push_namespace<NR, N>(&mut self, _name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+/// Merges the terms of `lc` per variable, drops zero coefficients and sorts the result by wire
+/// (inputs before aux), so that the same circuit is always written out identically.
+fn terms(lc: LinearCombination<Bls12>) -> Terms {
+    let mut terms: Terms = lc
+        .iter()
+        .map(|(var, coeff)| (var.get_unchecked(), *coeff))
+        .collect();
+    terms.sort_by_key(|(index, _)| match index {
+        Index::Input(i) => (0, *i),
+        Index::Aux(i) => (1, *i),
+    });
+
+    let mut merged: Terms = Vec::with_capacity(terms.len());
+    for (index, coeff) in terms {
+        match merged.last_mut() {
+            Some((last, acc)) if *last == index => acc.add_assign(&coeff),
+            _ => merged.push((index, coeff)),
+        }
+    }
+    merged.retain(|(_, coeff)| !coeff.is_zero());
+
+    merged
+}
+
+fn write_field_header<W: Write>(writer: &mut W) -> Result<()> {
+    writer.write_u32::<LittleEndian>(FIELD_SIZE as u32)?;
+    Fr::char().write_le(&mut *writer)?;
+
+    Ok(())
+}
+
+fn to_u32(value: usize, what: &str) -> Result<u32> {
+    ensure!(
+        value <= u32::MAX as usize,
+        "too many {} for the r1cs format: {}",
+        what,
+        value
+    );
+
+    Ok(value as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use byteorder::{ByteOrder, LittleEndian};
+
+    /// x * x = y, with y public.
+    fn square<CS: ConstraintSystem<Bls12>>(cs: &mut CS, x: Option<Fr>) {
+        let y = x.map(|x| {
+            let mut y = x;
+            y.square();
+            y
+        });
+
+        let x = cs
+            .alloc(|| "x", || x.ok_or(SynthesisError::AssignmentMissing))
+            .expect("alloc failure");
+        let y = cs
+            .alloc_input(|| "y", || y.ok_or(SynthesisError::AssignmentMissing))
+            .expect("alloc_input failure");
+
+        cs.enforce(|| "x * x = y", |lc| lc + x, |lc| lc + x, |lc| lc + y);
+    }
+
+    #[test]
+    fn test_r1cs_export() {
+        let mut cs = R1csCS::new();
+        square(
+            &mut cs,
+            Some(Fr::from_repr(3.into()).expect("from_repr failure")),
+        );
+
+        assert_eq!(cs.num_inputs(), 2);
+        assert_eq!(cs.num_aux(), 1);
+        assert_eq!(cs.num_constraints(), 1);
+        assert!(cs.is_satisfied().expect("is_satisfied failure"));
+        assert_eq!(
+            cs.public_inputs().expect("public_inputs failure"),
+            vec![Fr::from_repr(9.into()).expect("from_repr failure")]
+        );
+
+        let mut r1cs = Vec::new();
+        cs.write_r1cs(&mut r1cs).expect("write_r1cs failure");
+
+        assert_eq!(&r1cs[..4], R1CS_MAGIC);
+        assert_eq!(LittleEndian::read_u32(&r1cs[4..]), R1CS_VERSION);
+        assert_eq!(LittleEndian::read_u32(&r1cs[8..]), 3);
+        // Header section: wires, public outputs, public inputs, private inputs.
+        let header = 12 + 4 + 8 + 4 + FIELD_SIZE;
+        assert_eq!(LittleEndian::read_u32(&r1cs[header..]), 3);
+        assert_eq!(LittleEndian::read_u32(&r1cs[header + 4..]), 0);
+        assert_eq!(LittleEndian::read_u32(&r1cs[header + 8..]), 1);
+        assert_eq!(LittleEndian::read_u32(&r1cs[header + 12..]), 0);
+        assert_eq!(LittleEndian::read_u32(&r1cs[header + 24..]), 1);
+
+        // A single constraint with one term per linear combination, and three wire labels.
+        let constraints_size = 3 * (4 + 4 + FIELD_SIZE);
+        assert_eq!(
+            r1cs.len(),
+            12 + (12 + 4 + FIELD_SIZE + 28) + (12 + constraints_size) + (12 + 3 * 8)
+        );
+
+        let mut witness = Vec::new();
+        cs.write_witness(&mut witness)
+            .expect("write_witness failure");
+
+        assert_eq!(&witness[..4], WTNS_MAGIC);
+        assert_eq!(
+            witness.len(),
+            12 + (12 + 4 + FIELD_SIZE + 4) + 12 + 3 * FIELD_SIZE
+        );
+        // Wires are ordered one, y, x.
+        let values = &witness[witness.len() - 3 * FIELD_SIZE..];
+        assert_eq!(values[0], 1);
+        assert_eq!(values[FIELD_SIZE], 9);
+        assert_eq!(values[2 * FIELD_SIZE], 3);
+    }
+
+    #[test]
+    fn test_r1cs_export_blank() {
+        let mut cs = R1csCS::new();
+        square(&mut cs, None);
+
+        assert!(!cs.has_witness());
+        assert!(cs.is_satisfied().is_err());
+
+        let mut r1cs = Vec::new();
+        cs.write_r1cs(&mut r1cs).expect("write_r1cs failure");
+        assert!(cs.write_witness(&mut Vec::new()).is_err());
+
+        // The constraints of a blank circuit are the same as those of an assigned one.
+        let mut assigned = R1csCS::new();
+        square(
+            &mut assigned,
+            Some(Fr::from_repr(5.into()).expect("from_repr failure")),
+        );
+        let mut assigned_r1cs = Vec::new();
+        assigned
+            .write_r1cs(&mut assigned_r1cs)
+            .expect("write_r1cs failure");
+        assert_eq!(r1cs, assigned_r1cs);
+    }
+
+    #[test]
+    fn test_r1cs_unsatisfied() {
+        let mut cs = R1csCS::new();
+        let x = cs
+            .alloc(
+                || "x",
+                || Ok(Fr::from_repr(3.into()).expect("from_repr failure")),
+            )
+            .expect("alloc failure");
+        let y = cs
+            .alloc_input(
+                || "y",
+                || Ok(Fr::from_repr(8.into()).expect("from_repr failure")),
+            )
+            .expect("alloc_input failure");
+        cs.enforce(|| "x * x = y", |lc| lc + x, |lc| lc + x, |lc| lc + y);
+
+        assert_eq!(cs.which_is_unsatisfied().expect("failure"), Some(0));
+    }
+
+    #[test]
+    fn test_r1cs_merges_terms() {
+        let mut cs = R1csCS::new();
+        let one = R1csCS::one();
+        let x = cs.alloc(|| "x", || Ok(Fr::one())).expect("alloc failure");
+        cs.enforce(
+            || "x + x - x - x = 0",
+            |lc| lc + x + x - x - x + one,
+            |lc| lc + one,
+            |lc| lc + one,
+        );
+
+        let (a, _, _) = &cs.constraints[0];
+        assert_eq!(a, &vec![(Index::Input(0), Fr::one())]);
+    }
+}
@@ -21,6 +21,10 @@ pub struct Settings {
     pub column_write_batch_size: u32,
     pub use_gpu_tree_builder: bool,
     pub max_gpu_tree_batch_size: u32,
+    pub use_pipelined_tree_builder: bool,
+    pub pipelined_tree_builder_max_memory: u64,
+    pub use_batched_poseidon: bool,
+    pub use_direct_io: bool,
     pub rows_to_discard: u32,
     pub sdr_parents_cache_size: u32,
     pub window_post_synthesis_num_cpus: u32,
@@ -30,6 +34,8 @@ pub struct Settings {
     pub multicore_sdr_producers: usize,
     pub multicore_sdr_producer_stride: u64,
     pub multicore_sdr_lookahead: usize,
+    pub multicore_sdr_huge_pages: String,
+    pub multicore_sdr_hugetlbfs_path: String,
 }
 
 impl Default for Settings {
@@ -42,6 +48,11 @@ impl Default for Settings {
             column_write_batch_size: 262_144,
             use_gpu_tree_builder: false,
             max_gpu_tree_batch_size: 700_000,
+            use_pipelined_tree_builder: false,
+            // 4 GiB
+            pipelined_tree_builder_max_memory: 4 * 1024 * 1024 * 1024,
+            use_batched_poseidon: false,
+            use_direct_io: false,
             rows_to_discard: 2,
             sdr_parents_cache_size: 2_048,
             window_post_synthesis_num_cpus: num_cpus::get() as u32,
@@ -54,6 +65,9 @@ impl Default for Settings {
             multicore_sdr_producers: 3,
             multicore_sdr_producer_stride: 128,
             multicore_sdr_lookahead: 800,
+            // One of `off`, `thp` or `hugetlbfs`.
+            multicore_sdr_huge_pages: "off".to_string(),
+            multicore_sdr_hugetlbfs_path: "/dev/hugepages".to_string(),
         }
     }
 }
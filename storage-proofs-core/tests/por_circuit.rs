@@ -133,7 +133,7 @@ fn test_por_circuit<Tree: 'static + MerkleTreeTrait>(num_inputs: usize, num_cons
             <Tree::Hasher as Hasher>::Domain::try_from_bytes(leaf).expect("try_from_bytes failure");
         let priv_inputs = por::PrivateInputs::<ResTree<Tree>>::new(leaf_element, &tree);
         let p = tree.gen_proof(i).expect("gen_proof failure");
-        assert!(p.verify());
+        assert!(p.verify().expect("verify failure"));
 
         // create a non circuit proof
         let proof = PoR::<ResTree<Tree>>::prove(&pub_params, &pub_inputs, &priv_inputs)
@@ -19,7 +19,7 @@ use storage_proofs_core::{
         MerkleTreeWrapper, ResTree,
     },
     por,
-    proof::NoRequirements,
+    proof::{NoRequirements, ProofScheme},
     util::data_at_node,
     TEST_SEED,
 };
@@ -233,3 +233,78 @@ fn por_compound_private_root<Tree: 'static + MerkleTreeTrait>() {
         assert!(verified);
     }
 }
+
+#[test]
+fn test_por_compound_r1cs_export_poseidon_base_8() {
+    por_compound_r1cs_export::<TreeBase<PoseidonHasher, U8>>();
+}
+
+fn por_compound_r1cs_export<Tree: 'static + MerkleTreeTrait>() {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let leaves = 64 * get_base_tree_count::<Tree>();
+    let (data, tree) = generate_tree::<Tree, _>(rng, leaves, None);
+
+    let public_inputs = por::PublicInputs {
+        challenge: 3,
+        commitment: Some(tree.root()),
+    };
+
+    let setup_params = compound_proof::SetupParams {
+        vanilla_params: por::SetupParams {
+            leaves,
+            private: false,
+        },
+        partitions: None,
+        priority: false,
+    };
+    let public_params = PoRCompound::<Tree>::setup(&setup_params).expect("setup failed");
+
+    let private_inputs = por::PrivateInputs::<Tree>::new(
+        bytes_into_fr(
+            data_at_node(data.as_slice(), public_inputs.challenge).expect("data_at_node failure"),
+        )
+        .expect("failed to create Fr from node data")
+        .into(),
+        &tree,
+    );
+
+    let vanilla_proof = por::PoR::<Tree>::prove(
+        &public_params.vanilla_params,
+        &public_inputs,
+        &private_inputs,
+    )
+    .expect("vanilla proving failed");
+
+    let cs = PoRCompound::<Tree>::partition_r1cs(
+        &public_inputs,
+        &vanilla_proof,
+        0,
+        &public_params.vanilla_params,
+    )
+    .expect("partition_r1cs failure");
+    assert!(cs.is_satisfied().expect("is_satisfied failure"));
+
+    let blank_cs =
+        PoRCompound::<Tree>::blank_r1cs(&public_params.vanilla_params).expect("blank_r1cs failure");
+    assert!(!blank_cs.has_witness());
+    assert_eq!(blank_cs.num_constraints(), cs.num_constraints());
+    assert_eq!(blank_cs.num_inputs(), cs.num_inputs());
+    assert_eq!(blank_cs.num_aux(), cs.num_aux());
+
+    let mut r1cs = Vec::new();
+    cs.write_r1cs(&mut r1cs).expect("write_r1cs failure");
+    let mut blank_r1cs = Vec::new();
+    blank_cs
+        .write_r1cs(&mut blank_r1cs)
+        .expect("write_r1cs failure");
+    assert_eq!(&r1cs[..4], b"r1cs");
+    assert_eq!(r1cs.len(), blank_r1cs.len());
+
+    let mut witness = Vec::new();
+    cs.write_witness(&mut witness)
+        .expect("write_witness failure");
+    assert_eq!(&witness[..4], b"wtns");
+    // Magic, version, section count, header section and values section header.
+    assert_eq!(witness.len(), 12 + 12 + 40 + 12 + 32 * cs.num_wires());
+}
@@ -0,0 +1,92 @@
+use bellperson::bls::Fr;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use ff::Field;
+use filecoin_hashers::{
+    poseidon::{hash_batch, PoseidonDomain, PoseidonFunction, BATCH_SIZE},
+    HashFunction, POSEIDON_CONSTANTS_11,
+};
+use merkletree::hash::Algorithm;
+use neptune::poseidon::Poseidon;
+use rand::thread_rng;
+use rayon::prelude::{ParallelIterator, ParallelSlice};
+
+// Compares hashing one preimage at a time with `hash_batch`, split over the threads the way the
+// CPU tree builders split a row (see `FIL_PROOFS_USE_BATCHED_POSEIDON`).
+
+const LAYERS: usize = 11;
+const TREE_ARITY: usize = 8;
+const COLUMNS: usize = 64 * BATCH_SIZE;
+
+fn column_benchmark(c: &mut Criterion) {
+    let mut rng = thread_rng();
+    let columns: Vec<Fr> = (0..COLUMNS * LAYERS)
+        .map(|_| Fr::random(&mut rng))
+        .collect();
+
+    let mut group = c.benchmark_group("poseidon-columns");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(COLUMNS as u64));
+
+    group.bench_function("single", |b| {
+        b.iter(|| {
+            black_box(
+                columns
+                    .par_chunks(LAYERS)
+                    .map(|column| {
+                        Poseidon::new_with_preimage(column, &*POSEIDON_CONSTANTS_11).hash()
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+    });
+
+    group.bench_function("batched", |b| {
+        b.iter(|| {
+            black_box(
+                columns
+                    .par_chunks(LAYERS * BATCH_SIZE)
+                    .map(|columns| hash_batch(columns, LAYERS).expect("hash_batch failure"))
+                    .collect::<Vec<_>>(),
+            )
+        })
+    });
+
+    group.finish();
+}
+
+fn tree_row_benchmark(c: &mut Criterion) {
+    let mut rng = thread_rng();
+    let row: Vec<PoseidonDomain> = (0..COLUMNS).map(|_| Fr::random(&mut rng).into()).collect();
+
+    let mut group = c.benchmark_group("poseidon-tree-row");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((COLUMNS / TREE_ARITY) as u64));
+
+    group.bench_function("single", |b| {
+        b.iter(|| {
+            black_box(
+                row.par_chunks(TREE_ARITY)
+                    .map(|children| PoseidonFunction::default().multi_node(children, 0))
+                    .collect::<Vec<_>>(),
+            )
+        })
+    });
+
+    group.bench_function("batched", |b| {
+        b.iter(|| {
+            black_box(
+                row.par_chunks(TREE_ARITY * BATCH_SIZE)
+                    .map(|parts| {
+                        PoseidonFunction::try_multi_node_batch(parts, TREE_ARITY, 0)
+                            .expect("try_multi_node_batch failure")
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, column_benchmark, tree_row_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,530 @@
+//! Writing layers, tree stores and replicas with `O_DIRECT`, bypassing the page cache.
+//!
+//! Direct I/O is enabled with `SETTINGS.use_direct_io`. If it is disabled, or the filesystem
+//! does not support it, the functions here fall back to normal, buffered I/O.
+
+use std::alloc::{self, Layout};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Context;
+use log::warn;
+use storage_proofs_core::{
+    data::Data,
+    error::Result,
+    measurements::{measure_op, Operation},
+    metrics::METRICS,
+    settings::SETTINGS,
+    util::NODE_SIZE,
+};
+
+/// The alignment of offsets, lengths and buffers of direct I/O. Covers the logical block size of
+/// all common devices.
+pub const DIRECT_IO_ALIGNMENT: usize = 4096;
+
+/// The size of the aligned buffers data is copied through on its way to and from the disk.
+const BOUNCE_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+/// A zeroed heap buffer, aligned for direct I/O.
+pub struct AlignedBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// The buffer is owned memory, like a `Vec<u8>`.
+unsafe impl Send for AlignedBuffer {}
+unsafe impl Sync for AlignedBuffer {}
+
+impl AlignedBuffer {
+    pub fn new(len: usize) -> Self {
+        let layout = Self::layout(len);
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+
+        AlignedBuffer { ptr, len }
+    }
+
+    fn layout(len: usize) -> Layout {
+        Layout::from_size_align(len.max(1), DIRECT_IO_ALIGNMENT).expect("invalid buffer layout")
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), Self::layout(self.len)) }
+    }
+}
+
+impl Deref for AlignedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl std::fmt::Debug for AlignedBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AlignedBuffer")
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+fn align_down(offset: u64) -> u64 {
+    offset / DIRECT_IO_ALIGNMENT as u64 * DIRECT_IO_ALIGNMENT as u64
+}
+
+fn align_up(offset: u64) -> u64 {
+    align_down(offset + DIRECT_IO_ALIGNMENT as u64 - 1)
+}
+
+#[cfg(unix)]
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+
+    file.write_all_at(buf, offset)
+}
+
+/// Seeks before writing, so the file must not be accessed from several threads at once.
+#[cfg(not(unix))]
+fn write_all_at(mut file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    use std::io::{Seek, SeekFrom};
+
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(buf)
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+
+    file.read_exact_at(buf, offset)
+}
+
+/// Seeks before reading, so the file must not be accessed from several threads at once.
+#[cfg(not(unix))]
+fn read_exact_at(mut file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
+
+#[cfg(target_os = "linux")]
+fn open_direct(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_direct(_path: &Path) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "direct I/O is only supported on Linux",
+    ))
+}
+
+/// A file which is read and written with direct I/O where possible.
+///
+/// Only whole aligned blocks go through direct I/O, the unaligned parts at the start and end of
+/// each access use normal I/O. If the filesystem refuses direct I/O, all accesses fall back to
+/// normal I/O.
+#[derive(Debug)]
+pub struct DirectFile {
+    path: PathBuf,
+    file: File,
+    direct: Option<File>,
+    direct_failed: AtomicBool,
+}
+
+impl DirectFile {
+    /// Creates the file at `path`, truncating it if it exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::create_with(path.as_ref(), SETTINGS.use_direct_io)
+    }
+
+    fn create_with(path: &Path, use_direct_io: bool) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .with_context(|| format!("failed to create {:?}", path))?;
+
+        Ok(Self::with_file(path, file, use_direct_io))
+    }
+
+    /// Opens the existing file at `path` for reading and writing.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("failed to open {:?}", path))?;
+
+        Ok(Self::with_file(path, file, SETTINGS.use_direct_io))
+    }
+
+    fn with_file(path: &Path, file: File, use_direct_io: bool) -> Self {
+        let direct = if use_direct_io {
+            match open_direct(path) {
+                Ok(direct) => Some(direct),
+                Err(err) => {
+                    warn!("direct I/O not available for {:?}: {}", path, err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        DirectFile {
+            path: path.to_path_buf(),
+            file,
+            direct,
+            direct_failed: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns whether the file is accessed with direct I/O.
+    pub fn is_direct(&self) -> bool {
+        self.direct.is_some() && !self.direct_failed.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    pub fn set_len(&self, len: u64) -> Result<()> {
+        self.file
+            .set_len(len)
+            .with_context(|| format!("failed to resize {:?}", self.path))
+    }
+
+    pub fn sync_all(&self) -> Result<()> {
+        self.file
+            .sync_all()
+            .with_context(|| format!("failed to sync {:?}", self.path))
+    }
+
+    /// Returns the direct file handle, unless direct I/O is disabled or failed before.
+    fn direct(&self) -> Option<&File> {
+        if self.direct_failed.load(Ordering::Relaxed) {
+            None
+        } else {
+            self.direct.as_ref()
+        }
+    }
+
+    /// Stops using direct I/O if `err` shows the filesystem refuses it. Returns whether the
+    /// access should be retried with normal I/O.
+    fn direct_refused(&self, err: &io::Error) -> bool {
+        if err.raw_os_error() != Some(libc::EINVAL) {
+            return false;
+        }
+        if !self.direct_failed.swap(true, Ordering::Relaxed) {
+            warn!("direct I/O refused for {:?}: {}", self.path, err);
+        }
+
+        true
+    }
+
+    /// Writes all of `buf` at `offset`.
+    pub fn write_all_at(&self, buf: &[u8], offset: u64) -> Result<()> {
+        measure_op(Operation::StoreWrite, || {
+            self.write_all_at_inner(buf, offset)
+        })
+        .with_context(|| format!("failed to write {:?}", self.path))?;
+        METRICS.add_bytes(Operation::StoreWrite, buf.len() as u64);
+
+        Ok(())
+    }
+
+    fn write_all_at_inner(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        let end = offset + buf.len() as u64;
+        let (start_aligned, end_aligned) = (align_up(offset), align_down(end));
+        let direct = match self.direct() {
+            Some(direct) if start_aligned < end_aligned => direct,
+            _ => return write_all_at(&self.file, buf, offset),
+        };
+
+        let head = (start_aligned - offset) as usize;
+        let tail = (end_aligned - offset) as usize;
+        write_all_at(&self.file, &buf[..head], offset)?;
+
+        let mut bounce = AlignedBuffer::new(BOUNCE_BUFFER_SIZE.min(tail - head));
+        let mut pos = head;
+        while pos < tail {
+            let len = bounce.len().min(tail - pos);
+            bounce[..len].copy_from_slice(&buf[pos..pos + len]);
+            match write_all_at(direct, &bounce[..len], offset + pos as u64) {
+                Ok(()) => pos += len,
+                Err(err) if self.direct_refused(&err) => {
+                    return write_all_at(&self.file, &buf[pos..], offset + pos as u64);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        write_all_at(&self.file, &buf[tail..], end_aligned)
+    }
+
+    /// Reads exactly `buf.len()` bytes at `offset`.
+    pub fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<()> {
+        self.read_exact_at_inner(buf, offset)
+            .with_context(|| format!("failed to read {:?}", self.path))
+    }
+
+    fn read_exact_at_inner(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let end = offset + buf.len() as u64;
+        let (start_aligned, end_aligned) = (align_up(offset), align_down(end));
+        let direct = match self.direct() {
+            Some(direct) if start_aligned < end_aligned => direct,
+            _ => return read_exact_at(&self.file, buf, offset),
+        };
+
+        let head = (start_aligned - offset) as usize;
+        let tail = (end_aligned - offset) as usize;
+        read_exact_at(&self.file, &mut buf[..head], offset)?;
+
+        let mut bounce = AlignedBuffer::new(BOUNCE_BUFFER_SIZE.min(tail - head));
+        let mut pos = head;
+        while pos < tail {
+            let len = bounce.len().min(tail - pos);
+            match read_exact_at(direct, &mut bounce[..len], offset + pos as u64) {
+                Ok(()) => {
+                    buf[pos..pos + len].copy_from_slice(&bounce[..len]);
+                    pos += len;
+                }
+                Err(err) if self.direct_refused(&err) => {
+                    return read_exact_at(&self.file, &mut buf[pos..], offset + pos as u64);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        read_exact_at(&self.file, &mut buf[tail..], end_aligned)
+    }
+}
+
+/// Writes a file sequentially, in chunks which go through direct I/O where possible.
+#[derive(Debug)]
+pub struct DirectWriter {
+    file: DirectFile,
+    buf: Vec<u8>,
+    offset: u64,
+}
+
+impl DirectWriter {
+    /// Creates the file at `path`, truncating it if it exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(DirectWriter {
+            file: DirectFile::create(path)?,
+            buf: Vec::with_capacity(BOUNCE_BUFFER_SIZE),
+            offset: 0,
+        })
+    }
+
+    fn write_buffered(&mut self) -> Result<()> {
+        self.file.write_all_at(&self.buf, self.offset)?;
+        self.offset += self.buf.len() as u64;
+        self.buf.clear();
+
+        Ok(())
+    }
+
+    /// Writes the remaining data and syncs the file to disk.
+    pub fn finish(mut self) -> Result<()> {
+        self.write_buffered()?;
+        self.file.sync_all()
+    }
+}
+
+impl Write for DirectWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(BOUNCE_BUFFER_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..len]);
+        if self.buf.len() == BOUNCE_BUFFER_SIZE {
+            self.write_buffered()
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        }
+
+        Ok(len)
+    }
+
+    /// Does nothing, only whole chunks are written before `finish`, so that they stay aligned.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes `data` to the file at `path`, replacing it if it exists. The file is synced to disk
+/// if direct I/O is enabled.
+pub fn write_file<P: AsRef<Path>>(path: P, data: &[u8]) -> Result<()> {
+    let path = path.as_ref();
+    if !SETTINGS.use_direct_io {
+        measure_op(Operation::StoreWrite, || fs::write(path, data))
+            .with_context(|| format!("failed to write {:?}", path))?;
+        METRICS.add_bytes(Operation::StoreWrite, data.len() as u64);
+        return Ok(());
+    }
+
+    let file = DirectFile::create(path)?;
+    file.write_all_at(data, 0)?;
+    file.sync_all()
+}
+
+/// The replica being encoded into, a region of nodes at a time.
+///
+/// With direct I/O, each region is read into an aligned buffer and written back once it is
+/// encoded, so that the replica does not pass through the page cache and at most one region is
+/// held in memory. Otherwise the regions are slices of the replica in memory or mapped.
+#[derive(Debug)]
+pub enum Replica<'a> {
+    Mapped(&'a mut [u8]),
+    Direct(DirectFile),
+}
+
+impl<'a> Replica<'a> {
+    /// Opens the file backing `data` for direct I/O if it is enabled, or maps `data` otherwise.
+    /// Data which is not backed by a file is always used in memory.
+    pub fn new(data: &'a mut Data<'_>) -> Result<Self> {
+        if SETTINGS.use_direct_io {
+            if let Some(path) = data.path().map(Path::to_path_buf) {
+                data.drop_data();
+                return Ok(Replica::Direct(DirectFile::open(path)?));
+            }
+        }
+
+        data.ensure_data()?;
+        Ok(Replica::Mapped(data.as_mut()))
+    }
+
+    /// Returns the nodes `[start, end)` of the replica. Changes to them may only reach the
+    /// replica once `ReplicaRegion::write_back` is called.
+    pub fn region(&mut self, start: usize, end: usize) -> Result<ReplicaRegion<'_>> {
+        match self {
+            Replica::Mapped(data) => Ok(ReplicaRegion::Mapped(
+                &mut data[start * NODE_SIZE..end * NODE_SIZE],
+            )),
+            Replica::Direct(file) => {
+                let file = &*file;
+                let offset = (start * NODE_SIZE) as u64;
+                let mut buf = AlignedBuffer::new((end - start) * NODE_SIZE);
+                file.read_exact_at(&mut buf, offset)?;
+
+                Ok(ReplicaRegion::Direct { file, offset, buf })
+            }
+        }
+    }
+}
+
+/// A region of nodes of a `Replica`.
+#[derive(Debug)]
+pub enum ReplicaRegion<'a> {
+    Mapped(&'a mut [u8]),
+    Direct {
+        file: &'a DirectFile,
+        offset: u64,
+        buf: AlignedBuffer,
+    },
+}
+
+impl<'a> ReplicaRegion<'a> {
+    /// Writes the region back to the replica and syncs it to disk, if it was read with direct
+    /// I/O. Must be called before anything built from the region is marked complete.
+    pub fn write_back(&self) -> Result<()> {
+        match self {
+            ReplicaRegion::Mapped(_) => Ok(()),
+            ReplicaRegion::Direct { file, offset, buf } => {
+                file.write_all_at(buf, *offset)?;
+                file.sync_all()
+            }
+        }
+    }
+}
+
+impl<'a> Deref for ReplicaRegion<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            ReplicaRegion::Mapped(data) => &data[..],
+            ReplicaRegion::Direct { buf, .. } => &buf[..],
+        }
+    }
+}
+
+impl<'a> DerefMut for ReplicaRegion<'a> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            ReplicaRegion::Mapped(data) => &mut data[..],
+            ReplicaRegion::Direct { buf, .. } => &mut buf[..],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::TEST_SEED;
+
+    #[test]
+    fn test_direct_file_unaligned_access() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        let data: Vec<u8> = (0..3 * DIRECT_IO_ALIGNMENT + 100)
+            .map(|_| rng.gen())
+            .collect();
+
+        let dir = tempfile::tempdir().expect("tempdir failure");
+        let path = dir.path().join("direct");
+        // The temporary directory may be on a filesystem without direct I/O, in which case this
+        // covers the fallback.
+        let file = DirectFile::create_with(&path, true).expect("failed to create file");
+        file.set_len(4 * DIRECT_IO_ALIGNMENT as u64)
+            .expect("failed to resize file");
+
+        // Neither the offset nor the end are aligned.
+        file.write_all_at(&data, 1000).expect("failed to write");
+        file.write_all_at(&data[..10], 0).expect("failed to write");
+        file.sync_all().expect("failed to sync");
+
+        let mut read = vec![0u8; data.len()];
+        file.read_exact_at(&mut read, 1000).expect("failed to read");
+        assert_eq!(read, data);
+
+        let contents = fs::read(&path).expect("failed to read file");
+        assert_eq!(&contents[..10], &data[..10]);
+        assert_eq!(&contents[1000..1000 + data.len()], &data[..]);
+
+        let mut writer = DirectWriter::create(&path).expect("failed to create writer");
+        writer.write_all(&data).expect("failed to write");
+        writer.finish().expect("failed to finish");
+        assert_eq!(fs::read(&path).expect("failed to read file"), data);
+    }
+}
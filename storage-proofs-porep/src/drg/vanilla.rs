@@ -286,7 +286,7 @@ where
             let tree_r = &priv_inputs.tree_r;
             let tree_r_config_rows_to_discard = priv_inputs.tree_r_config_rows_to_discard;
 
-            let data = tree_r.read_at(challenge)?;
+            let data = tree_r.read_node(challenge)?;
             let tree_proof =
                 tree_r.gen_cached_proof(challenge, Some(tree_r_config_rows_to_discard))?;
             replica_nodes.push(DataProof {
@@ -304,7 +304,7 @@ where
                         .gen_cached_proof(*p as usize, Some(tree_r_config_rows_to_discard))?;
                     DataProof {
                         proof,
-                        data: tree_r.read_at(*p as usize)?,
+                        data: tree_r.read_node(*p as usize)?,
                     }
                 }));
             }
@@ -326,7 +326,7 @@ where
                     &pub_inputs.replica_id.context("missing replica_id")?,
                     tree_r,
                     challenge,
-                    tree_r.read_at(challenge)?,
+                    tree_r.read_node(challenge)?,
                     &parents,
                 )?;
                 data_nodes.push(DataProof {
@@ -390,12 +390,12 @@ where
             let challenge = pub_inputs.challenges[i] % pub_params.graph.size();
             ensure!(challenge != 0, "cannot prove the first node");
 
-            if !proof.replica_nodes[i].proof.validate(challenge) {
+            if !proof.replica_nodes[i].proof.validate(challenge)? {
                 return Ok(false);
             }
 
             for (parent_node, p) in &proof.replica_parents[i] {
-                if !p.proof.validate(*parent_node as usize) {
+                if !p.proof.validate(*parent_node as usize)? {
                     return Ok(false);
                 }
             }
@@ -418,7 +418,7 @@ where
                 return Ok(false);
             }
 
-            if !proof.nodes[i].proof.validate_data(unsealed) {
+            if !proof.nodes[i].proof.validate_data(unsealed)? {
                 println!("invalid data for merkle path {:?}", unsealed);
                 return Ok(false);
             }
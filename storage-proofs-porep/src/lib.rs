@@ -9,6 +9,8 @@ use filecoin_hashers::Hasher;
 use merkletree::store::StoreConfig;
 use storage_proofs_core::{error::Result, merkle::BinaryMerkleTree, proof::ProofScheme, Data};
 
+#[cfg(feature = "prover")]
+pub mod direct_io;
 pub mod drg;
 pub mod stacked;
 
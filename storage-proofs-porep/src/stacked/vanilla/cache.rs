@@ -15,6 +15,8 @@ use sha2::{Digest, Sha256};
 use storage_proofs_core::{
     drgraph::{Graph, BASE_DEGREE},
     error::Result,
+    measurements::{measure_op, Operation},
+    metrics::METRICS,
     parameter_cache::{with_exclusive_lock, LockedFile, ParameterSetMetadata, VERSION},
     settings::SETTINGS,
     util::NODE_SIZE,
@@ -77,13 +79,14 @@ impl CacheData {
         let offset = new_offset as usize * DEGREE * NODE_BYTES;
         let len = self.len as usize * DEGREE * NODE_BYTES;
 
-        self.data = unsafe {
+        self.data = measure_op(Operation::ParentCacheRead, || unsafe {
             MmapOptions::new()
                 .offset(offset as u64)
                 .len(len)
                 .map(self.file.as_ref())
-                .context("could not shift mmap}")?
-        };
+                .context("could not shift mmap}")
+        })?;
+        METRICS.add_bytes(Operation::ParentCacheRead, len as u64);
         self.offset = new_offset;
 
         Ok(())
@@ -131,13 +134,17 @@ impl CacheData {
             );
         }
 
-        let data = unsafe {
+        let data = measure_op(Operation::ParentCacheRead, || unsafe {
             MmapOptions::new()
                 .offset((offset as usize * DEGREE * NODE_BYTES) as u64)
                 .len(len as usize * DEGREE * NODE_BYTES)
                 .map(file.as_ref())
-                .with_context(|| format!("could not mmap path={}", path.display()))?
-        };
+                .with_context(|| format!("could not mmap path={}", path.display()))
+        })?;
+        METRICS.add_bytes(
+            Operation::ParentCacheRead,
+            (len as usize * DEGREE * NODE_BYTES) as u64,
+        );
 
         Ok(Self {
             data,
@@ -222,19 +229,8 @@ impl ParentCache {
         if verify_cache {
             // Always check all of the data for integrity checks, even
             // if we're only opening a portion of it.
-            let mut hasher = Sha256::new();
             info!("[open] parent cache: calculating consistency digest");
-            let file = File::open(&path)?;
-            let data = unsafe {
-                MmapOptions::new()
-                    .map(&file)
-                    .with_context(|| format!("could not mmap path={}", path.display()))?
-            };
-            hasher.update(&data);
-            drop(data);
-
-            let hash = hasher.finalize();
-            digest_hex = hash.iter().map(|x| format!("{:01$x}", x, 2)).collect();
+            digest_hex = parent_cache_digest(path)?;
 
             info!(
                 "[open] parent cache: calculated consistency digest: {:?}",
@@ -363,6 +359,62 @@ impl ParentCache {
         })
     }
 
+    /// Returns the path of the parent cache for all nodes of `graph` and the state of that file.
+    /// This hashes the whole file.
+    pub fn status<H, G>(graph: &StackedGraph<H, G>) -> Result<(PathBuf, ParentCacheStatus)>
+    where
+        H: Hasher,
+        G: Graph<H> + ParameterSetMetadata + Send + Sync,
+    {
+        let cache_entries = graph.size() as u32;
+        let path = parent_cache_path(graph);
+        let expected_len = (cache_entries as usize * DEGREE * NODE_BYTES) as u64;
+
+        let len = match path.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok((path, ParentCacheStatus::Missing));
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if len != expected_len {
+            return Ok((path, ParentCacheStatus::Partial { len, expected_len }));
+        }
+
+        let digest = parent_cache_digest(&path)?;
+        let status = match get_parent_cache_data(&path) {
+            Some(pcd) if pcd.digest == digest => ParentCacheStatus::Verified,
+            Some(_) => ParentCacheStatus::Corrupted { digest },
+            None => ParentCacheStatus::Unverifiable { digest },
+        };
+
+        Ok((path, status))
+    }
+
+    /// Regenerates the parent cache for all nodes of `graph` if it is missing, partial or
+    /// corrupted. Returns the state of the file before the repair.
+    pub fn repair<H, G>(graph: &StackedGraph<H, G>) -> Result<ParentCacheStatus>
+    where
+        H: Hasher,
+        G: Graph<H> + ParameterSetMetadata + Send + Sync,
+    {
+        let (path, status) = Self::status(graph)?;
+        match status {
+            ParentCacheStatus::Verified | ParentCacheStatus::Unverifiable { .. } => {}
+            ParentCacheStatus::Missing => {
+                Self::generate(1, graph.size() as u32, graph, &path)?;
+            }
+            ParentCacheStatus::Partial { .. } | ParentCacheStatus::Corrupted { .. } => {
+                info!("parent cache: repairing {}", path.display());
+                remove_file(&path)
+                    .with_context(|| format!("could not remove path={}", path.display()))?;
+                Self::generate(1, graph.size() as u32, graph, &path)?;
+            }
+        }
+
+        Ok(status)
+    }
+
     /// Read a single cache element at position `node`.
     pub fn read(&mut self, node: u32) -> Result<[u32; DEGREE]> {
         if self.cache.contains(node) {
@@ -392,11 +444,53 @@ impl ParentCache {
     }
 }
 
+/// The state of a parent cache file on disk, see `ParentCache::status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParentCacheStatus {
+    /// The file does not exist.
+    Missing,
+    /// The file does not have the expected size.
+    Partial { len: u64, expected_len: u64 },
+    /// The digest of the file matches the one in `PARENT_CACHE`.
+    Verified,
+    /// The digest of the file does not match the one in `PARENT_CACHE`.
+    Corrupted { digest: String },
+    /// The file is not listed in `PARENT_CACHE` (e.g. it belongs to a test sector), so its
+    /// digest cannot be checked.
+    Unverifiable { digest: String },
+}
+
+/// Returns the path of the parent cache for all nodes of `graph`.
+pub fn parent_cache_path<H, G>(graph: &StackedGraph<H, G>) -> PathBuf
+where
+    H: Hasher,
+    G: Graph<H> + ParameterSetMetadata + Send + Sync,
+{
+    cache_path(graph.size() as u32, graph)
+}
+
+/// Returns the hex encoded SHA256 digest of the parent cache file at `path`.
+pub fn parent_cache_digest(path: &Path) -> Result<String> {
+    let file =
+        File::open(&path).with_context(|| format!("could not open path={}", path.display()))?;
+    let data = unsafe {
+        MmapOptions::new()
+            .map(&file)
+            .with_context(|| format!("could not mmap path={}", path.display()))?
+    };
+
+    let mut hasher = Sha256::new();
+    hasher.update(&data);
+    let hash = hasher.finalize();
+
+    Ok(hash.iter().map(|x| format!("{:01$x}", x, 2)).collect())
+}
+
 fn parent_cache_dir_name() -> String {
     SETTINGS.parent_cache.clone()
 }
 
-fn parent_cache_id(path: &Path) -> String {
+pub fn parent_cache_id(path: &Path) -> String {
     Path::new(&path)
         .file_stem()
         .expect("parent_cache_id file_stem failure")
@@ -406,7 +500,7 @@ fn parent_cache_id(path: &Path) -> String {
 }
 
 /// Get the correct parent cache data for a given cache id.
-fn get_parent_cache_data(path: &Path) -> Option<&ParentCacheData> {
+pub fn get_parent_cache_data(path: &Path) -> Option<&ParentCacheData> {
     PARENT_CACHE.get(&parent_cache_id(path))
 }
 
@@ -525,4 +619,46 @@ mod tests {
             assert_eq!(expected_parents, parents);
         }
     }
+
+    #[test]
+    fn test_status_and_repair() {
+        let nodes = 32u32;
+        let graph = StackedBucketGraph::<PoseidonHasher>::new_stacked(
+            nodes as usize,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            [77u8; 32],
+            ApiVersion::V1_1_0,
+        )
+        .expect("new_stacked failure");
+
+        ParentCache::repair(&graph).expect("parent cache repair failure");
+        let (path, status) = ParentCache::status(&graph).expect("parent cache status failure");
+        let digest = match status {
+            ParentCacheStatus::Unverifiable { digest } => digest,
+            status => panic!("unexpected status {:?}", status),
+        };
+
+        // Simulate an interrupted generation.
+        let expected_len = (nodes as usize * DEGREE * NODE_BYTES) as u64;
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .expect("failed to open parent cache")
+            .set_len(expected_len / 2)
+            .expect("failed to truncate parent cache");
+
+        let status = ParentCache::repair(&graph).expect("parent cache repair failure");
+        assert_eq!(
+            status,
+            ParentCacheStatus::Partial {
+                len: expected_len / 2,
+                expected_len
+            }
+        );
+        assert_eq!(
+            ParentCache::status(&graph).expect("parent cache status failure"),
+            (path, ParentCacheStatus::Unverifiable { digest })
+        );
+    }
 }
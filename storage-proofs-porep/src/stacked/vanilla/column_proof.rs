@@ -48,13 +48,13 @@ impl<Proof: MerkleProofTrait> ColumnProof<Proof> {
         &self,
         challenge: u32,
         expected_root: &<Proof::Hasher as Hasher>::Domain,
-    ) -> bool {
+    ) -> Result<bool> {
         let c_i = self.column_hash();
 
         check_eq!(&self.inclusion_proof.root(), expected_root);
-        check!(self.inclusion_proof.validate_data(c_i.into()));
-        check!(self.inclusion_proof.validate(challenge as usize));
+        check!(self.inclusion_proof.validate_data(c_i.into())?);
+        check!(self.inclusion_proof.validate(challenge as usize)?);
 
-        true
+        Ok(true)
     }
 }
@@ -1,11 +1,14 @@
+use std::ops::Deref;
 use std::sync::{Mutex, MutexGuard};
 
-use anyhow::{format_err, Result};
+use anyhow::{ensure, format_err, Result};
 use hwloc::{Bitmap, ObjectType, Topology, TopologyObject, CPUBIND_THREAD};
 use lazy_static::lazy_static;
 use log::{debug, info, warn};
 use storage_proofs_core::settings::SETTINGS;
 
+use crate::stacked::vanilla::placement::{CoreGroupInfo, SdrPlacement, SdrTopology};
+
 type CoreGroup = Vec<CoreIndex>;
 lazy_static! {
     pub static ref TOPOLOGY: Mutex<Topology> = Mutex::new(Topology::new());
@@ -22,6 +25,12 @@ lazy_static! {
 /// created with a value known to be less than the number of visible cores.
 pub struct CoreIndex(usize);
 
+impl CoreIndex {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
 pub fn checkout_core_group() -> Option<MutexGuard<'static, CoreGroup>> {
     match &*CORE_GROUPS {
         Some(groups) => {
@@ -40,6 +49,134 @@ pub fn checkout_core_group() -> Option<MutexGuard<'static, CoreGroup>> {
     }
 }
 
+/// The cores a labelling job runs on.
+pub enum CheckedOutCores {
+    /// A group of `CORE_GROUPS`, which is checked in again when dropped.
+    Group(MutexGuard<'static, CoreGroup>),
+    /// Cores which were explicitly requested.
+    Explicit(CoreGroup),
+}
+
+impl Deref for CheckedOutCores {
+    type Target = [CoreIndex];
+
+    fn deref(&self) -> &[CoreIndex] {
+        match self {
+            CheckedOutCores::Group(group) => group,
+            CheckedOutCores::Explicit(cores) => cores,
+        }
+    }
+}
+
+/// Checks out the cores for a labelling job according to `placement`. Returns them, if any are
+/// available, together with the NUMA node the memory of the job should be placed on.
+pub fn checkout_cores(placement: &SdrPlacement) -> Result<(Option<CheckedOutCores>, Option<u32>)> {
+    match placement {
+        SdrPlacement::Auto => {
+            let cores = checkout_core_group().map(CheckedOutCores::Group);
+            let node = cores.as_ref().and_then(|cores| numa_node_of_cores(cores));
+
+            Ok((cores, node))
+        }
+        SdrPlacement::NumaNode(node) => {
+            ensure!(
+                numa_nodes().contains(node),
+                "NUMA node {} does not exist",
+                node
+            );
+
+            for (i, group) in CORE_GROUPS.iter().flatten().enumerate() {
+                if let Ok(guard) = group.try_lock() {
+                    if numa_node_of_cores(&guard) == Some(*node) {
+                        debug!("checked out core group {} on NUMA node {}", i, node);
+                        return Ok((Some(CheckedOutCores::Group(guard)), Some(*node)));
+                    }
+                }
+            }
+
+            warn!("no free core group on NUMA node {}, running unbound", node);
+            Ok((None, Some(*node)))
+        }
+        SdrPlacement::Cores(cores) => {
+            ensure!(!cores.is_empty(), "no cores given");
+            let core_count = core_count();
+            for core in cores {
+                ensure!(
+                    *core < core_count,
+                    "core {} out of range for {} cores",
+                    core,
+                    core_count
+                );
+            }
+
+            let cores: CoreGroup = cores.iter().copied().map(CoreIndex).collect();
+            let node = numa_node_of_cores(&cores);
+
+            Ok((Some(CheckedOutCores::Explicit(cores)), node))
+        }
+    }
+}
+
+/// Returns the topology the core groups are based on.
+pub fn topology() -> SdrTopology {
+    let cores_per_unit = SETTINGS.multicore_sdr_producers + 1;
+    let core_groups = core_group_indices(cores_per_unit)
+        .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+        .map(|(i, cores)| CoreGroupInfo {
+            numa_node: numa_node_of_cores(&cores),
+            cores: cores.iter().map(|core| core.0).collect(),
+            checked_out: CORE_GROUPS
+                .as_ref()
+                .and_then(|groups| groups.get(i))
+                .map(|group| group.try_lock().is_err())
+                .unwrap_or_default(),
+        })
+        .collect();
+
+    SdrTopology {
+        core_count: core_count(),
+        numa_nodes: numa_nodes(),
+        core_groups,
+    }
+}
+
+fn core_count() -> usize {
+    let topo = TOPOLOGY.lock().expect("poisoned lock");
+    topo.objects_with_type(&ObjectType::Core)
+        .map(|cores| cores.len())
+        .unwrap_or_default()
+}
+
+fn numa_nodes() -> Vec<u32> {
+    let topo = TOPOLOGY.lock().expect("poisoned lock");
+    topo.objects_with_type(&ObjectType::NUMANode)
+        .map(|nodes| nodes.iter().map(|node| node.os_index()).collect())
+        .unwrap_or_default()
+}
+
+/// Returns the NUMA node of the given cores, if they are all on the same one.
+fn numa_node_of_cores(cores: &[CoreIndex]) -> Option<u32> {
+    let topo = TOPOLOGY.lock().expect("poisoned lock");
+    let mut nodes = cores.iter().map(|core| numa_node_of_core(&topo, *core));
+    let first = nodes.next()??;
+    if nodes.all(|node| node == Some(first)) {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+fn numa_node_of_core(topo: &Topology, core_index: CoreIndex) -> Option<u32> {
+    let nodeset = get_core_by_index(topo, core_index).ok()?.nodeset()?;
+    if nodeset.weight() != 1 {
+        return None;
+    }
+
+    Some(nodeset.first() as u32)
+}
+
 #[cfg(not(target_os = "windows"))]
 pub type ThreadId = libc::pthread_t;
 
@@ -122,7 +259,11 @@ fn get_core_by_index(topo: &Topology, index: CoreIndex) -> Result<&TopologyObjec
     }
 }
 
-fn core_groups(cores_per_unit: usize) -> Option<Vec<Mutex<Vec<CoreIndex>>>> {
+fn core_groups(cores_per_unit: usize) -> Option<Vec<Mutex<CoreGroup>>> {
+    core_group_indices(cores_per_unit).map(|groups| groups.into_iter().map(Mutex::new).collect())
+}
+
+fn core_group_indices(cores_per_unit: usize) -> Option<Vec<CoreGroup>> {
     let topo = TOPOLOGY.lock().expect("poisoned lock");
 
     let core_depth = match topo.depth_or_below_for_type(&ObjectType::Core) {
@@ -182,12 +323,7 @@ fn core_groups(cores_per_unit: usize) -> Option<Vec<Mutex<Vec<CoreIndex>>>> {
         })
         .collect::<Vec<_>>();
 
-    Some(
-        core_groups
-            .iter()
-            .map(|group| Mutex::new(group.clone()))
-            .collect::<Vec<_>>(),
-    )
+    Some(core_groups)
 }
 
 #[cfg(test)]
@@ -199,6 +335,22 @@ mod tests {
         core_groups(2);
     }
 
+    #[test]
+    fn test_topology_and_explicit_cores() {
+        let topology = topology();
+        assert!(topology.core_count > 0);
+        for group in &topology.core_groups {
+            assert!(group.cores.iter().all(|core| *core < topology.core_count));
+        }
+
+        let (cores, _) = checkout_cores(&SdrPlacement::Cores(vec![0])).expect("checkout failed");
+        assert_eq!(cores.expect("no cores").to_vec(), vec![CoreIndex(0)]);
+
+        assert!(checkout_cores(&SdrPlacement::Cores(vec![topology.core_count])).is_err());
+        assert!(checkout_cores(&SdrPlacement::Cores(Vec::new())).is_err());
+        assert!(checkout_cores(&SdrPlacement::NumaNode(u32::MAX)).is_err());
+    }
+
     #[test]
     #[cfg(feature = "single-threaded")]
     fn test_checkout_cores() {
@@ -1,4 +1,4 @@
-use std::fs::{self, create_dir_all, remove_file, rename, File};
+use std::fs::{create_dir_all, remove_file, rename, File};
 use std::io::{self, BufReader};
 
 use anyhow::Context;
@@ -9,7 +9,10 @@ use storage_proofs_core::{
     cache_key::CacheKey, drgraph::Graph, error::Result, merkle::MerkleTreeTrait,
 };
 
-use crate::stacked::vanilla::{proof::LayerState, StackedBucketGraph};
+use crate::{
+    direct_io,
+    stacked::vanilla::{proof::LayerState, resume, StackedBucketGraph},
+};
 
 #[cfg(feature = "multicore-sdr")]
 pub mod multi;
@@ -44,6 +47,13 @@ pub fn prepare_layers<Tree: 'static + MerkleTreeTrait>(
         });
     }
 
+    // Trees built from labels which are about to be regenerated must not be reused.
+    if !states.iter().all(|state| state.generated) {
+        if let Err(err) = resume::clear_all_complete(&config.path) {
+            warn!("failed to clear completed trees: {}", err);
+        }
+    }
+
     states
 }
 
@@ -55,7 +65,7 @@ pub fn write_layer(data: &[u8], config: &StoreConfig) -> Result<()> {
     if let Some(parent) = data_path.parent() {
         create_dir_all(parent).context("failed to create parent directories")?;
     }
-    fs::write(&tmp_data_path, data).context("failed to write layer data")?;
+    direct_io::write_file(&tmp_data_path, data).context("failed to write layer data")?;
     rename(tmp_data_path, data_path).context("failed to rename tmp data")?;
 
     Ok(())
@@ -3,7 +3,7 @@ use std::marker::PhantomData;
 use std::mem::{self, size_of};
 use std::sync::{
     atomic::{AtomicU64, Ordering::SeqCst},
-    Arc, MutexGuard,
+    Arc,
 };
 use std::thread;
 use std::time::Duration;
@@ -20,7 +20,9 @@ use mapr::MmapMut;
 use merkletree::store::{DiskStore, Store, StoreConfig};
 use storage_proofs_core::{
     cache_key::CacheKey,
+    context::ThreadContext,
     drgraph::{Graph, BASE_DEGREE},
+    measurements::{measure_op, Operation},
     merkle::MerkleTreeTrait,
     settings::SETTINGS,
     util::NODE_SIZE,
@@ -28,11 +30,13 @@ use storage_proofs_core::{
 
 use crate::stacked::vanilla::{
     cache::ParentCache,
-    cores::{bind_core, checkout_core_group, CoreIndex},
+    cores::{bind_core, checkout_core_group, checkout_cores, CheckedOutCores},
     create_label::{prepare_layers, read_layer, write_layer},
     graph::{StackedBucketGraph, DEGREE, EXP_DEGREE},
     memory_handling::{setup_create_label_memory, CacheReader},
+    numa,
     params::{Labels, LabelsCache},
+    placement::{SdrPlacement, SdrPlacementReport},
     proof::LayerState,
     utils::{memset, prepare_block, BitMask, RingBuf, UnsafeSlice},
 };
@@ -207,7 +211,8 @@ fn create_layer_labels(
     exp_labels: Option<&mut MmapMut>,
     num_nodes: u64,
     cur_layer: u32,
-    core_group: Arc<Option<MutexGuard<'_, Vec<CoreIndex>>>>,
+    core_group: Arc<Option<CheckedOutCores>>,
+    numa_node: Option<u32>,
 ) {
     info!("Creating labels for layer {}", cur_layer);
     // num_producers is the number of producer threads
@@ -250,6 +255,8 @@ fn create_layer_labels(
     });
     let base_parent_missing = UnsafeSlice::from_slice(&mut base_parent_missing);
 
+    let context = &ThreadContext::current();
+
     crossbeam::thread::scope(|s| {
         let mut runners = Vec::with_capacity(num_producers);
 
@@ -267,11 +274,13 @@ fn create_layer_labels(
                 None
             };
             runners.push(s.spawn(move |_| {
+                let _context = context.enter();
                 // This could fail, but we will ignore the error if so.
                 // It will be logged as a warning by `bind_core`.
                 debug!("binding core in producer thread {}", i);
                 // When `_cleanup_handle` is dropped, the previous binding of thread will be restored.
                 let _cleanup_handle = core_index.map(|c| bind_core(*c));
+                let _prefer_node = prefer_numa_node(numa_node);
 
                 create_label_runner(
                     parents_cache,
@@ -433,6 +442,13 @@ fn create_layer_labels(
     .expect("crossbeam scope failure");
 }
 
+/// Makes the current thread prefer `node` for the memory it faults in, if a node is given.
+fn prefer_numa_node(node: Option<u32>) -> Option<numa::PreferNodeGuard> {
+    numa::prefer_node(node?)
+        .map_err(|err| debug!("{}", err))
+        .ok()
+}
+
 #[allow(clippy::type_complexity)]
 pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]>>(
     graph: &StackedBucketGraph<Tree::Hasher>,
@@ -440,7 +456,8 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
     layers: usize,
     replica_id: T,
     config: StoreConfig,
-) -> Result<(Labels<Tree>, Vec<LayerState>)> {
+    placement: &SdrPlacement,
+) -> Result<(Labels<Tree>, Vec<LayerState>, SdrPlacementReport)> {
     info!("create labels");
 
     let layer_states = prepare_layers::<Tree>(graph, &config, layers);
@@ -451,7 +468,8 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
 
     let default_cache_size = DEGREE * 4 * cache_window_nodes;
 
-    let core_group = Arc::new(checkout_core_group());
+    let (core_group, numa_node) = checkout_cores(placement)?;
+    let core_group = Arc::new(core_group);
 
     // When `_cleanup_handle` is dropped, the previous binding of thread will be restored.
     let _cleanup_handle = (*core_group).as_ref().map(|group| {
@@ -461,13 +479,27 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
         group.get(0).map(|core_index| bind_core(*core_index))
     });
 
+    let _prefer_node = prefer_numa_node(numa_node);
+
     // NOTE: this means we currently keep 2x sector size around, to improve speed
-    let (parents_cache, mut layer_labels, mut exp_labels) = setup_create_label_memory(
-        sector_size,
-        DEGREE,
-        Some(default_cache_size as usize),
-        &parents_cache.path,
-    )?;
+    let (parents_cache, mut layer_labels, mut exp_labels, memory_bound) =
+        setup_create_label_memory(
+            sector_size,
+            DEGREE,
+            Some(default_cache_size as usize),
+            &parents_cache.path,
+            numa_node,
+        )?;
+
+    let report = SdrPlacementReport {
+        cores: core_group
+            .iter()
+            .flat_map(|cores| cores.iter().map(|core| core.index()))
+            .collect(),
+        numa_node,
+        memory_bound,
+    };
+    info!("labelling placement: {:?}", report);
 
     for (layer, layer_state) in (1..=layers).zip(layer_states.iter()) {
         info!("Layer {}", layer);
@@ -486,19 +518,22 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
             parents_cache.finish_reset()?;
         }
 
-        create_layer_labels(
-            &parents_cache,
-            &replica_id.as_ref(),
-            &mut layer_labels,
-            if layer == 1 {
-                None
-            } else {
-                Some(&mut exp_labels)
-            },
-            node_count,
-            layer as u32,
-            core_group.clone(),
-        );
+        measure_op(Operation::LabelLayer, || {
+            create_layer_labels(
+                &parents_cache,
+                &replica_id.as_ref(),
+                &mut layer_labels,
+                if layer == 1 {
+                    None
+                } else {
+                    Some(&mut exp_labels)
+                },
+                node_count,
+                layer as u32,
+                core_group.clone(),
+                numa_node,
+            )
+        });
 
         // Cache reset happens in two parts.
         // The first part (the start) happens after each layer but the last.
@@ -526,6 +561,7 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
             _h: PhantomData,
         },
         layer_states,
+        report,
     ))
 }
 
@@ -549,7 +585,7 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
 
     let default_cache_size = DEGREE * 4 * cache_window_nodes;
 
-    let core_group = Arc::new(checkout_core_group());
+    let core_group = Arc::new(checkout_core_group().map(CheckedOutCores::Group));
 
     // When `_cleanup_handle` is dropped, the previous binding of thread will be restored.
     let _cleanup_handle = (*core_group).as_ref().map(|group| {
@@ -560,11 +596,12 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
     });
 
     // NOTE: this means we currently keep 2x sector size around, to improve speed
-    let (parents_cache, mut layer_labels, mut exp_labels) = setup_create_label_memory(
+    let (parents_cache, mut layer_labels, mut exp_labels, _) = setup_create_label_memory(
         sector_size,
         DEGREE,
         Some(default_cache_size as usize),
         &parents_cache.path,
+        None,
     )?;
 
     for layer in 1..=layers {
@@ -588,6 +625,7 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
             node_count,
             layer as u32,
             core_group.clone(),
+            None,
         );
 
         // Cache reset happens in two parts.
@@ -9,6 +9,7 @@ use merkletree::store::{DiskStore, Store, StoreConfig};
 use sha2raw::Sha256;
 use storage_proofs_core::{
     drgraph::Graph,
+    measurements::{measure_op, Operation},
     merkle::MerkleTreeTrait,
     util::{data_at_node_offset, NODE_SIZE},
 };
@@ -47,32 +48,36 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
             continue;
         }
 
-        parents_cache.reset()?;
-
-        if layer == 1 {
-            for node in 0..graph.size() {
-                create_label(
-                    graph,
-                    Some(parents_cache),
-                    &replica_id,
-                    &mut layer_labels,
-                    layer,
-                    node,
-                )?;
-            }
-        } else {
-            for node in 0..graph.size() {
-                create_label_exp(
-                    graph,
-                    Some(parents_cache),
-                    &replica_id,
-                    &exp_labels,
-                    &mut layer_labels,
-                    layer,
-                    node,
-                )?;
+        measure_op(Operation::LabelLayer, || -> Result<()> {
+            parents_cache.reset()?;
+
+            if layer == 1 {
+                for node in 0..graph.size() {
+                    create_label(
+                        graph,
+                        Some(parents_cache),
+                        &replica_id,
+                        &mut layer_labels,
+                        layer,
+                        node,
+                    )?;
+                }
+            } else {
+                for node in 0..graph.size() {
+                    create_label_exp(
+                        graph,
+                        Some(parents_cache),
+                        &replica_id,
+                        &exp_labels,
+                        &mut layer_labels,
+                        layer,
+                        node,
+                    )?;
+                }
             }
-        }
+
+            Ok(())
+        })?;
 
         // Write the result to disk to avoid keeping it in memory all the time.
         let layer_config = &layer_state.config;
@@ -6,6 +6,7 @@ use fr32::bytes_into_fr_repr_safe;
 use log::trace;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use storage_proofs_core::error::Result;
 
 use crate::encode::encode;
 
@@ -55,7 +56,7 @@ impl<H: Hasher> EncodingProof<H> {
         replica_id: &H::Domain,
         exp_encoded_node: &H::Domain,
         decoded_node: &G::Domain,
-    ) -> bool {
+    ) -> Result<bool> {
         let key = self.create_key(replica_id);
 
         let fr: Fr = (*decoded_node).into();
@@ -63,6 +64,6 @@ impl<H: Hasher> EncodingProof<H> {
 
         check_eq!(exp_encoded_node, &encoded_node);
 
-        true
+        Ok(true)
     }
 }
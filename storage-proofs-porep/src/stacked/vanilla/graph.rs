@@ -4,7 +4,9 @@ use std::marker::PhantomData;
 
 use anyhow::ensure;
 use filecoin_hashers::Hasher;
+#[cfg(feature = "prover")]
 use log::info;
+#[cfg(feature = "prover")]
 use sha2raw::Sha256;
 use storage_proofs_core::{
     api_version::ApiVersion,
@@ -16,16 +18,18 @@ use storage_proofs_core::{
     drgraph::{BucketGraph, Graph, BASE_DEGREE},
     error::Result,
     parameter_cache::ParameterSetMetadata,
-    settings::SETTINGS,
-    util::NODE_SIZE,
     PoRepID,
 };
+#[cfg(feature = "prover")]
+use storage_proofs_core::{settings::SETTINGS, util::NODE_SIZE};
 
+#[cfg(feature = "prover")]
 use crate::stacked::vanilla::cache::ParentCache;
 
 /// The expansion degree used for Stacked Graphs.
 pub const EXP_DEGREE: usize = 8;
 
+#[cfg(feature = "prover")]
 pub(crate) const DEGREE: usize = BASE_DEGREE + EXP_DEGREE;
 
 #[derive(Clone)]
@@ -60,6 +64,7 @@ where
 
 pub type StackedBucketGraph<H> = StackedGraph<H, BucketGraph<H>>;
 
+#[cfg(feature = "prover")]
 #[inline]
 fn prefetch(parents: &[u32], data: &[u8]) {
     for parent in parents {
@@ -70,6 +75,7 @@ fn prefetch(parents: &[u32], data: &[u8]) {
     }
 }
 
+#[cfg(feature = "prover")]
 #[inline]
 fn read_node<'a>(i: usize, parents: &[u32], data: &'a [u8]) -> &'a [u8] {
     let start = parents[i] as usize * NODE_SIZE;
@@ -131,7 +137,14 @@ where
 
         Ok(res)
     }
+}
 
+#[cfg(feature = "prover")]
+impl<H, G> StackedGraph<H, G>
+where
+    H: Hasher,
+    G: Graph<H> + ParameterSetMetadata + Sync + Send,
+{
     /// Returns a reference to the parent cache.
     pub fn parent_cache(&self) -> Result<ParentCache> {
         // Number of nodes to be cached in memory
@@ -143,6 +156,7 @@ where
 
         ParentCache::new(cache_size, cache_entries, self)
     }
+
     pub fn copy_parents_data_exp(
         &self,
         node: u32,
@@ -1,4 +1,6 @@
 use bellperson::bls::Fr;
+#[cfg(feature = "prover")]
+use filecoin_hashers::poseidon::hash_batch;
 use filecoin_hashers::{POSEIDON_CONSTANTS_11, POSEIDON_CONSTANTS_2};
 use neptune::poseidon::Poseidon;
 
@@ -16,3 +18,13 @@ pub fn hash_single_column(column: &[Fr]) -> Fr {
         _ => panic!("unsupported column size: {}", column.len()),
     }
 }
+
+/// Hash the columns laid out back to back in `columns`, each of `layers` elements, in one batch.
+/// Gives the same hashes as `hash_single_column` on each column.
+#[cfg(feature = "prover")]
+pub fn hash_column_batch(columns: &[Fr], layers: usize) -> Vec<Fr> {
+    match layers {
+        2 | 11 => hash_batch(columns, layers).expect("hash_batch failure"),
+        _ => panic!("unsupported column size: {}", layers),
+    }
+}
@@ -0,0 +1,218 @@
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{bail, ensure, Context, Result};
+use log::warn;
+use mapr::{MmapMut, MmapOptions};
+use storage_proofs_core::settings::SETTINGS;
+
+/// The magic number `statfs` reports for hugetlbfs mounts.
+#[cfg(target_os = "linux")]
+const HUGETLBFS_MAGIC: u32 = 0x9584_58f6;
+
+const THP_ENABLED_PATH: &str = "/sys/kernel/mm/transparent_hugepage/enabled";
+
+/// Which kind of huge pages the multicore SDR buffers should be backed by.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HugePages {
+    Off,
+    /// Anonymous memory, advised to be backed by transparent huge pages.
+    Transparent,
+    /// Unlinked files on the hugetlbfs mount at the given path.
+    Hugetlbfs(PathBuf),
+}
+
+/// The kind of pages a buffer actually got.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageBacking {
+    Normal,
+    /// Transparent huge pages were requested. The kernel may still back parts of the buffer with
+    /// normal pages.
+    Transparent,
+    /// Huge pages of the given size, reserved on a hugetlbfs mount.
+    Hugetlbfs(usize),
+}
+
+impl HugePages {
+    /// Parses the `multicore_sdr_huge_pages` setting: `off`, `thp` or `hugetlbfs`.
+    pub fn parse(mode: &str, hugetlbfs_path: &str) -> Result<Self> {
+        match mode.trim().to_lowercase().as_str() {
+            "" | "off" | "none" => Ok(HugePages::Off),
+            "thp" | "transparent" => Ok(HugePages::Transparent),
+            "hugetlbfs" => Ok(HugePages::Hugetlbfs(PathBuf::from(hugetlbfs_path))),
+            other => bail!("unknown huge pages mode: {}", other),
+        }
+    }
+
+    pub fn from_settings() -> Self {
+        Self::parse(
+            &SETTINGS.multicore_sdr_huge_pages,
+            &SETTINGS.multicore_sdr_hugetlbfs_path,
+        )
+        .unwrap_or_else(|err| {
+            warn!("{}, not using huge pages", err);
+            HugePages::Off
+        })
+    }
+}
+
+/// Maps `len` bytes of memory backed by huge pages as requested by `huge_pages`. Returns `None`
+/// if huge pages are off, and an error if they are not available, in which case callers fall
+/// back to normal pages.
+pub fn map_anon(len: usize, huge_pages: &HugePages) -> Result<Option<(MmapMut, PageBacking)>> {
+    match huge_pages {
+        HugePages::Off => Ok(None),
+        HugePages::Transparent => {
+            let buf = map_transparent(len)?;
+            Ok(Some((buf, PageBacking::Transparent)))
+        }
+        HugePages::Hugetlbfs(dir) => {
+            let (buf, page_size) = map_hugetlbfs(len, dir)?;
+            Ok(Some((buf, PageBacking::Hugetlbfs(page_size))))
+        }
+    }
+}
+
+/// Rounds `len` up to whole huge pages, if they are reserved on hugetlbfs.
+pub fn padded_len(len: usize, huge_pages: &HugePages) -> usize {
+    match huge_pages {
+        HugePages::Hugetlbfs(dir) => match hugetlbfs_page_size(dir) {
+            Ok(page_size) => (len + page_size - 1) / page_size * page_size,
+            Err(_) => len,
+        },
+        _ => len,
+    }
+}
+
+fn map_transparent(len: usize) -> Result<MmapMut> {
+    let enabled = fs::read_to_string(THP_ENABLED_PATH)
+        .with_context(|| format!("transparent huge pages not available: {}", THP_ENABLED_PATH))?;
+    ensure!(
+        !enabled.contains("[never]"),
+        "transparent huge pages are disabled"
+    );
+
+    let buf = MmapOptions::new().len(len).private().map_anon()?;
+    advise_huge_pages(&buf)?;
+
+    Ok(buf)
+}
+
+#[cfg(target_os = "linux")]
+fn advise_huge_pages(buf: &[u8]) -> Result<()> {
+    let res = unsafe {
+        libc::madvise(
+            buf.as_ptr() as *mut libc::c_void,
+            buf.len(),
+            libc::MADV_HUGEPAGE,
+        )
+    };
+    ensure!(
+        res == 0,
+        "failed to advise transparent huge pages: {}",
+        std::io::Error::last_os_error()
+    );
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn advise_huge_pages(_buf: &[u8]) -> Result<()> {
+    bail!("transparent huge pages are only supported on Linux")
+}
+
+/// Returns the huge page size of the hugetlbfs mount at `dir`.
+#[cfg(target_os = "linux")]
+fn hugetlbfs_page_size(dir: &Path) -> Result<usize> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_dir = CString::new(dir.as_os_str().as_bytes())?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    let res = unsafe { libc::statfs(c_dir.as_ptr(), &mut stat) };
+    ensure!(
+        res == 0,
+        "failed to stat {:?}: {}",
+        dir,
+        std::io::Error::last_os_error()
+    );
+    ensure!(
+        stat.f_type as u32 == HUGETLBFS_MAGIC,
+        "{:?} is not a hugetlbfs mount",
+        dir
+    );
+
+    Ok(stat.f_bsize as usize)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn hugetlbfs_page_size(_dir: &Path) -> Result<usize> {
+    bail!("hugetlbfs is only supported on Linux")
+}
+
+fn map_hugetlbfs(len: usize, dir: &Path) -> Result<(MmapMut, usize)> {
+    static FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let page_size = hugetlbfs_page_size(dir)?;
+    ensure!(
+        len % page_size == 0,
+        "{} bytes are not a multiple of the huge page size {}",
+        len,
+        page_size
+    );
+
+    let path = dir.join(format!(
+        "sdr-{}-{}",
+        process::id(),
+        FILE_COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .with_context(|| format!("failed to create {:?}", path))?;
+    // The mapping keeps the pages alive, unlinking right away ensures they are released even if
+    // the process dies.
+    fs::remove_file(&path).with_context(|| format!("failed to remove {:?}", path))?;
+    file.set_len(len as u64)
+        .with_context(|| format!("failed to reserve {} bytes of huge pages", len))?;
+
+    // Huge pages are reserved when mapping, so this fails if the pool is too small.
+    let buf = unsafe { MmapOptions::new().len(len).map_mut(&file) }
+        .with_context(|| format!("failed to map {} bytes of huge pages", len))?;
+
+    Ok((buf, page_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_huge_pages_modes() {
+        assert_eq!(
+            HugePages::parse("", "/dev/hugepages").expect("failed to parse huge pages mode"),
+            HugePages::Off
+        );
+        assert_eq!(
+            HugePages::parse("THP", "/dev/hugepages").expect("failed to parse huge pages mode"),
+            HugePages::Transparent
+        );
+        assert_eq!(
+            HugePages::parse("hugetlbfs", "/mnt/huge").expect("failed to parse huge pages mode"),
+            HugePages::Hugetlbfs(PathBuf::from("/mnt/huge"))
+        );
+        assert!(HugePages::parse("gigantic", "/dev/hugepages").is_err());
+
+        assert!(map_anon(4096, &HugePages::Off)
+            .expect("map_anon failed")
+            .is_none());
+
+        // A regular directory is not a hugetlbfs mount, callers fall back to normal pages.
+        let dir = tempfile::tempdir().expect("tempdir failure");
+        assert!(map_anon(4096, &HugePages::Hugetlbfs(dir.path().to_path_buf())).is_err());
+    }
+}
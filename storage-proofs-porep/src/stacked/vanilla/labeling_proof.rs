@@ -5,6 +5,7 @@ use fr32::bytes_into_fr_repr_safe;
 use log::trace;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use storage_proofs_core::error::Result;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LabelingProof<H: Hasher> {
@@ -49,10 +50,10 @@ impl<H: Hasher> LabelingProof<H> {
         bytes_into_fr_repr_safe(hasher.finalize().as_ref()).into()
     }
 
-    pub fn verify(&self, replica_id: &H::Domain, expected_label: &H::Domain) -> bool {
+    pub fn verify(&self, replica_id: &H::Domain, expected_label: &H::Domain) -> Result<bool> {
         let label = self.create_label(replica_id);
         check_eq!(expected_label, &label);
 
-        true
+        Ok(true)
     }
 }
@@ -1,4 +1,4 @@
-/// Checks that the two passed values are equal. If they are not equal it prints a trace and returns `false`.
+/// Checks that the two passed values are equal. If they are not equal it prints a trace and returns `Ok(false)`.
 macro_rules! check_eq {
     ($left:expr , $right:expr,) => ({
         check_eq!($left, $right)
@@ -12,7 +12,7 @@ macro_rules! check_eq {
                           \n{}\
                           \n",
                            pretty_assertions::Comparison::new(left_val, right_val));
-                    return false;
+                    return Ok(false);
                 }
             }
         }
@@ -27,23 +27,24 @@ macro_rules! check_eq {
                           \n",
                            format_args!($($arg)*),
                            pretty_assertions::Comparison::new(left_val, right_val));
-                    return false;
+                    return Ok(false);
                 }
             }
         }
     });
 }
 
-/// Checks that the passed in value is true. If they are not equal it prints a trace and returns `false`.
+/// Checks that the passed in value is true. If they are not equal it prints a trace and returns `Ok(false)`.
 macro_rules! check {
     ($val:expr) => {
         if !$val {
             trace!("expected {:?} to be true", dbg!($val));
-            return false;
+            return Ok(false);
         }
     };
 }
 
+#[cfg(feature = "prover")]
 macro_rules! prefetch {
     ($val:expr) => {
         #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
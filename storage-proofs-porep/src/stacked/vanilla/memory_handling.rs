@@ -1,23 +1,34 @@
 use std::cell::UnsafeCell;
 use std::fs::File;
 use std::hint::spin_loop;
+use std::io::{Read, Seek, SeekFrom};
 use std::marker::{PhantomData, Sync};
 use std::mem::size_of;
 use std::path::Path;
 use std::slice;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use byte_slice_cast::{AsSliceOf, FromByteSlice};
-use log::{info, warn};
+use log::{debug, info, warn};
 use mapr::{Mmap, MmapMut, MmapOptions};
+use storage_proofs_core::{
+    measurements::{measure_op, Operation},
+    metrics::METRICS,
+};
+
+use crate::stacked::vanilla::{
+    huge_pages::{self, HugePages, PageBacking},
+    numa,
+};
 
 pub struct CacheReader<T> {
     file: File,
-    bufs: UnsafeCell<[Mmap; 2]>,
+    bufs: UnsafeCell<[Window; 2]>,
     size: usize,
     degree: usize,
     window_size: usize,
+    numa_node: Option<u32>,
     cursor: IncrementingCursor,
     consumer: AtomicU64,
     _t: PhantomData<T>,
@@ -25,6 +36,23 @@ pub struct CacheReader<T> {
 
 unsafe impl<T> Sync for CacheReader<T> {}
 
+/// A window of the parent cache.
+enum Window {
+    /// A private mapping of the file, which is remapped when the window moves.
+    Mapped(Mmap),
+    /// A buffer backed by huge pages, which the file is read into when the window moves.
+    Buffered(MmapMut),
+}
+
+impl Window {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Window::Mapped(buf) => &buf[..],
+            Window::Buffered(buf) => &buf[..],
+        }
+    }
+}
+
 struct IncrementingCursor {
     cur: AtomicUsize,
     cur_safe: AtomicUsize,
@@ -93,7 +121,13 @@ impl IncrementingCursor {
 }
 
 impl<T: FromByteSlice> CacheReader<T> {
-    pub fn new(filename: &Path, window_size: Option<usize>, degree: usize) -> Result<Self> {
+    pub fn new(
+        filename: &Path,
+        window_size: Option<usize>,
+        degree: usize,
+        numa_node: Option<u32>,
+        huge_pages: HugePages,
+    ) -> Result<Self> {
         info!("initializing cache");
         let file = File::open(filename)?;
         let size = File::metadata(&file)?.len() as usize;
@@ -115,19 +149,61 @@ impl<T: FromByteSlice> CacheReader<T> {
             }
         };
 
-        let buf0 = Self::map_buf(0, window_size, &file)?;
-        let buf1 = Self::map_buf(window_size as u64, window_size, &file)?;
-        Ok(Self {
+        // The huge page buffers are allocated once and the file is read into them as the windows
+        // move. Windows are usually smaller than a huge page, they are padded to whole pages.
+        let padded_len = huge_pages::padded_len(window_size, &huge_pages);
+        let huge_bufs = huge_pages::map_anon(padded_len, &huge_pages)
+            .and_then(|buf0| Ok((buf0, huge_pages::map_anon(padded_len, &huge_pages)?)))
+            .unwrap_or_else(|err| {
+                debug!("parent cache windows: {}, falling back", err);
+                (None, None)
+            });
+
+        let (bufs, backing) = match huge_bufs {
+            (Some((buf0, backing)), Some((buf1, _))) => {
+                // Bind before faulting in the pages by reading into them.
+                if let Some(node) = numa_node {
+                    for buf in &[&buf0, &buf1] {
+                        if let Err(err) = numa::bind_memory(buf, node) {
+                            debug!("failed to bind parent cache window: {}", err);
+                        }
+                    }
+                }
+                ([Window::Buffered(buf0), Window::Buffered(buf1)], backing)
+            }
+            _ => {
+                let buf0 = Self::map_window(0, window_size, &file, numa_node)?;
+                let buf1 = Self::map_window(window_size as u64, window_size, &file, numa_node)?;
+                (
+                    [Window::Mapped(buf0), Window::Mapped(buf1)],
+                    PageBacking::Normal,
+                )
+            }
+        };
+        info!(
+            "parent cache windows: requested {:?}, got {:?}",
+            huge_pages, backing
+        );
+
+        let reader = Self {
             file,
-            bufs: UnsafeCell::new([buf0, buf1]),
+            bufs: UnsafeCell::new(bufs),
             size,
             degree,
             window_size,
+            numa_node,
             // The furthest window from which the cache has yet been read.
             cursor: IncrementingCursor::new(0),
             consumer: AtomicU64::new(0),
             _t: PhantomData::<T>,
-        })
+        };
+        // The huge page buffers are still empty, the first two windows are read into them.
+        if backing != PageBacking::Normal {
+            reader.start_reset()?;
+            reader.finish_reset()?;
+        }
+
+        Ok(reader)
     }
 
     pub fn size(&self) -> usize {
@@ -153,13 +229,13 @@ impl<T: FromByteSlice> CacheReader<T> {
     }
 
     #[inline]
-    fn get_bufs(&self) -> &[Mmap] {
+    fn get_bufs(&self) -> &[Window] {
         unsafe { &std::slice::from_raw_parts((*self.bufs.get()).as_ptr(), 2) }
     }
 
     #[inline]
     #[allow(clippy::mut_from_ref)]
-    unsafe fn get_mut_bufs(&self) -> &mut [Mmap] {
+    unsafe fn get_mut_bufs(&self) -> &mut [Window] {
         slice::from_raw_parts_mut((*self.bufs.get()).as_mut_ptr(), 2)
     }
 
@@ -172,29 +248,64 @@ impl<T: FromByteSlice> CacheReader<T> {
     }
 
     pub fn start_reset(&self) -> Result<()> {
-        let buf0 = Self::map_buf(0, self.window_size, &self.file)?;
         let bufs = unsafe { self.get_mut_bufs() };
-        bufs[0] = buf0;
-        Ok(())
+        self.fill_window(&mut bufs[0], 0)
     }
 
     pub fn finish_reset(&self) -> Result<()> {
-        let buf1 = Self::map_buf(self.window_size as u64, self.window_size, &self.file)?;
         let bufs = unsafe { self.get_mut_bufs() };
-        bufs[1] = buf1;
+        self.fill_window(&mut bufs[1], self.window_size as u64)?;
         self.cursor.store(0);
         Ok(())
     }
 
-    fn map_buf(offset: u64, len: usize, file: &File) -> Result<Mmap> {
-        unsafe {
+    /// Moves `buf` to the window of the parent cache starting at `offset`.
+    fn fill_window(&self, buf: &mut Window, offset: u64) -> Result<()> {
+        match buf {
+            Window::Buffered(buf) => {
+                // Windows of small caches extend past the end of the file, like a mapping of the
+                // file the rest of the buffer reads as zeros.
+                let read_len = (self.size as u64)
+                    .saturating_sub(offset)
+                    .min(self.window_size as u64) as usize;
+                measure_op(Operation::ParentCacheRead, || {
+                    let mut file = &self.file;
+                    file.seek(SeekFrom::Start(offset))?;
+                    file.read_exact(&mut buf[..read_len])
+                })
+                .context("failed to read parent cache window")?;
+                for byte in &mut buf[read_len..self.window_size] {
+                    *byte = 0;
+                }
+            }
+            Window::Mapped(buf) => {
+                *buf = Self::map_window(offset, self.window_size, &self.file, self.numa_node)?;
+            }
+        }
+        METRICS.add_bytes(Operation::ParentCacheRead, self.window_size as u64);
+
+        Ok(())
+    }
+
+    /// Privately maps a window of the parent cache.
+    fn map_window(offset: u64, len: usize, file: &File, numa_node: Option<u32>) -> Result<Mmap> {
+        let buf = measure_op(Operation::ParentCacheRead, || unsafe {
             MmapOptions::new()
                 .offset(offset)
                 .len(len)
                 .private()
                 .map(file)
-                .map_err(|e| e.into())
+        })?;
+
+        if let Some(node) = numa_node {
+            // Only moves the pages of the window which are not shared with other processes, new
+            // pages follow the memory policy of the faulting labelling thread.
+            if let Err(err) = numa::bind_memory(&buf, node) {
+                debug!("failed to bind parent cache window: {}", err);
+            }
         }
+
+        Ok(buf)
     }
 
     #[inline]
@@ -220,7 +331,10 @@ impl<T: FromByteSlice> CacheReader<T> {
         let pos = pos % self.window_element_count();
         let targeted_buf = &self.get_bufs()[window % 2];
 
-        &targeted_buf.as_slice_of::<T>().expect("as_slice_of failed")[pos..]
+        &targeted_buf
+            .as_bytes()
+            .as_slice_of::<T>()
+            .expect("as_slice_of failed")[pos..]
     }
 
     /// `pos` is in units of `T`.
@@ -253,7 +367,10 @@ impl<T: FromByteSlice> CacheReader<T> {
 
         let targeted_buf = &self.get_bufs()[window % 2];
 
-        &targeted_buf.as_slice_of::<T>().expect("as_slice_of failed")[pos..]
+        &targeted_buf
+            .as_bytes()
+            .as_slice_of::<T>()
+            .expect("as_slice_of failed")[pos..]
     }
 
     fn advance_rear_window(&self, new_window: usize) {
@@ -261,21 +378,58 @@ impl<T: FromByteSlice> CacheReader<T> {
 
         let replace_idx = (new_window % 2) as usize;
 
-        let new_buf = Self::map_buf(
-            (new_window * self.window_size) as u64,
-            self.window_size as usize,
-            &self.file,
-        )
-        .expect("map_buf failed");
-
         unsafe {
-            self.get_mut_bufs()[replace_idx] = new_buf;
+            self.fill_window(
+                &mut self.get_mut_bufs()[replace_idx],
+                (new_window * self.window_size) as u64,
+            )
+            .expect("fill_window failed");
+        }
+    }
+}
+
+/// Allocates a buffer for the labels of one layer. If a NUMA node is given, the buffer is bound
+/// to it before it is faulted in, and `true` is returned if that succeeded.
+fn allocate_layer(
+    sector_size: usize,
+    numa_node: Option<u32>,
+    huge_pages: &HugePages,
+) -> Result<(MmapMut, PageBacking, bool)> {
+    let huge_layer = huge_pages::map_anon(sector_size, huge_pages).unwrap_or_else(|err| {
+        warn!("layer buffer: {}, falling back", err);
+        None
+    });
+
+    let (mut layer, backing) = match huge_layer {
+        Some(huge_layer) => huge_layer,
+        None if numa_node.is_some() => (
+            MmapOptions::new().len(sector_size).private().map_anon()?,
+            PageBacking::Normal,
+        ),
+        None => return Ok((map_locked_layer(sector_size)?, PageBacking::Normal, false)),
+    };
+
+    let bound = match numa_node.map(|node| numa::bind_memory(&layer, node)) {
+        Some(Ok(())) => true,
+        Some(Err(err)) => {
+            warn!("{}, falling back", err);
+            false
+        }
+        None => false,
+    };
+    // Pages on hugetlbfs are never swapped out, so there is no need to lock them.
+    if !matches!(backing, PageBacking::Hugetlbfs(_)) {
+        if let Err(err) = layer.mlock() {
+            // fallback to not locked if permissions are not available
+            warn!("failed to lock map {:?}, falling back", err);
         }
     }
+
+    Ok((layer, backing, bound))
 }
 
-fn allocate_layer(sector_size: usize) -> Result<MmapMut> {
-    match MmapOptions::new()
+fn map_locked_layer(sector_size: usize) -> Result<MmapMut> {
+    let layer = match MmapOptions::new()
         .len(sector_size)
         .private()
         .clone()
@@ -285,25 +439,47 @@ fn allocate_layer(sector_size: usize) -> Result<MmapMut> {
             layer.mlock()?;
             Ok(layer)
         }) {
-        Ok(layer) => Ok(layer),
+        Ok(layer) => layer,
         Err(err) => {
             // fallback to not locked if permissions are not available
             warn!("failed to lock map {:?}, falling back", err);
-            let layer = MmapOptions::new().len(sector_size).private().map_anon()?;
-            Ok(layer)
+            MmapOptions::new().len(sector_size).private().map_anon()?
         }
-    }
+    };
+
+    Ok(layer)
 }
 
+/// Sets up the parent cache reader and the two layer buffers used for labelling. If a NUMA
+/// node is given, the memory is placed on it and the returned flag tells whether the layer
+/// buffers could be bound to it. The buffers are backed by huge pages if configured.
 pub fn setup_create_label_memory(
     sector_size: usize,
     degree: usize,
     window_size: Option<usize>,
     cache_path: &Path,
-) -> Result<(CacheReader<u32>, MmapMut, MmapMut)> {
-    let parents_cache = CacheReader::new(cache_path, window_size, degree)?;
-    let layer_labels = allocate_layer(sector_size)?;
-    let exp_labels = allocate_layer(sector_size)?;
-
-    Ok((parents_cache, layer_labels, exp_labels))
+    numa_node: Option<u32>,
+) -> Result<(CacheReader<u32>, MmapMut, MmapMut, bool)> {
+    let huge_pages = HugePages::from_settings();
+    let parents_cache = CacheReader::new(
+        cache_path,
+        window_size,
+        degree,
+        numa_node,
+        huge_pages.clone(),
+    )?;
+    let (layer_labels, layer_backing, layer_bound) =
+        allocate_layer(sector_size, numa_node, &huge_pages)?;
+    let (exp_labels, exp_backing, exp_bound) = allocate_layer(sector_size, numa_node, &huge_pages)?;
+    info!(
+        "layer buffers: requested {:?}, got {:?} and {:?}",
+        huge_pages, layer_backing, exp_backing
+    );
+
+    Ok((
+        parents_cache,
+        layer_labels,
+        exp_labels,
+        layer_bound && exp_bound,
+    ))
 }
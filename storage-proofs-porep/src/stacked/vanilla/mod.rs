@@ -1,9 +1,11 @@
 #[macro_use]
 mod macros;
 
+#[cfg(feature = "prover")]
 pub mod create_label;
 pub(crate) mod hash;
 
+#[cfg(feature = "prover")]
 mod cache;
 mod challenges;
 mod column;
@@ -12,16 +14,33 @@ mod column_proof;
 mod cores;
 mod encoding_proof;
 mod graph;
+#[cfg(feature = "multicore-sdr")]
+mod huge_pages;
 mod labeling_proof;
 #[cfg(feature = "multicore-sdr")]
 mod memory_handling;
+#[cfg(feature = "multicore-sdr")]
+mod numa;
 mod params;
+mod placement;
+#[cfg(feature = "prover")]
 mod porep;
 mod proof;
 mod proof_scheme;
+#[cfg(feature = "prover")]
+mod read_plan;
+#[cfg(feature = "prover")]
+mod resume;
+#[cfg(feature = "prover")]
+mod tree_pipeline;
 #[cfg(feature = "multicore-sdr")]
 mod utils;
 
+#[cfg(feature = "prover")]
+pub use cache::{
+    get_parent_cache_data, parent_cache_digest, parent_cache_id, parent_cache_path, ParentCache,
+    ParentCacheData, ParentCacheStatus, PARENT_CACHE,
+};
 pub use challenges::{ChallengeRequirements, LayerChallenges};
 pub use column::Column;
 pub use column_proof::ColumnProof;
@@ -29,4 +48,9 @@ pub use encoding_proof::EncodingProof;
 pub use graph::{StackedBucketGraph, StackedGraph, EXP_DEGREE};
 pub use labeling_proof::LabelingProof;
 pub use params::*;
+pub use placement::{sdr_topology, CoreGroupInfo, SdrPlacement, SdrPlacementReport, SdrTopology};
 pub use proof::{StackedDrg, TOTAL_PARENTS};
+#[cfg(feature = "prover")]
+pub use read_plan::{add_commit_reads, FileReads, ReadPlan, ReadPlanBuilder, ReadTarget};
+#[cfg(feature = "prover")]
+pub use tree_pipeline::{pipelined_tree_builder_memory, tree_builder_batch_size};
@@ -0,0 +1,104 @@
+use anyhow::{ensure, Result};
+
+/// The number of nodes the node masks passed to the kernel can hold.
+const MAX_NODES: usize = 1024;
+const MASK_WORDS: usize = MAX_NODES / 64;
+
+#[cfg(target_os = "linux")]
+const MPOL_DEFAULT: libc::c_long = 0;
+#[cfg(target_os = "linux")]
+const MPOL_PREFERRED: libc::c_long = 1;
+#[cfg(target_os = "linux")]
+const MPOL_BIND: libc::c_long = 2;
+#[cfg(target_os = "linux")]
+const MPOL_MF_MOVE: libc::c_long = 1 << 1;
+
+fn node_mask(node: u32) -> Result<[u64; MASK_WORDS]> {
+    ensure!(
+        (node as usize) < MAX_NODES,
+        "NUMA node {} out of range",
+        node
+    );
+
+    let mut mask = [0u64; MASK_WORDS];
+    mask[node as usize / 64] = 1 << (node % 64);
+    Ok(mask)
+}
+
+/// Binds the pages of `buf` to `node`. Pages which are already allocated elsewhere are moved, if
+/// they are not shared with other processes.
+#[cfg(target_os = "linux")]
+pub fn bind_memory(buf: &[u8], node: u32) -> Result<()> {
+    let mask = node_mask(node)?;
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_mbind,
+            buf.as_ptr(),
+            buf.len(),
+            MPOL_BIND,
+            mask.as_ptr(),
+            MAX_NODES + 1,
+            MPOL_MF_MOVE,
+        )
+    };
+    ensure!(
+        res == 0,
+        "failed to bind memory to NUMA node {}: {}",
+        node,
+        std::io::Error::last_os_error()
+    );
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn bind_memory(_buf: &[u8], node: u32) -> Result<()> {
+    node_mask(node)?;
+    anyhow::bail!("binding memory to NUMA nodes is only supported on Linux")
+}
+
+/// Resets the memory policy of the current thread when dropped.
+pub struct PreferNodeGuard(());
+
+impl Drop for PreferNodeGuard {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::syscall(
+                libc::SYS_set_mempolicy,
+                MPOL_DEFAULT,
+                std::ptr::null::<u64>(),
+                0,
+            );
+        }
+    }
+}
+
+/// Makes the kernel allocate the memory the current thread faults in on `node` where possible,
+/// e.g. the page cache of the parent cache file, until the returned guard is dropped.
+#[cfg(target_os = "linux")]
+pub fn prefer_node(node: u32) -> Result<PreferNodeGuard> {
+    let mask = node_mask(node)?;
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_set_mempolicy,
+            MPOL_PREFERRED,
+            mask.as_ptr(),
+            MAX_NODES + 1,
+        )
+    };
+    ensure!(
+        res == 0,
+        "failed to prefer NUMA node {}: {}",
+        node,
+        std::io::Error::last_os_error()
+    );
+
+    Ok(PreferNodeGuard(()))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn prefer_node(node: u32) -> Result<PreferNodeGuard> {
+    node_mask(node)?;
+    anyhow::bail!("preferring NUMA nodes is only supported on Linux")
+}
@@ -18,7 +18,7 @@ use storage_proofs_core::{
     drgraph::Graph,
     error::Result,
     merkle::{
-        create_disk_tree, create_lc_tree, get_base_tree_count, split_config,
+        create_disk_tree, create_lc_tree, get_base_tree_count, read_node, split_config,
         split_config_and_replica, BinaryMerkleTree, DiskTree, LCTree, MerkleProof,
         MerkleProofTrait, MerkleTreeTrait,
     },
@@ -203,7 +203,7 @@ impl<Tree: MerkleTreeTrait, G: Hasher> Proof<Tree, G> {
         pub_inputs: &PublicInputs<<Tree::Hasher as Hasher>::Domain, <G as Hasher>::Domain>,
         challenge: usize,
         graph: &StackedBucketGraph<Tree::Hasher>,
-    ) -> bool {
+    ) -> Result<bool> {
         let replica_id = &pub_inputs.replica_id;
 
         check!(challenge < graph.size());
@@ -217,20 +217,18 @@ impl<Tree: MerkleTreeTrait, G: Hasher> Proof<Tree, G> {
         if let Some(ref tau) = pub_inputs.tau {
             check_eq!(&self.comm_d_proofs.root(), &tau.comm_d);
         } else {
-            return false;
+            return Ok(false);
         }
 
         // Verify replica column openings
         trace!("verify replica column openings");
         let mut parents = vec![0; graph.degree()];
-        graph
-            .parents(challenge, &mut parents)
-            .expect("graph parents failure"); // FIXME: error handling
-        check!(self.replica_column_proofs.verify(challenge, &parents));
+        graph.parents(challenge, &mut parents)?;
+        check!(self.replica_column_proofs.verify(challenge, &parents)?);
 
-        check!(self.verify_final_replica_layer(challenge));
+        check!(self.verify_final_replica_layer(challenge)?);
 
-        check!(self.verify_labels(replica_id, &pub_params.layer_challenges));
+        check!(self.verify_labels(replica_id, &pub_params.layer_challenges)?);
 
         trace!("verify encoding");
 
@@ -238,9 +236,9 @@ impl<Tree: MerkleTreeTrait, G: Hasher> Proof<Tree, G> {
             replica_id,
             &self.comm_r_last_proof.leaf(),
             &self.comm_d_proofs.leaf()
-        ));
+        )?);
 
-        true
+        Ok(true)
     }
 
     /// Verify all labels.
@@ -248,7 +246,7 @@ impl<Tree: MerkleTreeTrait, G: Hasher> Proof<Tree, G> {
         &self,
         replica_id: &<Tree::Hasher as Hasher>::Domain,
         layer_challenges: &LayerChallenges,
-    ) -> bool {
+    ) -> Result<bool> {
         // Verify Labels Layer 1..layers
         for layer in 1..=layer_challenges.layers() {
             trace!("verify labeling (layer: {})", layer,);
@@ -258,23 +256,19 @@ impl<Tree: MerkleTreeTrait, G: Hasher> Proof<Tree, G> {
                 .labeling_proofs
                 .get(layer - 1)
                 .expect("labeling proofs get failure");
-            let labeled_node = self
-                .replica_column_proofs
-                .c_x
-                .get_node_at_layer(layer)
-                .expect("get_node_at_layer failure"); // FIXME: error handling
-            check!(labeling_proof.verify(replica_id, labeled_node));
+            let labeled_node = self.replica_column_proofs.c_x.get_node_at_layer(layer)?;
+            check!(labeling_proof.verify(replica_id, labeled_node)?);
         }
 
-        true
+        Ok(true)
     }
 
     /// Verify final replica layer openings
-    fn verify_final_replica_layer(&self, challenge: usize) -> bool {
+    fn verify_final_replica_layer(&self, challenge: usize) -> Result<bool> {
         trace!("verify final replica layer openings");
         check!(self.comm_r_last_proof.proves_challenge(challenge));
 
-        true
+        Ok(true)
     }
 }
 
@@ -298,15 +292,15 @@ pub struct ReplicaColumnProof<Proof: MerkleProofTrait> {
 }
 
 impl<Proof: MerkleProofTrait> ReplicaColumnProof<Proof> {
-    pub fn verify(&self, challenge: usize, parents: &[u32]) -> bool {
+    pub fn verify(&self, challenge: usize, parents: &[u32]) -> Result<bool> {
         let expected_comm_c = self.c_x.root();
 
         trace!("  verify c_x");
-        check!(self.c_x.verify(challenge as u32, &expected_comm_c));
+        check!(self.c_x.verify(challenge as u32, &expected_comm_c)?);
 
         trace!("  verify drg_parents");
         for (proof, parent) in self.drg_parents.iter().zip(parents.iter()) {
-            check!(proof.verify(*parent, &expected_comm_c));
+            check!(proof.verify(*parent, &expected_comm_c)?);
         }
 
         trace!("  verify exp_parents");
@@ -315,10 +309,10 @@ impl<Proof: MerkleProofTrait> ReplicaColumnProof<Proof> {
             .iter()
             .zip(parents.iter().skip(self.drg_parents.len()))
         {
-            check!(proof.verify(*parent, &expected_comm_c));
+            check!(proof.verify(*parent, &expected_comm_c)?);
         }
 
-        true
+        Ok(true)
     }
 }
 
@@ -393,7 +387,7 @@ impl<Tree: MerkleTreeTrait, G: Hasher> TemporaryAux<Tree, G> {
         layer: usize,
         node_index: u32,
     ) -> Result<<Tree::Hasher as Hasher>::Domain> {
-        self.labels_for_layer(layer)?.read_at(node_index as usize)
+        read_node(&self.labels_for_layer(layer)?, node_index as usize)
     }
 
     pub fn column(&self, column_index: u32) -> Result<Column<Tree::Hasher>> {
@@ -574,7 +568,7 @@ impl<Tree: MerkleTreeTrait, G: Hasher> TemporaryAuxCache<Tree, G> {
         layer: usize,
         node_index: u32,
     ) -> Result<<Tree::Hasher as Hasher>::Domain> {
-        self.labels_for_layer(layer).read_at(node_index as usize)
+        read_node(self.labels_for_layer(layer), node_index as usize)
     }
 
     pub fn column(&self, column_index: u32) -> Result<Column<Tree::Hasher>> {
@@ -675,7 +669,7 @@ impl<Tree: MerkleTreeTrait> Labels<Tree> {
                     Tree::Arity::to_usize(),
                     &label,
                 )?;
-                store.read_at(node as usize)
+                read_node(&store, node as usize)
             })
             .collect::<Result<_>>()?;
 
@@ -744,7 +738,7 @@ impl<Tree: MerkleTreeTrait> LabelsCache<Tree> {
         let rows = self
             .labels
             .iter()
-            .map(|labels| labels.read_at(node as usize))
+            .map(|labels| read_node(labels, node as usize))
             .collect::<Result<_>>()?;
 
         Column::new(node, rows)
@@ -752,7 +746,7 @@ impl<Tree: MerkleTreeTrait> LabelsCache<Tree> {
 }
 
 pub fn get_node<H: Hasher>(data: &[u8], index: usize) -> Result<H::Domain> {
-    H::Domain::try_from_bytes(data_at_node(data, index).expect("invalid node math"))
+    H::Domain::try_from_bytes(data_at_node(data, index)?).map_err(Into::into)
 }
 
 /// Generate the replica id as expected for Stacked DRG.
@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::error::Result;
+
+/// Where the multicore SDR labelling of a sector runs, and which memory it uses.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SdrPlacement {
+    /// Check out any free core group and bind the memory to the NUMA node of its cores.
+    Auto,
+    /// Run on the given cores, as indices into the cores of the topology. The first core runs
+    /// the consumer, the following ones the producers. The cores are not checked out, so they
+    /// may be shared with other labelling jobs.
+    Cores(Vec<usize>),
+    /// Check out a free core group on the given NUMA node and bind the memory to it.
+    NumaNode(u32),
+}
+
+impl Default for SdrPlacement {
+    fn default() -> Self {
+        SdrPlacement::Auto
+    }
+}
+
+/// The placement decision made for one labelling job.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SdrPlacementReport {
+    /// The cores the labelling threads were bound to, empty if they were not bound.
+    pub cores: Vec<usize>,
+    /// The NUMA node the layer buffers and parent cache windows were placed on.
+    pub numa_node: Option<u32>,
+    /// Whether the layer buffers were bound to `numa_node`. Binding is best effort, e.g. it is
+    /// not permitted in some containers.
+    pub memory_bound: bool,
+}
+
+/// A group of cores sharing a cache, which is checked out by one labelling job at a time.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoreGroupInfo {
+    pub cores: Vec<usize>,
+    /// The NUMA node of the cores, if they are all on the same one.
+    pub numa_node: Option<u32>,
+    /// Whether the group is currently checked out.
+    pub checked_out: bool,
+}
+
+/// The topology multicore SDR placement decisions are based on.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SdrTopology {
+    pub core_count: usize,
+    pub numa_nodes: Vec<u32>,
+    pub core_groups: Vec<CoreGroupInfo>,
+}
+
+/// Returns the topology used for placing multicore SDR labelling jobs.
+pub fn sdr_topology() -> Result<SdrTopology> {
+    #[cfg(feature = "multicore-sdr")]
+    {
+        Ok(crate::stacked::vanilla::cores::topology())
+    }
+
+    #[cfg(not(feature = "multicore-sdr"))]
+    {
+        anyhow::bail!("placement requires the multicore-sdr feature")
+    }
+}
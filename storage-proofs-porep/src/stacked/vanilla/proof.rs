@@ -1,58 +1,83 @@
+#[cfg(feature = "prover")]
 use std::fs;
 use std::marker::PhantomData;
+#[cfg(feature = "prover")]
 use std::panic::panic_any;
+#[cfg(feature = "prover")]
 use std::path::{Path, PathBuf};
+#[cfg(feature = "prover")]
 use std::sync::Mutex;
 
+#[cfg(feature = "prover")]
 use anyhow::Context;
+#[cfg(feature = "prover")]
 use bincode::deserialize;
+#[cfg(feature = "prover")]
 use fdlimit::raise_fd_limit;
-use filecoin_hashers::{Domain, HashFunction, Hasher, PoseidonArity};
+use filecoin_hashers::Hasher;
+#[cfg(feature = "prover")]
+use filecoin_hashers::{poseidon::BATCH_SIZE, Domain, HashFunction, PoseidonArity};
+#[cfg(feature = "prover")]
 use generic_array::typenum::{Unsigned, U0, U11, U2, U8};
+#[cfg(feature = "prover")]
 use lazy_static::lazy_static;
-use log::{error, info, trace};
+use log::trace;
+#[cfg(feature = "prover")]
+use log::{error, info, warn};
+#[cfg(feature = "prover")]
 use merkletree::{
-    merkle::{get_merkle_tree_len, is_merkle_tree_size_valid},
+    merkle::{get_merkle_tree_cache_size, get_merkle_tree_len, is_merkle_tree_size_valid},
     store::{Store, StoreConfig},
 };
-use rayon::prelude::{
-    IndexedParallelIterator, IntoParallelIterator, ParallelIterator, ParallelSliceMut,
-};
+use rayon::prelude::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+#[cfg(feature = "prover")]
+use rayon::prelude::{ParallelSlice, ParallelSliceMut};
+#[cfg(feature = "prover")]
 use storage_proofs_core::{
     cache_key::CacheKey,
     data::Data,
-    drgraph::Graph,
-    error::Result,
     measurements::{measure_op, Operation},
     merkle::{
         create_disk_tree, create_lc_tree, get_base_tree_count, split_config,
-        split_config_and_replica, BinaryMerkleTree, DiskTree, LCTree, MerkleProofTrait, MerkleTree,
-        MerkleTreeTrait,
+        split_config_and_replica, BinaryMerkleTree, DiskTree, LCTree, MerkleTree,
     },
     settings::SETTINGS,
     util::{default_rows_to_discard, NODE_SIZE},
 };
+use storage_proofs_core::{
+    context::ThreadContext,
+    drgraph::Graph,
+    error::Result,
+    merkle::{MerkleProofTrait, MerkleTreeTrait},
+};
 
+use crate::stacked::vanilla::{
+    challenges::LayerChallenges,
+    column::Column,
+    graph::StackedBucketGraph,
+    params::{PersistentAux, Proof, PublicInputs, ReplicaColumnProof, TemporaryAuxCache},
+    EncodingProof, LabelingProof,
+};
+#[cfg(feature = "prover")]
 use crate::{
+    direct_io::Replica,
     encode::{decode, encode},
     stacked::vanilla::{
-        challenges::LayerChallenges,
-        column::Column,
         create_label,
-        graph::StackedBucketGraph,
-        hash::hash_single_column,
+        hash::{hash_column_batch, hash_single_column},
         params::{
-            get_node, Labels, LabelsCache, PersistentAux, Proof, PublicInputs, PublicParams,
-            ReplicaColumnProof, Tau, TemporaryAux, TemporaryAuxCache, TransformedLayers,
+            get_node, Labels, LabelsCache, PublicParams, Tau, TemporaryAux, TransformedLayers,
             BINARY_ARITY,
         },
-        EncodingProof, LabelingProof,
+        placement::{SdrPlacement, SdrPlacementReport},
+        resume, tree_pipeline,
     },
     PoRep,
 };
 
 pub const TOTAL_PARENTS: usize = 37;
 
+#[cfg(feature = "prover")]
 lazy_static! {
     /// Ensure that only one `TreeBuilder` or `ColumnTreeBuilder` uses the GPU at a time.
     /// Curently, this is accomplished by only instantiating at most one at a time.
@@ -67,6 +92,7 @@ pub struct StackedDrg<'a, Tree: MerkleTreeTrait, G: Hasher> {
     _b: PhantomData<&'a G>,
 }
 
+#[cfg(feature = "prover")]
 #[derive(Debug)]
 pub struct LayerState {
     pub config: StoreConfig,
@@ -126,6 +152,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 .collect()
         };
 
+        let context = &ThreadContext::current();
         (0..partition_count)
             .map(|k| {
                 trace!("proving partition {}/{}", k + 1, partition_count);
@@ -138,13 +165,14 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     .into_par_iter()
                     .enumerate()
                     .map(|(challenge_index, challenge)| {
+                        let _context = context.enter();
                         trace!(" challenge {} ({})", challenge, challenge_index);
                         assert!(challenge < graph.size(), "Invalid challenge");
                         assert!(challenge > 0, "Invalid challenge");
 
                         // Initial data layer openings (c_X in Comm_D)
                         let comm_d_proof = t_aux.tree_d.gen_proof(challenge)?;
-                        assert!(comm_d_proof.validate(challenge));
+                        assert!(comm_d_proof.validate(challenge)?);
 
                         // Stacked replica column openings
                         let rcp = {
@@ -187,7 +215,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                             Some(t_aux.tree_r_last_config_rows_to_discard),
                         )?;
 
-                        debug_assert!(comm_r_last_proof.validate(challenge));
+                        debug_assert!(comm_r_last_proof.validate(challenge)?);
 
                         // Labeling Proofs Layer 1..l
                         let mut labeling_proofs = Vec::with_capacity(layers);
@@ -239,7 +267,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                             {
                                 let labeled_node = rcp.c_x.get_node_at_layer(layer)?;
                                 assert!(
-                                    proof.verify(&pub_inputs.replica_id, &labeled_node),
+                                    proof.verify(&pub_inputs.replica_id, &labeled_node)?,
                                     "Invalid encoding proof generated at layer {}",
                                     layer,
                                 );
@@ -269,7 +297,10 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             })
             .collect()
     }
+}
 
+#[cfg(feature = "prover")]
+impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tree, G> {
     pub(crate) fn extract_and_invert_transform_layers(
         graph: &StackedBucketGraph<Tree::Hasher>,
         layer_challenges: &LayerChallenges,
@@ -311,42 +342,60 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         replica_id: &<Tree::Hasher as Hasher>::Domain,
         config: StoreConfig,
     ) -> Result<(Labels<Tree>, Vec<LayerState>)> {
+        let (labels, layer_states, _) = Self::generate_labels_for_encoding_with_placement(
+            graph,
+            layer_challenges,
+            replica_id,
+            config,
+            &SdrPlacement::Auto,
+        )?;
+
+        Ok((labels, layer_states))
+    }
+
+    /// Generates the layers, running the multicore labelling according to `placement`.
+    /// Placement only applies to multicore SDR, the returned report is empty otherwise.
+    #[allow(clippy::type_complexity)]
+    pub fn generate_labels_for_encoding_with_placement(
+        graph: &StackedBucketGraph<Tree::Hasher>,
+        layer_challenges: &LayerChallenges,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        config: StoreConfig,
+        placement: &SdrPlacement,
+    ) -> Result<(Labels<Tree>, Vec<LayerState>, SdrPlacementReport)> {
         let mut parent_cache = graph.parent_cache()?;
 
         #[cfg(feature = "multicore-sdr")]
         {
             if SETTINGS.use_multicore_sdr {
                 info!("multi core replication");
-                create_label::multi::create_labels_for_encoding(
+                return create_label::multi::create_labels_for_encoding(
                     graph,
                     &parent_cache,
                     layer_challenges.layers(),
                     replica_id,
                     config,
-                )
-            } else {
-                info!("single core replication");
-                create_label::single::create_labels_for_encoding(
-                    graph,
-                    &mut parent_cache,
-                    layer_challenges.layers(),
-                    replica_id,
-                    config,
-                )
+                    placement,
+                );
             }
         }
 
-        #[cfg(not(feature = "multicore-sdr"))]
-        {
-            info!("single core replication");
-            create_label::single::create_labels_for_encoding(
-                graph,
-                &mut parent_cache,
-                layer_challenges.layers(),
-                replica_id,
-                config,
-            )
+        if *placement != SdrPlacement::Auto {
+            warn!(
+                "ignoring placement {:?} for single core replication",
+                placement
+            );
         }
+        info!("single core replication");
+        let (labels, layer_states) = create_label::single::create_labels_for_encoding(
+            graph,
+            &mut parent_cache,
+            layer_challenges.layers(),
+            replica_id,
+            config,
+        )?;
+
+        Ok((labels, layer_states, SdrPlacementReport::default()))
     }
 
     /// Generates the layers, as needed for decoding.
@@ -413,12 +462,63 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         Ok(tree)
     }
 
+    /// Builds tree_c over the columns of the given labels and returns its root. Only the
+    /// sub-trees in `pending` are built, the others must already be complete on disk.
+    fn generate_tree_c_root(
+        layers: usize,
+        nodes_count: usize,
+        tree_count: usize,
+        configs: Vec<StoreConfig>,
+        pending: &[usize],
+        labels: &LabelsCache<Tree>,
+    ) -> Result<<Tree::Hasher as Hasher>::Domain> {
+        let tree_c_root = match layers {
+            2 => {
+                let tree_c = Self::generate_tree_c::<U2, Tree::Arity>(
+                    layers,
+                    nodes_count,
+                    tree_count,
+                    configs,
+                    pending,
+                    labels,
+                )?;
+                tree_c.root()
+            }
+            8 => {
+                let tree_c = Self::generate_tree_c::<U8, Tree::Arity>(
+                    layers,
+                    nodes_count,
+                    tree_count,
+                    configs,
+                    pending,
+                    labels,
+                )?;
+                tree_c.root()
+            }
+            11 => {
+                let tree_c = Self::generate_tree_c::<U11, Tree::Arity>(
+                    layers,
+                    nodes_count,
+                    tree_count,
+                    configs,
+                    pending,
+                    labels,
+                )?;
+                tree_c.root()
+            }
+            _ => panic_any("Unsupported column arity"),
+        };
+
+        Ok(tree_c_root)
+    }
+
     #[cfg(any(feature = "gpu"))]
     fn generate_tree_c<ColumnArity, TreeArity>(
         layers: usize,
         nodes_count: usize,
         tree_count: usize,
         configs: Vec<StoreConfig>,
+        pending: &[usize],
         labels: &LabelsCache<Tree>,
     ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
@@ -431,6 +531,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 nodes_count,
                 tree_count,
                 configs,
+                pending,
                 labels,
             )
         } else {
@@ -439,6 +540,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 nodes_count,
                 tree_count,
                 configs,
+                pending,
                 labels,
             )
         }
@@ -450,6 +552,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         nodes_count: usize,
         tree_count: usize,
         configs: Vec<StoreConfig>,
+        pending: &[usize],
         labels: &LabelsCache<Tree>,
     ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
@@ -461,6 +564,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             nodes_count,
             tree_count,
             configs,
+            pending,
             labels,
         )
     }
@@ -472,6 +576,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         nodes_count: usize,
         tree_count: usize,
         configs: Vec<StoreConfig>,
+        pending: &[usize],
         labels: &LabelsCache<Tree>,
     ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
@@ -479,12 +584,15 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         TreeArity: PoseidonArity,
     {
         use std::cmp::min;
+        use std::io::Write;
         use std::sync::{mpsc::sync_channel, Arc, RwLock};
 
         use bellperson::bls::Fr;
         use fr32::fr_into_bytes;
         use generic_array::GenericArray;
         use merkletree::store::DiskStore;
+
+        use crate::direct_io::DirectWriter;
         use neptune::{
             batch_hasher::BatcherType,
             column_tree_builder::{ColumnTreeBuilder, ColumnTreeBuilderTrait},
@@ -510,14 +618,16 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
             // This channel will receive batches of columns and add them to the ColumnTreeBuilder.
             let (builder_tx, builder_rx) = sync_channel(0);
+            let context = &ThreadContext::current();
 
-            let config_count = configs.len(); // Don't move config into closure below.
             rayon::scope(|s| {
+                let _context = context.enter();
                 // This channel will receive the finished tree data to be written to disk.
                 let (writer_tx, writer_rx) = sync_channel::<(Vec<Fr>, Vec<Fr>)>(0);
 
                 s.spawn(move |_| {
-                    for i in 0..config_count {
+                    let _context = context.enter();
+                    for &i in pending {
                         let mut node_index = 0;
                         let builder_tx = builder_tx.clone();
                         while node_index != nodes_count {
@@ -586,6 +696,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     }
                 });
                 s.spawn(move |_| {
+                    let _context = context.enter();
                     let _gpu_lock = GPU_LOCK.lock().expect("failed to get gpu lock");
                     let mut column_tree_builder = ColumnTreeBuilder::<ColumnArity, TreeArity>::new(
                         Some(BatcherType::OpenCL),
@@ -595,23 +706,26 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     )
                     .expect("failed to create ColumnTreeBuilder");
 
-                    // Loop until all trees for all configs have been built.
-                    for i in 0..config_count {
+                    // Loop until all trees for all pending configs have been built.
+                    for &i in pending {
                         loop {
                             let (columns, is_final): (Vec<GenericArray<Fr, ColumnArity>>, bool) =
                                 builder_rx.recv().expect("failed to recv columns");
 
                             // Just add non-final column batches.
                             if !is_final {
-                                column_tree_builder
-                                    .add_columns(&columns)
-                                    .expect("failed to add columns");
+                                measure_op(Operation::GpuTreeBatch, || {
+                                    column_tree_builder.add_columns(&columns)
+                                })
+                                .expect("failed to add columns");
                                 continue;
                             };
 
                             // If we get here, this is a final column: build a sub-tree.
-                            let (base_data, tree_data) = column_tree_builder
-                                .add_final_columns(&columns)
+                            let (base_data, tree_data) =
+                                measure_op(Operation::GpuTreeBatch, || {
+                                    column_tree_builder.add_final_columns(&columns)
+                                })
                                 .expect("failed to add final columns");
                             trace!(
                                 "base data len {}, tree data len {}",
@@ -635,11 +749,17 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     }
                 });
 
-                for config in &configs {
+                for &i in pending {
+                    let config = &configs[i];
                     let (base_data, tree_data) = writer_rx
                         .recv()
                         .expect("failed to receive base_data, tree_data for tree_c");
                     let tree_len = base_data.len() + tree_data.len();
+                    let root: <Tree::Hasher as Hasher>::Domain = (*tree_data
+                        .last()
+                        .or_else(|| base_data.last())
+                        .expect("tree_c data is empty"))
+                    .into();
 
                     assert_eq!(base_data.len(), nodes_count);
                     assert_eq!(tree_len, config.size.expect("config size failure"));
@@ -652,59 +772,79 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                         tree_c_store_path,
                         tree_c_store_exists
                     );
+                    resume::clear_complete(config).expect("failed to clear tree_c marker");
                     if tree_c_store_exists {
                         std::fs::remove_file(&tree_c_store_path)
                             .expect("failed to remove tree_c_store_path");
                     }
 
-                    let tree_c_store =
-                        DiskStore::<<Tree::Hasher as Hasher>::Domain>::new_with_config(
-                            tree_len,
-                            Tree::Arity::to_usize(),
-                            config.clone(),
-                        )
-                        .expect("failed to create DiskStore for base tree data");
-
-                    let store = Arc::new(RwLock::new(tree_c_store));
-                    let batch_size = min(base_data.len(), column_write_batch_size);
-                    let flatten_and_write_store = |data: &Vec<Fr>, offset| {
-                        data.into_par_iter()
-                            .chunks(batch_size)
-                            .enumerate()
-                            .try_for_each(|(index, fr_elements)| {
-                                let mut buf = Vec::with_capacity(batch_size * NODE_SIZE);
-
-                                for fr in fr_elements {
-                                    buf.extend(fr_into_bytes(&fr));
-                                }
-                                store
-                                    .write()
-                                    .expect("failed to access store for write")
-                                    .copy_from_slice(&buf[..], offset + (batch_size * index))
-                            })
-                    };
+                    if SETTINGS.use_direct_io {
+                        let mut writer = DirectWriter::create(&tree_c_store_path)
+                            .expect("failed to create tree_c store");
+                        for fr_elements in base_data
+                            .chunks(column_write_batch_size)
+                            .chain(tree_data.chunks(column_write_batch_size))
+                        {
+                            let buf: Vec<u8> = fr_elements
+                                .into_par_iter()
+                                .flat_map(fr_into_bytes)
+                                .collect();
+                            writer
+                                .write_all(&buf)
+                                .expect("failed to write tree_c store");
+                        }
+                        writer.finish().expect("failed to finish tree_c store");
+                    } else {
+                        let tree_c_store =
+                            DiskStore::<<Tree::Hasher as Hasher>::Domain>::new_with_config(
+                                tree_len,
+                                Tree::Arity::to_usize(),
+                                config.clone(),
+                            )
+                            .expect("failed to create DiskStore for base tree data");
+
+                        let store = Arc::new(RwLock::new(tree_c_store));
+                        let batch_size = min(base_data.len(), column_write_batch_size);
+                        let flatten_and_write_store = |data: &Vec<Fr>, offset| {
+                            data.into_par_iter()
+                                .chunks(batch_size)
+                                .enumerate()
+                                .try_for_each(|(index, fr_elements)| {
+                                    let mut buf = Vec::with_capacity(batch_size * NODE_SIZE);
+
+                                    for fr in fr_elements {
+                                        buf.extend(fr_into_bytes(&fr));
+                                    }
+                                    store
+                                        .write()
+                                        .expect("failed to access store for write")
+                                        .copy_from_slice(&buf[..], offset + (batch_size * index))
+                                })
+                        };
 
-                    trace!(
-                        "flattening tree_c base data of {} nodes using batch size {}",
-                        base_data.len(),
-                        batch_size
-                    );
-                    flatten_and_write_store(&base_data, 0)
-                        .expect("failed to flatten and write store");
-                    trace!("done flattening tree_c base data");
-
-                    let base_offset = base_data.len();
-                    trace!("flattening tree_c tree data of {} nodes using batch size {} and base offset {}", tree_data.len(), batch_size, base_offset);
-                    flatten_and_write_store(&tree_data, base_offset)
-                        .expect("failed to flatten and write store");
-                    trace!("done flattening tree_c tree data");
-
-                    trace!("writing tree_c store data");
-                    store
-                        .write()
-                        .expect("failed to access store for sync")
-                        .sync()
-                        .expect("store sync failure");
+                        trace!(
+                            "flattening tree_c base data of {} nodes using batch size {}",
+                            base_data.len(),
+                            batch_size
+                        );
+                        flatten_and_write_store(&base_data, 0)
+                            .expect("failed to flatten and write store");
+                        trace!("done flattening tree_c base data");
+
+                        let base_offset = base_data.len();
+                        trace!("flattening tree_c tree data of {} nodes using batch size {} and base offset {}", tree_data.len(), batch_size, base_offset);
+                        flatten_and_write_store(&tree_data, base_offset)
+                            .expect("failed to flatten and write store");
+                        trace!("done flattening tree_c tree data");
+
+                        trace!("writing tree_c store data");
+                        store
+                            .write()
+                            .expect("failed to access store for sync")
+                            .sync()
+                            .expect("store sync failure");
+                    }
+                    resume::mark_complete(config, &root).expect("failed to mark tree_c complete");
                     trace!("done writing tree_c store data");
                 }
             });
@@ -720,18 +860,23 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         nodes_count: usize,
         tree_count: usize,
         configs: Vec<StoreConfig>,
+        pending: &[usize],
         labels: &LabelsCache<Tree>,
     ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         ColumnArity: PoseidonArity,
         TreeArity: PoseidonArity,
     {
+        use bellperson::bls::Fr;
+
         info!("generating tree c using the CPU");
         measure_op(Operation::GenerateTreeC, || {
             info!("Building column hashes");
 
-            let mut trees = Vec::with_capacity(tree_count);
-            for (i, config) in configs.iter().enumerate() {
+            let context = &ThreadContext::current();
+            for &i in pending {
+                let config = &configs[i];
+
                 let mut hashes: Vec<<Tree::Hasher as Hasher>::Domain> =
                     vec![<Tree::Hasher as Hasher>::Domain::default(); nodes_count];
 
@@ -749,6 +894,39 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                         let labels = &labels;
 
                         s.spawn(move |_| {
+                            let _context = context.enter();
+                            if SETTINGS.use_batched_poseidon {
+                                let first = (i * nodes_count) + chunk * chunk_size;
+                                for (k, hashes_batch) in
+                                    hashes_chunk.chunks_mut(BATCH_SIZE).enumerate()
+                                {
+                                    let start = first + k * BATCH_SIZE;
+                                    let end = start + hashes_batch.len();
+                                    let rows: Vec<Vec<<Tree::Hasher as Hasher>::Domain>> = (1
+                                        ..=layers)
+                                        .map(|layer| {
+                                            labels
+                                                .labels_for_layer(layer)
+                                                .read_range(start..end)
+                                                .expect("store read_range failure")
+                                        })
+                                        .collect();
+                                    let columns: Vec<Fr> = (0..hashes_batch.len())
+                                        .flat_map(|j| {
+                                            rows.iter().map(move |row| -> Fr { row[j].into() })
+                                        })
+                                        .collect();
+
+                                    for (hash, column_hash) in hashes_batch
+                                        .iter_mut()
+                                        .zip(hash_column_batch(&columns, layers))
+                                    {
+                                        *hash = column_hash.into();
+                                    }
+                                }
+                                return;
+                            }
+
                             for (j, hash) in hashes_chunk.iter_mut().enumerate() {
                                 let data: Vec<_> = (1..=layers)
                                     .map(|layer| {
@@ -767,16 +945,25 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 });
 
                 info!("building base tree_c {}/{}", i + 1, tree_count);
-                trees.push(
+
+                // Remove the tree_c store if it exists already, it may be left over from an
+                // interrupted run.
+                resume::clear_complete(config)?;
+                let tree_c_store_path = StoreConfig::data_path(&config.path, &config.id);
+                if Path::new(&tree_c_store_path).exists() {
+                    fs::remove_file(&tree_c_store_path).with_context(|| {
+                        format!("failed to remove tree_c store {:?}", tree_c_store_path)
+                    })?;
+                }
+
+                let tree =
                     DiskTree::<Tree::Hasher, Tree::Arity, U0, U0>::from_par_iter_with_config(
                         hashes.into_par_iter(),
                         config.clone(),
-                    ),
-                );
+                    )?;
+                resume::mark_complete(config, &tree.root())?;
             }
 
-            assert_eq!(tree_count, trees.len());
-
             create_disk_tree::<
                 DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
             >(configs[0].size.expect("config size failure"), &configs)
@@ -785,11 +972,12 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
     #[cfg(any(feature = "gpu"))]
     fn generate_tree_r_last<TreeArity>(
-        data: &mut Data<'_>,
+        replica: &mut Replica<'_>,
         nodes_count: usize,
         tree_count: usize,
         tree_r_last_config: StoreConfig,
         replica_path: PathBuf,
+        pending: &[usize],
         labels: &LabelsCache<Tree>,
     ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
@@ -797,20 +985,22 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
     {
         if SETTINGS.use_gpu_tree_builder {
             Self::generate_tree_r_last_gpu::<TreeArity>(
-                data,
+                replica,
                 nodes_count,
                 tree_count,
                 tree_r_last_config,
                 replica_path,
+                pending,
                 labels,
             )
         } else {
             Self::generate_tree_r_last_cpu::<TreeArity>(
-                data,
+                replica,
                 nodes_count,
                 tree_count,
                 tree_r_last_config,
                 replica_path,
+                pending,
                 labels,
             )
         }
@@ -818,51 +1008,54 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
     #[cfg(not(any(feature = "gpu")))]
     fn generate_tree_r_last<TreeArity>(
-        data: &mut Data<'_>,
+        replica: &mut Replica<'_>,
         nodes_count: usize,
         tree_count: usize,
         tree_r_last_config: StoreConfig,
         replica_path: PathBuf,
+        pending: &[usize],
         labels: &LabelsCache<Tree>,
     ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         TreeArity: PoseidonArity,
     {
         Self::generate_tree_r_last_cpu::<TreeArity>(
-            data,
+            replica,
             nodes_count,
             tree_count,
             tree_r_last_config,
             replica_path,
+            pending,
             labels,
         )
     }
 
     #[cfg(any(feature = "gpu"))]
     fn generate_tree_r_last_gpu<TreeArity>(
-        data: &mut Data<'_>,
+        replica: &mut Replica<'_>,
         nodes_count: usize,
         tree_count: usize,
         tree_r_last_config: StoreConfig,
         replica_path: PathBuf,
+        pending: &[usize],
         labels: &LabelsCache<Tree>,
     ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         TreeArity: PoseidonArity,
     {
         use std::cmp::min;
-        use std::fs::OpenOptions;
-        use std::io::Write;
         use std::sync::mpsc::sync_channel;
 
         use bellperson::bls::Fr;
         use fr32::fr_into_bytes;
-        use merkletree::merkle::{get_merkle_tree_cache_size, get_merkle_tree_leafs};
+        use merkletree::merkle::get_merkle_tree_leafs;
         use neptune::{
             batch_hasher::BatcherType,
             tree_builder::{TreeBuilder, TreeBuilderTrait},
         };
 
+        use crate::direct_io;
+
         let (configs, replica_config) = split_config_and_replica(
             tree_r_last_config.clone(),
             replica_path,
@@ -870,7 +1063,6 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             tree_count,
         )?;
 
-        data.ensure_data()?;
         let last_layer_labels = labels.labels_for_last_layer()?;
 
         info!("generating tree r last using the GPU");
@@ -878,15 +1070,17 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
         // This channel will receive batches of leaf nodes and add them to the TreeBuilder.
         let (builder_tx, builder_rx) = sync_channel::<(Vec<Fr>, bool)>(0);
-        let config_count = configs.len(); // Don't move config into closure below.
         let configs = &configs;
         let tree_r_last_config = &tree_r_last_config;
+        let context = &ThreadContext::current();
         rayon::scope(|s| {
+            let _context = context.enter();
             // This channel will receive the finished tree data to be written to disk.
             let (writer_tx, writer_rx) = sync_channel::<Vec<Fr>>(0);
 
             s.spawn(move |_| {
-                for i in 0..config_count {
+                let _context = context.enter();
+                for &i in pending {
                     let mut node_index = 0;
                     while node_index != nodes_count {
                         let chunked_nodes_count =
@@ -904,6 +1098,9 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                             end,
                         );
 
+                        let mut region = replica
+                            .region(start, end)
+                            .expect("failed to read replica region");
                         let encoded_data = {
                             use fr32::bytes_into_fr;
 
@@ -919,10 +1116,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                                 .map(|chunk| {
                                     bytes_into_fr(&chunk).expect("Could not create Fr from bytes.")
                                 })
-                                .zip(
-                                    data.as_mut()[(start * NODE_SIZE)..(end * NODE_SIZE)]
-                                        .par_chunks_mut(NODE_SIZE),
-                                )
+                                .zip(region.par_chunks_mut(NODE_SIZE))
                                 .map(|(key, data_node_bytes)| {
                                     let data_node =
                                         <Tree::Hasher as Hasher>::Domain::try_from_bytes(
@@ -951,6 +1145,11 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
                         let encoded: Vec<_> =
                             encoded_data.into_par_iter().map(|x| x.into()).collect();
+                        // The sub-tree is marked complete after its last batch is built, so
+                        // every batch is written back before.
+                        region
+                            .write_back()
+                            .expect("failed to write back replica region");
 
                         let is_final = node_index == nodes_count;
                         builder_tx
@@ -960,6 +1159,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 }
             });
             s.spawn(move |_| {
+                let _context = context.enter();
                 let _gpu_lock = GPU_LOCK.lock().expect("failed to get gpu lock");
                 let mut tree_builder = TreeBuilder::<Tree::Arity>::new(
                     Some(BatcherType::OpenCL),
@@ -969,17 +1169,18 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 )
                 .expect("failed to create TreeBuilder");
 
-                // Loop until all trees for all configs have been built.
-                for i in 0..config_count {
+                // Loop until all trees for all pending configs have been built.
+                for &i in pending {
                     loop {
                         let (encoded, is_final) =
                             builder_rx.recv().expect("failed to recv encoded data");
 
                         // Just add non-final leaf batches.
                         if !is_final {
-                            tree_builder
-                                .add_leaves(&encoded)
-                                .expect("failed to add leaves");
+                            measure_op(Operation::GpuTreeBatch, || {
+                                tree_builder.add_leaves(&encoded)
+                            })
+                            .expect("failed to add leaves");
                             continue;
                         };
 
@@ -989,9 +1190,10 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                             i + 1,
                             tree_count
                         );
-                        let (_, tree_data) = tree_builder
-                            .add_final_leaves(&encoded)
-                            .expect("failed to add final leaves");
+                        let (_, tree_data) = measure_op(Operation::GpuTreeBatch, || {
+                            tree_builder.add_final_leaves(&encoded)
+                        })
+                        .expect("failed to add final leaves");
 
                         writer_tx.send(tree_data).expect("failed to send tree_data");
                         break;
@@ -999,12 +1201,15 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 }
             });
 
-            for config in configs.iter() {
+            for &i in pending {
+                let config = &configs[i];
                 let tree_data = writer_rx
                     .recv()
                     .expect("failed to receive tree_data for tree_r_last");
 
                 let tree_data_len = tree_data.len();
+                let root: <Tree::Hasher as Hasher>::Domain =
+                    (*tree_data.last().expect("tree_r_last data is empty")).into();
                 let cache_size = get_merkle_tree_cache_size(
                     get_merkle_tree_leafs(
                         config.size.expect("config size failure"),
@@ -1030,13 +1235,10 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     config.rows_to_discard,
                     tree_r_last_path
                 );
-                let mut f = OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .open(&tree_r_last_path)
-                    .expect("failed to open file for tree_r_last");
-                f.write_all(&flat_tree_data)
-                    .expect("failed to wrote tree_r_last data");
+                resume::clear_complete(config).expect("failed to clear tree_r_last marker");
+                direct_io::write_file(&tree_r_last_path, &flat_tree_data)
+                    .expect("failed to write tree_r_last data");
+                resume::mark_complete(config, &root).expect("failed to mark tree_r_last complete");
             }
         });
 
@@ -1048,11 +1250,12 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
     }
 
     fn generate_tree_r_last_cpu<TreeArity>(
-        data: &mut Data<'_>,
+        replica: &mut Replica<'_>,
         nodes_count: usize,
         tree_count: usize,
         tree_r_last_config: StoreConfig,
         replica_path: PathBuf,
+        pending: &[usize],
         labels: &LabelsCache<Tree>,
     ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
@@ -1065,31 +1268,36 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             tree_count,
         )?;
 
-        data.ensure_data()?;
         let last_layer_labels = labels.labels_for_last_layer()?;
 
         info!("generating tree r last using the CPU");
         let size = Store::len(last_layer_labels);
 
-        let mut start = 0;
-        let mut end = size / tree_count;
+        for &i in pending {
+            let config = &configs[i];
+            let start = i * (size / tree_count);
+            let end = start + size / tree_count;
 
-        for (i, config) in configs.iter().enumerate() {
-            let encoded_data = last_layer_labels
+            // The region is encoded in place and written back before the sub-tree is built from
+            // it, so that it is persisted once the sub-tree is marked complete.
+            let mut region = replica.region(start, end)?;
+            last_layer_labels
                 .read_range(start..end)?
                 .into_par_iter()
-                .zip(
-                    data.as_mut()[(start * NODE_SIZE)..(end * NODE_SIZE)].par_chunks_mut(NODE_SIZE),
-                )
-                .map(|(key, data_node_bytes)| {
+                .zip(region.par_chunks_mut(NODE_SIZE))
+                .try_for_each(|(key, data_node_bytes)| -> Result<()> {
                     let data_node =
-                        <Tree::Hasher as Hasher>::Domain::try_from_bytes(data_node_bytes)
-                            .expect("try from bytes failed");
+                        <Tree::Hasher as Hasher>::Domain::try_from_bytes(data_node_bytes)?;
                     let encoded_node = encode::<<Tree::Hasher as Hasher>::Domain>(key, data_node);
                     data_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&encoded_node));
 
-                    encoded_node
-                });
+                    Ok(())
+                })?;
+            region.write_back()?;
+            let encoded_data = region.par_chunks(NODE_SIZE).map(|node| {
+                <Tree::Hasher as Hasher>::Domain::try_from_bytes(node)
+                    .expect("try from bytes failed")
+            });
 
             info!(
                 "building base tree_r_last with CPU {}/{}",
@@ -1105,19 +1313,18 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 tree_r_last_store_path,
                 tree_r_last_store_exists
             );
+            resume::clear_complete(config)?;
             if tree_r_last_store_exists {
                 std::fs::remove_file(&tree_r_last_store_path)
                     .expect("failed to remove tree_r_last_store_path");
             }
 
-            LCTree::<Tree::Hasher, Tree::Arity, U0, U0>::from_par_iter_with_config(
+            let tree = LCTree::<Tree::Hasher, Tree::Arity, U0, U0>::from_par_iter_with_config(
                 encoded_data,
                 config.clone(),
             )
             .with_context(|| format!("failed tree_r_last CPU {}/{}", i + 1, tree_count))?;
-
-            start = end;
-            end += size / tree_count;
+            resume::mark_complete(config, &tree.root())?;
         }
 
         create_lc_tree::<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>(
@@ -1232,6 +1439,29 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             LabelsCache::<Tree>::new(&label_configs).context("failed to create labels cache")?;
         let configs = split_config(tree_c_config.clone(), tree_count)?;
 
+        // Sub-trees which were completed by an interrupted run are reused. The replica regions
+        // of completed tree_r_last sub-trees are already encoded, so they can only be reused
+        // when tree_d is given, as it would otherwise be built over the encoded data.
+        let tree_c_pending = resume::pending_sub_trees::<<Tree::Hasher as Hasher>::Domain>(
+            "tree_c",
+            &configs,
+            tree_c_config.size.expect("config size failure"),
+        )?;
+        let data_tree_given = data_tree.is_some();
+        let tree_r_last_pending = if data_tree_given {
+            resume::pending_sub_trees::<<Tree::Hasher as Hasher>::Domain>(
+                "tree_r_last",
+                &split_config(tree_r_last_config.clone(), tree_count)?,
+                get_merkle_tree_cache_size(
+                    nodes_count,
+                    Tree::Arity::to_usize(),
+                    tree_r_last_config.rows_to_discard,
+                )?,
+            )?
+        } else {
+            (0..tree_count).collect()
+        };
+
         match raise_fd_limit() {
             Some(res) => {
                 info!("Building trees [{} descriptors max available]", res);
@@ -1239,40 +1469,26 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             None => error!("Failed to raise the fd limit"),
         };
 
-        let tree_c_root = match layers {
-            2 => {
-                let tree_c = Self::generate_tree_c::<U2, Tree::Arity>(
-                    layers,
-                    nodes_count,
-                    tree_count,
-                    configs,
-                    &labels,
-                )?;
-                tree_c.root()
-            }
-            8 => {
-                let tree_c = Self::generate_tree_c::<U8, Tree::Arity>(
-                    layers,
-                    nodes_count,
-                    tree_count,
-                    configs,
-                    &labels,
-                )?;
-                tree_c.root()
-            }
-            11 => {
-                let tree_c = Self::generate_tree_c::<U11, Tree::Arity>(
-                    layers,
-                    nodes_count,
-                    tree_count,
-                    configs,
-                    &labels,
-                )?;
-                tree_c.root()
-            }
-            _ => panic_any("Unsupported column arity"),
+        // The pipelined builder builds tree_c and tree_r_last on the CPU in one pass, after
+        // tree_d. It is not used if either tree is built on the GPU.
+        let gpu_tree_builder = cfg!(feature = "gpu")
+            && (SETTINGS.use_gpu_column_builder || SETTINGS.use_gpu_tree_builder);
+        let pipelined = SETTINGS.use_pipelined_tree_builder && !gpu_tree_builder;
+
+        let tree_c_root = if pipelined {
+            None
+        } else {
+            let tree_c_root = Self::generate_tree_c_root(
+                layers,
+                nodes_count,
+                tree_count,
+                configs,
+                &tree_c_pending,
+                &labels,
+            )?;
+            info!("tree_c done");
+            Some(tree_c_root)
         };
-        info!("tree_c done");
 
         // Build the MerkleTree over the original data (if needed).
         let tree_d = match data_tree {
@@ -1296,26 +1512,69 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             tree_d.len()
         );
         let tree_d_root = tree_d.root();
+
+        let mut replica = Replica::new(&mut data)?;
+
+        // An interrupted run may have encoded parts of the replica regions of the tree_r_last
+        // sub-trees which are built again, so their original data is restored from tree_d. This
+        // is done even if no sub-tree was completed, as the run may have been interrupted while
+        // building the first one.
+        if data_tree_given {
+            for &i in &tree_r_last_pending {
+                let (start, end) = (i * nodes_count, (i + 1) * nodes_count);
+                let mut region = replica.region(start, end)?;
+                tree_d
+                    .read_range_into(start, end, &mut region)
+                    .context("failed to restore replica data from tree_d")?;
+                region.write_back()?;
+            }
+        }
         drop(tree_d);
 
-        // Encode original data into the last layer.
-        info!("building tree_r_last");
-        let tree_r_last = measure_op(Operation::GenerateTreeRLast, || {
-            Self::generate_tree_r_last::<Tree::Arity>(
-                &mut data,
-                nodes_count,
-                tree_count,
-                tree_r_last_config.clone(),
-                replica_path.clone(),
-                &labels,
-            )
-            .context("failed to generate tree_r_last")
-        })?;
-        info!("tree_r_last done");
+        let (tree_c_root, tree_r_last_root) = match tree_c_root {
+            Some(tree_c_root) => {
+                // Encode original data into the last layer.
+                info!("building tree_r_last");
+                let tree_r_last = measure_op(Operation::GenerateTreeRLast, || {
+                    Self::generate_tree_r_last::<Tree::Arity>(
+                        &mut replica,
+                        nodes_count,
+                        tree_count,
+                        tree_r_last_config.clone(),
+                        replica_path.clone(),
+                        &tree_r_last_pending,
+                        &labels,
+                    )
+                    .context("failed to generate tree_r_last")
+                })?;
+                info!("tree_r_last done");
 
-        let tree_r_last_root = tree_r_last.root();
-        drop(tree_r_last);
+                (tree_c_root, tree_r_last.root())
+            }
+            None => {
+                info!("building tree_c and tree_r_last");
+                let roots = measure_op(Operation::GenerateTreesPipelined, || {
+                    tree_pipeline::generate_trees::<Tree>(
+                        &mut replica,
+                        layers,
+                        nodes_count,
+                        tree_count,
+                        tree_c_config.clone(),
+                        tree_r_last_config.clone(),
+                        replica_path.clone(),
+                        &tree_c_pending,
+                        &tree_r_last_pending,
+                        &labels,
+                    )
+                    .context("failed to generate tree_c and tree_r_last")
+                })?;
+                info!("tree_c and tree_r_last done");
+
+                roots
+            }
+        };
 
+        drop(replica);
         data.drop_data();
 
         // comm_r = H(comm_c || comm_r_last)
@@ -1347,14 +1606,30 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         replica_id: &<Tree::Hasher as Hasher>::Domain,
         config: StoreConfig,
     ) -> Result<Labels<Tree>> {
+        Self::replicate_phase1_with_placement(pp, replica_id, config, &SdrPlacement::Auto)
+            .map(|(labels, _)| labels)
+    }
+
+    /// Phase1 of replication, running the multicore labelling according to `placement`.
+    pub fn replicate_phase1_with_placement(
+        pp: &'a PublicParams<Tree>,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        config: StoreConfig,
+        placement: &SdrPlacement,
+    ) -> Result<(Labels<Tree>, SdrPlacementReport)> {
         info!("replicate_phase1");
 
-        let labels = measure_op(Operation::EncodeWindowTimeAll, || {
-            Self::generate_labels_for_encoding(&pp.graph, &pp.layer_challenges, replica_id, config)
-        })?
-        .0;
+        let (labels, _, report) = measure_op(Operation::EncodeWindowTimeAll, || {
+            Self::generate_labels_for_encoding_with_placement(
+                &pp.graph,
+                &pp.layer_challenges,
+                replica_id,
+                config,
+                placement,
+            )
+        })?;
 
-        Ok(labels)
+        Ok((labels, report))
     }
 
     /// Phase2 of replication.
@@ -1385,6 +1660,66 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         Ok((tau, (paux, taux)))
     }
 
+    /// Rebuilds tree_c from the layer labels persisted in `config.path`, replacing any existing
+    /// tree_c stores there. This allows a sector's cache to be repaired after tree_c was lost,
+    /// as long as the labels survived. Returns the root of the rebuilt tree (comm_c).
+    pub fn regenerate_tree_c(
+        pp: &'a PublicParams<Tree>,
+        config: StoreConfig,
+    ) -> Result<<Tree::Hasher as Hasher>::Domain> {
+        Self::regenerate_tree_c_with_id(pp, config, &CacheKey::CommCTree.to_string())
+    }
+
+    /// Like `regenerate_tree_c`, but writes the tree_c stores with the id `tree_c_id`, e.g. so
+    /// that the existing ones are only replaced once the rebuilt ones are known to be good.
+    pub fn regenerate_tree_c_with_id(
+        pp: &'a PublicParams<Tree>,
+        config: StoreConfig,
+        tree_c_id: &str,
+    ) -> Result<<Tree::Hasher as Hasher>::Domain> {
+        info!("regenerate_tree_c");
+
+        let layers = pp.layer_challenges.layers();
+        let tree_count = get_base_tree_count::<Tree>();
+        let nodes_count = pp.graph.size() / tree_count;
+
+        let label_configs = Labels::<Tree>::new(
+            (1..=layers)
+                .map(|layer| {
+                    StoreConfig::from_config(
+                        &config,
+                        CacheKey::label_layer(layer),
+                        Some(pp.graph.size()),
+                    )
+                })
+                .collect(),
+        );
+        let labels =
+            LabelsCache::<Tree>::new(&label_configs).context("failed to create labels cache")?;
+
+        let mut tree_c_config = StoreConfig::from_config(
+            &config,
+            tree_c_id,
+            Some(get_merkle_tree_len(nodes_count, Tree::Arity::to_usize())?),
+        );
+        tree_c_config.rows_to_discard =
+            default_rows_to_discard(nodes_count, Tree::Arity::to_usize());
+        let configs = split_config(tree_c_config, tree_count)?;
+
+        // Remove the tree_c stores if they exist already, so they are rebuilt from scratch.
+        for config in &configs {
+            let tree_c_store_path = StoreConfig::data_path(&config.path, &config.id);
+            if Path::new(&tree_c_store_path).exists() {
+                fs::remove_file(&tree_c_store_path).with_context(|| {
+                    format!("failed to remove tree_c store {:?}", tree_c_store_path)
+                })?;
+            }
+        }
+        let pending: Vec<usize> = (0..tree_count).collect();
+
+        Self::generate_tree_c_root(layers, nodes_count, tree_count, configs, &pending, &labels)
+    }
+
     // Assumes data is all zeros.
     // Replica path is used to create configs, but is not read.
     // Instead new zeros are provided (hence the need for replica to be all zeros).
@@ -1404,7 +1739,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         use bellperson::bls::Fr;
         use ff::Field;
         use fr32::fr_into_bytes;
-        use merkletree::merkle::{get_merkle_tree_cache_size, get_merkle_tree_leafs};
+        use merkletree::merkle::get_merkle_tree_leafs;
         use neptune::{
             batch_hasher::BatcherType,
             tree_builder::{TreeBuilder, TreeBuilderTrait},
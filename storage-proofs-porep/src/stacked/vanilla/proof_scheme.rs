@@ -89,47 +89,57 @@ impl<'a, 'c, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> ProofScheme<'
             return Ok(false);
         };
 
-        let res = partition_proofs.par_iter().enumerate().all(|(k, proofs)| {
-            trace!(
-                "verifying partition proof {}/{}",
-                k + 1,
-                partition_proofs.len()
-            );
-
-            trace!("verify comm_r");
-            let actual_comm_r: <Tree::Hasher as Hasher>::Domain = {
-                let comm_c = proofs[0].comm_c();
-                let comm_r_last = proofs[0].comm_r_last();
-                <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &comm_r_last)
-            };
-
-            if expected_comm_r != &actual_comm_r {
-                return false;
-            }
-
-            let challenges =
-                pub_inputs.challenges(&pub_params.layer_challenges, graph.size(), Some(k));
-
-            proofs.par_iter().enumerate().all(|(i, proof)| {
-                trace!("verify challenge {}/{}", i + 1, challenges.len());
-
-                // Validate for this challenge
-                let challenge = challenges[i];
-
-                // make sure all proofs have the same comm_c
-                if proof.comm_c() != proofs[0].comm_c() {
-                    return false;
-                }
-                // make sure all proofs have the same comm_r_last
-                if proof.comm_r_last() != proofs[0].comm_r_last() {
-                    return false;
+        // A partition, or a challenge in it, is invalid or fails to be checked as soon as one
+        // does.
+        partition_proofs
+            .par_iter()
+            .enumerate()
+            .map(|(k, proofs)| -> Result<bool> {
+                trace!(
+                    "verifying partition proof {}/{}",
+                    k + 1,
+                    partition_proofs.len()
+                );
+
+                trace!("verify comm_r");
+                let actual_comm_r: <Tree::Hasher as Hasher>::Domain = {
+                    let comm_c = proofs[0].comm_c();
+                    let comm_r_last = proofs[0].comm_r_last();
+                    <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &comm_r_last)
+                };
+
+                if expected_comm_r != &actual_comm_r {
+                    return Ok(false);
                 }
 
-                proof.verify(pub_params, pub_inputs, challenge, graph)
+                let challenges =
+                    pub_inputs.challenges(&pub_params.layer_challenges, graph.size(), Some(k));
+
+                proofs
+                    .par_iter()
+                    .enumerate()
+                    .map(|(i, proof)| -> Result<bool> {
+                        trace!("verify challenge {}/{}", i + 1, challenges.len());
+
+                        // Validate for this challenge
+                        let challenge = challenges[i];
+
+                        // make sure all proofs have the same comm_c
+                        if proof.comm_c() != proofs[0].comm_c() {
+                            return Ok(false);
+                        }
+                        // make sure all proofs have the same comm_r_last
+                        if proof.comm_r_last() != proofs[0].comm_r_last() {
+                            return Ok(false);
+                        }
+
+                        proof.verify(pub_params, pub_inputs, challenge, graph)
+                    })
+                    .find_any(|valid| !matches!(valid, Ok(true)))
+                    .unwrap_or(Ok(true))
             })
-        });
-
-        Ok(res)
+            .find_any(|valid| !matches!(valid, Ok(true)))
+            .unwrap_or(Ok(true))
     }
 
     fn with_partition(pub_in: Self::PublicInputs, k: Option<usize>) -> Self::PublicInputs {
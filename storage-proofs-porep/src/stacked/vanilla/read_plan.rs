@@ -0,0 +1,413 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context};
+use filecoin_hashers::Hasher;
+use generic_array::typenum::Unsigned;
+use merkletree::{
+    merkle::{get_merkle_tree_cache_size, get_merkle_tree_leafs},
+    store::StoreConfig,
+};
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::{
+    drgraph::Graph,
+    error::Result,
+    merkle::{get_base_tree_count, split_config, MerkleTreeTrait},
+    util::NODE_SIZE,
+};
+
+use crate::stacked::vanilla::{
+    params::{PublicInputs, PublicParams, TemporaryAux, BINARY_ARITY},
+    StackedBucketGraph,
+};
+
+/// A file which is read when proving.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ReadTarget {
+    /// A file within the cache directory, given relative to it.
+    Cache(PathBuf),
+    /// The replica, i.e. the sealed sector.
+    Replica,
+}
+
+impl ReadTarget {
+    /// The path of the target, given the cache directory and the replica path.
+    pub fn path(&self, cache_path: &Path, replica_path: &Path) -> PathBuf {
+        match self {
+            ReadTarget::Cache(name) => cache_path.join(name),
+            ReadTarget::Replica => replica_path.to_path_buf(),
+        }
+    }
+}
+
+/// The byte ranges which are read from a single file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileReads {
+    pub target: ReadTarget,
+    /// The size of the whole file in bytes.
+    pub len: u64,
+    /// Sorted, non-overlapping and non-adjacent byte ranges.
+    pub ranges: Vec<Range<u64>>,
+}
+
+impl FileReads {
+    /// The number of bytes read from the file.
+    pub fn bytes(&self) -> u64 {
+        self.ranges
+            .iter()
+            .map(|range| range.end - range.start)
+            .sum()
+    }
+}
+
+/// The files and byte ranges which are read when proving, see `add_commit_reads`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadPlan {
+    pub files: Vec<FileReads>,
+}
+
+impl ReadPlan {
+    /// The number of bytes read from all files.
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(FileReads::bytes).sum()
+    }
+
+    /// Copies the planned ranges from the cache directory and replica into a sparse copy of
+    /// them. All files are created with their full size, everything outside of the planned
+    /// ranges is left as a hole.
+    pub fn write_sparse_copy(
+        &self,
+        cache_path: &Path,
+        replica_path: &Path,
+        sparse_cache_path: &Path,
+        sparse_replica_path: &Path,
+    ) -> Result<()> {
+        fs::create_dir_all(sparse_cache_path)
+            .with_context(|| format!("could not create {:?}", sparse_cache_path))?;
+
+        let mut buf = Vec::new();
+        for file in &self.files {
+            let src_path = file.target.path(cache_path, replica_path);
+            let dst_path = file.target.path(sparse_cache_path, sparse_replica_path);
+
+            let mut src =
+                File::open(&src_path).with_context(|| format!("could not open {:?}", src_path))?;
+            let src_len = src.metadata()?.len();
+            ensure!(
+                src_len == file.len,
+                "{:?} has size {}, expected {}",
+                src_path,
+                src_len,
+                file.len
+            );
+
+            let mut dst = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&dst_path)
+                .with_context(|| format!("could not create {:?}", dst_path))?;
+            dst.set_len(file.len)?;
+
+            for range in &file.ranges {
+                buf.resize((range.end - range.start) as usize, 0);
+                src.seek(SeekFrom::Start(range.start))?;
+                src.read_exact(&mut buf)
+                    .with_context(|| format!("could not read {:?} from {:?}", range, src_path))?;
+                dst.seek(SeekFrom::Start(range.start))?;
+                dst.write_all(&buf)
+                    .with_context(|| format!("could not write {:?} to {:?}", range, dst_path))?;
+            }
+            dst.sync_all()?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that all planned files exist with their full size.
+    pub fn verify_files(&self, cache_path: &Path, replica_path: &Path) -> Result<()> {
+        for file in &self.files {
+            let path = file.target.path(cache_path, replica_path);
+            let len = fs::metadata(&path)
+                .with_context(|| format!("missing file {:?}", path))?
+                .len();
+            ensure!(
+                len == file.len,
+                "{:?} has size {}, expected {}",
+                path,
+                len,
+                file.len
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Collects the reads of a `ReadPlan`.
+#[derive(Debug, Default)]
+pub struct ReadPlanBuilder {
+    files: BTreeMap<ReadTarget, (u64, Vec<Range<u64>>)>,
+}
+
+impl ReadPlanBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a file of `len` bytes. Registering the same file again with the same size is a
+    /// no-op.
+    pub fn add_file(&mut self, target: ReadTarget, len: u64) -> Result<()> {
+        if let Some((existing_len, _)) = self.files.get(&target) {
+            ensure!(
+                *existing_len == len,
+                "{:?} registered with sizes {} and {}",
+                target,
+                existing_len,
+                len
+            );
+            return Ok(());
+        }
+        self.files.insert(target, (len, Vec::new()));
+
+        Ok(())
+    }
+
+    /// Adds a byte range of a registered file.
+    pub fn add_range(&mut self, target: &ReadTarget, range: Range<u64>) -> Result<()> {
+        let (len, ranges) = match self.files.get_mut(target) {
+            Some(file) => file,
+            None => bail!("{:?} is not registered", target),
+        };
+        ensure!(
+            range.start < range.end && range.end <= *len,
+            "invalid range {:?} for {:?} of size {}",
+            range,
+            target,
+            len
+        );
+        ranges.push(range);
+
+        Ok(())
+    }
+
+    /// Adds `count` nodes of a registered file, starting at node `first`.
+    pub fn add_nodes(&mut self, target: &ReadTarget, first: usize, count: usize) -> Result<()> {
+        self.add_range(
+            target,
+            (first * NODE_SIZE) as u64..((first + count) * NODE_SIZE) as u64,
+        )
+    }
+
+    /// Adds a whole registered file.
+    pub fn add_whole_file(&mut self, target: &ReadTarget) -> Result<()> {
+        let len = match self.files.get(target) {
+            Some((len, _)) => *len,
+            None => bail!("{:?} is not registered", target),
+        };
+        if len > 0 {
+            self.add_range(target, 0..len)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sorts and merges the collected ranges.
+    pub fn build(self) -> ReadPlan {
+        let files = self
+            .files
+            .into_iter()
+            .map(|(target, (len, mut ranges))| {
+                ranges.sort_by_key(|range| range.start);
+
+                let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+                for range in ranges {
+                    match merged.last_mut() {
+                        Some(last) if range.start <= last.end => {
+                            last.end = last.end.max(range.end);
+                        }
+                        _ => merged.push(range),
+                    }
+                }
+
+                FileReads {
+                    target,
+                    len,
+                    ranges: merged,
+                }
+            })
+            .collect();
+
+        ReadPlan { files }
+    }
+}
+
+fn cache_target(config: &StoreConfig) -> ReadTarget {
+    ReadTarget::Cache(StoreConfig::data_path(&PathBuf::new(), &config.id))
+}
+
+/// Adds the nodes read when generating an inclusion proof for `leaf` in a base tree with `leafs`
+/// leaves. The rows of the tree are stored back to back in `target`, starting with row
+/// `first_row` (the leaves being row 0); rows below it are not read from `target`.
+fn add_tree_path(
+    builder: &mut ReadPlanBuilder,
+    target: &ReadTarget,
+    leafs: usize,
+    arity: usize,
+    first_row: usize,
+    leaf: usize,
+) -> Result<()> {
+    let mut row = 0;
+    let mut row_start = 0;
+    let mut row_len = leafs;
+    let mut index = leaf;
+
+    while row_len > 1 {
+        if row >= first_row {
+            builder.add_nodes(target, row_start + (index / arity) * arity, arity)?;
+            row_start += row_len;
+        }
+        row += 1;
+        row_len /= arity;
+        index /= arity;
+    }
+
+    // The root.
+    builder.add_nodes(target, row_start, 1)
+}
+
+/// Adds the reads which `StackedDrg::prove_all_partitions` does for `partition_count`
+/// partitions, given the replica id and seed in `pub_inputs`. This covers the labels, `tree_d`,
+/// `tree_c` and `tree_r_last` files described by `t_aux`, as well as the replica.
+///
+/// # Arguments
+///
+/// * `builder` - the builder to add the reads to.
+/// * `pub_params` - the public parameters of the sector.
+/// * `pub_inputs` - the public inputs; only `replica_id` and `seed` are used.
+/// * `t_aux` - the temporary aux of the sector.
+/// * `partition_count` - the number of partitions which are proven.
+pub fn add_commit_reads<Tree: 'static + MerkleTreeTrait, G: Hasher>(
+    builder: &mut ReadPlanBuilder,
+    pub_params: &PublicParams<Tree>,
+    pub_inputs: &PublicInputs<<Tree::Hasher as Hasher>::Domain, G::Domain>,
+    t_aux: &TemporaryAux<Tree, G>,
+    partition_count: usize,
+) -> Result<()> {
+    let graph: &StackedBucketGraph<Tree::Hasher> = &pub_params.graph;
+    let nodes = graph.size();
+    let arity = Tree::Arity::to_usize();
+    let tree_count = get_base_tree_count::<Tree>();
+    let base_leafs = nodes / tree_count;
+    ensure!(
+        base_leafs * tree_count == nodes,
+        "nodes must be divisible by the base tree count"
+    );
+
+    let mut challenges = BTreeSet::new();
+    let mut columns = BTreeSet::new();
+    let mut drg_parents = vec![0; graph.base_graph().degree()];
+    let mut exp_parents = vec![0; graph.expansion_degree()];
+    for k in 0..partition_count {
+        for challenge in pub_inputs.challenges(&pub_params.layer_challenges, nodes, Some(k)) {
+            graph.base_parents(challenge, &mut drg_parents)?;
+            graph.expanded_parents(challenge, &mut exp_parents)?;
+
+            challenges.insert(challenge);
+            columns.insert(challenge);
+            columns.extend(drg_parents.iter().map(|parent| *parent as usize));
+            columns.extend(exp_parents.iter().map(|parent| *parent as usize));
+        }
+    }
+
+    // The labels of every layer at the challenged nodes and their parents.
+    for config in &t_aux.labels.labels {
+        let target = cache_target(config);
+        builder.add_file(target.clone(), (nodes * NODE_SIZE) as u64)?;
+        for column in &columns {
+            builder.add_nodes(&target, *column, 1)?;
+        }
+    }
+
+    // The inclusion proofs of the challenges in tree_d.
+    let tree_d_size = t_aux
+        .tree_d_config
+        .size
+        .context("tree_d config has no size")?;
+    let tree_d = cache_target(&t_aux.tree_d_config);
+    builder.add_file(tree_d.clone(), (tree_d_size * NODE_SIZE) as u64)?;
+    for challenge in &challenges {
+        add_tree_path(builder, &tree_d, nodes, BINARY_ARITY, 0, *challenge)?;
+    }
+
+    // The inclusion proofs of the columns in tree_c. Instantiating a tree with sub-trees reads
+    // the root of every base tree.
+    let tree_c_size = t_aux
+        .tree_c_config
+        .size
+        .context("tree_c config has no size")?;
+    let tree_c: Vec<_> = split_config(t_aux.tree_c_config.clone(), tree_count)?
+        .iter()
+        .map(cache_target)
+        .collect();
+    for target in &tree_c {
+        builder.add_file(target.clone(), (tree_c_size * NODE_SIZE) as u64)?;
+        builder.add_nodes(target, tree_c_size - 1, 1)?;
+    }
+    for column in &columns {
+        add_tree_path(
+            builder,
+            &tree_c[column / base_leafs],
+            base_leafs,
+            arity,
+            0,
+            column % base_leafs,
+        )?;
+    }
+
+    // The inclusion proofs of the challenges in tree_r_last. Only the rows above
+    // `rows_to_discard` are cached, the discarded ones are rebuilt from a segment of the
+    // replica.
+    let tree_r_last_size = t_aux
+        .tree_r_last_config
+        .size
+        .context("tree_r_last config has no size")?;
+    let rows_to_discard = t_aux.tree_r_last_config.rows_to_discard;
+    let cache_size = get_merkle_tree_cache_size(
+        get_merkle_tree_leafs(tree_r_last_size, arity)?,
+        arity,
+        rows_to_discard,
+    )?;
+    let segment_width = base_leafs / get_merkle_tree_leafs(cache_size, arity)?;
+    let tree_r_last: Vec<_> = split_config(t_aux.tree_r_last_config.clone(), tree_count)?
+        .iter()
+        .map(cache_target)
+        .collect();
+    for target in &tree_r_last {
+        builder.add_file(target.clone(), (cache_size * NODE_SIZE) as u64)?;
+        builder.add_nodes(target, cache_size - 1, 1)?;
+    }
+    builder.add_file(ReadTarget::Replica, (nodes * NODE_SIZE) as u64)?;
+    for challenge in &challenges {
+        let (tree, leaf) = (challenge / base_leafs, challenge % base_leafs);
+        let segment_start = (leaf / segment_width) * segment_width;
+        builder.add_nodes(
+            &ReadTarget::Replica,
+            tree * base_leafs + segment_start,
+            segment_width,
+        )?;
+        add_tree_path(
+            builder,
+            &tree_r_last[tree],
+            base_leafs,
+            arity,
+            rows_to_discard + 1,
+            leaf,
+        )?;
+    }
+
+    Ok(())
+}
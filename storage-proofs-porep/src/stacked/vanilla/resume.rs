@@ -0,0 +1,124 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use filecoin_hashers::Domain;
+use log::info;
+use merkletree::store::StoreConfig;
+use storage_proofs_core::{error::Result, util::NODE_SIZE};
+
+/// The marker written next to a sub-tree store once it is complete. It holds the root of the
+/// sub-tree.
+fn marker_path(config: &StoreConfig) -> PathBuf {
+    let mut path = StoreConfig::data_path(&config.path, &config.id).into_os_string();
+    path.push(".done");
+    path.into()
+}
+
+/// Records that the sub-tree store of `config` is complete and has the given root. The store is
+/// synced to disk before the marker is written.
+pub(crate) fn mark_complete<D: Domain>(config: &StoreConfig, root: &D) -> Result<()> {
+    let store_path = StoreConfig::data_path(&config.path, &config.id);
+    File::open(&store_path)
+        .and_then(|file| file.sync_all())
+        .with_context(|| format!("failed to sync {:?}", store_path))?;
+
+    let path = marker_path(config);
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, root.as_ref())
+        .with_context(|| format!("failed to write {:?}", tmp_path))?;
+    File::open(&tmp_path)
+        .and_then(|file| file.sync_all())
+        .with_context(|| format!("failed to sync {:?}", tmp_path))?;
+    fs::rename(&tmp_path, &path).with_context(|| format!("failed to write {:?}", path))?;
+
+    Ok(())
+}
+
+/// Removes the completion marker of `config`, if any. Must be called before the store is
+/// rebuilt.
+pub(crate) fn clear_complete(config: &StoreConfig) -> Result<()> {
+    let path = marker_path(config);
+    if path.exists() {
+        fs::remove_file(&path).with_context(|| format!("failed to remove {:?}", path))?;
+    }
+
+    Ok(())
+}
+
+/// Removes all completion markers in the cache directory `dir`. Must be called whenever the
+/// labels are regenerated, as every tree built from the old labels is stale.
+pub(crate) fn clear_all_complete(dir: &Path) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to read {:?}", dir))? {
+        let path = entry?.path();
+        let is_marker = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.ends_with(".dat.done"))
+            .unwrap_or(false);
+        if is_marker {
+            fs::remove_file(&path).with_context(|| format!("failed to remove {:?}", path))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the root of the sub-tree store of `config` if it is marked complete, holds `len`
+/// nodes and its last node is the recorded root.
+pub(crate) fn complete_root<D: Domain>(config: &StoreConfig, len: usize) -> Result<Option<D>> {
+    let path = marker_path(config);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let root = D::try_from_bytes(&fs::read(&path).with_context(|| format!("{:?}", path))?)?;
+
+    let store_path = StoreConfig::data_path(&config.path, &config.id);
+    let mut store = match File::open(&store_path) {
+        Ok(store) => store,
+        Err(_) => return Ok(None),
+    };
+    if store.metadata()?.len() != (len * NODE_SIZE) as u64 {
+        return Ok(None);
+    }
+
+    let mut last = [0u8; NODE_SIZE];
+    store.seek(SeekFrom::Start(((len - 1) * NODE_SIZE) as u64))?;
+    store.read_exact(&mut last)?;
+    if D::try_from_bytes(&last)? != root {
+        return Ok(None);
+    }
+
+    Ok(Some(root))
+}
+
+/// Returns the indices of the sub-trees in `configs` whose stores of `len` nodes are not
+/// complete and have to be (re)built.
+pub(crate) fn pending_sub_trees<D: Domain>(
+    name: &str,
+    configs: &[StoreConfig],
+    len: usize,
+) -> Result<Vec<usize>> {
+    let mut pending = Vec::with_capacity(configs.len());
+    for (i, config) in configs.iter().enumerate() {
+        if complete_root::<D>(config, len)?.is_none() {
+            pending.push(i);
+        }
+    }
+
+    if pending.len() < configs.len() {
+        info!(
+            "{}: reusing {}/{} completed sub-trees",
+            name,
+            configs.len() - pending.len(),
+            configs.len()
+        );
+    }
+
+    Ok(pending)
+}
@@ -0,0 +1,672 @@
+use std::cmp::max;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::sync_channel;
+
+use anyhow::{anyhow, Context};
+use bellperson::bls::Fr;
+use filecoin_hashers::{poseidon::BATCH_SIZE, Domain, HashFunction, Hasher};
+use generic_array::typenum::Unsigned;
+use log::{info, trace};
+use merkletree::{
+    hash::Algorithm,
+    merkle::get_merkle_tree_cache_size,
+    store::{Store, StoreConfig},
+};
+use rayon::prelude::{
+    IndexedParallelIterator, IntoParallelIterator, ParallelIterator, ParallelSlice,
+    ParallelSliceMut,
+};
+use storage_proofs_core::{
+    context::ThreadContext,
+    error::Result,
+    measurements::Operation,
+    merkle::{
+        create_disk_tree, create_lc_tree, split_config, split_config_and_replica, DiskTree, LCTree,
+        MerkleTreeTrait,
+    },
+    metrics::METRICS,
+    settings::SETTINGS,
+    util::NODE_SIZE,
+};
+
+use crate::{
+    direct_io::{DirectFile, Replica},
+    encode::encode,
+    stacked::vanilla::{
+        hash::{hash_column_batch, hash_single_column},
+        params::LabelsCache,
+        resume,
+    },
+};
+
+/// The number of batches each stage of the pipeline holds at most: one being received, one
+/// being worked on and one queued for the next stage.
+const BATCHES_IN_FLIGHT: usize = 3;
+
+/// The labels of one batch of nodes of a sub-tree. Holds all layers if tree_c is built for the
+/// sub-tree, only the last layer otherwise.
+struct LabelBatch {
+    tree: usize,
+    offset: usize,
+    build_tree_c: bool,
+    build_tree_r_last: bool,
+    layers: Vec<Vec<u8>>,
+}
+
+/// The rows of the sub-trees over one batch of nodes, from the leaves up to the batch root.
+struct RowBatch<D: Domain> {
+    tree: usize,
+    offset: usize,
+    tree_c: Option<Vec<Vec<D>>>,
+    tree_r_last: Option<Vec<Vec<D>>>,
+}
+
+/// Where each row of a sub-tree starts in its store, rows are stored leaves first.
+struct RowLayout {
+    arity: usize,
+    starts: Vec<usize>,
+}
+
+impl RowLayout {
+    fn new(leafs: usize, arity: usize) -> Self {
+        let mut starts = vec![0];
+        let mut width = leafs;
+        while width > 1 {
+            starts.push(starts[starts.len() - 1] + width);
+            width /= arity;
+        }
+
+        RowLayout { arity, starts }
+    }
+
+    fn tree_len(&self) -> usize {
+        self.starts[self.starts.len() - 1] + 1
+    }
+}
+
+/// A sub-tree store being written. Only the nodes at or after `skip` in the full tree are
+/// stored, which for tree_r_last are the cached rows.
+struct SubTreeStore<'a, D: Domain> {
+    config: &'a StoreConfig,
+    file: DirectFile,
+    skip: usize,
+    batch_roots: Vec<D>,
+}
+
+impl<'a, D: Domain> SubTreeStore<'a, D> {
+    fn create(config: &'a StoreConfig, len: usize, skip: usize) -> Result<Self> {
+        // Remove the store if it exists already, it may be left over from an interrupted run.
+        resume::clear_complete(config)?;
+        let path = StoreConfig::data_path(&config.path, &config.id);
+        if Path::new(&path).exists() {
+            fs::remove_file(&path).with_context(|| format!("failed to remove {:?}", path))?;
+        }
+
+        let file = DirectFile::create(&path)?;
+        file.set_len((len * NODE_SIZE) as u64)?;
+
+        Ok(SubTreeStore {
+            config,
+            file,
+            skip,
+            batch_roots: Vec::new(),
+        })
+    }
+
+    /// Writes `rows`, the first of which is at `level` and starts at `index` within its row.
+    fn write_rows(
+        &self,
+        layout: &RowLayout,
+        level: usize,
+        mut index: usize,
+        rows: &[Vec<D>],
+    ) -> Result<()> {
+        let mut buf = Vec::new();
+        for (row_level, row) in (level..).zip(rows) {
+            let pos = layout.starts[row_level] + index;
+            let first = self.skip.saturating_sub(pos).min(row.len());
+            if first < row.len() {
+                buf.clear();
+                for node in &row[first..] {
+                    buf.extend_from_slice(node.as_ref());
+                }
+                self.file
+                    .write_all_at(&buf, ((pos + first - self.skip) * NODE_SIZE) as u64)?;
+            }
+            index /= layout.arity;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the rows over the batch of leaves starting at `offset`.
+    fn add_batch(&mut self, layout: &RowLayout, offset: usize, rows: Vec<Vec<D>>) -> Result<()> {
+        self.write_rows(layout, 0, offset, &rows)?;
+        self.batch_roots.push(rows[rows.len() - 1][0]);
+
+        Ok(())
+    }
+
+    /// Writes the rows above the batch roots and marks the store complete.
+    fn finish<H: Hasher<Domain = D>>(
+        mut self,
+        layout: &RowLayout,
+        batch_level: usize,
+    ) -> Result<D> {
+        let batch_roots = std::mem::take(&mut self.batch_roots);
+        let rows = build_rows::<H>(batch_roots, layout.arity, batch_level)?;
+        self.write_rows(layout, batch_level + 1, 0, &rows[1..])?;
+        let root = rows[rows.len() - 1][0];
+
+        drop(self.file);
+        resume::mark_complete(self.config, &root)?;
+
+        Ok(root)
+    }
+}
+
+/// Returns the memory the batches in flight take per node of a batch.
+fn batch_node_memory(layers: usize) -> u64 {
+    // Per node, each batch holds the labels of all layers. The rows of tree_c and tree_r_last
+    // over a batch take a bit more than a node each.
+    (BATCHES_IN_FLIGHT * NODE_SIZE * (layers + 3)) as u64
+}
+
+/// Returns the memory the pipelined builder takes at most for its batches when building the
+/// sub-trees over `nodes_count` nodes each, with the current settings.
+pub fn pipelined_tree_builder_memory(nodes_count: usize, arity: usize, layers: usize) -> u64 {
+    tree_builder_batch_size(nodes_count, arity, layers) as u64 * batch_node_memory(layers)
+}
+
+/// Returns the number of nodes per batch the pipelined builder uses for the
+/// sub-trees over `nodes_count` nodes each, with labels of `layers` layers and the current
+/// settings.
+pub fn tree_builder_batch_size(nodes_count: usize, arity: usize, layers: usize) -> usize {
+    batch_size(
+        nodes_count,
+        arity,
+        layers,
+        SETTINGS.pipelined_tree_builder_max_memory,
+    )
+}
+
+/// Returns the number of nodes per batch: the largest power of `arity` up to `nodes_count`
+/// for which the batches in flight stay below `max_memory` bytes, but at least `arity`.
+fn batch_size(nodes_count: usize, arity: usize, layers: usize, max_memory: u64) -> usize {
+    let max_nodes = max(max_memory / batch_node_memory(layers), 1) as usize;
+
+    let mut batch = arity;
+    while batch * arity <= nodes_count && batch * arity <= max_nodes {
+        batch *= arity;
+    }
+
+    batch
+}
+
+/// Hashes each group of `arity` nodes of `row`, at `height`, into the row above. With `batched`,
+/// the groups are hashed in batches of `BATCH_SIZE`.
+fn hash_row<H: Hasher>(
+    row: &[H::Domain],
+    arity: usize,
+    height: usize,
+    batched: bool,
+) -> Result<Vec<H::Domain>> {
+    if !batched {
+        return Ok(row
+            .par_chunks(arity)
+            .map(|children| H::Function::default().multi_node(children, height))
+            .collect());
+    }
+
+    let batches = row
+        .par_chunks(arity * BATCH_SIZE)
+        .map(|parts| H::Function::try_multi_node_batch(parts, arity, height))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(batches.concat())
+}
+
+/// Hashes rows of `arity` nodes into the row above until the root is reached. Returns all rows,
+/// starting with `leaves`, which are at `level`.
+fn build_rows<H: Hasher>(
+    leaves: Vec<H::Domain>,
+    arity: usize,
+    level: usize,
+) -> Result<Vec<Vec<H::Domain>>> {
+    let mut rows = vec![leaves];
+    while rows[rows.len() - 1].len() > 1 {
+        let height = level + rows.len() - 1;
+        let next = hash_row::<H>(
+            &rows[rows.len() - 1],
+            arity,
+            height,
+            SETTINGS.use_batched_poseidon,
+        )?;
+        rows.push(next);
+    }
+
+    Ok(rows)
+}
+
+/// Hashes the columns of the given layer labels, returning the leaves of tree_c.
+fn hash_columns<D: Domain>(layers: &[Vec<u8>], nodes: usize) -> Result<Vec<D>> {
+    if SETTINGS.use_batched_poseidon {
+        return hash_columns_batched(layers, nodes);
+    }
+
+    (0..nodes)
+        .into_par_iter()
+        .map(|i| {
+            let column = layers
+                .iter()
+                .map(|layer| {
+                    D::try_from_bytes(&layer[i * NODE_SIZE..(i + 1) * NODE_SIZE]).map(Into::into)
+                })
+                .collect::<Result<Vec<Fr>, _>>()?;
+
+            Ok(hash_single_column(&column).into())
+        })
+        .collect()
+}
+
+/// Like `hash_columns`, but hashes the columns in batches of `BATCH_SIZE`.
+fn hash_columns_batched<D: Domain>(layers: &[Vec<u8>], nodes: usize) -> Result<Vec<D>> {
+    let batches = (0..(nodes + BATCH_SIZE - 1) / BATCH_SIZE)
+        .into_par_iter()
+        .map(|batch| {
+            let start = batch * BATCH_SIZE;
+            let end = (start + BATCH_SIZE).min(nodes);
+            let columns = (start..end)
+                .flat_map(|i| {
+                    layers.iter().map(move |layer| {
+                        D::try_from_bytes(&layer[i * NODE_SIZE..(i + 1) * NODE_SIZE])
+                            .map(Into::into)
+                    })
+                })
+                .collect::<Result<Vec<Fr>, _>>()?;
+
+            Ok(hash_column_batch(&columns, layers.len())
+                .into_iter()
+                .map(Into::into)
+                .collect::<Vec<D>>())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(batches.concat())
+}
+
+/// Returns the level of the roots of batches of `batch` nodes.
+fn batch_level(batch: usize, arity: usize) -> usize {
+    let mut level = 0;
+    while arity.pow(level as u32) < batch {
+        level += 1;
+    }
+
+    level
+}
+
+/// Reads the labels of `layers` for the nodes from `start` to `end`.
+fn read_layers<Tree: MerkleTreeTrait>(
+    labels: &LabelsCache<Tree>,
+    layers: RangeInclusive<usize>,
+    start: usize,
+    end: usize,
+) -> Result<Vec<Vec<u8>>> {
+    layers
+        .map(|layer| {
+            let mut buf = vec![0u8; (end - start) * NODE_SIZE];
+            labels
+                .labels_for_layer(layer)
+                .read_range_into(start, end, &mut buf)
+                .with_context(|| format!("failed to read labels of layer {}", layer))?;
+            Ok(buf)
+        })
+        .collect()
+}
+
+/// Encodes `data` in place with the labels of the last layer, returning the leaves of
+/// tree_r_last.
+fn encode_nodes<D: Domain>(last_layer: &[u8], data: &mut [u8]) -> Result<Vec<D>> {
+    last_layer
+        .par_chunks(NODE_SIZE)
+        .zip(data.par_chunks_mut(NODE_SIZE))
+        .map(|(key, data_node_bytes)| {
+            let key = D::try_from_bytes(key)?;
+            let data_node = D::try_from_bytes(data_node_bytes)?;
+            let encoded_node = encode::<D>(key, data_node);
+            data_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&encoded_node));
+
+            Ok(encoded_node)
+        })
+        .collect()
+}
+
+/// Builds the sub-trees of tree_c and tree_r_last in `tree_c_pending` and `tree_r_last_pending`
+/// in one pass over the labels, encoding the replica on the way. Returns the roots
+/// of tree_c and tree_r_last.
+///
+/// Reading the labels, hashing and encoding, and writing the stores run concurrently on batches
+/// of nodes. The batches are sized so that the memory they take stays below
+/// `SETTINGS.pipelined_tree_builder_max_memory`.
+///
+/// Nothing records which batches of a sub-tree an interrupted run encoded, so the replica
+/// regions of the sub-trees in `tree_r_last_pending` must hold the original data. The caller
+/// restores them from tree_d before the trees are built, which is why an interrupted run is only
+/// resumed when tree_d is given.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn generate_trees<Tree: 'static + MerkleTreeTrait>(
+    replica: &mut Replica<'_>,
+    layers: usize,
+    nodes_count: usize,
+    tree_count: usize,
+    tree_c_config: StoreConfig,
+    tree_r_last_config: StoreConfig,
+    replica_path: PathBuf,
+    tree_c_pending: &[usize],
+    tree_r_last_pending: &[usize],
+    labels: &LabelsCache<Tree>,
+) -> Result<(
+    <Tree::Hasher as Hasher>::Domain,
+    <Tree::Hasher as Hasher>::Domain,
+)> {
+    let arity = Tree::Arity::to_usize();
+    let tree_c_configs = split_config(tree_c_config.clone(), tree_count)?;
+    let (tree_r_last_configs, replica_config) = split_config_and_replica(
+        tree_r_last_config.clone(),
+        replica_path,
+        nodes_count,
+        tree_count,
+    )?;
+
+    let layout = RowLayout::new(nodes_count, arity);
+    let tree_len = layout.tree_len();
+    let cache_size =
+        get_merkle_tree_cache_size(nodes_count, arity, tree_r_last_config.rows_to_discard)?;
+
+    let batch = tree_builder_batch_size(nodes_count, arity, layers);
+    let batch_level = batch_level(batch, arity);
+
+    info!(
+        "generating tree c and tree r last using the pipelined CPU builder, {} nodes per batch",
+        batch
+    );
+
+    let trees: Vec<usize> = (0..tree_count)
+        .filter(|i| tree_c_pending.contains(i) || tree_r_last_pending.contains(i))
+        .collect();
+    let tree_c_configs = &tree_c_configs;
+    let tree_r_last_configs = &tree_r_last_configs;
+    let layout = &layout;
+    let context = &ThreadContext::current();
+
+    let (label_tx, label_rx) = sync_channel::<LabelBatch>(1);
+    let (row_tx, row_rx) = sync_channel::<RowBatch<<Tree::Hasher as Hasher>::Domain>>(1);
+
+    let (reader, hasher, writer) = crossbeam::thread::scope(|s| {
+        let trees = &trees;
+        let reader = s.spawn(move |_| -> Result<()> {
+            let _context = context.enter();
+            for &i in trees {
+                let build_tree_c = tree_c_pending.contains(&i);
+                let first_layer = if build_tree_c { 1 } else { layers };
+
+                for offset in (0..nodes_count).step_by(batch) {
+                    let start = i * nodes_count + offset;
+                    let batch_layers =
+                        read_layers(labels, first_layer..=layers, start, start + batch)?;
+
+                    label_tx
+                        .send(LabelBatch {
+                            tree: i,
+                            offset,
+                            build_tree_c,
+                            build_tree_r_last: tree_r_last_pending.contains(&i),
+                            layers: batch_layers,
+                        })
+                        .map_err(|_| anyhow!("tree builder stopped"))?;
+                }
+            }
+
+            Ok(())
+        });
+
+        let hasher = s.spawn(move |_| -> Result<()> {
+            let _context = context.enter();
+            for batch_labels in label_rx {
+                let start = batch_labels.tree * nodes_count + batch_labels.offset;
+                let last_layer = &batch_labels.layers[batch_labels.layers.len() - 1];
+
+                // Column hashing and encoding are independent, so they share the thread pool.
+                let (tree_c, tree_r_last) = rayon::join(
+                    || -> Result<_> {
+                        if !batch_labels.build_tree_c {
+                            return Ok(None);
+                        }
+                        let leaves = hash_columns(&batch_labels.layers, batch)?;
+                        Ok(Some(build_rows::<Tree::Hasher>(leaves, arity, 0)?))
+                    },
+                    || -> Result<_> {
+                        if !batch_labels.build_tree_r_last {
+                            return Ok(None);
+                        }
+                        // The batch is written back before its rows are sent to the writer, which
+                        // marks the sub-tree complete.
+                        let mut region = replica.region(start, start + batch)?;
+                        let leaves = encode_nodes(last_layer, &mut region)?;
+                        region.write_back()?;
+                        Ok(Some(build_rows::<Tree::Hasher>(leaves, arity, 0)?))
+                    },
+                );
+
+                row_tx
+                    .send(RowBatch {
+                        tree: batch_labels.tree,
+                        offset: batch_labels.offset,
+                        tree_c: tree_c?,
+                        tree_r_last: tree_r_last?,
+                    })
+                    .map_err(|_| anyhow!("tree writer stopped"))?;
+            }
+
+            Ok(())
+        });
+
+        let writer = s.spawn(move |_| -> Result<()> {
+            let _context = context.enter();
+            let mut tree_c_store = None;
+            let mut tree_r_last_store = None;
+
+            for rows in row_rx {
+                if rows.offset == 0 {
+                    trace!("building sub-trees {}/{}", rows.tree + 1, tree_count);
+                    if rows.tree_c.is_some() {
+                        let config = &tree_c_configs[rows.tree];
+                        tree_c_store = Some(SubTreeStore::create(config, tree_len, 0)?);
+                    }
+                    if rows.tree_r_last.is_some() {
+                        let config = &tree_r_last_configs[rows.tree];
+                        let skip = tree_len - cache_size;
+                        tree_r_last_store = Some(SubTreeStore::create(config, cache_size, skip)?);
+                    }
+                }
+
+                if let Some(tree_c_rows) = rows.tree_c {
+                    tree_c_store
+                        .as_mut()
+                        .expect("tree_c store not created")
+                        .add_batch(layout, rows.offset, tree_c_rows)?;
+                }
+                if let Some(tree_r_last_rows) = rows.tree_r_last {
+                    tree_r_last_store
+                        .as_mut()
+                        .expect("tree_r_last store not created")
+                        .add_batch(layout, rows.offset, tree_r_last_rows)?;
+                }
+
+                if rows.offset + batch == nodes_count {
+                    if let Some(store) = tree_c_store.take() {
+                        store.finish::<Tree::Hasher>(layout, batch_level)?;
+                    }
+                    if let Some(store) = tree_r_last_store.take() {
+                        store.finish::<Tree::Hasher>(layout, batch_level)?;
+                    }
+                    METRICS.add_bytes(
+                        Operation::GenerateTreesPipelined,
+                        (nodes_count * NODE_SIZE) as u64,
+                    );
+                    info!("built sub-trees {}/{}", rows.tree + 1, tree_count);
+                }
+            }
+
+            Ok(())
+        });
+
+        (reader.join(), hasher.join(), writer.join())
+    })
+    .expect("crossbeam scope failure");
+
+    // A stage fails when the next one stopped, so the error of the last failed stage is the
+    // cause.
+    writer.expect("tree writer panicked")?;
+    hasher.expect("tree builder panicked")?;
+    reader.expect("label reader panicked")?;
+
+    let tree_c = create_disk_tree::<
+        DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    >(
+        tree_c_config.size.expect("config size failure"),
+        tree_c_configs,
+    )?;
+    let tree_r_last = create_lc_tree::<
+        LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    >(
+        tree_r_last_config.size.expect("config size failure"),
+        tree_r_last_configs,
+        &replica_config,
+    )?;
+
+    Ok((tree_c.root(), tree_r_last.root()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use filecoin_hashers::poseidon::PoseidonHasher;
+    use generic_array::typenum::{U0, U8};
+    use merkletree::merkle::get_merkle_tree_len;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::{merkle::create_base_merkle_tree, TEST_SEED};
+
+    #[test]
+    fn test_batch_size() {
+        // 11 layers take 3 * 32 * 14 bytes per node.
+        assert_eq!(batch_size(4096, 8, 11, 1344 * 512), 512);
+        assert_eq!(batch_size(4096, 8, 11, 1344 * 511), 64);
+        assert_eq!(batch_size(4096, 8, 11, 0), 8);
+        assert_eq!(batch_size(4096, 8, 11, u64::max_value()), 4096);
+        assert_eq!(batch_size(64, 2, 2, 3 * 32 * 5 * 16), 16);
+    }
+
+    #[test]
+    fn test_rows_match_merkle_tree() {
+        type Tree = DiskTree<PoseidonHasher, U8, U0, U0>;
+
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        let leaves: Vec<<PoseidonHasher as Hasher>::Domain> =
+            (0..512).map(|_| Domain::random(rng)).collect();
+        let data: Vec<u8> = leaves
+            .iter()
+            .flat_map(|leaf| leaf.as_ref().to_vec())
+            .collect();
+        let tree = create_base_merkle_tree::<Tree>(None, 512, &data).expect("failed to build tree");
+
+        // Build the tree in batches of 64 leaves, as the pipeline does.
+        let layout = RowLayout::new(512, 8);
+        assert_eq!(
+            layout.tree_len(),
+            get_merkle_tree_len(512, 8).expect("get_merkle_tree_len failed")
+        );
+
+        let mut nodes = vec![Default::default(); layout.tree_len()];
+        let mut roots = Vec::new();
+        for offset in (0..512).step_by(64) {
+            let rows = build_rows::<PoseidonHasher>(leaves[offset..offset + 64].to_vec(), 8, 0)
+                .expect("build_rows failed");
+            let mut index = offset;
+            for (level, row) in rows.iter().enumerate() {
+                let start = layout.starts[level] + index;
+                nodes[start..start + row.len()].copy_from_slice(row);
+                index /= 8;
+            }
+            roots.push(rows[rows.len() - 1][0]);
+        }
+        let top = build_rows::<PoseidonHasher>(roots, 8, 2).expect("build_rows failed");
+        assert_eq!(top.len(), 2);
+        nodes[layout.starts[3]] = top[1][0];
+
+        assert_eq!(nodes[layout.tree_len() - 1], tree.root());
+        for (i, node) in nodes.iter().enumerate() {
+            assert_eq!(
+                *node,
+                tree.read_at(i).expect("read_at failed"),
+                "node {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn test_batched_rows_match_scalar() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        let row: Vec<<PoseidonHasher as Hasher>::Domain> = (0..8 * (BATCH_SIZE + 3))
+            .map(|_| Domain::random(rng))
+            .collect();
+
+        for &(arity, height) in &[(2, 0), (8, 0), (8, 3)] {
+            let scalar =
+                hash_row::<PoseidonHasher>(&row, arity, height, false).expect("hash_row failed");
+            let batched =
+                hash_row::<PoseidonHasher>(&row, arity, height, true).expect("hash_row failed");
+            assert_eq!(scalar.len(), row.len() / arity);
+            assert_eq!(scalar, batched, "arity {}", arity);
+        }
+    }
+
+    #[test]
+    fn test_batched_columns_match_scalar() {
+        type D = <PoseidonHasher as Hasher>::Domain;
+
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        let nodes = BATCH_SIZE + 5;
+        for &layers in &[2, 11] {
+            let labels: Vec<Vec<u8>> = (0..layers)
+                .map(|_| {
+                    (0..nodes)
+                        .flat_map(|_| D::random(rng).as_ref().to_vec())
+                        .collect()
+                })
+                .collect();
+
+            let batched: Vec<D> =
+                hash_columns_batched(&labels, nodes).expect("hash_columns_batched failed");
+            let scalar: Vec<D> = (0..nodes)
+                .map(|i| {
+                    let column: Vec<Fr> = labels
+                        .iter()
+                        .map(|layer| {
+                            D::try_from_bytes(&layer[i * NODE_SIZE..(i + 1) * NODE_SIZE])
+                                .expect("invalid label")
+                                .into()
+                        })
+                        .collect();
+                    hash_single_column(&column).into()
+                })
+                .collect();
+            assert_eq!(batched, scalar, "layers {}", layers);
+        }
+    }
+}
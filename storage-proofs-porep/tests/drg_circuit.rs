@@ -137,13 +137,17 @@ fn test_drg_porep_circuit() {
     let replica_id = Some(replica_id);
 
     assert!(
-        proof_nc.nodes[0].proof.validate(challenge),
+        proof_nc.nodes[0]
+            .proof
+            .validate(challenge)
+            .expect("failed to validate"),
         "failed to verify data commitment"
     );
     assert!(
         proof_nc.nodes[0]
             .proof
-            .validate_data(data_node.unwrap().into()),
+            .validate_data(data_node.unwrap().into())
+            .expect("failed to validate data"),
         "failed to verify data commitment with data"
     );
 
@@ -1,5 +1,6 @@
 use bellperson::{
     bls::Fr,
+    groth16::verify_proof,
     util_cs::{metric_cs::MetricCS, test_cs::TestConstraintSystem},
     Circuit,
 };
@@ -16,6 +17,8 @@ use storage_proofs_core::{
     compound_proof::{self, CompoundProof},
     drgraph::BASE_DEGREE,
     merkle::{get_base_tree_count, DiskTree, MerkleTreeTrait},
+    multi_proof::MultiProof,
+    proof::ProofScheme,
     test_helper::setup_replica,
     util::default_rows_to_discard,
     TEST_SEED,
@@ -47,6 +50,12 @@ fn test_stacked_compound_poseidon_top_8_4_2() {
     test_stacked_compound::<DiskTree<PoseidonHasher, U8, U4, U2>>();
 }
 
+#[test]
+#[ignore]
+fn test_stacked_compound_partitions_poseidon_base_8() {
+    test_stacked_compound_partitions::<DiskTree<PoseidonHasher, U8, U0, U0>>();
+}
+
 fn test_stacked_compound<Tree: 'static + MerkleTreeTrait>() {
     let nodes = 8 * get_base_tree_count::<Tree>();
 
@@ -202,3 +211,134 @@ fn test_stacked_compound<Tree: 'static + MerkleTreeTrait>() {
 
     cache_dir.close().expect("Failed to remove cache dir");
 }
+
+// Proves each partition on its own, as `seal_commit_phase2_partition` does, and checks every
+// proof against the public inputs of its own partition only.
+fn test_stacked_compound_partitions<Tree: 'static + MerkleTreeTrait>() {
+    let nodes = 8 * get_base_tree_count::<Tree>();
+    let partition_count = 2;
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let replica_id: Fr = Fr::random(rng);
+    let data: Vec<u8> = (0..nodes)
+        .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+        .collect();
+
+    let setup_params = compound_proof::SetupParams {
+        vanilla_params: SetupParams {
+            nodes,
+            degree: BASE_DEGREE,
+            expansion_degree: EXP_DEGREE,
+            porep_id: [55; 32],
+            layer_challenges: LayerChallenges::new(2, 1),
+            api_version: ApiVersion::V1_1_0,
+        },
+        partitions: Some(partition_count),
+        priority: false,
+    };
+
+    let cache_dir = tempdir().expect("tempdir failure");
+    let config = StoreConfig::new(
+        cache_dir.path(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(nodes, BINARY_ARITY),
+    );
+    let replica_path = cache_dir.path().join("replica-path");
+    let mut mmapped_data = setup_replica(&data, &replica_path);
+
+    let public_params = StackedCompound::setup(&setup_params).expect("setup failed");
+    let (tau, (p_aux, t_aux)) = StackedDrg::<Tree, _>::replicate(
+        &public_params.vanilla_params,
+        &replica_id.into(),
+        (mmapped_data.as_mut()).into(),
+        None,
+        config,
+        replica_path.clone(),
+    )
+    .expect("replication failed");
+
+    let public_inputs =
+        PublicInputs::<<Tree::Hasher as Hasher>::Domain, <Sha256Hasher as Hasher>::Domain> {
+            replica_id: replica_id.into(),
+            seed: rng.gen(),
+            tau: Some(tau),
+            k: None,
+        };
+    let t_aux = TemporaryAuxCache::<Tree, _>::new(&t_aux, replica_path)
+        .expect("failed to restore contents of t_aux");
+    let private_inputs = PrivateInputs::<Tree, Sha256Hasher> { p_aux, t_aux };
+
+    let vanilla_proofs = StackedDrg::<Tree, Sha256Hasher>::prove_all_partitions(
+        &public_params.vanilla_params,
+        &public_inputs,
+        &private_inputs,
+        partition_count,
+    )
+    .expect("failed to generate vanilla proofs");
+    assert_eq!(vanilla_proofs.len(), partition_count);
+
+    let groth_params = <StackedCompound<Tree, Sha256Hasher> as CompoundProof<
+        StackedDrg<'_, Tree, Sha256Hasher>,
+        _,
+    >>::groth_params(Some(rng), &public_params.vanilla_params)
+    .expect("failed to generate groth params");
+
+    // Prove the partitions out of order, each one only sees its own vanilla proof.
+    let mut groth_proofs: Vec<_> = (0..partition_count)
+        .rev()
+        .map(|k| {
+            StackedCompound::<Tree, Sha256Hasher>::partition_circuit_proof(
+                &public_inputs,
+                &vanilla_proofs[k],
+                k,
+                &public_params.vanilla_params,
+                &groth_params,
+                false,
+            )
+            .expect("failed to prove partition")
+        })
+        .collect();
+    groth_proofs.reverse();
+
+    let partition_inputs: Vec<_> = (0..partition_count)
+        .map(|k| {
+            StackedCompound::<Tree, Sha256Hasher>::generate_public_inputs(
+                &public_inputs,
+                &public_params.vanilla_params,
+                Some(k),
+            )
+            .expect("failed to generate public inputs")
+        })
+        .collect();
+    assert_ne!(partition_inputs[0], partition_inputs[1]);
+
+    for (k, proof) in groth_proofs.iter().enumerate() {
+        for (j, inputs) in partition_inputs.iter().enumerate() {
+            let verified =
+                verify_proof(&groth_params.pvk, proof, inputs).expect("failed while verifying");
+            assert_eq!(
+                verified,
+                j == k,
+                "proof of partition {} against inputs of partition {}",
+                k,
+                j
+            );
+        }
+    }
+
+    // Put together in partition order, the proofs verify like those of `prove`.
+    let multi_proof = MultiProof::new(groth_proofs, &groth_params.pvk);
+    let verified = StackedCompound::verify(
+        &public_params,
+        &public_inputs,
+        &multi_proof,
+        &ChallengeRequirements {
+            minimum_challenges: 1,
+        },
+    )
+    .expect("failed while verifying");
+    assert!(verified);
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
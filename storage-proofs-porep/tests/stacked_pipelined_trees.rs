@@ -0,0 +1,284 @@
+use std::env;
+use std::fs::{remove_file, OpenOptions};
+use std::path::Path;
+
+use bellperson::bls::Fr;
+use ff::Field;
+use filecoin_hashers::{blake2s::Blake2sHasher, poseidon::PoseidonHasher, Domain, Hasher};
+use fr32::fr_into_bytes;
+use generic_array::typenum::{Unsigned, U0, U2, U8};
+use merkletree::{hash::Algorithm, store::StoreConfig};
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use storage_proofs_core::{
+    api_version::ApiVersion,
+    cache_key::CacheKey,
+    drgraph::BASE_DEGREE,
+    merkle::{
+        create_base_merkle_tree, get_base_tree_count, BinaryMerkleTree, DiskTree, MerkleTreeTrait,
+    },
+    proof::ProofScheme,
+    test_helper::setup_replica,
+    util::{default_rows_to_discard, NODE_SIZE},
+    TEST_SEED,
+};
+use storage_proofs_porep::{
+    stacked::{
+        LayerChallenges, PrivateInputs, PublicInputs, SetupParams, StackedDrg, TemporaryAuxCache,
+        BINARY_ARITY, EXP_DEGREE,
+    },
+    PoRep,
+};
+use tempfile::tempdir;
+
+// The settings are read once per process, so the pipelined builder is only enabled in this
+// test binary. The memory ceiling makes the builder use batches of 64 nodes.
+fn enable_pipelined_tree_builder() {
+    env::set_var("FIL_PROOFS_USE_PIPELINED_TREE_BUILDER", "1");
+    env::set_var("FIL_PROOFS_PIPELINED_TREE_BUILDER_MAX_MEMORY", "100000");
+}
+
+#[test]
+fn test_stacked_porep_pipelined_tree_builder() {
+    enable_pipelined_tree_builder();
+
+    test_pipelined_tree_builder::<DiskTree<PoseidonHasher, U8, U0, U0>>();
+    test_pipelined_tree_builder::<DiskTree<PoseidonHasher, U8, U8, U2>>();
+}
+
+#[test]
+fn test_stacked_porep_pipelined_resume_tree_building() {
+    enable_pipelined_tree_builder();
+
+    type Tree = DiskTree<PoseidonHasher, U8, U2, U0>;
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sub_tree_nodes = 512;
+    let nodes = sub_tree_nodes * get_base_tree_count::<Tree>();
+
+    let replica_id = <PoseidonHasher as Hasher>::Domain::random(rng);
+    let data: Vec<u8> = (0..nodes)
+        .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+        .collect();
+
+    let cache_dir = tempdir().expect("tempdir failure");
+    let config = StoreConfig::new(
+        cache_dir.path(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(nodes, BINARY_ARITY),
+    );
+    let replica_path = cache_dir.path().join("replica-path");
+    let mut mmapped_data = setup_replica(&data, &replica_path);
+
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [32; 32],
+        layer_challenges: LayerChallenges::new(11, 5),
+        api_version: ApiVersion::V1_1_0,
+    };
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+    let seal = |mmapped_data: &mut [u8]| {
+        let labels =
+            StackedDrg::<Tree, Blake2sHasher>::replicate_phase1(&pp, &replica_id, config.clone())
+                .expect("replicate_phase1 failed");
+        let tree_d = create_base_merkle_tree::<BinaryMerkleTree<Blake2sHasher>>(
+            Some(config.clone()),
+            nodes,
+            &data,
+        )
+        .expect("failed to build tree_d");
+
+        StackedDrg::<Tree, Blake2sHasher>::replicate_phase2(
+            &pp,
+            labels,
+            mmapped_data.into(),
+            tree_d,
+            config.clone(),
+            replica_path.clone(),
+        )
+        .expect("replicate_phase2 failed")
+    };
+
+    let (tau, (p_aux, _)) = seal(mmapped_data.as_mut());
+    let replica = mmapped_data.to_vec();
+    assert_ne!(data, replica, "replication did not change data");
+
+    // Simulate a crash partway through the second sub-tree, after three of its batches of 64
+    // nodes: its stores are partially written and not marked complete, and the replica region
+    // is encoded up to the end of the third batch.
+    for id in &["tree-c-1", "tree-r-last-1"] {
+        let path = StoreConfig::data_path(cache_dir.path(), id);
+        let len = path.metadata().expect("failed to read metadata").len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_len(len / 2))
+            .expect("failed to truncate sub-tree store");
+        remove_marker(&path);
+    }
+    let encoded = (sub_tree_nodes + 3 * 64) * NODE_SIZE;
+    mmapped_data.as_mut()[sub_tree_nodes * NODE_SIZE..]
+        .copy_from_slice(&data[sub_tree_nodes * NODE_SIZE..]);
+    mmapped_data.as_mut()[..encoded].copy_from_slice(&replica[..encoded]);
+    let completed = StoreConfig::data_path(cache_dir.path(), "tree-r-last-0");
+    let modified = completed
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .expect("failed to read metadata");
+
+    let (resumed_tau, (resumed_p_aux, _)) = seal(mmapped_data.as_mut());
+
+    assert_eq!(tau, resumed_tau);
+    assert_eq!(p_aux, resumed_p_aux);
+    assert_eq!(replica, mmapped_data.as_ref());
+    assert_eq!(
+        modified,
+        completed
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .expect("failed to read metadata"),
+        "completed sub-tree was rebuilt"
+    );
+
+    // Simulate a crash after tree_c of the second sub-tree was marked complete, but before
+    // tree_r_last was, with the replica region encoded partway through a batch. Only tree_r_last
+    // is built again.
+    let tree_r_last_path = StoreConfig::data_path(cache_dir.path(), "tree-r-last-1");
+    remove_file(&tree_r_last_path).expect("failed to remove sub-tree store");
+    remove_marker(&tree_r_last_path);
+    let encoded = (sub_tree_nodes + 5 * 64 + 17) * NODE_SIZE;
+    mmapped_data.as_mut()[encoded..].copy_from_slice(&data[encoded..]);
+    let completed = StoreConfig::data_path(cache_dir.path(), "tree-c-1");
+    let modified = completed
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .expect("failed to read metadata");
+
+    let (resumed_tau, (resumed_p_aux, _)) = seal(mmapped_data.as_mut());
+
+    assert_eq!(tau, resumed_tau);
+    assert_eq!(p_aux, resumed_p_aux);
+    assert_eq!(replica, mmapped_data.as_ref());
+    assert_eq!(
+        modified,
+        completed
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .expect("failed to read metadata"),
+        "completed sub-tree was rebuilt"
+    );
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
+
+/// Removes the completion marker of the sub-tree store at `path`.
+fn remove_marker(path: &Path) {
+    let mut marker = path.as_os_str().to_owned();
+    marker.push(".done");
+    remove_file(marker).expect("failed to remove sub-tree marker");
+}
+
+fn test_pipelined_tree_builder<Tree: 'static + MerkleTreeTrait>() {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let tree_count = get_base_tree_count::<Tree>();
+    let sub_tree_nodes = 512;
+    let nodes = sub_tree_nodes * tree_count;
+
+    let replica_id = <Tree::Hasher as Hasher>::Domain::random(rng);
+    let data: Vec<u8> = (0..nodes)
+        .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+        .collect();
+
+    let cache_dir = tempdir().expect("tempdir failure");
+    let config = StoreConfig::new(
+        cache_dir.path(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(nodes, BINARY_ARITY),
+    );
+    let replica_path = cache_dir.path().join("replica-path");
+    let mut mmapped_data = setup_replica(&data, &replica_path);
+
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [32; 32],
+        layer_challenges: LayerChallenges::new(11, 5),
+        api_version: ApiVersion::V1_1_0,
+    };
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+    let (tau, (p_aux, t_aux)) = StackedDrg::<Tree, Blake2sHasher>::replicate(
+        &pp,
+        &replica_id,
+        mmapped_data.as_mut().into(),
+        None,
+        config.clone(),
+        replica_path.clone(),
+    )
+    .expect("replication failed");
+    let replica = mmapped_data.to_vec();
+    assert_ne!(data, replica, "replication did not change data");
+
+    // tree_r_last is built over the replica.
+    let mut roots: Vec<_> = replica
+        .chunks(sub_tree_nodes * NODE_SIZE)
+        .map(|sub_tree_data| {
+            create_base_merkle_tree::<DiskTree<Tree::Hasher, Tree::Arity, U0, U0>>(
+                None,
+                sub_tree_nodes,
+                sub_tree_data,
+            )
+            .expect("failed to build tree_r_last sub-tree")
+            .root()
+        })
+        .collect();
+    for arity in &[
+        Tree::SubTreeArity::to_usize(),
+        Tree::TopTreeArity::to_usize(),
+    ] {
+        if *arity > 0 {
+            roots = roots
+                .chunks(*arity)
+                .map(|children| {
+                    <Tree::Hasher as Hasher>::Function::default().multi_node(children, 0)
+                })
+                .collect();
+        }
+    }
+    assert_eq!(roots, vec![p_aux.comm_r_last]);
+
+    // The stores are usable for proving.
+    let seed = rng.gen();
+    let pub_inputs =
+        PublicInputs::<<Tree::Hasher as Hasher>::Domain, <Blake2sHasher as Hasher>::Domain> {
+            replica_id,
+            seed,
+            tau: Some(tau),
+            k: None,
+        };
+    let t_aux = TemporaryAuxCache::<Tree, Blake2sHasher>::new(&t_aux, replica_path)
+        .expect("failed to restore contents of t_aux");
+    let priv_inputs = PrivateInputs {
+        p_aux: p_aux.clone(),
+        t_aux,
+    };
+    let proofs =
+        StackedDrg::<Tree, Blake2sHasher>::prove_all_partitions(&pp, &pub_inputs, &priv_inputs, 1)
+            .expect("failed to generate partition proofs");
+    assert!(
+        StackedDrg::<Tree, Blake2sHasher>::verify_all_partitions(&pp, &pub_inputs, &proofs)
+            .expect("failed to verify partition proofs")
+    );
+    drop(priv_inputs);
+
+    // tree_c matches the one built by the CPU column builder.
+    let comm_c = StackedDrg::<Tree, Blake2sHasher>::regenerate_tree_c(&pp, config)
+        .expect("failed to regenerate tree_c");
+    assert_eq!(comm_c, p_aux.comm_c);
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
@@ -1,4 +1,5 @@
 use std::fs::remove_file;
+use std::path::{Path, PathBuf};
 
 use bellperson::bls::{Fr, FrRepr};
 use ff::{Field, PrimeField};
@@ -15,7 +16,9 @@ use storage_proofs_core::{
     api_version::ApiVersion,
     cache_key::CacheKey,
     drgraph::BASE_DEGREE,
-    merkle::{get_base_tree_count, DiskTree, MerkleTreeTrait},
+    merkle::{
+        create_base_merkle_tree, get_base_tree_count, BinaryMerkleTree, DiskTree, MerkleTreeTrait,
+    },
     proof::ProofScheme,
     table_tests,
     test_helper::setup_replica,
@@ -24,8 +27,9 @@ use storage_proofs_core::{
 };
 use storage_proofs_porep::{
     stacked::{
-        LayerChallenges, PrivateInputs, PublicInputs, SetupParams, StackedBucketGraph, StackedDrg,
-        TemporaryAux, TemporaryAuxCache, BINARY_ARITY, EXP_DEGREE,
+        add_commit_reads, LayerChallenges, PrivateInputs, PublicInputs, ReadPlanBuilder,
+        SdrPlacement, SetupParams, StackedBucketGraph, StackedDrg, TemporaryAux, TemporaryAuxCache,
+        BINARY_ARITY, EXP_DEGREE,
     },
     PoRep,
 };
@@ -307,6 +311,127 @@ fn test_stacked_porep_resume_seal() {
     cache_dir.close().expect("Failed to remove cache dir");
 }
 
+#[test]
+fn test_stacked_porep_resume_tree_building() {
+    // pretty_env_logger::try_init().ok();
+
+    type Tree = DiskTree<PoseidonHasher, U8, U8, U2>;
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let replica_id = <PoseidonHasher as Hasher>::Domain::random(rng);
+    let nodes = 64 * get_base_tree_count::<Tree>();
+
+    let data: Vec<u8> = (0..nodes)
+        .flat_map(|_| {
+            let v = <PoseidonHasher as Hasher>::Domain::random(rng);
+            v.into_bytes()
+        })
+        .collect();
+
+    let cache_dir = tempdir().expect("tempdir failure");
+    let config = StoreConfig::new(
+        cache_dir.path(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(nodes, BINARY_ARITY),
+    );
+
+    let replica_path = cache_dir.path().join("replica-path");
+    let mut mmapped_data = setup_replica(&data, &replica_path);
+
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [32; 32],
+        layer_challenges: LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5),
+        api_version: ApiVersion::V1_1_0,
+    };
+
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+    let seal = |mmapped_data: &mut [u8]| {
+        let labels =
+            StackedDrg::<Tree, Blake2sHasher>::replicate_phase1(&pp, &replica_id, config.clone())
+                .expect("replicate_phase1 failed");
+        let tree_d = create_base_merkle_tree::<BinaryMerkleTree<Blake2sHasher>>(
+            Some(config.clone()),
+            nodes,
+            &data,
+        )
+        .expect("failed to build tree_d");
+
+        StackedDrg::<Tree, Blake2sHasher>::replicate_phase2(
+            &pp,
+            labels,
+            mmapped_data.into(),
+            tree_d,
+            config.clone(),
+            replica_path.clone(),
+        )
+        .expect("replicate_phase2 failed")
+    };
+
+    let (tau, (p_aux, _)) = seal(mmapped_data.as_mut());
+    let replica = mmapped_data.to_vec();
+    assert_ne!(data, replica, "replication did not change data");
+
+    // Simulate a crash while building the second tree_c and tree_r_last sub-trees: their stores
+    // are gone, but the replica region of the tree_r_last sub-tree is already encoded.
+    for id in &["tree-c-1", "tree-r-last-1"] {
+        let path = StoreConfig::data_path(cache_dir.path(), id);
+        remove_file(path).expect("failed to remove sub-tree store");
+    }
+    let completed = StoreConfig::data_path(cache_dir.path(), "tree-c-0");
+    let modified = completed
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .expect("failed to read metadata");
+
+    let (resumed_tau, (resumed_p_aux, _)) = seal(mmapped_data.as_mut());
+
+    assert_eq!(tau, resumed_tau);
+    assert_eq!(p_aux, resumed_p_aux);
+    assert_eq!(replica, mmapped_data.as_ref());
+    assert_eq!(
+        modified,
+        completed
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .expect("failed to read metadata"),
+        "completed sub-tree was rebuilt"
+    );
+
+    // Simulate a crash partway through the first tree_r_last sub-tree: no sub-tree is complete,
+    // but the first half of the replica region of the first sub-tree is already encoded.
+    for i in 0..get_base_tree_count::<Tree>() {
+        remove_sub_tree(cache_dir.path(), &format!("tree-c-{}", i));
+        remove_sub_tree(cache_dir.path(), &format!("tree-r-last-{}", i));
+    }
+    let encoded = 32 * NODE_SIZE;
+    mmapped_data.as_mut()[..encoded].copy_from_slice(&replica[..encoded]);
+    mmapped_data.as_mut()[encoded..].copy_from_slice(&data[encoded..]);
+
+    let (resumed_tau, (resumed_p_aux, _)) = seal(mmapped_data.as_mut());
+
+    assert_eq!(tau, resumed_tau);
+    assert_eq!(p_aux, resumed_p_aux);
+    assert_eq!(replica, mmapped_data.as_ref());
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
+
+/// Removes the store of the sub-tree `id` in `dir` and its completion marker.
+fn remove_sub_tree(dir: &Path, id: &str) {
+    let path = StoreConfig::data_path(dir, id);
+    let mut marker = path.clone().into_os_string();
+    marker.push(".done");
+    for path in &[path, PathBuf::from(marker)] {
+        if path.exists() {
+            remove_file(path).expect("failed to remove sub-tree store");
+        }
+    }
+}
+
 table_tests! {
     test_prove_verify_fixed {
        test_stacked_porep_prove_verify(64);
@@ -440,6 +565,118 @@ fn test_prove_verify<Tree: 'static + MerkleTreeTrait>(n: usize, challenges: Laye
     cache_dir.close().expect("Failed to remove cache dir");
 }
 
+#[test]
+fn test_stacked_porep_commit_read_plan() {
+    let challenges = LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5);
+
+    test_commit_read_plan::<DiskTree<PoseidonHasher, U8, U0, U0>>(64, challenges.clone());
+    test_commit_read_plan::<DiskTree<PoseidonHasher, U8, U2, U0>>(64, challenges.clone());
+    test_commit_read_plan::<DiskTree<PoseidonHasher, U8, U8, U2>>(64, challenges);
+}
+
+fn test_commit_read_plan<Tree: 'static + MerkleTreeTrait>(n: usize, challenges: LayerChallenges) {
+    let nodes = n * get_base_tree_count::<Tree>();
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let replica_id: <Tree::Hasher as Hasher>::Domain =
+        <Tree::Hasher as Hasher>::Domain::random(rng);
+    let data: Vec<u8> = (0..nodes)
+        .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+        .collect();
+
+    let cache_dir = tempdir().expect("tempdir failure");
+    let config = StoreConfig::new(
+        cache_dir.path(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(nodes, BINARY_ARITY),
+    );
+    let replica_path = cache_dir.path().join("replica-path");
+    let mut mmapped_data = setup_replica(&data, &replica_path);
+
+    let partitions = 2;
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [92; 32],
+        layer_challenges: challenges,
+        api_version: ApiVersion::V1_1_0,
+    };
+
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+    let (tau, (p_aux, t_aux)) = StackedDrg::<Tree, Blake2sHasher>::replicate(
+        &pp,
+        &replica_id,
+        (mmapped_data.as_mut()).into(),
+        None,
+        config,
+        replica_path.clone(),
+    )
+    .expect("replication failed");
+
+    let pub_inputs =
+        PublicInputs::<<Tree::Hasher as Hasher>::Domain, <Blake2sHasher as Hasher>::Domain> {
+            replica_id,
+            seed: rng.gen(),
+            tau: Some(tau),
+            k: None,
+        };
+
+    let mut builder = ReadPlanBuilder::new();
+    add_commit_reads(&mut builder, &pp, &pub_inputs, &t_aux, partitions)
+        .expect("failed to plan reads");
+    let plan = builder.build();
+
+    let full_size: u64 = plan.files.iter().map(|file| file.len).sum();
+    assert!(plan.total_bytes() > 0);
+    assert!(plan.total_bytes() < full_size);
+    for file in &plan.files {
+        for pair in file.ranges.windows(2) {
+            assert!(pair[0].end < pair[1].start, "ranges are not merged");
+        }
+    }
+
+    let sparse_dir = tempdir().expect("tempdir failure");
+    let sparse_replica_path = sparse_dir.path().join("replica-path");
+    plan.write_sparse_copy(
+        cache_dir.path(),
+        &replica_path,
+        sparse_dir.path(),
+        &sparse_replica_path,
+    )
+    .expect("failed to write sparse copy");
+    plan.verify_files(sparse_dir.path(), &sparse_replica_path)
+        .expect("sparse copy is incomplete");
+
+    let prove = |t_aux: &TemporaryAux<Tree, Blake2sHasher>, replica_path: PathBuf| {
+        let priv_inputs = PrivateInputs {
+            p_aux: p_aux.clone(),
+            t_aux: TemporaryAuxCache::<Tree, Blake2sHasher>::new(t_aux, replica_path)
+                .expect("failed to restore contents of t_aux"),
+        };
+        let proofs = StackedDrg::<Tree, Blake2sHasher>::prove_all_partitions(
+            &pp,
+            &pub_inputs,
+            &priv_inputs,
+            partitions,
+        )
+        .expect("failed to generate partition proofs");
+        bincode::serialize(&proofs).expect("failed to serialize proofs")
+    };
+
+    // Proving from the sparse copy must only touch the planned ranges, so it yields the same
+    // proofs as proving from the full cache.
+    let mut sparse_t_aux = t_aux.clone();
+    sparse_t_aux.set_cache_path(sparse_dir.path());
+    assert_eq!(
+        prove(&t_aux, replica_path),
+        prove(&sparse_t_aux, sparse_replica_path)
+    );
+
+    sparse_dir.close().expect("Failed to remove sparse dir");
+    cache_dir.close().expect("Failed to remove cache dir");
+}
+
 // We are seeing a bug, in which setup never terminates for some sector sizes. This test is to
 // debug that and should remain as a regression test.
 #[test]
@@ -579,3 +816,60 @@ fn test_generate_labels_aux(
 
     assert_eq!(expected_last_label.into_repr(), last_label.0);
 }
+
+#[test]
+fn test_stacked_porep_generate_labels_with_placement() {
+    let layers = 11;
+    let nodes = (1 << 11) / NODE_SIZE;
+    let replica_id = [9u8; 32];
+    let porep_id = [123; 32];
+
+    let graph = StackedBucketGraph::<PoseidonHasher>::new(
+        None,
+        nodes,
+        BASE_DEGREE,
+        EXP_DEGREE,
+        porep_id,
+        ApiVersion::V1_1_0,
+    )
+    .unwrap();
+    let unused_layer_challenges = LayerChallenges::new(layers, 0);
+
+    // The placement only decides where the labelling runs, the labels must not depend on it.
+    for placement in &[SdrPlacement::Auto, SdrPlacement::Cores(vec![0])] {
+        let cache_dir = tempdir().expect("tempdir failure");
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            nodes.trailing_zeros() as usize,
+        );
+
+        let (labels, _, report) = StackedDrg::<
+            DiskTree<PoseidonHasher, U8, U8, U2>,
+            Sha256Hasher,
+        >::generate_labels_for_encoding_with_placement(
+            &graph,
+            &unused_layer_challenges,
+            &<PoseidonHasher as Hasher>::Domain::try_from_bytes(&replica_id).unwrap(),
+            config,
+            placement,
+        )
+        .unwrap();
+
+        if let SdrPlacement::Cores(cores) = placement {
+            assert!(report.cores.is_empty() || &report.cores == cores);
+        }
+
+        let final_labels = labels.labels_for_last_layer().unwrap();
+        let last_label = final_labels.read_at(nodes - 1).unwrap();
+        assert_eq!(
+            FrRepr([
+                0xabb3f38bb70defcf,
+                0x777a2e4d7769119f,
+                0x3448959d495490bc,
+                0x06021188c7a71cb5,
+            ]),
+            last_label.0
+        );
+    }
+}
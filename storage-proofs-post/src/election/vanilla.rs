@@ -215,10 +215,10 @@ fn generate_candidate<Tree: MerkleTreeTrait>(
         let challenge =
             generate_leaf_challenge(pub_params, randomness, sector_challenge_index, n as u64)?;
 
-        let val: Fr = measure_op(Operation::PostReadChallengedRange, || {
+        let val = measure_op(Operation::PostReadChallengedRange, || {
             tree.read_at(challenge as usize)
         })?
-        .into();
+        .try_to_fr()?;
         data.push(val.into());
     }
 
@@ -442,7 +442,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait> ProofScheme<'a> for ElectionPoSt<'a, T
                     return Ok(false);
                 }
 
-                if !merkle_proof.validate(challenged_leaf_start as usize + i) {
+                if !merkle_proof.validate(challenged_leaf_start as usize + i)? {
                     return Ok(false);
                 }
             }
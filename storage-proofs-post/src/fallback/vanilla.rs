@@ -14,6 +14,7 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use storage_proofs_core::{
     api_version::ApiVersion,
+    context::ThreadContext,
     error::{Error, Result},
     merkle::{MerkleProof, MerkleProofTrait, MerkleTreeTrait, MerkleTreeWrapper},
     parameter_cache::ParameterSetMetadata,
@@ -285,7 +286,8 @@ pub fn vanilla_proof<Tree: MerkleTreeTrait>(
             let proof = tree.gen_cached_proof(challenged_leaf as usize, Some(rows_to_discard))?;
 
             ensure!(
-                proof.validate(challenged_leaf as usize) && proof.root() == priv_sector.comm_r_last,
+                proof.validate(challenged_leaf as usize)?
+                    && proof.root() == priv_sector.comm_r_last,
                 "Generated vanilla proof for sector {} is invalid",
                 sector_id
             );
@@ -366,6 +368,7 @@ impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for FallbackPoSt<'a, Tree>
 
         // Use `BTreeSet` so failure result will be canonically ordered (sorted).
         let mut faulty_sectors = BTreeSet::new();
+        let context = &ThreadContext::current();
 
         for (j, (pub_sectors_chunk, priv_sectors_chunk)) in pub_inputs
             .sectors
@@ -378,6 +381,7 @@ impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for FallbackPoSt<'a, Tree>
                 .zip(priv_sectors_chunk.par_iter())
                 .enumerate()
                 .map(|(i, (pub_sector, priv_sector))| {
+                    let _context = context.enter();
                     let sector_id = pub_sector.id;
                     let tree = priv_sector.tree;
                     let tree_leafs = tree.leafs();
@@ -415,9 +419,12 @@ impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for FallbackPoSt<'a, Tree>
                                     Some(rows_to_discard),
                                 );
 
-                                match proof {
-                                    Ok(proof) => {
-                                        if proof.validate(challenged_leaf as usize)
+                                match proof.and_then(|proof| {
+                                    let valid = proof.validate(challenged_leaf as usize)?;
+                                    Ok((proof, valid))
+                                }) {
+                                    Ok((proof, valid)) => {
+                                        if valid
                                             && proof.root() == priv_sector.comm_r_last
                                             && pub_sector.comm_r
                                                 == <Tree::Hasher as Hasher>::Function::hash2(
@@ -591,7 +598,7 @@ impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for FallbackPoSt<'a, Tree>
                                 return Ok(false);
                             }
 
-                            if !inclusion_proof.validate(challenged_leaf as usize) {
+                            if !inclusion_proof.validate(challenged_leaf as usize)? {
                                 error!("invalid inclusion proof: {:?}", sector_id);
                                 return Ok(false);
                             }
@@ -217,7 +217,7 @@ impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for RationalPoSt<'a, Tree>
                 return Ok(false);
             }
 
-            if !merkle_proof.validate(challenged_leaf as usize) {
+            if !merkle_proof.validate(challenged_leaf as usize)? {
                 return Ok(false);
             }
         }
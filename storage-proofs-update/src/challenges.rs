@@ -0,0 +1,28 @@
+use byteorder::{ByteOrder, LittleEndian};
+use filecoin_hashers::Domain;
+use sha2::{Digest, Sha256};
+
+/// Generates the nodes challenged in a sector update proof. Challenges are bound to the
+/// verifier's randomness and to the new replica commitment, so they cannot be known before
+/// the update has been committed to.
+pub fn generate_update_challenges<T: Domain>(
+    randomness: &T,
+    comm_r_new: &T,
+    sector_nodes: usize,
+    challenge_count: usize,
+) -> Vec<usize> {
+    // avoid rehashing fixed inputs
+    let mut hasher = Sha256::new();
+    hasher.update(AsRef::<[u8]>::as_ref(randomness));
+    hasher.update(AsRef::<[u8]>::as_ref(comm_r_new));
+
+    (0..challenge_count)
+        .map(|challenge_index| {
+            let mut hasher = hasher.clone();
+            hasher.update(&(challenge_index as u64).to_le_bytes()[..]);
+            let hash = hasher.finalize();
+
+            (LittleEndian::read_u64(&hash[..8]) % sector_nodes as u64) as usize
+        })
+        .collect()
+}
@@ -0,0 +1,88 @@
+use anyhow::{ensure, Context};
+use bellperson::bls::Fr;
+use ff::Field;
+use filecoin_hashers::{Domain, HashFunction, Hasher};
+use storage_proofs_core::error::Result;
+
+/// The default number of high bits of a node index used to select that node's `rho`.
+pub const DEFAULT_H: usize = 8;
+
+/// Returns the number of index bits (`h`) used to select `rho` in a sector of `sector_nodes`.
+pub fn h_for_sector_nodes(sector_nodes: usize) -> usize {
+    let log2_nodes = sector_nodes.trailing_zeros() as usize;
+    std::cmp::min(DEFAULT_H, log2_nodes)
+}
+
+/// Derives the update's encoding randomness `phi` from the new data commitment and the old
+/// replica commitment.
+pub fn phi<H: Hasher, D: Domain>(comm_d_new: &D, comm_r_old: &H::Domain) -> H::Domain {
+    let comm_d_new: Fr = (*comm_d_new).into();
+    <H::Function as HashFunction<H::Domain>>::hash2(&comm_d_new.into(), comm_r_old)
+}
+
+/// The per-node encoding factors of a sector update. Node `i` is encoded using
+/// `rho(i) = phi^high(i)`, where `high(i)` is the value of the `h` most significant bits of `i`.
+#[derive(Debug, Clone)]
+pub struct Rhos {
+    /// The number of bits a node index is shifted right to obtain its `high` bits.
+    shift: usize,
+    rhos: Vec<Fr>,
+    rhos_inv: Vec<Fr>,
+}
+
+impl Rhos {
+    pub fn new<D: Domain>(phi: &D, h: usize, sector_nodes: usize) -> Result<Self> {
+        ensure!(
+            sector_nodes.is_power_of_two(),
+            "sector nodes must be a power of two"
+        );
+        let log2_nodes = sector_nodes.trailing_zeros() as usize;
+        ensure!(h <= log2_nodes, "h ({}) exceeds log2(sector nodes)", h);
+
+        let phi: Fr = (*phi).into();
+        let phi_inv = phi.inverse().context("phi is not invertible")?;
+
+        let count = 1 << h;
+        let mut rhos = Vec::with_capacity(count);
+        let mut rhos_inv = Vec::with_capacity(count);
+        let mut rho = Fr::one();
+        let mut rho_inv = Fr::one();
+        for _ in 0..count {
+            rhos.push(rho);
+            rhos_inv.push(rho_inv);
+            rho.mul_assign(&phi);
+            rho_inv.mul_assign(&phi_inv);
+        }
+
+        Ok(Rhos {
+            shift: log2_nodes - h,
+            rhos,
+            rhos_inv,
+        })
+    }
+
+    pub fn rho(&self, node: usize) -> Fr {
+        self.rhos[node >> self.shift]
+    }
+
+    pub fn rho_inv(&self, node: usize) -> Fr {
+        self.rhos_inv[node >> self.shift]
+    }
+}
+
+/// Encodes a single data node into a sector key node: `new = old + data * rho`.
+pub fn encode<T: Domain, D: Domain>(sector_key_node: T, data_node: D, rho: &Fr) -> T {
+    let mut result: Fr = data_node.into();
+    result.mul_assign(rho);
+    result.add_assign(&sector_key_node.into());
+    result.into()
+}
+
+/// Recovers a single data node from a replica node and its sector key node:
+/// `data = (new - old) * rho^-1`.
+pub fn decode<T: Domain, D: Domain>(sector_key_node: T, replica_node: T, rho_inv: &Fr) -> D {
+    let mut result: Fr = replica_node.into();
+    result.sub_assign(&sector_key_node.into());
+    result.mul_assign(rho_inv);
+    result.into()
+}
@@ -0,0 +1,10 @@
+#![deny(clippy::all, clippy::perf, clippy::correctness, rust_2018_idioms)]
+#![warn(clippy::unwrap_used)]
+
+mod challenges;
+mod encode;
+mod vanilla;
+
+pub use challenges::*;
+pub use encode::*;
+pub use vanilla::*;
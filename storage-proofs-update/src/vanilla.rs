@@ -0,0 +1,267 @@
+use std::marker::PhantomData;
+
+use anyhow::ensure;
+use filecoin_hashers::{Domain, HashFunction, Hasher};
+use generic_array::typenum::{Unsigned, U2};
+use log::trace;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use storage_proofs_core::{
+    error::Result,
+    merkle::{BinaryMerkleTree, MerkleProof, MerkleProofTrait, MerkleTreeTrait, MerkleTreeWrapper},
+    proof::{NoRequirements, ProofScheme},
+    util::{default_rows_to_discard, NODE_SIZE},
+};
+
+use crate::{
+    challenges::generate_update_challenges,
+    encode::{encode, h_for_sector_nodes, phi, Rhos},
+};
+
+#[derive(Debug, Clone)]
+pub struct SetupParams {
+    /// Size of the sector in bytes.
+    pub sector_bytes: u64,
+    /// Number of challenged nodes.
+    pub challenge_count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct PublicParams {
+    /// Number of nodes in the sector.
+    pub sector_nodes: usize,
+    /// Number of challenged nodes.
+    pub challenge_count: usize,
+    /// Number of high index bits used to select a node's `rho`.
+    pub h: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicInputs<T: Domain, D: Domain> {
+    /// Replica commitment of the sector key (the original CC replica).
+    #[serde(bound = "")]
+    pub comm_r_old: T,
+    /// Data commitment of the new data.
+    #[serde(bound = "")]
+    pub comm_d_new: D,
+    /// Replica commitment of the updated replica.
+    #[serde(bound = "")]
+    pub comm_r_new: T,
+    /// Verifier randomness used to derive the challenges.
+    #[serde(bound = "")]
+    pub randomness: T,
+}
+
+#[derive(Debug)]
+pub struct PrivateInputs<'a, Tree: MerkleTreeTrait, G: Hasher> {
+    /// Column commitment, shared by the sector key and the updated replica.
+    pub comm_c: <Tree::Hasher as Hasher>::Domain,
+    pub tree_r_old: &'a MerkleTreeWrapper<
+        Tree::Hasher,
+        Tree::Store,
+        Tree::Arity,
+        Tree::SubTreeArity,
+        Tree::TopTreeArity,
+    >,
+    pub tree_r_new: &'a MerkleTreeWrapper<
+        Tree::Hasher,
+        Tree::Store,
+        Tree::Arity,
+        Tree::SubTreeArity,
+        Tree::TopTreeArity,
+    >,
+    pub tree_d_new: &'a BinaryMerkleTree<G>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChallengeProof<P: MerkleProofTrait, G: Hasher> {
+    #[serde(bound(
+        serialize = "MerkleProof<P::Hasher, P::Arity, P::SubTreeArity, P::TopTreeArity>: Serialize",
+        deserialize = "MerkleProof<P::Hasher, P::Arity, P::SubTreeArity, P::TopTreeArity>: DeserializeOwned"
+    ))]
+    pub proof_r_old: MerkleProof<P::Hasher, P::Arity, P::SubTreeArity, P::TopTreeArity>,
+    #[serde(bound(
+        serialize = "MerkleProof<P::Hasher, P::Arity, P::SubTreeArity, P::TopTreeArity>: Serialize",
+        deserialize = "MerkleProof<P::Hasher, P::Arity, P::SubTreeArity, P::TopTreeArity>: DeserializeOwned"
+    ))]
+    pub proof_r_new: MerkleProof<P::Hasher, P::Arity, P::SubTreeArity, P::TopTreeArity>,
+    #[serde(bound(
+        serialize = "MerkleProof<G, U2>: Serialize",
+        deserialize = "MerkleProof<G, U2>: DeserializeOwned"
+    ))]
+    pub proof_d_new: MerkleProof<G, U2>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proof<P: MerkleProofTrait, G: Hasher> {
+    pub comm_c: <P::Hasher as Hasher>::Domain,
+    pub comm_r_last_old: <P::Hasher as Hasher>::Domain,
+    pub comm_r_last_new: <P::Hasher as Hasher>::Domain,
+    #[serde(bound(
+        serialize = "ChallengeProof<P, G>: Serialize",
+        deserialize = "ChallengeProof<P, G>: Deserialize<'de>"
+    ))]
+    pub challenge_proofs: Vec<ChallengeProof<P, G>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EmptySectorUpdate<'a, Tree, G>
+where
+    Tree: MerkleTreeTrait,
+    G: Hasher,
+{
+    _t: PhantomData<&'a (Tree, G)>,
+}
+
+impl<'a, Tree: 'a + MerkleTreeTrait, G: 'a + Hasher> ProofScheme<'a>
+    for EmptySectorUpdate<'a, Tree, G>
+{
+    type PublicParams = PublicParams;
+    type SetupParams = SetupParams;
+    type PublicInputs = PublicInputs<<Tree::Hasher as Hasher>::Domain, G::Domain>;
+    type PrivateInputs = PrivateInputs<'a, Tree, G>;
+    type Proof = Proof<Tree::Proof, G>;
+    type Requirements = NoRequirements;
+
+    fn setup(sp: &Self::SetupParams) -> Result<Self::PublicParams> {
+        let sector_nodes = sp.sector_bytes as usize / NODE_SIZE;
+        ensure!(
+            sector_nodes.is_power_of_two(),
+            "sector size must be a power of two number of nodes"
+        );
+
+        Ok(PublicParams {
+            sector_nodes,
+            challenge_count: sp.challenge_count,
+            h: h_for_sector_nodes(sector_nodes),
+        })
+    }
+
+    fn prove<'b>(
+        pub_params: &'b Self::PublicParams,
+        pub_inputs: &'b Self::PublicInputs,
+        priv_inputs: &'b Self::PrivateInputs,
+    ) -> Result<Self::Proof> {
+        let PrivateInputs {
+            comm_c,
+            tree_r_old,
+            tree_r_new,
+            tree_d_new,
+        } = priv_inputs;
+
+        let comm_r_last_old = tree_r_old.root();
+        let comm_r_last_new = tree_r_new.root();
+
+        ensure!(
+            <Tree::Hasher as Hasher>::Function::hash2(comm_c, &comm_r_last_old)
+                == pub_inputs.comm_r_old,
+            "comm_r_old does not match the sector key's tree_r_last"
+        );
+        ensure!(
+            <Tree::Hasher as Hasher>::Function::hash2(comm_c, &comm_r_last_new)
+                == pub_inputs.comm_r_new,
+            "comm_r_new does not match the updated replica's tree_r_last"
+        );
+        ensure!(
+            tree_d_new.root() == pub_inputs.comm_d_new,
+            "comm_d_new does not match tree_d"
+        );
+
+        let rows_to_discard_r =
+            default_rows_to_discard(tree_r_new.leafs(), Tree::Arity::to_usize());
+        let rows_to_discard_d = default_rows_to_discard(tree_d_new.leafs(), U2::to_usize());
+
+        let challenges = generate_update_challenges(
+            &pub_inputs.randomness,
+            &pub_inputs.comm_r_new,
+            pub_params.sector_nodes,
+            pub_params.challenge_count,
+        );
+        trace!("generating {} update challenge proofs", challenges.len());
+
+        let challenge_proofs = challenges
+            .par_iter()
+            .map(|&challenge| {
+                Ok(ChallengeProof {
+                    proof_r_old: tree_r_old.gen_cached_proof(challenge, Some(rows_to_discard_r))?,
+                    proof_r_new: tree_r_new.gen_cached_proof(challenge, Some(rows_to_discard_r))?,
+                    proof_d_new: tree_d_new.gen_cached_proof(challenge, Some(rows_to_discard_d))?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Proof {
+            comm_c: *comm_c,
+            comm_r_last_old,
+            comm_r_last_new,
+            challenge_proofs,
+        })
+    }
+
+    fn verify(
+        pub_params: &Self::PublicParams,
+        pub_inputs: &Self::PublicInputs,
+        proof: &Self::Proof,
+    ) -> Result<bool> {
+        let PublicInputs {
+            comm_r_old,
+            comm_d_new,
+            comm_r_new,
+            randomness,
+        } = pub_inputs;
+
+        if <Tree::Hasher as Hasher>::Function::hash2(&proof.comm_c, &proof.comm_r_last_old)
+            != *comm_r_old
+        {
+            trace!("invalid comm_r_old");
+            return Ok(false);
+        }
+        if <Tree::Hasher as Hasher>::Function::hash2(&proof.comm_c, &proof.comm_r_last_new)
+            != *comm_r_new
+        {
+            trace!("invalid comm_r_new");
+            return Ok(false);
+        }
+        if proof.challenge_proofs.len() != pub_params.challenge_count {
+            trace!("invalid number of challenge proofs");
+            return Ok(false);
+        }
+
+        let phi = phi::<Tree::Hasher, G::Domain>(comm_d_new, comm_r_old);
+        let rhos = Rhos::new(&phi, pub_params.h, pub_params.sector_nodes)?;
+
+        let challenges = generate_update_challenges(
+            randomness,
+            comm_r_new,
+            pub_params.sector_nodes,
+            pub_params.challenge_count,
+        );
+
+        for (challenge, challenge_proof) in challenges.into_iter().zip(&proof.challenge_proofs) {
+            let ChallengeProof {
+                proof_r_old,
+                proof_r_new,
+                proof_d_new,
+            } = challenge_proof;
+
+            if !proof_r_old.validate(challenge)?
+                || proof_r_old.root() != proof.comm_r_last_old
+                || !proof_r_new.validate(challenge)?
+                || proof_r_new.root() != proof.comm_r_last_new
+                || !proof_d_new.validate(challenge)?
+                || proof_d_new.root() != *comm_d_new
+            {
+                trace!("invalid inclusion proof for challenge {}", challenge);
+                return Ok(false);
+            }
+
+            let expected = encode(proof_r_old.leaf(), proof_d_new.leaf(), &rhos.rho(challenge));
+            if expected != proof_r_new.leaf() {
+                trace!("invalid encoding for challenge {}", challenge);
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,119 @@
+use filecoin_hashers::{
+    poseidon::PoseidonHasher, sha256::Sha256Hasher, Domain, HashFunction, Hasher,
+};
+use generic_array::typenum::{U0, U8};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use storage_proofs_core::{
+    merkle::{BinaryMerkleTree, DiskTree, MerkleTreeTrait},
+    proof::ProofScheme,
+    util::NODE_SIZE,
+    TEST_SEED,
+};
+use storage_proofs_update::{
+    decode, encode, phi, EmptySectorUpdate, PrivateInputs, PublicInputs, Rhos, SetupParams,
+};
+
+type TreeR = DiskTree<PoseidonHasher, U8, U0, U0>;
+type TreeRDomain = <PoseidonHasher as Hasher>::Domain;
+type TreeDDomain = <Sha256Hasher as Hasher>::Domain;
+
+#[test]
+fn test_empty_sector_update_64_nodes() {
+    test_empty_sector_update(64, 5);
+}
+
+#[test]
+fn test_empty_sector_update_512_nodes() {
+    test_empty_sector_update(512, 10);
+}
+
+fn test_empty_sector_update(sector_nodes: usize, challenge_count: usize) {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let sector_key: Vec<TreeRDomain> = (0..sector_nodes)
+        .map(|_| TreeRDomain::random(rng))
+        .collect();
+    let data: Vec<TreeDDomain> = (0..sector_nodes)
+        .map(|_| TreeDDomain::random(rng))
+        .collect();
+    let comm_c = TreeRDomain::random(rng);
+    let randomness = TreeRDomain::random(rng);
+
+    let tree_r_old: TreeR =
+        TreeR::try_from_iter(sector_key.iter().map(|node| Ok(*node))).expect("tree_r_old");
+    let tree_d_new =
+        BinaryMerkleTree::<Sha256Hasher>::try_from_iter(data.iter().map(|node| Ok(*node)))
+            .expect("tree_d_new");
+
+    let comm_r_old = <PoseidonHasher as Hasher>::Function::hash2(&comm_c, &tree_r_old.root());
+    let comm_d_new = tree_d_new.root();
+
+    let phi = phi::<PoseidonHasher, TreeDDomain>(&comm_d_new, &comm_r_old);
+    let pub_params = EmptySectorUpdate::<TreeR, Sha256Hasher>::setup(&SetupParams {
+        sector_bytes: (sector_nodes * NODE_SIZE) as u64,
+        challenge_count,
+    })
+    .expect("setup failed");
+    let rhos = Rhos::new(&phi, pub_params.h, sector_nodes).expect("rhos failed");
+
+    let replica: Vec<TreeRDomain> = sector_key
+        .iter()
+        .zip(data.iter())
+        .enumerate()
+        .map(|(i, (old, data))| encode(*old, *data, &rhos.rho(i)))
+        .collect();
+
+    // The data can be recovered from the updated replica and the sector key.
+    for (i, (old, new)) in sector_key.iter().zip(replica.iter()).enumerate() {
+        let decoded: TreeDDomain = decode(*old, *new, &rhos.rho_inv(i));
+        assert_eq!(decoded, data[i]);
+    }
+
+    let tree_r_new: TreeR =
+        TreeR::try_from_iter(replica.iter().map(|node| Ok(*node))).expect("tree_r_new");
+    let comm_r_new = <PoseidonHasher as Hasher>::Function::hash2(&comm_c, &tree_r_new.root());
+
+    let pub_inputs = PublicInputs {
+        comm_r_old,
+        comm_d_new,
+        comm_r_new,
+        randomness,
+    };
+    let priv_inputs = PrivateInputs::<TreeR, Sha256Hasher> {
+        comm_c,
+        tree_r_old: &tree_r_old,
+        tree_r_new: &tree_r_new,
+        tree_d_new: &tree_d_new,
+    };
+
+    let proof =
+        EmptySectorUpdate::<TreeR, Sha256Hasher>::prove(&pub_params, &pub_inputs, &priv_inputs)
+            .expect("proving failed");
+    assert_eq!(proof.challenge_proofs.len(), challenge_count);
+
+    let is_valid =
+        EmptySectorUpdate::<TreeR, Sha256Hasher>::verify(&pub_params, &pub_inputs, &proof)
+            .expect("verification failed");
+    assert!(is_valid);
+
+    // A proof must not verify against a different data commitment.
+    let bad_pub_inputs = PublicInputs {
+        comm_d_new: TreeDDomain::random(rng),
+        ..pub_inputs.clone()
+    };
+    let is_valid =
+        EmptySectorUpdate::<TreeR, Sha256Hasher>::verify(&pub_params, &bad_pub_inputs, &proof)
+            .expect("verification failed");
+    assert!(!is_valid);
+
+    // Nor against a different new replica commitment.
+    let bad_pub_inputs = PublicInputs {
+        comm_r_new: TreeRDomain::random(rng),
+        ..pub_inputs
+    };
+    let is_valid =
+        EmptySectorUpdate::<TreeR, Sha256Hasher>::verify(&pub_params, &bad_pub_inputs, &proof)
+            .expect("verification failed");
+    assert!(!is_valid);
+}